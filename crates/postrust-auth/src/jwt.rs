@@ -6,11 +6,29 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// Validate a JWT token and extract claims.
+///
+/// During secret rotation a token whose signature doesn't match the current
+/// secret is tried against `secret_previous`.
 pub fn validate_token(token: &str, config: &JwtConfig) -> Result<AuthResult, JwtError> {
     let secret = config.secret.as_ref().ok_or_else(|| {
         JwtError::InvalidToken("No JWT secret configured".into())
     })?;
 
+    match validate_with_secret(token, secret, config) {
+        Err(JwtError::InvalidSignature) => match &config.secret_previous {
+            Some(previous) => validate_with_secret(token, previous, config),
+            None => Err(JwtError::InvalidSignature),
+        },
+        result => result,
+    }
+}
+
+/// Validate a JWT token against a single secret.
+fn validate_with_secret(
+    token: &str,
+    secret: &str,
+    config: &JwtConfig,
+) -> Result<AuthResult, JwtError> {
     // Decode secret
     let key_bytes = if config.secret_is_base64 {
         base64_decode(secret)?
@@ -175,4 +193,39 @@ mod tests {
         let result = validate_token(&token, &config);
         assert!(matches!(result, Err(JwtError::Expired)));
     }
+
+    #[test]
+    fn test_previous_secret_during_rotation() {
+        let previous = "old_secret_key_at_least_32_bytes!!";
+        let current = "new_secret_key_at_least_32_bytes!!";
+
+        let claims = Claims {
+            sub: None,
+            iss: None,
+            exp: Some(chrono::Utc::now().timestamp() + 3600),
+            nbf: None,
+            iat: None,
+            aud: None,
+            extra: {
+                let mut m = HashMap::new();
+                m.insert("role".into(), serde_json::Value::String("web_user".into()));
+                m
+            },
+        };
+        let token = make_token(&claims, previous);
+
+        let mut config = JwtConfig {
+            secret: Some(current.into()),
+            secret_previous: Some(previous.into()),
+            ..Default::default()
+        };
+        assert_eq!(validate_token(&token, &config).unwrap().role, "web_user");
+
+        // Once rotation finishes, old tokens are rejected
+        config.secret_previous = None;
+        assert!(matches!(
+            validate_token(&token, &config),
+            Err(JwtError::InvalidSignature)
+        ));
+    }
 }
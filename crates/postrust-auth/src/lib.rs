@@ -45,6 +45,8 @@ impl AuthResult {
 pub struct JwtConfig {
     /// Secret key for HS256/HS384/HS512
     pub secret: Option<String>,
+    /// Previous secret, still accepted while rotating keys
+    pub secret_previous: Option<String>,
     /// Whether secret is base64 encoded
     pub secret_is_base64: bool,
     /// Required audience claim
@@ -53,16 +55,20 @@ pub struct JwtConfig {
     pub role_claim_key: String,
     /// Default role for anonymous requests
     pub anon_role: Option<String>,
+    /// Roles a token may assume; any role when `None`. The anon role is always allowed.
+    pub role_allowlist: Option<Vec<String>>,
 }
 
 impl Default for JwtConfig {
     fn default() -> Self {
         Self {
             secret: None,
+            secret_previous: None,
             secret_is_base64: false,
             audience: None,
             role_claim_key: "role".to_string(),
             anon_role: None,
+            role_allowlist: None,
         }
     }
 }
@@ -93,6 +99,9 @@ pub enum JwtError {
 
     #[error("Invalid audience")]
     InvalidAudience,
+
+    #[error("Role not allowed: {0}")]
+    RoleNotAllowed(String),
 }
 
 /// Extract and validate JWT from Authorization header.
@@ -112,7 +121,35 @@ pub fn authenticate(
     };
 
     // Validate token
-    validate_token(token, config)
+    let result = validate_token(token, config)?;
+    check_role(&result.role, config)?;
+    Ok(result)
+}
+
+/// Accept a role forwarded by a trusted proxy, without a JWT.
+///
+/// The proxy is responsible for authenticating the client; the role is still
+/// subject to the allowlist.
+pub fn authenticate_forwarded(role: &str, config: &JwtConfig) -> Result<AuthResult, JwtError> {
+    check_role(role, config)?;
+    let mut claims = HashMap::new();
+    claims.insert("role".to_string(), serde_json::Value::String(role.to_string()));
+    Ok(AuthResult {
+        role: role.to_string(),
+        claims,
+    })
+}
+
+/// Reject roles outside the configured allowlist.
+fn check_role(role: &str, config: &JwtConfig) -> Result<(), JwtError> {
+    let Some(allowlist) = &config.role_allowlist else {
+        return Ok(());
+    };
+    if config.anon_role.as_deref() == Some(role) || allowlist.iter().any(|r| r == role) {
+        Ok(())
+    } else {
+        Err(JwtError::RoleNotAllowed(role.to_string()))
+    }
 }
 
 /// Extract Bearer token from Authorization header.
@@ -168,4 +205,54 @@ mod tests {
         let config = JwtConfig::default();
         assert!(authenticate(None, &config).is_err());
     }
+
+    fn bearer(role: &str, secret: &str) -> String {
+        let claims = serde_json::json!({
+            "role": role,
+            "exp": chrono::Utc::now().timestamp() + 3600,
+        });
+        let key = jsonwebtoken::EncodingKey::from_secret(secret.as_bytes());
+        let token = jsonwebtoken::encode(&jsonwebtoken::Header::default(), &claims, &key).unwrap();
+        format!("Bearer {}", token)
+    }
+
+    #[test]
+    fn test_role_allowlist() {
+        let secret = "test_secret_key_at_least_32_bytes!";
+        let config = JwtConfig {
+            secret: Some(secret.into()),
+            anon_role: Some("web_anon".into()),
+            role_allowlist: Some(vec!["web_user".into()]),
+            ..Default::default()
+        };
+
+        let result = authenticate(Some(&bearer("web_user", secret)), &config).unwrap();
+        assert_eq!(result.role, "web_user");
+
+        let err = authenticate(Some(&bearer("postgres", secret)), &config).unwrap_err();
+        assert!(matches!(err, JwtError::RoleNotAllowed(ref r) if r == "postgres"), "{:?}", err);
+
+        // The anon role is implicitly allowed, with or without a token
+        assert!(authenticate(Some(&bearer("web_anon", secret)), &config).is_ok());
+        assert_eq!(authenticate(None, &config).unwrap().role, "web_anon");
+
+        // Without an allowlist any role is accepted
+        let config = JwtConfig { role_allowlist: None, ..config };
+        assert!(authenticate(Some(&bearer("postgres", secret)), &config).is_ok());
+    }
+
+    #[test]
+    fn test_authenticate_forwarded_role() {
+        let config = JwtConfig {
+            role_allowlist: Some(vec!["web_user".into()]),
+            ..Default::default()
+        };
+
+        let result = authenticate_forwarded("web_user", &config).unwrap();
+        assert_eq!(result.role, "web_user");
+        assert_eq!(result.get_claim("role").unwrap(), "web_user");
+
+        let err = authenticate_forwarded("postgres", &config).unwrap_err();
+        assert!(matches!(err, JwtError::RoleNotAllowed(_)), "{:?}", err);
+    }
 }
@@ -9,7 +9,7 @@ pub mod payload;
 pub mod preferences;
 
 pub use types::*;
-pub use query_params::parse_query_params;
+pub use query_params::{parse_query_params, parse_routine_query_params, QueryLimits};
 pub use preferences::parse_preferences;
 
 use crate::error::{Error, Result};
@@ -33,13 +33,17 @@ where
     let resource = parse_resource(path)?;
 
     // Determine schema from headers or use default
-    let (schema, negotiated_by_profile) = parse_schema(req, default_schema, schemas)?;
+    let (schema, representation_schema, negotiated_by_profile) =
+        parse_schema(req, default_schema, schemas)?;
 
     // Parse action from method and resource
     let action = parse_action(method, &resource, &schema)?;
 
-    // Parse query parameters
-    let query_params = parse_query_params(query)?;
+    // Parse query parameters; routine arguments may appear among them
+    let query_params = match resource {
+        Resource::Routine(_) => parse_routine_query_params(query)?,
+        _ => parse_query_params(query)?,
+    };
 
     // Parse preferences from Prefer header
     let preferences = parse_preferences(req.headers())?;
@@ -47,19 +51,28 @@ where
     // Parse Accept header for content negotiation
     let accept_media_types = parse_accept(req.headers())?;
 
+    let bytea_encoding = parse_bytea_encoding(req.headers())?;
+
     // Parse Content-Type header
     let content_media_type = parse_content_type(req.headers())?;
 
-    // Parse Range header
-    let top_level_range = parse_range(req.headers())?;
+    // Parse Range header; `limit`/`offset` in the query take precedence
+    let top_level_range = match query_params.ranges.get("") {
+        Some(range) => range.clone(),
+        None => parse_range(req.headers())?,
+    };
 
     // Extract headers and cookies for GUC passthrough
     let headers = extract_headers(req.headers());
     let cookies = extract_cookies(req.headers());
 
+    // Parse If-Match for conditional updates
+    let if_match = parse_if_match(req.headers())?;
+
     Ok(ApiRequest {
         action,
         schema,
+        representation_schema,
         payload: None, // Payload parsed separately
         query_params,
         accept_media_types,
@@ -73,6 +86,9 @@ where
         path: path.to_string(),
         headers,
         cookies,
+        if_match,
+        bytea_encoding,
+        search_path: None, // Set by the server from trusted headers
     })
 }
 
@@ -101,30 +117,55 @@ fn parse_resource(path: &str) -> Result<Resource> {
 }
 
 /// Parse the schema from Accept-Profile or Content-Profile headers.
+///
+/// Reads (GET/HEAD) use `Accept-Profile`. Writes target the
+/// `Content-Profile` schema and, when `Accept-Profile` names a different
+/// schema, resolve the returned representation there.
 fn parse_schema<B>(
     req: &Request<B>,
     default_schema: &str,
     schemas: &[String],
-) -> Result<(String, bool)> {
-    // Check Accept-Profile header first (for reads)
-    if let Some(profile) = req.headers().get("accept-profile") {
-        let schema = profile.to_str().map_err(|_| Error::InvalidHeader("Accept-Profile"))?;
-        if !schemas.contains(&schema.to_string()) {
-            return Err(Error::UnacceptableSchema(schema.into()));
-        }
-        return Ok((schema.to_string(), true));
+) -> Result<(String, Option<String>, bool)> {
+    let accept = profile_header(req, "accept-profile", "Accept-Profile", schemas)?;
+    let content = profile_header(req, "content-profile", "Content-Profile", schemas)?;
+    let negotiated = accept.is_some() || content.is_some();
+
+    let is_write = matches!(
+        *req.method(),
+        Method::POST | Method::PATCH | Method::PUT | Method::DELETE
+    );
+
+    if is_write {
+        let target = content.unwrap_or_else(|| default_schema.to_string());
+        let representation = accept.filter(|s| *s != target);
+        return Ok((target, representation, negotiated));
     }
 
-    // Check Content-Profile header (for writes)
-    if let Some(profile) = req.headers().get("content-profile") {
-        let schema = profile.to_str().map_err(|_| Error::InvalidHeader("Content-Profile"))?;
-        if !schemas.contains(&schema.to_string()) {
-            return Err(Error::UnacceptableSchema(schema.into()));
-        }
-        return Ok((schema.to_string(), true));
-    }
+    let schema = accept
+        .or(content)
+        .unwrap_or_else(|| default_schema.to_string());
+    Ok((schema, None, negotiated))
+}
 
-    Ok((default_schema.to_string(), false))
+/// Read a profile header, checking it names an exposed schema.
+fn profile_header<B>(
+    req: &Request<B>,
+    header: &str,
+    display: &'static str,
+    schemas: &[String],
+) -> Result<Option<String>> {
+    let Some(profile) = req.headers().get(header) else {
+        return Ok(None);
+    };
+
+    let schema = profile.to_str().map_err(|_| Error::InvalidHeader(display))?;
+    if !schemas.iter().any(|s| s == schema) {
+        return Err(Error::UnacceptableSchema {
+            schema: schema.into(),
+            acceptable: schemas.to_vec(),
+        });
+    }
+    Ok(Some(schema.to_string()))
 }
 
 /// Parse the action from HTTP method and resource.
@@ -201,7 +242,6 @@ fn parse_accept(headers: &http::HeaderMap) -> Result<Vec<MediaType>> {
         let types: Vec<MediaType> = accept_str
             .split(',')
             .map(|s| s.trim())
-            .map(|s| s.split(';').next().unwrap_or(s).trim())
             .map(parse_media_type)
             .collect();
         if types.is_empty() {
@@ -212,26 +252,59 @@ fn parse_accept(headers: &http::HeaderMap) -> Result<Vec<MediaType>> {
     Ok(vec![MediaType::ApplicationJson])
 }
 
-/// Parse a single media type string.
+/// Parse a single media type string, including its parameters.
 fn parse_media_type(s: &str) -> MediaType {
-    match s {
+    let base = s.split(';').next().unwrap_or(s).trim();
+    let nulls = media_type_param(s, "nulls");
+    match base {
+        "application/json" | "application/vnd.pgrst.array+json" if nulls.as_deref() == Some("stripped") => {
+            MediaType::ArrayJsonStrip
+        }
+        "application/json" if media_type_param(s, "keyed").as_deref() == Some("true") => MediaType::KeyedJson,
         "application/json" => MediaType::ApplicationJson,
         "application/geo+json" => MediaType::GeoJson,
-        "text/csv" => MediaType::TextCsv,
+        "text/csv" => MediaType::TextCsv {
+            null: media_type_param(s, "null"),
+        },
         "text/plain" => MediaType::TextPlain,
         "text/xml" => MediaType::TextXml,
         "application/openapi+json" => MediaType::OpenApi,
         "application/x-www-form-urlencoded" => MediaType::UrlEncoded,
         "application/octet-stream" => MediaType::OctetStream,
+        "application/x-ndjson" => MediaType::NdJson,
         "*/*" => MediaType::Any,
-        s if s.starts_with("application/vnd.pgrst.object") => {
-            MediaType::SingularJson { nullable: s.contains("nulls=null") }
-        }
-        s if s.starts_with("application/vnd.pgrst.array") => MediaType::ArrayJsonStrip,
+        b if b.starts_with("application/vnd.pgrst.object") => MediaType::SingularJson {
+            nullable: nulls.as_deref() == Some("null"),
+            scalar: media_type_param(s, "scalar").as_deref() == Some("true"),
+        },
+        b if b.starts_with("application/vnd.pgrst.array") => MediaType::ApplicationJson,
         other => MediaType::Other(other.to_string()),
     }
 }
 
+/// Get a media type parameter, with surrounding quotes removed.
+fn media_type_param(s: &str, name: &str) -> Option<String> {
+    s.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Read the `bytea` parameter (`base64` or `hex`) of the Accept header.
+fn parse_bytea_encoding(headers: &http::HeaderMap) -> Result<Option<ByteaEncoding>> {
+    let Some(accept) = headers.get(http::header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+        return Ok(None);
+    };
+    accept
+        .split(',')
+        .find_map(|media_type| media_type_param(media_type, "bytea"))
+        .map(|value| {
+            ByteaEncoding::parse(&value)
+                .ok_or_else(|| Error::InvalidMediaType(format!("unknown bytea encoding '{}'", value)))
+        })
+        .transpose()
+}
+
 /// Parse Content-Type header.
 fn parse_content_type(headers: &http::HeaderMap) -> Result<MediaType> {
     if let Some(ct) = headers.get(http::header::CONTENT_TYPE) {
@@ -242,6 +315,20 @@ fn parse_content_type(headers: &http::HeaderMap) -> Result<MediaType> {
     Ok(MediaType::ApplicationJson)
 }
 
+/// Parse the If-Match header into a bare ETag.
+fn parse_if_match(headers: &http::HeaderMap) -> Result<Option<String>> {
+    let Some(value) = headers.get(http::header::IF_MATCH) else {
+        return Ok(None);
+    };
+
+    let value = value.to_str().map_err(|_| Error::InvalidHeader("If-Match"))?.trim();
+    let etag = value.strip_prefix("W/").unwrap_or(value).trim_matches('"');
+    if etag.is_empty() {
+        return Err(Error::InvalidHeader("If-Match"));
+    }
+    Ok(Some(etag.to_string()))
+}
+
 /// Parse Range header for pagination.
 fn parse_range(headers: &http::HeaderMap) -> Result<Range> {
     if let Some(range) = headers.get(http::header::RANGE) {
@@ -308,7 +395,151 @@ mod tests {
     #[test]
     fn test_parse_media_type() {
         assert_eq!(parse_media_type("application/json"), MediaType::ApplicationJson);
-        assert_eq!(parse_media_type("text/csv"), MediaType::TextCsv);
+        assert_eq!(parse_media_type("text/csv"), MediaType::TextCsv { null: None });
+        assert_eq!(
+            parse_media_type(r#"text/csv; null="\N""#),
+            MediaType::TextCsv { null: Some(r"\N".into()) }
+        );
         assert_eq!(parse_media_type("*/*"), MediaType::Any);
+        assert_eq!(parse_media_type("application/x-ndjson"), MediaType::NdJson);
+        assert_eq!(
+            parse_media_type("application/json; nulls=stripped"),
+            MediaType::ArrayJsonStrip
+        );
+        assert_eq!(
+            parse_media_type("application/vnd.pgrst.array+json;nulls=stripped"),
+            MediaType::ArrayJsonStrip
+        );
+        assert_eq!(
+            parse_media_type("application/vnd.pgrst.array+json"),
+            MediaType::ApplicationJson
+        );
+        assert_eq!(parse_media_type("application/json; keyed=true"), MediaType::KeyedJson);
+        assert_eq!(
+            parse_media_type("application/vnd.pgrst.object+json; nulls=null"),
+            MediaType::SingularJson { nullable: true, scalar: false }
+        );
+        assert_eq!(
+            parse_media_type("application/vnd.pgrst.object+json; scalar=true"),
+            MediaType::SingularJson { nullable: false, scalar: true }
+        );
+    }
+
+    #[test]
+    fn test_head_actions_are_headers_only_and_read_only() {
+        let head_rpc = parse_action(&Method::HEAD, &Resource::Routine("f".into()), "public").unwrap();
+        assert!(head_rpc.is_headers_only());
+        assert!(head_rpc.is_read_only());
+
+        let head_root = parse_action(&Method::HEAD, &Resource::Schema, "public").unwrap();
+        assert!(head_root.is_headers_only());
+
+        let post_rpc = parse_action(&Method::POST, &Resource::Routine("f".into()), "public").unwrap();
+        assert!(!post_rpc.is_headers_only());
+        assert!(!post_rpc.is_read_only());
+    }
+
+    #[test]
+    fn test_write_uses_content_profile_and_accept_profile_for_representation() {
+        let schemas = vec!["v1".to_string(), "v2".to_string()];
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/items")
+            .header("content-profile", "v2")
+            .header("accept-profile", "v1")
+            .body(Vec::<u8>::new())
+            .unwrap();
+
+        let api = parse_request(&req, "v1", &schemas).unwrap();
+        assert_eq!(api.schema, "v2");
+        assert_eq!(api.representation_schema.as_deref(), Some("v1"));
+        assert_eq!(api.response_schema(), "v1");
+        assert!(matches!(
+            api.action,
+            Action::Db(DbAction::RelationMut { ref qi, .. }) if qi.schema == "v2"
+        ));
+    }
+
+    #[test]
+    fn test_read_uses_accept_profile() {
+        let schemas = vec!["v1".to_string(), "v2".to_string()];
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("/items")
+            .header("accept-profile", "v2")
+            .body(Vec::<u8>::new())
+            .unwrap();
+
+        let api = parse_request(&req, "v1", &schemas).unwrap();
+        assert_eq!(api.schema, "v2");
+        assert_eq!(api.representation_schema, None);
+    }
+
+    #[test]
+    fn test_unacceptable_profile_lists_exposed_schemas() {
+        let schemas = vec!["v1".to_string(), "v2".to_string()];
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("/items")
+            .header("accept-profile", "internal")
+            .body(Vec::<u8>::new())
+            .unwrap();
+
+        let err = parse_request(&req, "v1", &schemas).unwrap_err();
+        assert_eq!(err.status_code(), http::StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(err.code(), "PGRST105");
+        let json = err.to_json();
+        assert_eq!(json["message"], "Unacceptable schema: internal");
+        assert_eq!(json["details"], "Acceptable profiles: v1, v2");
+    }
+
+    #[test]
+    fn test_parse_if_match() {
+        let mut headers = http::HeaderMap::new();
+        assert_eq!(parse_if_match(&headers).unwrap(), None);
+
+        headers.insert(http::header::IF_MATCH, "W/\"abc123\"".parse().unwrap());
+        assert_eq!(parse_if_match(&headers).unwrap().as_deref(), Some("abc123"));
+
+        headers.insert(http::header::IF_MATCH, "*".parse().unwrap());
+        assert_eq!(parse_if_match(&headers).unwrap().as_deref(), Some("*"));
+    }
+
+    #[test]
+    fn test_parse_bytea_encoding() {
+        let mut headers = http::HeaderMap::new();
+        assert_eq!(parse_bytea_encoding(&headers).unwrap(), None);
+
+        headers.insert(http::header::ACCEPT, "application/json; bytea=hex".parse().unwrap());
+        assert_eq!(parse_bytea_encoding(&headers).unwrap(), Some(ByteaEncoding::Hex));
+
+        headers.insert(http::header::ACCEPT, "text/csv, application/json;bytea=base64".parse().unwrap());
+        assert_eq!(parse_bytea_encoding(&headers).unwrap(), Some(ByteaEncoding::Base64));
+
+        headers.insert(http::header::ACCEPT, "application/json; bytea=octal".parse().unwrap());
+        assert!(matches!(parse_bytea_encoding(&headers).unwrap_err(), Error::InvalidMediaType(_)));
+    }
+
+    #[test]
+    fn test_limit_param_overrides_range_header() {
+        let schemas = vec!["public".to_string()];
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("/items?limit=100&offset=5")
+            .header("range", "0-9")
+            .body(Vec::<u8>::new())
+            .unwrap();
+
+        let api = parse_request(&req, "public", &schemas).unwrap();
+        assert_eq!(api.top_level_range, Range::new(5, Some(100)));
+
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("/items")
+            .header("range", "0-9")
+            .body(Vec::<u8>::new())
+            .unwrap();
+        let api = parse_request(&req, "public", &schemas).unwrap();
+        assert_eq!(api.top_level_range, Range::new(0, Some(10)));
     }
 }
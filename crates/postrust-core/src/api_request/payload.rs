@@ -8,15 +8,23 @@ use bytes::Bytes;
 use std::collections::HashSet;
 
 /// Parse request body based on content type.
-pub fn parse_payload(body: Bytes, content_type: &MediaType) -> Result<Option<Payload>> {
+///
+/// With `?columns=` the JSON body's keys are already known, so it is passed
+/// through unparsed as [`Payload::RawJson`].
+pub fn parse_payload(
+    body: Bytes,
+    content_type: &MediaType,
+    columns: Option<&HashSet<FieldName>>,
+) -> Result<Option<Payload>> {
     if body.is_empty() {
         return Ok(None);
     }
 
     match content_type {
+        MediaType::ApplicationJson if columns.is_some() => Ok(Some(Payload::RawJson(body))),
         MediaType::ApplicationJson => parse_json_payload(body),
         MediaType::UrlEncoded => parse_urlencoded_payload(body),
-        MediaType::TextCsv => {
+        MediaType::TextCsv { .. } => {
             // CSV is handled as raw JSON for processing
             Ok(Some(Payload::RawJson(body)))
         }
@@ -31,7 +39,7 @@ pub fn parse_payload(body: Bytes, content_type: &MediaType) -> Result<Option<Pay
 fn parse_json_payload(body: Bytes) -> Result<Option<Payload>> {
     // Parse to extract keys
     let value: serde_json::Value =
-        serde_json::from_slice(&body).map_err(|e| Error::InvalidBody(e.to_string()))?;
+        serde_json::from_slice(&body).map_err(|e| Error::invalid_json(&e))?;
 
     let keys = extract_json_keys(&value);
 
@@ -94,7 +102,7 @@ mod tests {
     #[test]
     fn test_parse_json_object() {
         let body = Bytes::from(r#"{"name": "John", "age": 30}"#);
-        let payload = parse_payload(body, &MediaType::ApplicationJson)
+        let payload = parse_payload(body, &MediaType::ApplicationJson, None)
             .unwrap()
             .unwrap();
 
@@ -110,7 +118,7 @@ mod tests {
     #[test]
     fn test_parse_json_array() {
         let body = Bytes::from(r#"[{"id": 1}, {"id": 2, "name": "test"}]"#);
-        let payload = parse_payload(body, &MediaType::ApplicationJson)
+        let payload = parse_payload(body, &MediaType::ApplicationJson, None)
             .unwrap()
             .unwrap();
 
@@ -126,7 +134,7 @@ mod tests {
     #[test]
     fn test_parse_urlencoded() {
         let body = Bytes::from("name=John&age=30");
-        let payload = parse_payload(body, &MediaType::UrlEncoded)
+        let payload = parse_payload(body, &MediaType::UrlEncoded, None)
             .unwrap()
             .unwrap();
 
@@ -140,17 +148,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_columns_skip_json_parsing() {
+        let body = Bytes::from(r#"[{"id": 1, "name": "a"}]"#);
+        let columns: HashSet<FieldName> = ["id".to_string()].into_iter().collect();
+        let payload = parse_payload(body.clone(), &MediaType::ApplicationJson, Some(&columns))
+            .unwrap()
+            .unwrap();
+        assert_eq!(payload, Payload::RawJson(body));
+    }
+
+    #[test]
+    fn test_truncated_json_is_bad_request() {
+        let body = Bytes::from(r#"[{"id": 1}, {"id""#);
+        let err = parse_payload(body, &MediaType::ApplicationJson, None).unwrap_err();
+
+        assert_eq!(err.status_code(), http::StatusCode::BAD_REQUEST);
+        assert!(
+            matches!(err, Error::InvalidJson { line: 1, column: 17, ref message } if message.contains("EOF")),
+            "{:?}",
+            err
+        );
+    }
+
     #[test]
     fn test_parse_empty_body() {
         let body = Bytes::new();
-        let payload = parse_payload(body, &MediaType::ApplicationJson).unwrap();
+        let payload = parse_payload(body, &MediaType::ApplicationJson, None).unwrap();
         assert!(payload.is_none());
     }
 
     #[test]
     fn test_parse_octet_stream() {
         let body = Bytes::from(vec![0u8, 1, 2, 3]);
-        let payload = parse_payload(body.clone(), &MediaType::OctetStream)
+        let payload = parse_payload(body.clone(), &MediaType::OctetStream, None)
             .unwrap()
             .unwrap();
 
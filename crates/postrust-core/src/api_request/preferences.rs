@@ -6,92 +6,105 @@ use super::types::*;
 use crate::error::{Error, Result};
 use http::HeaderMap;
 
-/// Parse Prefer header into Preferences struct.
+/// Parse Prefer headers into Preferences struct.
+///
+/// Tokens may be spread over several `Prefer` headers or combined in one
+/// with commas. Keys and values match case-insensitively. Unknown tokens,
+/// and known keys with unknown values, are collected in `invalid` instead
+/// of failing the request.
 pub fn parse_preferences(headers: &HeaderMap) -> Result<Preferences> {
     let mut prefs = Preferences::default();
 
-    let prefer = match headers.get("prefer") {
-        Some(v) => v.to_str().map_err(|_| Error::InvalidHeader("Prefer"))?,
-        None => return Ok(prefs),
-    };
-
-    for pref in prefer.split(',').map(|s| s.trim()) {
-        parse_preference(&mut prefs, pref);
+    for value in headers.get_all("prefer") {
+        let prefer = value.to_str().map_err(|_| Error::InvalidHeader("Prefer"))?;
+        for pref in prefer.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            parse_preference(&mut prefs, pref);
+        }
     }
 
     Ok(prefs)
 }
 
+/// Build a `Warning` header value listing invalid preferences, when
+/// `handling=strict` asked for them to be reported.
+pub fn invalid_preferences_warning(prefs: &Preferences) -> Option<String> {
+    if prefs.handling != Some(PreferHandling::Strict) || prefs.invalid.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "199 postrust \"Invalid preferences ignored: {}\"",
+        prefs.invalid.join(", ").replace('"', "'")
+    ))
+}
+
 fn parse_preference(prefs: &mut Preferences, pref: &str) {
     let pref = pref.trim();
 
     // Handle key=value preferences
     if let Some((key, value)) = pref.split_once('=') {
-        let key = key.trim();
-        let value = value.trim().trim_matches('"');
-
-        match key {
-            "resolution" => {
-                prefs.resolution = match value {
-                    "merge-duplicates" => Some(PreferResolution::MergeDuplicates),
-                    "ignore-duplicates" => Some(PreferResolution::IgnoreDuplicates),
-                    _ => None,
-                };
-            }
-            "return" => {
-                prefs.representation = match value {
-                    "representation" => PreferRepresentation::Full,
-                    "headers-only" => PreferRepresentation::HeadersOnly,
-                    "minimal" => PreferRepresentation::None,
-                    _ => PreferRepresentation::None,
-                };
-            }
-            "count" => {
-                prefs.count = match value {
-                    "exact" => Some(PreferCount::Exact),
-                    "planned" => Some(PreferCount::Planned),
-                    "estimated" => Some(PreferCount::Estimated),
-                    _ => None,
-                };
-            }
-            "tx" => {
-                prefs.transaction = match value {
-                    "commit" => PreferTransaction::Commit,
-                    "rollback" => PreferTransaction::Rollback,
-                    _ => PreferTransaction::Commit,
-                };
-            }
-            "missing" => {
-                prefs.missing = match value {
-                    "default" => PreferMissing::ApplyDefaults,
-                    "null" => PreferMissing::ApplyNulls,
-                    _ => PreferMissing::ApplyDefaults,
-                };
-            }
-            "handling" => {
-                prefs.handling = match value {
-                    "strict" => PreferHandling::Strict,
-                    "lenient" => PreferHandling::Lenient,
-                    _ => PreferHandling::Strict,
-                };
-            }
+        let key = key.trim().to_ascii_lowercase();
+        let raw_value = value.trim().trim_matches('"');
+        let value = raw_value.to_ascii_lowercase();
+        let value = value.as_str();
+
+        let invalid = &mut prefs.invalid;
+        let mut reject = || invalid.push(pref.to_string());
+
+        match key.as_str() {
+            "resolution" => match value {
+                "merge-duplicates" => prefs.resolution = Some(PreferResolution::MergeDuplicates),
+                "ignore-duplicates" => prefs.resolution = Some(PreferResolution::IgnoreDuplicates),
+                _ => reject(),
+            },
+            "return" => match value {
+                "representation" => prefs.representation = PreferRepresentation::Full,
+                "headers-only" => prefs.representation = PreferRepresentation::HeadersOnly,
+                "minimal" => prefs.representation = PreferRepresentation::None,
+                _ => reject(),
+            },
+            "count" => match value {
+                "exact" => prefs.count = Some(PreferCount::Exact),
+                "planned" => prefs.count = Some(PreferCount::Planned),
+                "estimated" => prefs.count = Some(PreferCount::Estimated),
+                _ => reject(),
+            },
+            "tx" => match value {
+                "commit" => prefs.transaction = PreferTransaction::Commit,
+                "rollback" => prefs.transaction = PreferTransaction::Rollback,
+                _ => reject(),
+            },
+            "missing" => match value {
+                "default" => prefs.missing = Some(PreferMissing::ApplyDefaults),
+                "null" => prefs.missing = Some(PreferMissing::ApplyNulls),
+                _ => reject(),
+            },
+            "handling" => match value {
+                "strict" => prefs.handling = Some(PreferHandling::Strict),
+                "lenient" => prefs.handling = Some(PreferHandling::Lenient),
+                _ => reject(),
+            },
+            // Time zone names are case-sensitive
             "timezone" => {
-                prefs.timezone = Some(value.to_string());
-            }
-            "max-affected" => {
-                if let Ok(n) = value.parse::<i64>() {
-                    prefs.max_affected = Some(n);
-                }
-            }
-            _ => {
-                prefs.invalid.push(pref.to_string());
+                prefs.timezone = Some(raw_value.to_string());
             }
+            "max-affected" => match value.parse::<i64>() {
+                Ok(n) => prefs.max_affected = Some(n),
+                Err(_) => reject(),
+            },
+            "bulk-update" => match value {
+                "pk" => prefs.bulk_update = true,
+                _ => reject(),
+            },
+            // RPC parameter mode
+            "params" if matches!(value, "single-object" | "multiple-objects") => {}
+            _ => reject(),
         }
         return;
     }
 
     // Handle standalone preferences
-    match pref {
+    match pref.to_ascii_lowercase().as_str() {
         "return=representation" => prefs.representation = PreferRepresentation::Full,
         "return=headers-only" => prefs.representation = PreferRepresentation::HeadersOnly,
         "return=minimal" => prefs.representation = PreferRepresentation::None,
@@ -112,45 +125,84 @@ fn parse_preference(prefs: &mut Preferences, pref: &str) {
     }
 }
 
-/// Build Preference-Applied header from applied preferences.
-pub fn preference_applied(prefs: &Preferences) -> Option<String> {
-    let mut applied = Vec::new();
+/// Build Preference-Applied header from the preferences that were honored.
+pub fn preference_applied(applied: &AppliedPreferences) -> Option<String> {
+    let mut values: Vec<String> = Vec::new();
 
-    if prefs.resolution.is_some() {
-        let val = match prefs.resolution {
-            Some(PreferResolution::MergeDuplicates) => "resolution=merge-duplicates",
-            Some(PreferResolution::IgnoreDuplicates) => "resolution=ignore-duplicates",
-            None => "",
-        };
-        if !val.is_empty() {
-            applied.push(val);
-        }
+    if let Some(resolution) = &applied.resolution {
+        values.push(
+            match resolution {
+                PreferResolution::MergeDuplicates => "resolution=merge-duplicates",
+                PreferResolution::IgnoreDuplicates => "resolution=ignore-duplicates",
+            }
+            .into(),
+        );
+    }
+
+    if let Some(representation) = &applied.representation {
+        values.push(
+            match representation {
+                PreferRepresentation::Full => "return=representation",
+                PreferRepresentation::HeadersOnly => "return=headers-only",
+                PreferRepresentation::None => "return=minimal",
+            }
+            .into(),
+        );
     }
 
-    match prefs.representation {
-        PreferRepresentation::Full => applied.push("return=representation"),
-        PreferRepresentation::HeadersOnly => applied.push("return=headers-only"),
-        PreferRepresentation::None => {}
+    if let Some(count) = &applied.count {
+        values.push(
+            match count {
+                PreferCount::Exact => "count=exact",
+                PreferCount::Planned => "count=planned",
+                PreferCount::Estimated => "count=estimated",
+            }
+            .into(),
+        );
     }
 
-    if let Some(count) = &prefs.count {
-        let val = match count {
-            PreferCount::Exact => "count=exact",
-            PreferCount::Planned => "count=planned",
-            PreferCount::Estimated => "count=estimated",
-        };
-        applied.push(val);
+    if let Some(missing) = &applied.missing {
+        values.push(
+            match missing {
+                PreferMissing::ApplyDefaults => "missing=default",
+                PreferMissing::ApplyNulls => "missing=null",
+            }
+            .into(),
+        );
+    }
+
+    if let Some(handling) = &applied.handling {
+        values.push(
+            match handling {
+                PreferHandling::Strict => "handling=strict",
+                PreferHandling::Lenient => "handling=lenient",
+            }
+            .into(),
+        );
     }
 
-    match prefs.transaction {
-        PreferTransaction::Rollback => applied.push("tx=rollback"),
-        PreferTransaction::Commit => {}
+    if let Some(transaction) = &applied.transaction {
+        values.push(
+            match transaction {
+                PreferTransaction::Commit => "tx=commit",
+                PreferTransaction::Rollback => "tx=rollback",
+            }
+            .into(),
+        );
+    }
+
+    if let Some(timezone) = &applied.timezone {
+        values.push(format!("timezone={}", timezone));
     }
 
-    if applied.is_empty() {
+    if applied.bulk_update {
+        values.push("bulk-update=pk".into());
+    }
+
+    if values.is_empty() {
         None
     } else {
-        Some(applied.join(", "))
+        Some(values.join(", "))
     }
 }
 
@@ -209,14 +261,70 @@ mod tests {
         assert_eq!(prefs.max_affected, Some(100));
     }
 
+    #[test]
+    fn test_tokens_match_case_insensitively() {
+        let headers = headers_with_prefer("Return=Representation, COUNT=Exact, Resolution=Merge-Duplicates");
+        let prefs = parse_preferences(&headers).unwrap();
+        assert_eq!(prefs.representation, PreferRepresentation::Full);
+        assert_eq!(prefs.count, Some(PreferCount::Exact));
+        assert_eq!(prefs.resolution, Some(PreferResolution::MergeDuplicates));
+        assert!(prefs.invalid.is_empty());
+
+        // Time zone names keep their case
+        let prefs = parse_preferences(&headers_with_prefer("TimeZone=America/New_York")).unwrap();
+        assert_eq!(prefs.timezone.as_deref(), Some("America/New_York"));
+    }
+
+    #[test]
+    fn test_invalid_tokens_are_collected() {
+        let headers = headers_with_prefer("return=representation, bogus, count=sometimes, tx=rollback");
+        let prefs = parse_preferences(&headers).unwrap();
+
+        assert_eq!(prefs.representation, PreferRepresentation::Full);
+        assert_eq!(prefs.transaction, PreferTransaction::Rollback);
+        assert_eq!(prefs.count, None);
+        assert_eq!(prefs.invalid, vec!["bogus".to_string(), "count=sometimes".to_string()]);
+    }
+
+    #[test]
+    fn test_multiple_prefer_headers_are_combined() {
+        let mut headers = headers_with_prefer("return=representation");
+        headers.append("prefer", HeaderValue::from_static("count=exact"));
+        let prefs = parse_preferences(&headers).unwrap();
+
+        assert_eq!(prefs.representation, PreferRepresentation::Full);
+        assert_eq!(prefs.count, Some(PreferCount::Exact));
+        assert!(prefs.invalid.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_preferences_warning_only_when_strict() {
+        let lenient = parse_preferences(&headers_with_prefer("handling=lenient, bogus")).unwrap();
+        assert_eq!(invalid_preferences_warning(&lenient), None);
+
+        let strict = parse_preferences(&headers_with_prefer("handling=strict, bogus")).unwrap();
+        assert_eq!(
+            invalid_preferences_warning(&strict).as_deref(),
+            Some("199 postrust \"Invalid preferences ignored: bogus\"")
+        );
+    }
+
     #[test]
     fn test_preference_applied() {
-        let mut prefs = Preferences::default();
-        prefs.representation = PreferRepresentation::Full;
-        prefs.count = Some(PreferCount::Exact);
+        let applied = AppliedPreferences {
+            representation: Some(PreferRepresentation::Full),
+            count: Some(PreferCount::Exact),
+            ..Default::default()
+        };
 
-        let applied = preference_applied(&prefs).unwrap();
-        assert!(applied.contains("return=representation"));
-        assert!(applied.contains("count=exact"));
+        assert_eq!(
+            preference_applied(&applied).as_deref(),
+            Some("return=representation, count=exact")
+        );
+    }
+
+    #[test]
+    fn test_preference_applied_none() {
+        assert_eq!(preference_applied(&AppliedPreferences::default()), None);
     }
 }
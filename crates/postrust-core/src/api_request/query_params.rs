@@ -7,31 +7,51 @@ use super::types::*;
 use crate::error::{Error, Result};
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_until, take_while1},
+    bytes::complete::{tag, take_while1},
     character::complete::{char, digit1},
-    combinator::{map, opt, value},
+    combinator::{map, opt, recognize, value, verify},
     multi::{many0, separated_list0},
-    sequence::preceded,
+    sequence::{pair, preceded, tuple},
     IResult,
 };
 use percent_encoding::percent_decode_str;
+use std::collections::HashSet;
 
 /// Parse a query string into QueryParams.
 pub fn parse_query_params(query: &str) -> Result<QueryParams> {
+    parse_params(query, false)
+}
+
+/// Parse the query string of a routine call.
+///
+/// Top-level keys may be function arguments (`q=hello`) or filters on the
+/// result (`age=gt.18`); which is decided at planning time against the
+/// routine's parameters. Each is kept raw in `params`, and also as a filter
+/// when it parses as one.
+pub fn parse_routine_query_params(query: &str) -> Result<QueryParams> {
+    parse_params(query, true)
+}
+
+fn parse_params(query: &str, routine: bool) -> Result<QueryParams> {
     let mut params = QueryParams::default();
 
     if query.is_empty() {
         return Ok(params);
     }
 
-    // Sort parameters for canonical form
+    // Pairs are separated by `&` or `;`; empty ones (`a=1&&b=2`, a trailing
+    // `&`) are skipped. Only the first `=` splits, so values may contain `=`.
     let mut pairs: Vec<(&str, &str)> = query
-        .split('&')
-        .filter_map(|pair| {
-            let mut parts = pair.splitn(2, '=');
-            Some((parts.next()?, parts.next().unwrap_or("")))
-        })
+        .split(['&', ';'])
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
         .collect();
+    if let Some((_, value)) = pairs.iter().find(|(key, _)| key.is_empty()) {
+        return Err(Error::InvalidQueryParam(format!("={}", value)));
+    }
+
+    // Sort parameters for canonical form. The sort is stable, so repeated
+    // keys (`id=gt.1&id=lt.9`) keep their order and each becomes its own filter.
     pairs.sort_by_key(|(k, _)| *k);
     params.canonical = pairs
         .iter()
@@ -39,6 +59,22 @@ pub fn parse_query_params(query: &str) -> Result<QueryParams> {
         .collect::<Vec<_>>()
         .join("&");
 
+    // Parameters bound into the select list (`$q`) are values, not filters
+    if let Some((_, value)) = pairs.iter().find(|(k, _)| *k == "select") {
+        let decoded_value = percent_decode_str(value)
+            .decode_utf8()
+            .map_err(|_| Error::InvalidQueryParam("select".into()))?;
+        params.select = parse_select(&decoded_value)?;
+    }
+    let bound: HashSet<&str> = params
+        .select
+        .iter()
+        .filter_map(|item| match item {
+            SelectItem::Headline { param, .. } => Some(param.as_str()),
+            _ => None,
+        })
+        .collect();
+
     for (key, value) in pairs {
         let decoded_value = percent_decode_str(value)
             .decode_utf8()
@@ -46,9 +82,7 @@ pub fn parse_query_params(query: &str) -> Result<QueryParams> {
             .to_string();
 
         match key {
-            "select" => {
-                params.select = parse_select(&decoded_value)?;
-            }
+            "select" => {}
             "order" => {
                 let (path, terms) = parse_order_param(&decoded_value)?;
                 params.order.push((path, terms));
@@ -73,6 +107,9 @@ pub fn parse_query_params(query: &str) -> Result<QueryParams> {
                         .collect(),
                 );
             }
+            "pretty" => {
+                params.pretty = decoded_value.is_empty() || decoded_value == "true";
+            }
             "on_conflict" => {
                 params.on_conflict = Some(
                     decoded_value
@@ -81,10 +118,45 @@ pub fn parse_query_params(query: &str) -> Result<QueryParams> {
                         .collect(),
                 );
             }
-            "and" | "or" => {
+            "and" | "or" | "not.and" | "not.or" => {
                 let logic = parse_logic_param(key, &decoded_value)?;
                 params.logic.push((vec![], logic));
             }
+            key if key.ends_with(".limit") || key.ends_with(".offset") => {
+                // Page an embed per parent row: `posts.limit=2`
+                let (path, kind) = key.rsplit_once('.').unwrap();
+                let n: i64 = decoded_value
+                    .parse()
+                    .map_err(|_| Error::InvalidQueryParam(key.into()))?;
+                let range = params.ranges.entry(path.to_string()).or_default();
+                match kind {
+                    "limit" => range.limit = Some(n),
+                    // Embeds have no total to count back from
+                    _ if n < 0 => return Err(Error::InvalidQueryParam(key.into())),
+                    _ => range.offset = n,
+                }
+            }
+            key if key.ends_with(".and") || key.ends_with(".or") => {
+                // Logic group scoped to an embed: `posts.or=(...)`, `posts.not.or=(...)`
+                let (path, op) = key.rsplit_once('.').unwrap();
+                let (path, op) = match path.strip_suffix(".not") {
+                    Some(path) => (path, format!("not.{}", op)),
+                    None => (path, op.to_string()),
+                };
+                let logic = parse_logic_param(&op, &decoded_value)?;
+                params.logic.push((path.split('.').map(String::from).collect(), logic));
+            }
+            key if bound.contains(key) => {
+                params.params.push((key.to_string(), decoded_value));
+            }
+            key if routine && !key.starts_with('_') && !key.contains('.') => {
+                // Routine argument or filter on the routine's output
+                if let Ok((_, filter)) = parse_filter_param(key, &decoded_value) {
+                    params.filter_fields.insert(filter.field.name.clone());
+                    params.filters_root.push(filter);
+                }
+                params.params.push((key.to_string(), decoded_value));
+            }
             key if !key.starts_with('_') => {
                 // Filter parameter
                 let (path, filter) = parse_filter_param(key, &decoded_value)?;
@@ -115,9 +187,11 @@ pub fn parse_select(input: &str) -> Result<Vec<SelectItem>> {
         return Ok(vec![]);
     }
 
+    // Anything left unparsed would silently drop items, e.g. everything
+    // after a `*` the item parsers did not accept
     match parse_select_items(input) {
-        Ok((_, items)) => Ok(items),
-        Err(_) => Err(Error::InvalidQueryParam("select".into())),
+        Ok(("", items)) => Ok(items),
+        _ => Err(Error::InvalidQueryParam("select".into())),
     }
 }
 
@@ -128,17 +202,66 @@ fn parse_select_items(input: &str) -> IResult<&str, Vec<SelectItem>> {
 fn parse_select_item(input: &str) -> IResult<&str, SelectItem> {
     alt((
         parse_spread_relation,
+        parse_headline_select,
+        // Before relations, which `total:count()` would otherwise match
+        parse_aggregate_select,
         parse_relation_select,
+        parse_wildcard_select,
         parse_field_select,
     ))(input)
 }
 
-/// Parse spread relation: `...relation`
+/// Parse the `*` wildcard, which selects every column of the resource.
+fn parse_wildcard_select(input: &str) -> IResult<&str, SelectItem> {
+    value(SelectItem::field("*"), char('*'))(input)
+}
+
+/// Parse an embed's parenthesized select list: `(id,title)`, `(*)`, `()`.
+fn parse_embedded_select(input: &str) -> IResult<&str, Vec<SelectItem>> {
+    nom::sequence::delimited(char('('), parse_select_items, char(')'))(input)
+}
+
+/// Parse a highlighted search column:
+/// `alias:ts_headline(col,websearch_to_tsquery(english,$q))`
+fn parse_headline_select(input: &str) -> IResult<&str, SelectItem> {
+    let (input, alias) = opt(nom::sequence::terminated(parse_identifier, char(':')))(input)?;
+    let (input, _) = tag("ts_headline(")(input)?;
+    let (input, column) = parse_identifier(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, function) = alt((
+        value(FtsOperator::Fts, tag("to_tsquery(")),
+        value(FtsOperator::Plain, tag("plainto_tsquery(")),
+        value(FtsOperator::Phrase, tag("phraseto_tsquery(")),
+        value(FtsOperator::Websearch, tag("websearch_to_tsquery(")),
+    ))(input)?;
+    let language = alt((
+        nom::sequence::delimited(char('\''), parse_identifier, char('\'')),
+        parse_identifier,
+    ));
+    let (input, language) = opt(nom::sequence::terminated(language, char(',')))(input)?;
+    let (input, _) = char('$')(input)?;
+    let (input, param) = parse_identifier(input)?;
+    let (input, _) = tag("))")(input)?;
+
+    Ok((
+        input,
+        SelectItem::Headline {
+            column: column.to_string(),
+            function,
+            language: language.map(|s| s.to_string()),
+            param: param.to_string(),
+            alias: alias.map(|s| s.to_string()),
+        },
+    ))
+}
+
+/// Parse spread relation: `...relation` or `...relation(select_items)`
 fn parse_spread_relation(input: &str) -> IResult<&str, SelectItem> {
     let (input, _) = tag("...")(input)?;
     let (input, relation) = parse_identifier(input)?;
-    let (input, hint) = opt(preceded(char('!'), parse_identifier))(input)?;
+    let (input, hint) = opt(preceded(char('!'), parse_embed_hint))(input)?;
     let (input, join_type) = opt(preceded(char('!'), parse_join_type))(input)?;
+    let (input, select) = opt(parse_embedded_select)(input)?;
 
     Ok((
         input,
@@ -146,19 +269,19 @@ fn parse_spread_relation(input: &str) -> IResult<&str, SelectItem> {
             relation: relation.to_string(),
             hint: hint.map(|s| s.to_string()),
             join_type,
+            select: select.unwrap_or_default(),
         },
     ))
 }
 
-/// Parse relation with embedded select: `relation(select_items)`
+/// Parse relation with embedded select: `relation(select_items)`, optionally
+/// aliased: `alias:relation(select_items)`
 fn parse_relation_select(input: &str) -> IResult<&str, SelectItem> {
+    let (input, alias) = opt(nom::sequence::terminated(parse_identifier, char(':')))(input)?;
     let (input, name) = parse_identifier(input)?;
-    let (input, alias) = opt(preceded(char(':'), parse_identifier))(input)?;
-    let (input, hint) = opt(preceded(char('!'), parse_identifier))(input)?;
+    let (input, hint) = opt(preceded(char('!'), parse_embed_hint))(input)?;
     let (input, join_type) = opt(preceded(char('!'), parse_join_type))(input)?;
-    let (input, _) = char('(')(input)?;
-    let (input, _nested) = take_until(")")(input)?;
-    let (input, _) = char(')')(input)?;
+    let (input, select) = parse_embedded_select(input)?;
 
     Ok((
         input,
@@ -167,34 +290,60 @@ fn parse_relation_select(input: &str) -> IResult<&str, SelectItem> {
             alias: alias.map(|s| s.to_string()),
             hint: hint.map(|s| s.to_string()),
             join_type,
+            select,
         },
     ))
 }
 
-/// Parse field select: `field`, `field::cast`, `field:alias`, `agg(field)`
-fn parse_field_select(input: &str) -> IResult<&str, SelectItem> {
-    // Check for aggregate function
-    let (input, aggregate) = opt(parse_aggregate_prefix)(input)?;
-
-    let (input, name) = parse_identifier(input)?;
+/// Parse an aggregate: `agg(field)`, `count()`, with an optional cast of
+/// the result and an alias given either first (`total:count()`) or last
+/// (`sum(amount):total`).
+fn parse_aggregate_select(input: &str) -> IResult<&str, SelectItem> {
+    let (input, leading_alias) = opt(nom::sequence::terminated(parse_identifier, char(':')))(input)?;
+    let (input, aggregate) = parse_aggregate_prefix(input)?;
+
+    // `count()` counts rows rather than a column
+    let (input, name) = match aggregate {
+        AggregateFunction::Count => opt(parse_identifier)(input)?,
+        _ => map(parse_identifier, Some)(input)?,
+    };
     let (input, json_path) = parse_json_path(input)?;
-
-    // Close aggregate if present
-    let (input, aggregate_cast) = if aggregate.is_some() {
-        let (input, _) = char(')')(input)?;
-        let (input, cast) = opt(preceded(tag("::"), parse_identifier))(input)?;
-        (input, cast.map(|s| s.to_string()))
-    } else {
-        (input, None)
+    let (input, _) = char(')')(input)?;
+    let (input, aggregate_cast) = opt(preceded(tag("::"), parse_cast_type))(input)?;
+    let (input, alias) = match leading_alias {
+        Some(alias) => (input, Some(alias)),
+        None => opt(preceded(char(':'), parse_identifier))(input)?,
     };
 
-    let (input, cast) = if aggregate.is_none() {
-        opt(preceded(tag("::"), parse_identifier))(input)?
-    } else {
-        (input, None)
-    };
+    Ok((
+        input,
+        SelectItem::Field {
+            field: Field {
+                name: name.unwrap_or("*").to_string(),
+                json_path,
+            },
+            aggregate: Some(aggregate),
+            aggregate_cast: aggregate_cast.map(|s| s.to_string()),
+            cast: None,
+            alias: alias.map(|s| s.to_string()),
+        },
+    ))
+}
 
-    let (input, alias) = opt(preceded(char(':'), parse_identifier))(input)?;
+/// Parse field select: `field`, `field::cast`, with an alias given either
+/// first (`alias:field`) or after a cast (`field::cast:alias`).
+fn parse_field_select(input: &str) -> IResult<&str, SelectItem> {
+    let (input, leading_alias) = opt(nom::sequence::terminated(
+        parse_identifier,
+        pair(char(':'), nom::combinator::not(char(':'))),
+    ))(input)?;
+    let (input, name) = parse_identifier(input)?;
+    let (input, json_path) = parse_json_path(input)?;
+    let (input, cast) = opt(preceded(tag("::"), parse_cast_type))(input)?;
+    let (input, alias) = match leading_alias {
+        Some(alias) => (input, Some(alias)),
+        None => opt(preceded(char(':'), parse_identifier))(input)?,
+    };
 
     Ok((
         input,
@@ -203,8 +352,8 @@ fn parse_field_select(input: &str) -> IResult<&str, SelectItem> {
                 name: name.to_string(),
                 json_path,
             },
-            aggregate,
-            aggregate_cast,
+            aggregate: None,
+            aggregate_cast: None,
             cast: cast.map(|s| s.to_string()),
             alias: alias.map(|s| s.to_string()),
         },
@@ -221,6 +370,11 @@ fn parse_aggregate_prefix(input: &str) -> IResult<&str, AggregateFunction> {
     ))(input)
 }
 
+/// Parse an embed hint, leaving a bare `!inner`/`!left` to `parse_join_type`.
+fn parse_embed_hint(input: &str) -> IResult<&str, &str> {
+    verify(parse_identifier, |hint: &str| !matches!(hint, "inner" | "left"))(input)
+}
+
 fn parse_join_type(input: &str) -> IResult<&str, JoinType> {
     alt((
         value(JoinType::Inner, tag("inner")),
@@ -234,6 +388,10 @@ fn parse_join_type(input: &str) -> IResult<&str, JoinType> {
 
 /// Parse a filter parameter (key=value where key is a field name).
 fn parse_filter_param(key: &str, value: &str) -> Result<(EmbedPath, Filter)> {
+    if value.is_empty() {
+        return Err(Error::InvalidQueryParam(format!("{}: missing operator", key)));
+    }
+
     // Parse the key for embedded path: rel.field or field
     let (path, field_name) = parse_filter_key(key)?;
 
@@ -247,7 +405,7 @@ fn parse_filter_param(key: &str, value: &str) -> Result<(EmbedPath, Filter)> {
 /// Parse a filter key into path and field name.
 fn parse_filter_key(key: &str) -> Result<(EmbedPath, String)> {
     let parts: Vec<&str> = key.split('.').collect();
-    if parts.is_empty() {
+    if parts.iter().any(|part| part.is_empty()) {
         return Err(Error::InvalidQueryParam(key.into()));
     }
 
@@ -446,29 +604,112 @@ fn parse_in_list(value: &str) -> Result<Vec<String>> {
 }
 
 /// Parse FTS operation: `(language).query` or `.query`
+///
+/// `fts` also takes a raw tsquery as `raw:query`, which is checked here so
+/// malformed syntax never reaches `to_tsquery`.
 fn parse_fts(op: FtsOperator, rest: &str) -> Result<Operation> {
-    if let Some(rest) = rest.strip_prefix('(') {
+    let (language, query) = if let Some(rest) = rest.strip_prefix('(') {
         // Has language specifier
         let (lang, query) = rest
             .split_once(").")
             .ok_or_else(|| Error::InvalidQueryParam(format!("fts{}", rest)))?;
-        return Ok(Operation::Fts {
-            op,
-            language: Some(lang.to_string()),
-            value: query.to_string(),
-        });
-    }
+        (Some(lang.to_string()), query)
+    } else {
+        let query = rest
+            .strip_prefix('.')
+            .ok_or_else(|| Error::InvalidQueryParam(format!("fts{}", rest)))?;
+        (None, query)
+    };
 
-    let query = rest
-        .strip_prefix('.')
-        .ok_or_else(|| Error::InvalidQueryParam(format!("fts{}", rest)))?;
+    let value = match query.strip_prefix("raw:") {
+        Some(raw) if op == FtsOperator::Fts => {
+            validate_tsquery(raw)
+                .map_err(|e| Error::InvalidQueryParam(format!("invalid tsquery '{}': {}", raw, e)))?;
+            raw
+        }
+        _ => query,
+    };
     Ok(Operation::Fts {
         op,
-        language: None,
-        value: query.to_string(),
+        language,
+        value: value.to_string(),
     })
 }
 
+/// Check that `query` is valid `to_tsquery` syntax: operands (bare or
+/// `'quoted'`, with an optional `:*`/weight suffix) joined by `&`, `|`,
+/// `<->` or `<N>`, negated with `!` and grouped with parentheses.
+fn validate_tsquery(query: &str) -> std::result::Result<(), &'static str> {
+    let mut chars = query.chars().peekable();
+    let skip_spaces = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    };
+    // Whether an operand is expected next, and the open parentheses
+    let mut expect_operand = true;
+    let mut depth = 0usize;
+
+    loop {
+        skip_spaces(&mut chars);
+        let Some(c) = chars.next() else { break };
+        match (expect_operand, c) {
+            (true, '!') => {}
+            (true, '(') => depth += 1,
+            (true, '\'') => {
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.next_if_eq(&'\'').is_none() => break,
+                        Some('\'') | Some(_) => {}
+                        None => return Err("unterminated quoted operand"),
+                    }
+                }
+                expect_operand = false;
+            }
+            (true, c) if !is_tsquery_special(c) => {
+                while chars.next_if(|c| !c.is_whitespace() && !is_tsquery_special(*c)).is_some() {}
+                expect_operand = false;
+            }
+            (true, _) => return Err("expected an operand"),
+            (false, ':') => {
+                if chars.next_if(|c| matches!(c, '*' | 'A'..='D' | 'a'..='d')).is_none() {
+                    return Err("expected a prefix or weight after ':'");
+                }
+                while chars.next_if(|c| matches!(c, '*' | 'A'..='D' | 'a'..='d')).is_some() {}
+            }
+            (false, ')') if depth > 0 => depth -= 1,
+            (false, '&' | '|') => expect_operand = true,
+            (false, '<') => {
+                if chars.next_if_eq(&'-').is_none() {
+                    let mut distance = false;
+                    while chars.next_if(|c| c.is_ascii_digit()).is_some() {
+                        distance = true;
+                    }
+                    if !distance {
+                        return Err("expected '<->' or '<N>'");
+                    }
+                }
+                if chars.next_if_eq(&'>').is_none() {
+                    return Err("expected '<->' or '<N>'");
+                }
+                expect_operand = true;
+            }
+            (false, _) => return Err("expected an operator"),
+        }
+    }
+
+    if expect_operand {
+        Err("expected an operand")
+    } else if depth > 0 {
+        Err("unbalanced parentheses")
+    } else {
+        Ok(())
+    }
+}
+
+/// Characters with a meaning of their own in tsquery syntax.
+fn is_tsquery_special(c: char) -> bool {
+    matches!(c, '&' | '|' | '!' | '(' | ')' | '<' | ':' | '\'')
+}
+
 // ============================================================================
 // Order Parsing
 // ============================================================================
@@ -482,17 +723,30 @@ fn parse_order_param(value: &str) -> Result<(EmbedPath, Vec<OrderTerm>)> {
     Ok((vec![], terms))
 }
 
+/// Parse one order term: `name.desc`, `data->>key::int.desc`, or a to-one
+/// embed's column as `author.name.desc` or `author(name).desc`.
 fn parse_order_term(value: &str) -> Result<OrderTerm> {
     let parts: Vec<&str> = value.split('.').collect();
-    if parts.is_empty() {
+    if parts[0].is_empty() {
         return Err(Error::InvalidQueryParam("order".into()));
     }
 
-    let field_name = parts[0];
+    let is_modifier = |part: &str| matches!(part, "asc" | "desc" | "nullsfirst" | "nullslast");
+    let (relation, field_name, modifiers) = match parts[0].split_once('(') {
+        Some((relation, field)) => {
+            let field = field
+                .strip_suffix(')')
+                .ok_or_else(|| Error::InvalidQueryParam(format!("order={}", value)))?;
+            (Some(relation), field, &parts[1..])
+        }
+        None if parts.len() > 1 && !is_modifier(parts[1]) => (Some(parts[0]), parts[1], &parts[2..]),
+        None => (None, parts[0], &parts[1..]),
+    };
+
     let mut direction = None;
     let mut nulls = None;
 
-    for part in &parts[1..] {
+    for part in modifiers {
         match *part {
             "asc" => direction = Some(OrderDirection::Asc),
             "desc" => direction = Some(OrderDirection::Desc),
@@ -502,20 +756,56 @@ fn parse_order_term(value: &str) -> Result<OrderTerm> {
         }
     }
 
-    Ok(OrderTerm::Field {
-        field: Field::simple(field_name),
-        direction,
-        nulls,
+    Ok(match relation {
+        Some(relation) => OrderTerm::Relation {
+            relation: relation.to_string(),
+            field: Field::simple(field_name),
+            direction,
+            nulls,
+        },
+        None => {
+            let (field, cast) = parse_order_field(field_name)
+                .ok_or_else(|| Error::InvalidQueryParam(format!("order={}", value)))?;
+            OrderTerm::Field {
+                field,
+                direction,
+                nulls,
+                cast,
+            }
+        }
     })
 }
 
+/// Parse an ordered column with an optional JSON path and cast.
+fn parse_order_field(input: &str) -> Option<(Field, Option<String>)> {
+    let (rest, name) = parse_identifier(input).ok()?;
+    let (rest, json_path) = parse_json_path(rest).ok()?;
+    let (rest, cast) = opt(preceded(tag("::"), parse_cast_type))(rest).ok()?;
+    if !rest.is_empty() {
+        return None;
+    }
+    Some((
+        Field {
+            name: name.to_string(),
+            json_path,
+        },
+        cast.map(str::to_string),
+    ))
+}
+
 // ============================================================================
 // Logic Tree Parsing
 // ============================================================================
 
 /// Parse `and` or `or` parameter: `(filter1,filter2)`
+///
+/// `op` may be negated (`not.or`), and groups nest: `or=(a.eq.1,and(b.eq.2,c.not.eq.3))`.
 fn parse_logic_param(op: &str, value: &str) -> Result<LogicTree> {
-    let logic_op = match op {
+    let (negated, name) = match op.strip_prefix("not.") {
+        Some(name) => (true, name),
+        None => (false, op),
+    };
+    let logic_op = match name {
         "and" => LogicOperator::And,
         "or" => LogicOperator::Or,
         _ => return Err(Error::InvalidQueryParam(op.into())),
@@ -527,9 +817,15 @@ fn parse_logic_param(op: &str, value: &str) -> Result<LogicTree> {
         .and_then(|s| s.strip_suffix(')'))
         .ok_or_else(|| Error::InvalidQueryParam(format!("{}={}", op, value)))?;
 
-    let children: Vec<LogicTree> = value
-        .split(',')
+    let children: Vec<LogicTree> = split_logic_items(value)
+        .into_iter()
         .map(|s| {
+            // Nested group: `and(...)`, `not.or(...)`
+            if let Some((group, rest)) = s.split_once('(') {
+                if matches!(group, "and" | "or" | "not.and" | "not.or") {
+                    return parse_logic_param(group, &format!("({}", rest));
+                }
+            }
             let (key, val) = s
                 .split_once('.')
                 .ok_or_else(|| Error::InvalidQueryParam(s.into()))?;
@@ -539,12 +835,35 @@ fn parse_logic_param(op: &str, value: &str) -> Result<LogicTree> {
         .collect::<Result<Vec<_>>>()?;
 
     Ok(LogicTree::Expr {
-        negated: false,
+        negated,
         op: logic_op,
         children,
     })
 }
 
+/// Split a logic group on its top-level commas, keeping the commas inside
+/// nested groups, `in.(...)` lists, `{...}` arrays and double quotes.
+fn split_logic_items(value: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut depth = 0i32;
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' | '{' if !quoted => depth += 1,
+            ')' | '}' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                items.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&value[start..]);
+    items
+}
+
 // ============================================================================
 // Helper Parsers
 // ============================================================================
@@ -553,6 +872,16 @@ fn parse_identifier(input: &str) -> IResult<&str, &str> {
     take_while1(|c: char| c.is_alphanumeric() || c == '_')(input)
 }
 
+/// Parse a cast target: `date`, `api.mood`, `varchar(10)`, `numeric(10,2)`, `text[]`.
+fn parse_cast_type(input: &str) -> IResult<&str, &str> {
+    recognize(tuple((
+        parse_identifier,
+        opt(pair(char('.'), parse_identifier)),
+        opt(tuple((char('('), digit1, opt(pair(char(','), digit1)), char(')')))),
+        many0(tag("[]")),
+    )))(input)
+}
+
 fn parse_json_path(input: &str) -> IResult<&str, JsonPath> {
     many0(alt((parse_arrow, parse_double_arrow)))(input)
 }
@@ -579,6 +908,57 @@ fn parse_double_arrow(input: &str) -> IResult<&str, JsonOperation> {
     Ok((input, JsonOperation::DoubleArrow(operand)))
 }
 
+/// Caps on the size of a single query; `None` leaves a count unlimited.
+#[derive(Clone, Debug, Default)]
+pub struct QueryLimits {
+    pub max_filters: Option<usize>,
+    pub max_select_items: Option<usize>,
+    pub max_embeds: Option<usize>,
+}
+
+impl QueryLimits {
+    /// Reject a query with more filters, select items or embeds than allowed.
+    ///
+    /// Filters count at every embed level and inside logic groups; select
+    /// items and embeds count at every level of nesting.
+    pub fn check(&self, params: &QueryParams) -> Result<()> {
+        fn logic_filters(tree: &LogicTree) -> usize {
+            match tree {
+                LogicTree::Expr { children, .. } => children.iter().map(logic_filters).sum(),
+                LogicTree::Stmt(_) => 1,
+            }
+        }
+        fn select_counts(items: &[SelectItem]) -> (usize, usize) {
+            items.iter().fold((0, 0), |(count, embeds), item| match item {
+                SelectItem::Relation { select, .. } | SelectItem::SpreadRelation { select, .. } => {
+                    let (nested, nested_embeds) = select_counts(select);
+                    (count + 1 + nested, embeds + 1 + nested_embeds)
+                }
+                _ => (count + 1, embeds),
+            })
+        }
+
+        let filters = params.filters_root.len()
+            + params.filters.len()
+            + params.logic.iter().map(|(_, tree)| logic_filters(tree)).sum::<usize>();
+        let (select_items, embeds) = select_counts(&params.select);
+
+        for (what, count, max) in [
+            ("filters", filters, self.max_filters),
+            ("select items", select_items, self.max_select_items),
+            ("embedded resources", embeds, self.max_embeds),
+        ] {
+            if let Some(max) = max.filter(|max| count > *max) {
+                return Err(Error::RequestTooComplex(format!(
+                    "{} {} exceed the limit of {}",
+                    count, what, max
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -590,12 +970,67 @@ mod tests {
         assert_eq!(params.filters_root[0].field.name, "name");
     }
 
+    #[test]
+    fn test_parse_repeated_filter_key() {
+        let params = parse_query_params("id=gt.1&name=eq.John&id=lt.9").unwrap();
+        let id_filters: Vec<_> = params
+            .filters_root
+            .iter()
+            .filter(|f| f.field.name == "id")
+            .collect();
+
+        assert_eq!(id_filters.len(), 2);
+        assert!(matches!(&id_filters[0].op_expr.operation, Operation::Quant { op: QuantOperator::GreaterThan, value, .. } if value == "1"));
+        assert!(matches!(&id_filters[1].op_expr.operation, Operation::Quant { op: QuantOperator::LessThan, value, .. } if value == "9"));
+    }
+
+    #[test]
+    fn test_parse_embedded_logic() {
+        let params = parse_query_params("posts.or=(title.eq.a,views.gt.10)").unwrap();
+        assert!(params.filters.is_empty());
+        assert_eq!(params.logic.len(), 1);
+        assert_eq!(params.logic[0].0, vec!["posts".to_string()]);
+        assert!(matches!(
+            &params.logic[0].1,
+            LogicTree::Expr { op: LogicOperator::Or, children, .. } if children.len() == 2
+        ));
+    }
+
+    #[test]
+    fn test_parse_nested_logic() {
+        let params =
+            parse_query_params("not.or=(age.lt.18,and(id.in.(1,2),status.not.eq.a),tags.cs.{x,y})").unwrap();
+        let LogicTree::Expr { negated, op, children } = &params.logic[0].1 else {
+            panic!("Expected logic group");
+        };
+        assert!(negated);
+        assert_eq!(*op, LogicOperator::Or);
+        assert_eq!(children.len(), 3);
+        assert!(matches!(
+            &children[1],
+            LogicTree::Expr { op: LogicOperator::And, negated: false, children } if children.len() == 2
+        ));
+
+        let params = parse_query_params("posts.not.and=(title.eq.a,views.gt.10)").unwrap();
+        assert_eq!(params.logic[0].0, vec!["posts".to_string()]);
+        assert!(matches!(&params.logic[0].1, LogicTree::Expr { negated: true, .. }));
+    }
+
     #[test]
     fn test_parse_negated_filter() {
         let params = parse_query_params("status=not.eq.active").unwrap();
         assert!(params.filters_root[0].op_expr.negated);
     }
 
+    #[test]
+    fn test_parse_pretty() {
+        assert!(parse_query_params("pretty").unwrap().pretty);
+        assert!(parse_query_params("id=eq.1&pretty=true").unwrap().pretty);
+        assert!(!parse_query_params("pretty=false").unwrap().pretty);
+        assert!(!parse_query_params("id=eq.1").unwrap().pretty);
+        assert!(parse_query_params("pretty").unwrap().filters_root.is_empty());
+    }
+
     #[test]
     fn test_parse_in_filter() {
         let params = parse_query_params("id=in.(1,2,3)").unwrap();
@@ -614,6 +1049,23 @@ mod tests {
             Operation::Is(IsValue::Null) => {}
             _ => panic!("Expected Is Null"),
         }
+
+        let params = parse_query_params("a=is.true&b=is.false&c=is.unknown&d=not.is.null").unwrap();
+        let ops: Vec<_> = params
+            .filters_root
+            .iter()
+            .map(|f| (f.op_expr.negated, f.op_expr.operation.clone()))
+            .collect();
+        assert_eq!(
+            ops,
+            [
+                (false, Operation::Is(IsValue::True)),
+                (false, Operation::Is(IsValue::False)),
+                (false, Operation::Is(IsValue::Unknown)),
+                (true, Operation::Is(IsValue::Null)),
+            ]
+        );
+        assert!(parse_query_params("a=is.maybe").is_err());
     }
 
     #[test]
@@ -632,12 +1084,149 @@ mod tests {
         assert_eq!(range.offset, 20);
     }
 
+    #[test]
+    fn test_malformed_pairs() {
+        // Empty pairs and a trailing separator are ignored
+        let params = parse_query_params("id=eq.1&").unwrap();
+        assert_eq!(params.filters_root.len(), 1);
+        assert_eq!(params.canonical, "id=eq.1");
+        let params = parse_query_params("&&id=eq.1&&select=id&").unwrap();
+        assert_eq!(params.filters_root.len(), 1);
+        assert_eq!(params.select.len(), 1);
+
+        // `;` separates pairs like `&`
+        let params = parse_query_params("id=eq.1;name=eq.x").unwrap();
+        assert_eq!(params.filters_root.len(), 2);
+
+        // A filter needs an operator
+        let err = parse_query_params("a=&b").unwrap_err();
+        assert!(matches!(&err, Error::InvalidQueryParam(msg) if msg == "a: missing operator"), "{:?}", err);
+        assert!(parse_query_params("b").is_err());
+
+        // Only the first `=` splits the pair
+        assert!(matches!(parse_query_params("a==b").unwrap_err(), Error::InvalidQueryParam(_)));
+        let params = parse_query_params("a=eq.x=y").unwrap();
+        assert!(matches!(
+            &params.filters_root[0].op_expr.operation,
+            Operation::Quant { value, .. } if value == "x=y"
+        ));
+
+        // Keys must name something
+        assert!(parse_query_params("=x").is_err());
+        assert!(parse_query_params("posts..id=eq.1").is_err());
+        assert!(parse_query_params(".id=eq.1").is_err());
+    }
+
     #[test]
     fn test_parse_select() {
         let items = parse_select("id,name,orders(id,amount)").unwrap();
         assert_eq!(items.len(), 3);
     }
 
+    #[test]
+    fn test_parse_wildcard_with_embeds() {
+        let items = parse_select("*,author(name),comments(*,user(id))").unwrap();
+        assert_eq!(items.len(), 3);
+        assert!(items[0].is_wildcard());
+        match &items[1] {
+            SelectItem::Relation { relation, select, .. } => {
+                assert_eq!(relation, "author");
+                assert_eq!(select, &vec![SelectItem::field("name")]);
+            }
+            other => panic!("expected a relation, got {:?}", other),
+        }
+        match &items[2] {
+            SelectItem::Relation { select, .. } => {
+                assert!(select[0].is_wildcard());
+                assert!(matches!(&select[1], SelectItem::Relation { relation, .. } if relation == "user"));
+            }
+            other => panic!("expected a relation, got {:?}", other),
+        }
+
+        // Unparsed input is an error rather than silently dropped
+        assert!(parse_select("id,*name").is_err());
+        assert!(parse_select("author(name").is_err());
+    }
+
+    #[test]
+    fn test_parse_aliased_relation() {
+        let items = parse_select("id,writer:author!author_fkey(name)").unwrap();
+        assert_eq!(
+            items[1],
+            SelectItem::Relation {
+                relation: "author".into(),
+                alias: Some("writer".into()),
+                hint: Some("author_fkey".into()),
+                join_type: None,
+                select: vec![SelectItem::field("name")],
+            }
+        );
+
+        // A join type alone is not taken for a hint
+        let items = parse_select("tags!inner(id),author!author_fkey!left(name)").unwrap();
+        assert!(matches!(
+            &items[0],
+            SelectItem::Relation { hint: None, join_type: Some(JoinType::Inner), .. }
+        ));
+        assert!(matches!(
+            &items[1],
+            SelectItem::Relation { hint: Some(hint), join_type: Some(JoinType::Left), .. } if hint == "author_fkey"
+        ));
+    }
+
+    #[test]
+    fn test_parse_spread_with_aggregates() {
+        let items = parse_select("category,...stats(total:count(),sum(amount)::int:amount)").unwrap();
+        let SelectItem::SpreadRelation { relation, select, .. } = &items[1] else {
+            panic!("expected a spread, got {:?}", items[1]);
+        };
+        assert_eq!(relation, "stats");
+        assert!(matches!(
+            &select[0],
+            SelectItem::Field { field, aggregate: Some(AggregateFunction::Count), alias: Some(alias), .. }
+                if field.name == "*" && alias == "total"
+        ));
+        assert!(!select[0].is_wildcard());
+        assert!(matches!(
+            &select[1],
+            SelectItem::Field { field, aggregate: Some(AggregateFunction::Sum), aggregate_cast: Some(cast), alias: Some(alias), .. }
+                if field.name == "amount" && cast == "int" && alias == "amount"
+        ));
+
+        // The alias may also follow the aggregate
+        assert_eq!(parse_select("count():total").unwrap(), parse_select("total:count()").unwrap());
+
+        // Plain fields take the alias first, or after a cast
+        assert!(matches!(
+            &parse_select("full:name").unwrap()[0],
+            SelectItem::Field { field, alias: Some(alias), .. } if field.name == "name" && alias == "full"
+        ));
+        assert_eq!(parse_select("n:name::int").unwrap(), parse_select("name::int:n").unwrap());
+    }
+
+    #[test]
+    fn test_parse_select_cast_types() {
+        let items = parse_select("a::date,b::api.mood,c::numeric(10,2):c2,d::text[]").unwrap();
+        let casts: Vec<_> = items
+            .iter()
+            .map(|item| match item {
+                SelectItem::Field { cast, aggregate_cast, alias, .. } => {
+                    (cast.clone().or(aggregate_cast.clone()).unwrap(), alias.clone())
+                }
+                _ => panic!("expected a field"),
+            })
+            .collect();
+        assert_eq!(
+            casts,
+            vec![
+                ("date".to_string(), None),
+                ("api.mood".to_string(), None),
+                ("numeric(10,2)".to_string(), Some("c2".to_string())),
+                ("text[]".to_string(), None),
+            ]
+        );
+    }
+
     #[test]
     fn test_parse_fts() {
         let params = parse_query_params("content=fts(english).search+term").unwrap();
@@ -650,4 +1239,174 @@ mod tests {
             _ => panic!("Expected FTS operation"),
         }
     }
+
+    #[test]
+    fn test_parse_raw_tsquery() {
+        let params = parse_query_params("body=fts(english).raw:(cat | dog) <-> !'fat rat':*A").unwrap();
+        match &params.filters_root[0].op_expr.operation {
+            Operation::Fts { op, language, value } => {
+                assert_eq!(*op, FtsOperator::Fts);
+                assert_eq!(language.as_deref(), Some("english"));
+                assert_eq!(value, "(cat | dog) <-> !'fat rat':*A");
+            }
+            _ => panic!("Expected FTS operation"),
+        }
+        assert!(parse_query_params("body=fts.raw:cat <2> dog | 'it''s'").is_ok());
+        assert!(validate_tsquery("fat & (rat | !cat:*)").is_ok());
+
+        for invalid in ["cat dog", "cat |", "(cat | dog", "cat <> dog", "'cat", "cat:x", ""] {
+            let err = parse_query_params(&format!("body=fts.raw:{}", invalid)).unwrap_err();
+            assert!(matches!(err, Error::InvalidQueryParam(_)), "{}: {:?}", invalid, err);
+            assert_eq!(err.status_code(), http::StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[test]
+    fn test_parse_routine_query_params() {
+        let params = parse_routine_query_params("q=hello&age=gt.18&order=name").unwrap();
+
+        // Every top-level key is a candidate argument...
+        assert_eq!(
+            params.params,
+            vec![("age".to_string(), "gt.18".to_string()), ("q".to_string(), "hello".to_string())]
+        );
+        // ...and the ones shaped like filters are also kept as filters
+        assert_eq!(params.filters_root.len(), 1);
+        assert_eq!(params.filters_root[0].field.name, "age");
+
+        // Tables still reject values that are not filters
+        assert!(parse_query_params("q=hello").is_err());
+    }
+
+    #[test]
+    fn test_parse_headline_select() {
+        let params =
+            parse_query_params("select=id,hl:ts_headline(body,plainto_tsquery(english,$q))&q=cat").unwrap();
+        assert_eq!(
+            params.select[1],
+            SelectItem::Headline {
+                column: "body".into(),
+                function: FtsOperator::Plain,
+                language: Some("english".into()),
+                param: "q".into(),
+                alias: Some("hl".into()),
+            }
+        );
+        // The bound parameter is a value, not a filter
+        assert!(params.filters_root.is_empty());
+        assert_eq!(params.params, vec![("q".to_string(), "cat".to_string())]);
+    }
+
+    #[test]
+    fn test_parse_order_by_json_path() {
+        let params = parse_query_params("order=data->>priority.desc,data->tags->0::int").unwrap();
+        let terms = &params.order[0].1;
+
+        match &terms[0] {
+            OrderTerm::Field { field, direction, cast, .. } => {
+                assert_eq!(field.name, "data");
+                assert_eq!(
+                    field.json_path,
+                    vec![JsonOperation::DoubleArrow(JsonOperand::Key("priority".into()))]
+                );
+                assert_eq!(*direction, Some(OrderDirection::Desc));
+                assert_eq!(*cast, None);
+            }
+            other => panic!("Expected field term, got {:?}", other),
+        }
+        match &terms[1] {
+            OrderTerm::Field { field, cast, .. } => {
+                assert_eq!(field.json_path.len(), 2);
+                assert_eq!(cast.as_deref(), Some("int"));
+            }
+            other => panic!("Expected field term, got {:?}", other),
+        }
+
+        assert!(parse_query_params("order=data->>").is_err());
+    }
+
+    #[test]
+    fn test_parse_order_by_embedded_column() {
+        let params = parse_query_params("order=author.name.desc,author(id),id.asc").unwrap();
+        let terms = &params.order[0].1;
+
+        match &terms[0] {
+            OrderTerm::Relation { relation, field, direction, .. } => {
+                assert_eq!(relation, "author");
+                assert_eq!(field.name, "name");
+                assert_eq!(*direction, Some(OrderDirection::Desc));
+            }
+            other => panic!("Expected relation order, got {:?}", other),
+        }
+        assert!(matches!(&terms[1], OrderTerm::Relation { relation, field, .. }
+            if relation == "author" && field.name == "id"));
+        assert!(matches!(&terms[2], OrderTerm::Field { field, .. } if field.name == "id"));
+    }
+
+    #[test]
+    fn test_query_limits() {
+        let limits = QueryLimits {
+            max_filters: Some(3),
+            max_select_items: Some(4),
+            max_embeds: Some(1),
+        };
+        let params = parse_query_params("select=id,posts(id,title)&a=eq.1&posts.b=eq.2&or=(c.eq.3)").unwrap();
+        assert!(limits.check(&params).is_ok());
+        assert!(QueryLimits::default().check(&params).is_ok());
+
+        // Logic group members count as filters
+        let params = parse_query_params("a=eq.1&b=eq.2&or=(c.eq.3,d.eq.4)").unwrap();
+        let err = limits.check(&params).unwrap_err();
+        assert!(matches!(err, Error::RequestTooComplex(ref msg) if msg == "4 filters exceed the limit of 3"), "{:?}", err);
+        assert_eq!(err.status_code(), http::StatusCode::BAD_REQUEST);
+
+        let params = parse_query_params("select=id,posts(id,comments(id))").unwrap();
+        let err = limits.check(&params).unwrap_err();
+        assert!(matches!(err, Error::RequestTooComplex(ref msg) if msg.contains("5 select items")), "{:?}", err);
+
+        let params = parse_query_params("select=posts(id),...users(id)").unwrap();
+        let err = limits.check(&params).unwrap_err();
+        assert!(matches!(err, Error::RequestTooComplex(ref msg) if msg.contains("2 embedded resources")), "{:?}", err);
+    }
+}
+
+#[cfg(test)]
+mod fuzz {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(2000))]
+
+        #[test]
+        fn query_params_never_panic(query in "[a-z0-9_.=&;,()!:*%$<>{}\\[\\]\"' +\\-]{0,40}") {
+            let _ = parse_query_params(&query);
+            let _ = parse_routine_query_params(&query);
+        }
+
+        #[test]
+        fn structured_pairs_never_panic(
+            pairs in prop::collection::vec(
+                (
+                    "(select|order|limit|offset|columns|on_conflict|or|and|not\\.or|id|posts\\.id|posts\\.limit|posts\\.or|a\\.b\\.c)",
+                    "(not\\.)?(eq|gt|in|is|cs|fts|like|ilike|or|and)?[.(),*a-z0-9:!{}\"\\\\%-]{0,20}",
+                ),
+                0..5,
+            )
+        ) {
+            let query = pairs
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join("&");
+            let _ = parse_query_params(&query);
+            let _ = parse_routine_query_params(&query);
+        }
+
+        #[test]
+        fn arbitrary_unicode_never_panics(query in "\\PC{0,40}") {
+            let _ = parse_query_params(&query);
+            let _ = parse_routine_query_params(&query);
+        }
+    }
 }
@@ -175,6 +175,32 @@ pub enum Action {
     SchemaInfo,
 }
 
+impl Action {
+    /// Whether the response carries headers only (HEAD requests).
+    pub fn is_headers_only(&self) -> bool {
+        matches!(
+            self,
+            Self::Db(DbAction::RelationRead { headers_only: true, .. })
+                | Self::Db(DbAction::SchemaRead { headers_only: true, .. })
+                | Self::Db(DbAction::Routine {
+                    invoke_method: InvokeMethod::InvRead { headers_only: true },
+                    ..
+                })
+        )
+    }
+
+    /// Whether the action must not have side effects (GET, HEAD, OPTIONS).
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            Self::Db(DbAction::RelationMut { .. }) => false,
+            Self::Db(DbAction::Routine { invoke_method, .. }) => {
+                matches!(invoke_method, InvokeMethod::InvRead { .. })
+            }
+            _ => true,
+        }
+    }
+}
+
 // ============================================================================
 // Filter Operations
 // ============================================================================
@@ -475,12 +501,26 @@ pub enum SelectItem {
         alias: Option<Alias>,
         hint: Option<Hint>,
         join_type: Option<JoinType>,
+        /// Select list of the embedded resource; all columns when empty
+        select: Vec<SelectItem>,
     },
     /// Spread a related resource's columns (horizontal embedding)
     SpreadRelation {
         relation: FieldName,
         hint: Option<Hint>,
         join_type: Option<JoinType>,
+        /// Select list of the embedded resource; all columns when empty
+        select: Vec<SelectItem>,
+    },
+    /// Highlight search matches in a text column:
+    /// `alias:ts_headline(col,websearch_to_tsquery(english,$q))`, where the
+    /// search text comes from the `q` query parameter
+    Headline {
+        column: FieldName,
+        function: FtsOperator,
+        language: Option<Language>,
+        param: String,
+        alias: Option<Alias>,
     },
 }
 
@@ -503,8 +543,14 @@ impl SelectItem {
             alias: None,
             hint: None,
             join_type: None,
+            select: vec![],
         }
     }
+
+    /// Whether this is the `*` wildcard.
+    pub fn is_wildcard(&self) -> bool {
+        matches!(self, Self::Field { field, aggregate: None, .. } if field.name == "*")
+    }
 }
 
 // ============================================================================
@@ -547,11 +593,13 @@ impl OrderNulls {
 /// An ORDER BY term.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum OrderTerm {
-    /// Order by a field
+    /// Order by a field, optionally through a JSON path and a cast
     Field {
         field: Field,
         direction: Option<OrderDirection>,
         nulls: Option<OrderNulls>,
+        #[serde(default)]
+        cast: Option<String>,
     },
     /// Order by a field from an embedded relation
     Relation {
@@ -568,6 +616,7 @@ impl OrderTerm {
             field: Field::simple(name),
             direction: None,
             nulls: None,
+            cast: None,
         }
     }
 
@@ -576,6 +625,7 @@ impl OrderTerm {
             field: Field::simple(name),
             direction: Some(OrderDirection::Desc),
             nulls: None,
+            cast: None,
         }
     }
 }
@@ -608,6 +658,40 @@ impl Range {
     pub fn has_limit(&self) -> bool {
         self.limit.is_some()
     }
+
+    /// Check if this range selects no rows (`limit=0`).
+    pub fn is_empty(&self) -> bool {
+        self.limit == Some(0)
+    }
+
+    /// Check if the offset counts back from the end (`offset=-10`).
+    pub fn is_from_end(&self) -> bool {
+        self.offset < 0
+    }
+
+    /// Resolve an offset counting back from the end against the total row
+    /// count, so `offset=-10` of 95 rows starts at row 85.
+    pub fn from_end(&self, total: i64) -> Self {
+        Self {
+            offset: if self.is_from_end() { (total + self.offset).max(0) } else { self.offset },
+            limit: self.limit,
+        }
+    }
+
+    /// Apply the server's page size settings.
+    ///
+    /// `default_limit` fills in a missing limit; `max_rows` caps any limit,
+    /// including one the client asked for.
+    pub fn bounded(&self, default_limit: Option<i64>, max_rows: Option<i64>) -> Self {
+        let limit = match (self.limit.or(default_limit), max_rows) {
+            (Some(limit), Some(max)) => Some(limit.min(max)),
+            (limit, max) => limit.or(max),
+        };
+        Self {
+            offset: self.offset,
+            limit,
+        }
+    }
 }
 
 // ============================================================================
@@ -644,8 +728,8 @@ pub enum MediaType {
     ApplicationJson,
     /// application/geo+json
     GeoJson,
-    /// text/csv
-    TextCsv,
+    /// text/csv, optionally with a `null` sentinel for NULL fields
+    TextCsv { null: Option<String> },
     /// text/plain
     TextPlain,
     /// text/xml
@@ -656,14 +740,19 @@ pub enum MediaType {
     UrlEncoded,
     /// application/octet-stream
     OctetStream,
+    /// application/x-ndjson (one JSON object per line)
+    NdJson,
     /// */*
     Any,
     /// Custom media type
     Other(String),
-    /// Singular JSON object (vnd.pgrst.object)
-    SingularJson { nullable: bool },
-    /// Array JSON with nulls stripped
+    /// Singular JSON object (vnd.pgrst.object); with `scalar`, an object
+    /// holding a single field is unwrapped to that field's value
+    SingularJson { nullable: bool, scalar: bool },
+    /// Array JSON with null-valued keys stripped (`nulls=stripped`)
     ArrayJsonStrip,
+    /// JSON object of rows keyed by primary key (`keyed=true`)
+    KeyedJson,
     /// EXPLAIN plan output
     Plan {
         base: Box<MediaType>,
@@ -683,21 +772,62 @@ impl MediaType {
         match self {
             Self::ApplicationJson => "application/json",
             Self::GeoJson => "application/geo+json",
-            Self::TextCsv => "text/csv",
+            Self::TextCsv { .. } => "text/csv",
             Self::TextPlain => "text/plain",
             Self::TextXml => "text/xml",
             Self::OpenApi => "application/openapi+json",
             Self::UrlEncoded => "application/x-www-form-urlencoded",
             Self::OctetStream => "application/octet-stream",
+            Self::NdJson => "application/x-ndjson",
             Self::Any => "*/*",
             Self::Other(s) => s,
             Self::SingularJson { .. } => "application/vnd.pgrst.object+json",
             Self::ArrayJsonStrip => "application/vnd.pgrst.array+json",
+            Self::KeyedJson => "application/json",
             Self::Plan { .. } => "application/vnd.pgrst.plan+json",
         }
     }
 }
 
+/// How bytea columns are written in JSON output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ByteaEncoding {
+    /// Standard base64 with padding
+    #[default]
+    Base64,
+    /// PostgreSQL's hex format, e.g. `\x48690a`
+    Hex,
+}
+
+impl ByteaEncoding {
+    /// Parse `base64` or `hex`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "base64" => Some(Self::Base64),
+            "hex" => Some(Self::Hex),
+            _ => None,
+        }
+    }
+
+    /// Encode bytes as a JSON string value.
+    pub fn encode(&self, bytes: &[u8]) -> String {
+        match self {
+            Self::Base64 => {
+                use base64::{engine::general_purpose::STANDARD, Engine};
+                STANDARD.encode(bytes)
+            }
+            Self::Hex => {
+                use std::fmt::Write;
+                bytes.iter().fold(String::from("\\x"), |mut hex, b| {
+                    let _ = write!(hex, "{:02x}", b);
+                    hex
+                })
+            }
+        }
+    }
+}
+
 /// EXPLAIN plan format.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PlanFormat {
@@ -784,13 +914,31 @@ pub struct Preferences {
     pub representation: PreferRepresentation,
     pub count: Option<PreferCount>,
     pub transaction: PreferTransaction,
-    pub missing: PreferMissing,
-    pub handling: PreferHandling,
+    pub missing: Option<PreferMissing>,
+    pub handling: Option<PreferHandling>,
     pub timezone: Option<String>,
     pub max_affected: Option<i64>,
+    /// `bulk-update=pk`: an array PATCH updates each row by its primary key
+    pub bulk_update: bool,
     pub invalid: Vec<String>,
 }
 
+/// Preferences that were actually honored, for `Preference-Applied`.
+///
+/// Filled in from the execution outcome rather than the parsed request, so
+/// a preference only shows up when it had an effect.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AppliedPreferences {
+    pub resolution: Option<PreferResolution>,
+    pub representation: Option<PreferRepresentation>,
+    pub count: Option<PreferCount>,
+    pub missing: Option<PreferMissing>,
+    pub handling: Option<PreferHandling>,
+    pub transaction: Option<PreferTransaction>,
+    pub timezone: Option<String>,
+    pub bulk_update: bool,
+}
+
 // ============================================================================
 // Query Parameters
 // ============================================================================
@@ -823,6 +971,8 @@ pub struct QueryParams {
     pub filter_fields: HashSet<FieldName>,
     /// Conflict columns for upsert
     pub on_conflict: Option<Vec<FieldName>>,
+    /// Indent JSON responses (`?pretty`)
+    pub pretty: bool,
 }
 
 // ============================================================================
@@ -834,8 +984,11 @@ pub struct QueryParams {
 pub struct ApiRequest {
     /// The action to perform
     pub action: Action,
-    /// Target schema
+    /// Target schema (`Content-Profile` for writes, `Accept-Profile` for reads)
     pub schema: Schema,
+    /// Schema for the returned representation, when a write's
+    /// `Accept-Profile` differs from its `Content-Profile`
+    pub representation_schema: Option<Schema>,
     /// Request body
     pub payload: Option<Payload>,
     /// Parsed query parameters
@@ -862,6 +1015,20 @@ pub struct ApiRequest {
     pub headers: IndexMap<String, String>,
     /// Request cookies
     pub cookies: IndexMap<String, String>,
+    /// ETag from `If-Match` (quotes and weak prefix stripped; `*` kept)
+    pub if_match: Option<String>,
+    /// bytea encoding from the `Accept` header's `bytea` parameter
+    pub bytea_encoding: Option<ByteaEncoding>,
+    /// Schemas put on the `search_path` in place of the request schema, from
+    /// a trusted `X-Search-Path` header
+    pub search_path: Option<Vec<Schema>>,
+}
+
+impl ApiRequest {
+    /// Schema the response body is resolved in.
+    pub fn response_schema(&self) -> &str {
+        self.representation_schema.as_deref().unwrap_or(&self.schema)
+    }
 }
 
 impl Default for ApiRequest {
@@ -869,6 +1036,7 @@ impl Default for ApiRequest {
         Self {
             action: Action::SchemaInfo,
             schema: String::new(),
+            representation_schema: None,
             payload: None,
             query_params: QueryParams::default(),
             accept_media_types: vec![MediaType::ApplicationJson],
@@ -882,6 +1050,9 @@ impl Default for ApiRequest {
             path: String::new(),
             headers: IndexMap::new(),
             cookies: IndexMap::new(),
+            if_match: None,
+            bytea_encoding: None,
+            search_path: None,
         }
     }
 }
@@ -899,6 +1070,16 @@ mod tests {
         assert_eq!(unqual.to_string(), "users");
     }
 
+    #[test]
+    fn test_bytea_encodings() {
+        let bytes = b"Hi\n\xff";
+        assert_eq!(ByteaEncoding::Base64.encode(bytes), "SGkK/w==");
+        assert_eq!(ByteaEncoding::Hex.encode(bytes), "\\x48690aff");
+        assert_eq!(ByteaEncoding::Hex.encode(b""), "\\x");
+        assert_eq!(ByteaEncoding::parse("HEX"), Some(ByteaEncoding::Hex));
+        assert_eq!(ByteaEncoding::parse("base32"), None);
+    }
+
     #[test]
     fn test_simple_operator_sql() {
         assert_eq!(SimpleOperator::NotEqual.to_sql(), "<>");
@@ -923,4 +1104,34 @@ mod tests {
         assert_eq!(range.offset, 10);
         assert_eq!(range.limit, Some(10));
     }
+
+    #[test]
+    fn test_range_from_end() {
+        let range = Range::new(-10, None);
+        assert!(range.is_from_end());
+        assert_eq!(range.from_end(95), Range::new(85, None));
+
+        // Fewer rows than asked for start at the first
+        assert_eq!(range.from_end(4), Range::new(0, None));
+        assert_eq!(Range::new(-10, Some(5)).from_end(95), Range::new(85, Some(5)));
+        assert_eq!(Range::new(20, Some(5)).from_end(95), Range::new(20, Some(5)));
+    }
+
+    #[test]
+    fn test_range_bounded_by_default_limit() {
+        // No limit asked for: the default applies
+        let range = Range::default().bounded(Some(25), Some(1000));
+        assert_eq!(range, Range::new(0, Some(25)));
+
+        // A larger explicit limit overrides the default...
+        let range = Range::new(10, Some(100)).bounded(Some(25), Some(1000));
+        assert_eq!(range, Range::new(10, Some(100)));
+
+        // ...up to max rows
+        let range = Range::new(0, Some(5000)).bounded(Some(25), Some(1000));
+        assert_eq!(range, Range::new(0, Some(1000)));
+
+        // Without settings nothing changes
+        assert_eq!(Range::default().bounded(None, None), Range::default());
+    }
 }
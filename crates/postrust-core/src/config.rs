@@ -2,6 +2,7 @@
 //!
 //! Mirrors PostgREST's configuration options.
 
+use crate::api_request::ByteaEncoding;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -34,7 +35,7 @@ pub struct AppConfig {
     #[serde(default = "default_true")]
     pub db_prepared_statements: bool,
 
-    /// Extra search path schemas
+    /// Schemas appended to the search_path after the exposed schema
     #[serde(default)]
     pub db_extra_search_path: Vec<String>,
 
@@ -49,13 +50,40 @@ pub struct AppConfig {
     /// Pre-request function to call
     pub db_pre_request: Option<String>,
 
+    /// Function called with the response rows; a non-null return replaces the body
+    pub db_post_request: Option<String>,
+
     /// Maximum rows allowed in a response
     pub db_max_rows: Option<i64>,
 
+    /// Page size applied when a read gives no Range or `limit` (capped by `db_max_rows`)
+    pub default_limit: Option<i64>,
+
     /// Enable aggregate functions
     #[serde(default = "default_true")]
     pub db_aggregates_enabled: bool,
 
+    /// Request headers exposed via the `request.headers` GUC (all when unset)
+    pub db_request_headers: Option<Vec<String>>,
+
+    /// Resolve table and column names regardless of case
+    #[serde(default)]
+    pub db_case_insensitive_names: bool,
+
+    /// Schemas whose routines are exposed (all exposed schemas when unset)
+    pub db_routine_schemas: Option<Vec<String>>,
+
+    /// Routines (`name` or `schema.name`, `*` as a wildcard) hidden from the API
+    #[serde(default)]
+    pub db_hidden_routines: Vec<String>,
+
+    /// Volatilities of the routines exposed (all when unset)
+    pub db_routine_volatility: Option<Vec<crate::schema_cache::FuncVolatility>>,
+
+    /// HTTP status for SQLSTATE codes or code prefixes, overriding the defaults
+    #[serde(default)]
+    pub db_sqlstate_status: HashMap<String, u16>,
+
     // ========================================================================
     // Server Settings
     // ========================================================================
@@ -67,6 +95,59 @@ pub struct AppConfig {
     #[serde(default = "default_port")]
     pub server_port: u16,
 
+    /// Seconds to remember `Idempotency-Key` responses for POST (disabled when unset)
+    pub server_idempotency_ttl: Option<u64>,
+
+    /// Echo the normalized query string in `X-Canonical-Query` (debugging only)
+    #[serde(default)]
+    pub server_debug_headers: bool,
+
+    /// Add `Link` headers to the next and previous pages of a read
+    #[serde(default)]
+    pub server_pagination_links: bool,
+
+    /// Share one execution between identical concurrent reads
+    #[serde(default)]
+    pub server_coalesce_reads: bool,
+
+    /// How bytea columns are written in JSON, unless the request asks otherwise
+    #[serde(default)]
+    pub server_bytea_encoding: ByteaEncoding,
+
+    /// Take the role from `X-Forwarded-Role` on requests from a trusted proxy
+    #[serde(default)]
+    pub server_trust_forwarded_role: bool,
+
+    /// Build absolute `Location` and `Link` URLs from `X-Forwarded-Proto`
+    /// and `X-Forwarded-Host` on requests from a trusted proxy
+    #[serde(default)]
+    pub server_trust_forwarded_host: bool,
+
+    /// Take the `search_path` schemas from `X-Search-Path` on requests from
+    /// a trusted proxy
+    #[serde(default)]
+    pub server_trust_search_path: bool,
+
+    /// Most filters a request may contain, at all embed levels
+    #[serde(default)]
+    pub server_max_filters: Option<usize>,
+
+    /// Most select items a request may contain, at all embed levels
+    #[serde(default)]
+    pub server_max_select_items: Option<usize>,
+
+    /// Most resources a request may embed
+    #[serde(default)]
+    pub server_max_embeds: Option<usize>,
+
+    /// Peer addresses whose forwarded headers are honored
+    #[serde(default = "default_trusted_proxies")]
+    pub server_trusted_proxies: Vec<String>,
+
+    /// Origins allowed by CORS (`None` allows any)
+    #[serde(default)]
+    pub server_cors_origins: Option<Vec<String>>,
+
     /// Unix socket path (alternative to host/port)
     pub server_unix_socket: Option<String>,
 
@@ -79,6 +160,9 @@ pub struct AppConfig {
     /// JWT secret key (or JWKS URL)
     pub jwt_secret: Option<String>,
 
+    /// Previous JWT secret, accepted alongside `jwt_secret` during rotation
+    pub jwt_secret_previous: Option<String>,
+
     /// JWT secret as base64
     #[serde(default)]
     pub jwt_secret_is_base64: bool,
@@ -90,6 +174,9 @@ pub struct AppConfig {
     #[serde(default = "default_jwt_role_claim")]
     pub jwt_role_claim_key: String,
 
+    /// Roles a JWT may assume (any when unset); the anon role is always allowed
+    pub jwt_role_allowlist: Option<Vec<String>>,
+
     /// Cache JWT validations
     #[serde(default = "default_true")]
     pub jwt_cache_enabled: bool,
@@ -108,6 +195,45 @@ pub struct AppConfig {
     #[serde(default = "default_openapi_mode")]
     pub openapi_mode: OpenApiMode,
 
+    // ========================================================================
+    // GraphQL Settings
+    // ========================================================================
+    /// Prefix GraphQL type and field names with their schema when several are exposed
+    #[serde(default)]
+    pub graphql_schema_prefix: bool,
+
+    /// Reject GraphQL introspection queries
+    #[serde(default)]
+    pub graphql_disable_introspection: bool,
+
+    /// Seconds a GraphQL WebSocket may stay silent (no pings) before it is closed
+    pub graphql_ws_keepalive: Option<u64>,
+
+    /// Maximum lifetime of a GraphQL WebSocket connection in seconds
+    pub graphql_ws_max_lifetime: Option<u64>,
+
+    /// Start the GraphQL subscription broker (LISTEN connection and change triggers)
+    #[serde(default)]
+    pub graphql_subscriptions: bool,
+
+    /// Create notify triggers for subscribed tables on startup
+    #[serde(default)]
+    pub graphql_subscription_triggers: bool,
+
+    /// Notify payloads above this many bytes carry only the primary key
+    pub graphql_notify_max_payload: Option<usize>,
+
+    /// Tables that get GraphQL subscriptions (all when unset)
+    pub graphql_subscription_tables: Option<Vec<String>>,
+
+    /// Tables that never get GraphQL subscriptions
+    #[serde(default)]
+    pub graphql_subscription_exclude: Vec<String>,
+
+    /// Columns (`table.column` or `schema.table.column`) left out of the GraphQL schema
+    #[serde(default)]
+    pub graphql_hidden_columns: Vec<String>,
+
     // ========================================================================
     // Logging Settings
     // ========================================================================
@@ -140,21 +266,54 @@ impl Default for AppConfig {
             db_channel: default_db_channel(),
             db_channel_enabled: false,
             db_pre_request: None,
+            db_post_request: None,
             db_max_rows: None,
+            default_limit: None,
             db_aggregates_enabled: true,
+            db_case_insensitive_names: false,
+            db_routine_schemas: None,
+            db_hidden_routines: vec![],
+            db_routine_volatility: None,
+            db_sqlstate_status: HashMap::new(),
+            db_request_headers: None,
             server_host: default_host(),
             server_port: default_port(),
+            server_idempotency_ttl: None,
+            server_debug_headers: false,
+            server_pagination_links: false,
+            server_coalesce_reads: false,
+            server_bytea_encoding: ByteaEncoding::default(),
+            server_trust_forwarded_role: false,
+            server_trust_forwarded_host: false,
+            server_trust_search_path: false,
+            server_max_filters: None,
+            server_max_select_items: None,
+            server_max_embeds: None,
+            server_trusted_proxies: default_trusted_proxies(),
+            server_cors_origins: None,
             server_unix_socket: None,
             admin_server_port: None,
             jwt_secret: None,
+            jwt_secret_previous: None,
             jwt_secret_is_base64: false,
             jwt_aud: None,
             jwt_role_claim_key: default_jwt_role_claim(),
+            jwt_role_allowlist: None,
             jwt_cache_enabled: true,
             jwt_cache_max_lifetime: default_jwt_cache_max(),
             openapi_server_proxy_uri: None,
             openapi_mode: OpenApiMode::FollowPrivileges,
-            log_level: LogLevel::Error,
+            graphql_schema_prefix: false,
+            graphql_disable_introspection: false,
+            graphql_ws_keepalive: None,
+            graphql_ws_max_lifetime: None,
+            graphql_subscriptions: false,
+            graphql_subscription_triggers: false,
+            graphql_notify_max_payload: None,
+            graphql_subscription_tables: None,
+            graphql_subscription_exclude: vec![],
+            graphql_hidden_columns: vec![],
+            log_level: LogLevel::Info,
             role_settings: HashMap::new(),
             app_settings: HashMap::new(),
         }
@@ -162,42 +321,236 @@ impl Default for AppConfig {
 }
 
 impl AppConfig {
+    /// The configured caps on a single query's size.
+    pub fn query_limits(&self) -> crate::api_request::QueryLimits {
+        crate::api_request::QueryLimits {
+            max_filters: self.server_max_filters,
+            max_select_items: self.server_max_select_items,
+            max_embeds: self.server_max_embeds,
+        }
+    }
+
+    /// The configured rules for which routines are exposed.
+    pub fn routine_filter(&self) -> crate::schema_cache::RoutineFilter {
+        crate::schema_cache::RoutineFilter {
+            schemas: self.db_routine_schemas.clone(),
+            hidden: self.db_hidden_routines.clone(),
+            volatility: self.db_routine_volatility.clone(),
+        }
+    }
+
     /// Load configuration from environment variables.
+    ///
+    /// Variables in a `.env` file fill in for those the environment leaves unset.
     pub fn from_env() -> Self {
+        let dotenv: HashMap<String, String> = dotenvy::dotenv_iter()
+            .map(|vars| vars.filter_map(|var| var.ok()).collect())
+            .unwrap_or_default();
+        Self::from_vars(|key| std::env::var(key).ok().or_else(|| dotenv.get(key).cloned()))
+    }
+
+    /// Load configuration from `PGRST_*` variables looked up by name.
+    fn from_vars(lookup: impl Fn(&str) -> Option<String>) -> Self {
         let mut config = Self::default();
 
-        if let Ok(uri) = std::env::var("PGRST_DB_URI") {
+        if let Some(uri) = lookup("PGRST_DB_URI") {
             config.db_uri = uri;
         }
-        if let Ok(uri) = std::env::var("DATABASE_URL") {
+        if let Some(uri) = lookup("DATABASE_URL") {
             config.db_uri = uri;
         }
-        if let Ok(schemas) = std::env::var("PGRST_DB_SCHEMAS") {
+        if let Some(schemas) = lookup("PGRST_DB_SCHEMAS") {
             config.db_schemas = schemas.split(',').map(|s| s.trim().to_string()).collect();
         }
-        if let Ok(role) = std::env::var("PGRST_DB_ANON_ROLE") {
+        if let Some(role) = lookup("PGRST_DB_ANON_ROLE") {
             config.db_anon_role = Some(role);
         }
-        if let Ok(size) = std::env::var("PGRST_DB_POOL") {
+        if let Some(level) = lookup("PGRST_LOG_LEVEL") {
+            if let Some(level) = LogLevel::parse(&level) {
+                config.log_level = level;
+            }
+        }
+        if let Some(size) = lookup("PGRST_DB_POOL") {
             if let Ok(n) = size.parse() {
                 config.db_pool_size = n;
             }
         }
-        if let Ok(secret) = std::env::var("PGRST_JWT_SECRET") {
+        if let Some(max_rows) = lookup("PGRST_MAX_ROWS") {
+            config.db_max_rows = max_rows.parse().ok();
+        }
+        if let Some(limit) = lookup("PGRST_DEFAULT_LIMIT") {
+            config.default_limit = limit.parse().ok();
+        }
+        if let Some(function) = lookup("PGRST_DB_POST_REQUEST") {
+            config.db_post_request = Some(function);
+        }
+        if let Some(path) = lookup("PGRST_DB_EXTRA_SEARCH_PATH") {
+            config.db_extra_search_path = path
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Some(headers) = lookup("PGRST_DB_REQUEST_HEADERS") {
+            config.db_request_headers = Some(
+                headers
+                    .split(',')
+                    .map(|s| s.trim().to_lowercase())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+            );
+        }
+        if let Some(names) = lookup("PGRST_DB_CASE_INSENSITIVE_NAMES") {
+            config.db_case_insensitive_names = names == "true" || names == "1";
+        }
+        if let Some(schemas) = lookup("PGRST_DB_ROUTINE_SCHEMAS") {
+            config.db_routine_schemas = Some(
+                schemas
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+            );
+        }
+        if let Some(routines) = lookup("PGRST_DB_HIDDEN_ROUTINES") {
+            config.db_hidden_routines = routines
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Some(volatility) = lookup("PGRST_DB_ROUTINE_VOLATILITY") {
+            config.db_routine_volatility = Some(
+                volatility
+                    .split(',')
+                    .filter_map(|s| crate::schema_cache::FuncVolatility::from_name(s.trim()))
+                    .collect(),
+            );
+        }
+        if let Some(mapping) = lookup("PGRST_DB_SQLSTATE_STATUS") {
+            config.db_sqlstate_status = parse_sqlstate_status(&mapping);
+        }
+        if let Some(ttl) = lookup("PGRST_SERVER_IDEMPOTENCY_TTL") {
+            config.server_idempotency_ttl = ttl.parse().ok();
+        }
+        if let Some(debug) = lookup("PGRST_SERVER_DEBUG_HEADERS") {
+            config.server_debug_headers = debug == "true" || debug == "1";
+        }
+        if let Some(links) = lookup("PGRST_SERVER_PAGINATION_LINKS") {
+            config.server_pagination_links = links == "true" || links == "1";
+        }
+        if let Some(coalesce) = lookup("PGRST_SERVER_COALESCE_READS") {
+            config.server_coalesce_reads = coalesce == "true" || coalesce == "1";
+        }
+        if let Some(encoding) = lookup("PGRST_SERVER_BYTEA_ENCODING") {
+            if let Some(encoding) = ByteaEncoding::parse(&encoding) {
+                config.server_bytea_encoding = encoding;
+            }
+        }
+        if let Some(trust) = lookup("PGRST_SERVER_TRUST_FORWARDED_ROLE") {
+            config.server_trust_forwarded_role = trust == "true" || trust == "1";
+        }
+        if let Some(trust) = lookup("PGRST_SERVER_TRUST_FORWARDED_HOST") {
+            config.server_trust_forwarded_host = trust == "true" || trust == "1";
+        }
+        if let Some(trust) = lookup("PGRST_SERVER_TRUST_SEARCH_PATH") {
+            config.server_trust_search_path = trust == "true" || trust == "1";
+        }
+        if let Some(max) = lookup("PGRST_SERVER_MAX_FILTERS") {
+            config.server_max_filters = max.parse().ok();
+        }
+        if let Some(max) = lookup("PGRST_SERVER_MAX_SELECT_ITEMS") {
+            config.server_max_select_items = max.parse().ok();
+        }
+        if let Some(max) = lookup("PGRST_SERVER_MAX_EMBEDS") {
+            config.server_max_embeds = max.parse().ok();
+        }
+        if let Some(proxies) = lookup("PGRST_SERVER_TRUSTED_PROXIES") {
+            config.server_trusted_proxies = proxies
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Some(origins) = lookup("PGRST_SERVER_CORS_ORIGINS") {
+            let origins: Vec<String> = origins
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+            config.server_cors_origins = (!origins.iter().any(|o| o == "*")).then_some(origins);
+        }
+        if let Some(prefix) = lookup("PGRST_GRAPHQL_SCHEMA_PREFIX") {
+            config.graphql_schema_prefix = prefix == "true" || prefix == "1";
+        }
+        if let Some(disable) = lookup("PGRST_GRAPHQL_DISABLE_INTROSPECTION") {
+            config.graphql_disable_introspection = disable == "true" || disable == "1";
+        }
+        if let Some(secs) = lookup("PGRST_GRAPHQL_WS_KEEPALIVE") {
+            config.graphql_ws_keepalive = secs.parse().ok();
+        }
+        if let Some(secs) = lookup("PGRST_GRAPHQL_WS_MAX_LIFETIME") {
+            config.graphql_ws_max_lifetime = secs.parse().ok();
+        }
+        if let Some(enabled) = lookup("PGRST_GRAPHQL_SUBSCRIPTIONS") {
+            config.graphql_subscriptions = enabled == "true" || enabled == "1";
+        }
+        if let Some(enabled) = lookup("PGRST_GRAPHQL_SUBSCRIPTION_TRIGGERS") {
+            config.graphql_subscription_triggers = enabled == "true" || enabled == "1";
+        }
+        if let Some(bytes) = lookup("PGRST_GRAPHQL_NOTIFY_MAX_PAYLOAD") {
+            config.graphql_notify_max_payload = bytes.parse().ok();
+        }
+        if let Some(tables) = lookup("PGRST_GRAPHQL_SUBSCRIPTION_TABLES") {
+            config.graphql_subscription_tables = Some(
+                tables
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+            );
+        }
+        if let Some(tables) = lookup("PGRST_GRAPHQL_SUBSCRIPTION_EXCLUDE") {
+            config.graphql_subscription_exclude = tables
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Some(columns) = lookup("PGRST_GRAPHQL_HIDDEN_COLUMNS") {
+            config.graphql_hidden_columns = columns
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Some(secret) = lookup("PGRST_JWT_SECRET") {
             config.jwt_secret = Some(secret);
         }
-        if let Ok(aud) = std::env::var("PGRST_JWT_AUD") {
+        if let Some(secret) = lookup("PGRST_JWT_SECRET_PREVIOUS") {
+            config.jwt_secret_previous = Some(secret);
+        }
+        if let Some(roles) = lookup("PGRST_JWT_ROLE_ALLOWLIST") {
+            config.jwt_role_allowlist = Some(
+                roles
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+            );
+        }
+        if let Some(aud) = lookup("PGRST_JWT_AUD") {
             config.jwt_aud = Some(aud);
         }
-        if let Ok(host) = std::env::var("PGRST_SERVER_HOST") {
+        if let Some(host) = lookup("PGRST_SERVER_HOST") {
             config.server_host = host;
         }
-        if let Ok(port) = std::env::var("PGRST_SERVER_PORT") {
+        if let Some(port) = lookup("PGRST_SERVER_PORT") {
             if let Ok(p) = port.parse() {
                 config.server_port = p;
             }
         }
-        if let Ok(port) = std::env::var("PORT") {
+        if let Some(port) = lookup("PORT") {
             if let Ok(p) = port.parse() {
                 config.server_port = p;
             }
@@ -206,6 +559,47 @@ impl AppConfig {
         config
     }
 
+    /// This configuration with the settings that may change while running
+    /// taken from `new`.
+    ///
+    /// Connection, listener, JWT and GraphQL settings, and those that
+    /// shape the schema cache, keep their startup values.
+    pub fn with_runtime_settings(&self, new: &AppConfig) -> Self {
+        Self {
+            db_extra_search_path: new.db_extra_search_path.clone(),
+            db_pre_request: new.db_pre_request.clone(),
+            db_post_request: new.db_post_request.clone(),
+            db_max_rows: new.db_max_rows,
+            default_limit: new.default_limit,
+            db_aggregates_enabled: new.db_aggregates_enabled,
+            db_request_headers: new.db_request_headers.clone(),
+            db_sqlstate_status: new.db_sqlstate_status.clone(),
+            server_debug_headers: new.server_debug_headers,
+            server_pagination_links: new.server_pagination_links,
+            server_bytea_encoding: new.server_bytea_encoding,
+            server_trust_forwarded_role: new.server_trust_forwarded_role,
+            server_trust_forwarded_host: new.server_trust_forwarded_host,
+            server_trust_search_path: new.server_trust_search_path,
+            server_trusted_proxies: new.server_trusted_proxies.clone(),
+            server_cors_origins: new.server_cors_origins.clone(),
+            server_max_filters: new.server_max_filters,
+            server_max_select_items: new.server_max_select_items,
+            server_max_embeds: new.server_max_embeds,
+            role_settings: new.role_settings.clone(),
+            app_settings: new.app_settings.clone(),
+            log_level: new.log_level.clone(),
+            ..self.clone()
+        }
+    }
+
+    /// Whether CORS allows requests from `origin`.
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        match &self.server_cors_origins {
+            Some(origins) => origins.iter().any(|o| o == origin),
+            None => true,
+        }
+    }
+
     /// Get the default schema (first in the list).
     pub fn default_schema(&self) -> &str {
         self.db_schemas.first().map(|s| s.as_str()).unwrap_or("public")
@@ -259,6 +653,18 @@ pub enum LogLevel {
 }
 
 impl LogLevel {
+    /// Parse `crit`, `error`, `warn`, `info` or `debug`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "crit" => Some(Self::Crit),
+            "error" => Some(Self::Error),
+            "warn" => Some(Self::Warn),
+            "info" => Some(Self::Info),
+            "debug" => Some(Self::Debug),
+            _ => None,
+        }
+    }
+
     pub fn to_tracing(&self) -> tracing::Level {
         match self {
             Self::Crit | Self::Error => tracing::Level::ERROR,
@@ -298,6 +704,10 @@ fn default_port() -> u16 {
     3000
 }
 
+fn default_trusted_proxies() -> Vec<String> {
+    vec!["127.0.0.1".to_string(), "::1".to_string()]
+}
+
 fn default_jwt_role_claim() -> String {
     "role".to_string()
 }
@@ -311,13 +721,26 @@ fn default_openapi_mode() -> OpenApiMode {
 }
 
 fn default_log_level() -> LogLevel {
-    LogLevel::Error
+    LogLevel::Info
 }
 
 fn default_true() -> bool {
     true
 }
 
+/// Parse `code:status` pairs such as `PT402:402,23:422`, skipping malformed ones.
+fn parse_sqlstate_status(mapping: &str) -> HashMap<String, u16> {
+    mapping
+        .split(',')
+        .filter_map(|pair| {
+            let (code, status) = pair.split_once(':')?;
+            let status = status.trim().parse().ok().filter(|s| (100..600).contains(s))?;
+            Some((code.trim().to_uppercase(), status))
+        })
+        .filter(|(code, _)| !code.is_empty())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -328,6 +751,7 @@ mod tests {
         assert_eq!(config.server_port, 3000);
         assert_eq!(config.db_pool_size, 10);
         assert!(config.db_prepared_statements);
+        assert!(!config.server_trust_forwarded_role);
     }
 
     #[test]
@@ -339,6 +763,39 @@ mod tests {
         assert_eq!(config.default_schema(), "api");
     }
 
+    #[test]
+    fn test_runtime_settings_from_vars() {
+        let vars: HashMap<&str, &str> = [
+            ("PGRST_MAX_ROWS", "100"),
+            ("PGRST_LOG_LEVEL", "debug"),
+            ("PGRST_SERVER_CORS_ORIGINS", "https://example.com, https://app.example.com"),
+            ("PGRST_DB_URI", "postgres://elsewhere/db"),
+        ]
+        .into_iter()
+        .collect();
+        let new = AppConfig::from_vars(|key| vars.get(key).map(|v| v.to_string()));
+        assert_eq!(new.db_max_rows, Some(100));
+        assert_eq!(new.log_level, LogLevel::Debug);
+        assert_eq!(new.db_uri, "postgres://elsewhere/db");
+
+        // The connection is not changed while running
+        let current = AppConfig::default();
+        let reloaded = current.with_runtime_settings(&new);
+        assert_eq!(reloaded.db_max_rows, Some(100));
+        assert_eq!(reloaded.log_level, LogLevel::Debug);
+        assert!(reloaded.allows_origin("https://app.example.com"));
+        assert!(!reloaded.allows_origin("https://evil.example.com"));
+        assert_eq!(reloaded.db_uri, current.db_uri);
+    }
+
+    #[test]
+    fn test_parse_sqlstate_status() {
+        let mapping = parse_sqlstate_status("PT402:402, 23:422,bad,P0002:abc,P0003:999");
+        assert_eq!(mapping.len(), 2);
+        assert_eq!(mapping["PT402"], 402);
+        assert_eq!(mapping["23"], 422);
+    }
+
     #[test]
     fn test_isolation_level_sql() {
         assert_eq!(IsolationLevel::ReadCommitted.to_sql(), "READ COMMITTED");
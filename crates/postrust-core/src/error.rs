@@ -3,6 +3,7 @@
 //! Provides comprehensive error handling with HTTP status code mapping.
 
 use http::StatusCode;
+use std::collections::HashMap;
 use thiserror::Error;
 
 /// Result type for Postrust operations.
@@ -26,11 +27,21 @@ pub enum Error {
     #[error("Invalid request body: {0}")]
     InvalidBody(String),
 
+    #[error("Invalid JSON body: {message}")]
+    InvalidJson {
+        message: String,
+        line: usize,
+        column: usize,
+    },
+
     #[error("Unsupported HTTP method: {0}")]
     UnsupportedMethod(String),
 
-    #[error("Unacceptable schema: {0}")]
-    UnacceptableSchema(String),
+    #[error("Unacceptable schema: {schema}")]
+    UnacceptableSchema {
+        schema: String,
+        acceptable: Vec<String>,
+    },
 
     #[error("Unknown column: {0}")]
     UnknownColumn(String),
@@ -47,6 +58,32 @@ pub enum Error {
     #[error("Ambiguous request: {0}")]
     AmbiguousRequest(String),
 
+    #[error("More than one relationship was found for '{from}' and '{to}'")]
+    AmbiguousEmbedding {
+        from: String,
+        to: String,
+        /// Embed that selects each relationship, with its description
+        candidates: Vec<(String, String)>,
+    },
+
+    #[error("Column is not updatable: {0}")]
+    ColumnNotUpdatable(String),
+
+    #[error("Column is not insertable: {0}")]
+    ColumnNotInsertable(String),
+
+    #[error("Request too complex: {0}")]
+    RequestTooComplex(String),
+
+    #[error("Idempotency key reused with a different request: {0}")]
+    IdempotencyKeyReused(String),
+
+    #[error("A request with this Idempotency-Key is still in progress: {0}")]
+    IdempotencyKeyInFlight(String),
+
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(String),
+
     // ========================================================================
     // Authentication/Authorization Errors (401/403)
     // ========================================================================
@@ -68,11 +105,19 @@ pub enum Error {
     #[error("Resource not found: {0}")]
     NotFound(String),
 
-    #[error("Table not found: {0}")]
-    TableNotFound(String),
+    #[error("Table not found: {name}")]
+    TableNotFound {
+        name: String,
+        suggestion: Option<String>,
+        schemas: Vec<String>,
+    },
 
-    #[error("Function not found: {0}")]
-    FunctionNotFound(String),
+    #[error("Function not found: {name}")]
+    FunctionNotFound {
+        name: String,
+        suggestion: Option<String>,
+        schemas: Vec<String>,
+    },
 
     #[error("Column not found: {0}")]
     ColumnNotFound(String),
@@ -98,6 +143,9 @@ pub enum Error {
     #[error("Connection pool error: {0}")]
     ConnectionPool(String),
 
+    #[error("Timed out waiting for a database connection")]
+    PoolExhausted,
+
     // ========================================================================
     // Internal Errors (500)
     // ========================================================================
@@ -126,14 +174,30 @@ impl Error {
             | Self::InvalidQueryParam(_)
             | Self::InvalidHeader(_)
             | Self::InvalidBody(_)
+            | Self::InvalidJson { .. }
             | Self::InvalidRange(_)
             | Self::InvalidMediaType(_)
             | Self::MissingParameter(_)
             | Self::AmbiguousRequest(_)
             | Self::UnknownColumn(_)
+            | Self::ColumnNotUpdatable(_)
+            | Self::ColumnNotInsertable(_)
+            | Self::RequestTooComplex(_)
             | Self::InvalidPlan(_)
             | Self::EmbeddingError(_) => StatusCode::BAD_REQUEST,
 
+            // 300 Multiple Choices
+            Self::AmbiguousEmbedding { .. } => StatusCode::MULTIPLE_CHOICES,
+
+            // 412 Precondition Failed
+            Self::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+
+            // 422 Unprocessable Entity
+            Self::IdempotencyKeyReused(_) => StatusCode::UNPROCESSABLE_ENTITY,
+
+            // 409 Conflict
+            Self::IdempotencyKeyInFlight(_) => StatusCode::CONFLICT,
+
             // 401 Unauthorized
             Self::InvalidJwt(_) | Self::JwtExpired | Self::MissingAuth => StatusCode::UNAUTHORIZED,
 
@@ -142,8 +206,8 @@ impl Error {
 
             // 404 Not Found
             Self::NotFound(_)
-            | Self::TableNotFound(_)
-            | Self::FunctionNotFound(_)
+            | Self::TableNotFound { .. }
+            | Self::FunctionNotFound { .. }
             | Self::ColumnNotFound(_)
             | Self::RelationshipNotFound(_) => StatusCode::NOT_FOUND,
 
@@ -151,7 +215,7 @@ impl Error {
             Self::UnsupportedMethod(_) => StatusCode::METHOD_NOT_ALLOWED,
 
             // 406 Not Acceptable
-            Self::UnacceptableSchema(_) => StatusCode::NOT_ACCEPTABLE,
+            Self::UnacceptableSchema { .. } => StatusCode::NOT_ACCEPTABLE,
 
             // 500 Internal Server Error
             Self::SchemaCacheNotLoaded
@@ -160,6 +224,9 @@ impl Error {
             | Self::Internal(_)
             | Self::Config(_) => StatusCode::INTERNAL_SERVER_ERROR,
 
+            // 503 Service Unavailable
+            Self::PoolExhausted => StatusCode::SERVICE_UNAVAILABLE,
+
             // Database errors map based on type
             Self::Database(db_err) => db_err.status_code(),
         }
@@ -173,12 +240,20 @@ impl Error {
             Self::InvalidHeader(_) => "PGRST102",
             Self::InvalidBody(_) => "PGRST103",
             Self::UnsupportedMethod(_) => "PGRST104",
-            Self::UnacceptableSchema(_) => "PGRST105",
+            Self::UnacceptableSchema { .. } => "PGRST105",
             Self::UnknownColumn(_) => "PGRST106",
             Self::InvalidRange(_) => "PGRST107",
             Self::InvalidMediaType(_) => "PGRST108",
             Self::MissingParameter(_) => "PGRST109",
             Self::AmbiguousRequest(_) => "PGRST110",
+            Self::ColumnNotUpdatable(_) => "PGRST111",
+            Self::IdempotencyKeyReused(_) => "PGRST112",
+            Self::PreconditionFailed(_) => "PGRST113",
+            Self::InvalidJson { .. } => "PGRST114",
+            Self::ColumnNotInsertable(_) => "PGRST115",
+            Self::RequestTooComplex(_) => "PGRST116",
+            Self::AmbiguousEmbedding { .. } => "PGRST117",
+            Self::IdempotencyKeyInFlight(_) => "PGRST118",
 
             Self::InvalidJwt(_) => "PGRST200",
             Self::JwtExpired => "PGRST201",
@@ -186,8 +261,8 @@ impl Error {
             Self::InsufficientPermissions(_) => "PGRST203",
 
             Self::NotFound(_) => "PGRST300",
-            Self::TableNotFound(_) => "PGRST301",
-            Self::FunctionNotFound(_) => "PGRST302",
+            Self::TableNotFound { .. } => "PGRST301",
+            Self::FunctionNotFound { .. } => "PGRST302",
             Self::ColumnNotFound(_) => "PGRST303",
             Self::RelationshipNotFound(_) => "PGRST304",
 
@@ -196,6 +271,7 @@ impl Error {
 
             Self::Database(e) => e.code(),
             Self::ConnectionPool(_) => "PGRST500",
+            Self::PoolExhausted => "PGRST501",
 
             Self::Internal(_) => "PGRST900",
             Self::Config(_) => "PGRST901",
@@ -215,9 +291,76 @@ impl Error {
         })
     }
 
+    /// The HTTP status, with database errors first looked up in the
+    /// configured SQLSTATE mapping (`db_sqlstate_status`).
+    pub fn status_code_with(&self, overrides: &HashMap<String, u16>) -> StatusCode {
+        match self {
+            Self::Database(db_err) => db_err.configured_status(overrides).unwrap_or_else(|| self.status_code()),
+            _ => self.status_code(),
+        }
+    }
+
+    /// Seconds a client should wait before retrying, for temporary failures.
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            Self::PoolExhausted => Some(POOL_RETRY_AFTER_SECS),
+            _ => None,
+        }
+    }
+
+    /// Create a `TableNotFound` error without a suggestion.
+    pub fn table_not_found(name: impl Into<String>) -> Self {
+        Self::TableNotFound {
+            name: name.into(),
+            suggestion: None,
+            schemas: vec![],
+        }
+    }
+
+    /// Create an `InvalidJson` error from a body that failed to parse.
+    pub fn invalid_json(error: &serde_json::Error) -> Self {
+        Self::InvalidJson {
+            message: error.to_string(),
+            line: error.line(),
+            column: error.column(),
+        }
+    }
+
+    /// Create a `FunctionNotFound` error without a suggestion.
+    pub fn function_not_found(name: impl Into<String>) -> Self {
+        Self::FunctionNotFound {
+            name: name.into(),
+            suggestion: None,
+            schemas: vec![],
+        }
+    }
+
     /// Get additional details for the error.
     fn details(&self) -> Option<String> {
         match self {
+            Self::TableNotFound { name, schemas, .. }
+            | Self::FunctionNotFound { name, schemas, .. }
+                if !schemas.is_empty() =>
+            {
+                Some(format!(
+                    "Searched for '{}' in the exposed schemas: {}",
+                    name,
+                    schemas.join(", ")
+                ))
+            }
+            Self::InvalidJson { line, column, .. } => {
+                Some(format!("Parse error at line {}, column {}", line, column))
+            }
+            Self::UnacceptableSchema { acceptable, .. } => {
+                Some(format!("Acceptable profiles: {}", acceptable.join(", ")))
+            }
+            Self::AmbiguousEmbedding { candidates, .. } => Some(
+                candidates
+                    .iter()
+                    .map(|(embed, description)| format!("{}: {}", embed, description))
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            ),
             Self::Database(db_err) => db_err.details.clone(),
             _ => None,
         }
@@ -228,14 +371,34 @@ impl Error {
         match self {
             Self::InvalidJwt(_) => Some("Check that the JWT is properly signed and not expired".into()),
             Self::MissingAuth => Some("Provide a valid JWT in the Authorization header".into()),
-            Self::TableNotFound(_) => Some("Check the table name and schema".into()),
+            Self::TableNotFound { suggestion: Some(s), .. } => {
+                Some(format!("Perhaps you meant the table '{}'", s))
+            }
+            Self::TableNotFound { .. } => Some("Check the table name and schema".into()),
+            Self::FunctionNotFound { suggestion: Some(s), .. } => {
+                Some(format!("Perhaps you meant the function '{}'", s))
+            }
             Self::UnknownColumn(_) => Some("Check column names against the table schema".into()),
+            Self::ColumnNotUpdatable(_) => Some("Only columns that map directly to a base table column can be written through a view".into()),
+            Self::ColumnNotInsertable(_) => Some("Generated columns are filled in by the database; leave them out of columns".into()),
+            Self::RequestTooComplex(_) => Some("Split the request into smaller ones".into()),
+            Self::AmbiguousEmbedding { to, candidates, .. } => {
+                let embeds: Vec<String> = candidates.iter().map(|(embed, _)| format!("'{}'", embed)).collect();
+                Some(format!("Try changing '{}' to one of: {}", to, embeds.join(", ")))
+            }
+            Self::IdempotencyKeyReused(_) => Some("Use a new Idempotency-Key for a different request".into()),
+            Self::IdempotencyKeyInFlight(_) => Some("Retry once the first request with this key has completed".into()),
+            Self::PreconditionFailed(_) => Some("The row changed since it was read; fetch it again and retry".into()),
+            Self::PoolExhausted => Some("The server is busy; retry after the delay in the Retry-After header".into()),
             Self::Database(db_err) => db_err.hint.clone(),
             _ => None,
         }
     }
 }
 
+/// Backoff suggested to clients when no pooled connection is available.
+pub const POOL_RETRY_AFTER_SECS: u64 = 1;
+
 /// Database-specific error type.
 #[derive(Error, Debug)]
 #[error("Database error [{code}]: {message}")]
@@ -273,6 +436,18 @@ impl DatabaseError {
         }
     }
 
+    /// HTTP status configured for this error's SQLSTATE, if any.
+    ///
+    /// `overrides` maps codes or code prefixes to statuses; the longest
+    /// matching prefix wins, so an exact code beats its class.
+    pub fn configured_status(&self, overrides: &HashMap<String, u16>) -> Option<StatusCode> {
+        overrides
+            .iter()
+            .filter(|(prefix, _)| self.code.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .and_then(|(_, status)| StatusCode::from_u16(*status).ok())
+    }
+
     /// Get error code for API response.
     pub fn code(&self) -> &'static str {
         match self.code.as_str() {
@@ -296,7 +471,7 @@ mod tests {
         );
         assert_eq!(Error::MissingAuth.status_code(), StatusCode::UNAUTHORIZED);
         assert_eq!(
-            Error::TableNotFound("users".into()).status_code(),
+            Error::table_not_found("users").status_code(),
             StatusCode::NOT_FOUND
         );
         assert_eq!(
@@ -309,7 +484,16 @@ mod tests {
     fn test_error_codes() {
         assert_eq!(Error::InvalidQueryParam("test".into()).code(), "PGRST101");
         assert_eq!(Error::MissingAuth.code(), "PGRST202");
-        assert_eq!(Error::TableNotFound("users".into()).code(), "PGRST301");
+        assert_eq!(Error::table_not_found("users").code(), "PGRST301");
+    }
+
+    #[test]
+    fn test_pool_exhausted_is_retryable() {
+        let error = Error::PoolExhausted;
+        assert_eq!(error.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error.code(), "PGRST501");
+        assert_eq!(error.retry_after(), Some(POOL_RETRY_AFTER_SECS));
+        assert_eq!(Error::ConnectionPool("closed".into()).retry_after(), None);
     }
 
     #[test]
@@ -326,6 +510,44 @@ mod tests {
         assert_eq!(constraint_error.status_code(), StatusCode::CONFLICT);
     }
 
+    #[test]
+    fn test_configured_sqlstate_status_overrides_default() {
+        let error = |code: &str| {
+            Error::Database(DatabaseError {
+                code: code.into(),
+                message: "failed".into(),
+                details: None,
+                hint: None,
+                constraint: None,
+                table: None,
+                column: None,
+            })
+        };
+        let overrides: HashMap<String, u16> =
+            [("23".to_string(), 422), ("23505".to_string(), 409), ("PT402".to_string(), 402)].into();
+
+        assert_eq!(error("23503").status_code_with(&overrides), StatusCode::UNPROCESSABLE_ENTITY);
+        // The longest matching prefix wins
+        assert_eq!(error("23505").status_code_with(&overrides), StatusCode::CONFLICT);
+        assert_eq!(error("PT402").status_code_with(&overrides), StatusCode::PAYMENT_REQUIRED);
+        // Unmapped codes keep their default
+        assert_eq!(error("PT402").status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(error("42501").status_code_with(&overrides), StatusCode::BAD_REQUEST);
+        assert_eq!(Error::MissingAuth.status_code_with(&overrides), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_invalid_json_reports_position() {
+        let parse_error = serde_json::from_str::<serde_json::Value>("{\"id\": 1,\n \"name\":").unwrap_err();
+        let error = Error::invalid_json(&parse_error);
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(error.code(), "PGRST114");
+
+        let json = error.to_json();
+        assert!(json["message"].as_str().unwrap().contains("EOF"), "{}", json);
+        assert_eq!(json["details"], "Parse error at line 2, column 8");
+    }
+
     #[test]
     fn test_error_to_json() {
         let error = Error::InvalidQueryParam("bad filter".into());
@@ -31,6 +31,7 @@
 pub mod api_request;
 pub mod config;
 pub mod error;
+pub mod openapi;
 pub mod plan;
 pub mod query;
 pub mod schema_cache;
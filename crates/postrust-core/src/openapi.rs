@@ -0,0 +1,281 @@
+//! OpenAPI description of exposed tables.
+//!
+//! Column schemas carry the constraints the database enforces where they map
+//! onto JSON Schema: `maxLength` from `varchar(n)` or a `length(col) <= n`
+//! check, `enum` from enum types or a `col = ANY (ARRAY[...])` check, and
+//! `pattern` from a `col ~ '...'` check.
+
+use crate::schema_cache::{Column, SchemaCache, Table};
+use serde_json::{json, Map, Value};
+
+/// Build the OpenAPI document for the tables of one schema.
+pub fn openapi_spec(schema_cache: &SchemaCache, schema: &str) -> Value {
+    let mut tables: Vec<&Table> = schema_cache
+        .tables
+        .values()
+        .filter(|t| t.schema == schema)
+        .collect();
+    tables.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut paths = Map::new();
+    let mut schemas = Map::new();
+    for table in tables {
+        paths.insert(format!("/{}", table.name), json!({}));
+        schemas.insert(table.name.clone(), table_schema(table));
+    }
+
+    json!({
+        "openapi": "3.0.0",
+        "info": {
+            "title": "postrust",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "PostgREST-compatible REST API for PostgreSQL"
+        },
+        "paths": paths,
+        "components": { "schemas": schemas }
+    })
+}
+
+/// Build the object schema for a table.
+pub fn table_schema(table: &Table) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for column in table.columns.values() {
+        if !column.nullable && !column.has_default() {
+            required.push(column.name.clone());
+        }
+        properties.insert(column.name.clone(), column_schema(column));
+    }
+
+    let mut schema = json!({ "type": "object", "properties": properties });
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+    if let Some(description) = &table.description {
+        schema["description"] = json!(description);
+    }
+    schema
+}
+
+/// Build the schema for a single column.
+pub fn column_schema(column: &Column) -> Value {
+    let mut schema = match column.data_type.as_str() {
+        "smallint" | "integer" => json!({ "type": "integer", "format": "int32" }),
+        "bigint" => json!({ "type": "integer", "format": "int64" }),
+        "numeric" | "real" | "double precision" => json!({ "type": "number" }),
+        "boolean" => json!({ "type": "boolean" }),
+        "json" | "jsonb" => json!({}),
+        "ARRAY" => json!({ "type": "array", "items": {} }),
+        _ => json!({ "type": "string" }),
+    };
+    if let Some(domain) = &column.domain {
+        schema["format"] = json!(domain.name);
+    } else if schema.get("format").is_none() {
+        schema["format"] = json!(column.data_type);
+    }
+
+    if let Some(max_len) = column.max_len.or_else(|| check_max_length(column)) {
+        schema["maxLength"] = json!(max_len);
+    }
+
+    let enum_values = if column.enum_values.is_empty() {
+        check_enum(column)
+    } else {
+        Some(column.enum_values.clone())
+    };
+    if let Some(values) = enum_values {
+        schema["enum"] = json!(values);
+    }
+
+    if let Some(pattern) = check_pattern(column) {
+        schema["pattern"] = json!(pattern);
+    }
+
+    if let Some(description) = &column.description {
+        schema["description"] = json!(description);
+    }
+    schema
+}
+
+/// `CHECK ((char_length(col) <= n))` or `CHECK ((length(col) < n))`.
+///
+/// Every such term of every check counts, and the tightest bound wins.
+fn check_max_length(column: &Column) -> Option<i32> {
+    column
+        .checks
+        .iter()
+        .flat_map(|check| length_bounds(check, &column.name))
+        .min()
+        .filter(|n| *n > 0)
+}
+
+/// Upper bounds `check` places on `char_length(column)` or `length(column)`.
+fn length_bounds(check: &str, column: &str) -> Vec<i32> {
+    let mut bounds = Vec::new();
+    let mut from = 0;
+    while let Some(pos) = check[from..].find("length(").map(|p| from + p) {
+        from = pos + "length(".len();
+        // `char_length(` or a bare `length(`, not `octet_length(` or `array_length(`
+        let name_start = check[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        if !matches!(&check[name_start..from], "length(" | "char_length(") {
+            continue;
+        }
+        let args = &check[from..];
+        let mut depth = 1;
+        let Some(close) = args.find(|c| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        }) else {
+            break;
+        };
+        if !is_column_ref(&args[..close], column) {
+            continue;
+        }
+        let rest = args[close + 1..].trim_start();
+        let (strict, bound) = match rest.strip_prefix("<=") {
+            Some(bound) => (false, bound),
+            None => match rest.strip_prefix('<') {
+                Some(bound) => (true, bound),
+                None => continue,
+            },
+        };
+        let digits: String = bound.trim_start().chars().take_while(|c| c.is_ascii_digit()).collect();
+        if let Ok(n) = digits.parse::<i32>() {
+            bounds.push(if strict { n - 1 } else { n });
+        }
+    }
+    bounds
+}
+
+/// Whether `arg` is `column` itself, as Postgres renders it: possibly quoted,
+/// parenthesized or cast, e.g. `(name)::text`.
+fn is_column_ref(arg: &str, column: &str) -> bool {
+    let arg = arg.split("::").next().unwrap_or(arg).trim();
+    let arg = arg.trim_start_matches('(').trim_end_matches(')').trim();
+    arg == column || arg.strip_prefix('"').and_then(|a| a.strip_suffix('"')) == Some(column)
+}
+
+/// `CHECK ((col = ANY (ARRAY['a'::text, 'b'::text])))`, as Postgres renders `col IN (...)`.
+fn check_enum(column: &Column) -> Option<Vec<String>> {
+    column.checks.iter().find_map(|check| {
+        if !check.contains("= ANY") {
+            return None;
+        }
+        let start = check.find("ARRAY[")?;
+        let end = start + check[start..].find(']')?;
+        let values = quoted_literals(&check[start..end]);
+        (!values.is_empty()).then_some(values)
+    })
+}
+
+/// `CHECK ((col ~ '^[a-z]+$'::text))`.
+fn check_pattern(column: &Column) -> Option<String> {
+    column.checks.iter().find_map(|check| {
+        let rest = &check[check.find(" ~ '")? + 3..];
+        quoted_literals(rest).into_iter().next()
+    })
+}
+
+/// Extract the single-quoted SQL literals from an expression.
+fn quoted_literals(s: &str) -> Vec<String> {
+    let mut literals = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\'' {
+            continue;
+        }
+        let mut literal = String::new();
+        while let Some(c) = chars.next() {
+            if c == '\'' {
+                if chars.peek() == Some(&'\'') {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            literal.push(c);
+        }
+        literals.push(literal);
+    }
+    literals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, data_type: &str) -> Column {
+        Column {
+            name: name.into(),
+            nullable: true,
+            data_type: data_type.into(),
+            nominal_type: data_type.into(),
+            position: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_varchar_yields_max_length() {
+        let mut code = column("code", "character varying");
+        code.max_len = Some(10);
+
+        let schema = column_schema(&code);
+        assert_eq!(schema["type"], "string");
+        assert_eq!(schema["maxLength"], 10);
+    }
+
+    #[test]
+    fn test_check_in_list_yields_enum() {
+        let mut status = column("status", "text");
+        status.checks =
+            vec!["CHECK ((status = ANY (ARRAY['draft'::text, 'it''s live'::text])))".into()];
+
+        let schema = column_schema(&status);
+        assert_eq!(schema["enum"], json!(["draft", "it's live"]));
+    }
+
+    #[test]
+    fn test_length_and_pattern_checks() {
+        let mut slug = column("slug", "text");
+        slug.checks = vec![
+            "CHECK ((char_length(slug) < 33))".into(),
+            "CHECK ((slug ~ '^[a-z-]+$'::text))".into(),
+        ];
+
+        let schema = column_schema(&slug);
+        assert_eq!(schema["maxLength"], 32);
+        assert_eq!(schema["pattern"], "^[a-z-]+$");
+        assert!(schema.get("enum").is_none());
+    }
+
+    #[test]
+    fn test_max_length_from_every_length_term() {
+        let max_length = |checks: &[&str]| {
+            let mut name = column("name", "character varying");
+            name.checks = checks.iter().map(|c| c.to_string()).collect();
+            column_schema(&name).get("maxLength").cloned()
+        };
+
+        assert_eq!(
+            max_length(&["CHECK (((length((name)::text) > 3) AND (length((name)::text) <= 10)))"]),
+            Some(json!(10))
+        );
+        assert_eq!(
+            max_length(&["CHECK ((char_length(name) <= 20))", "CHECK ((length(name) < 12))"]),
+            Some(json!(11))
+        );
+
+        // Byte lengths, array lengths, other columns and empty bounds don't count
+        assert_eq!(max_length(&["CHECK ((octet_length(name) <= 10))"]), None);
+        assert_eq!(max_length(&["CHECK ((array_length(name, 1) <= 3))"]), None);
+        assert_eq!(max_length(&["CHECK ((length(other) <= 10))"]), None);
+        assert_eq!(max_length(&["CHECK ((length(name) < 0))"]), None);
+    }
+}
@@ -18,6 +18,9 @@ pub struct CallPlan {
     pub returns_set: bool,
     /// Function volatility (for transaction handling)
     pub volatility: String,
+    /// Casts for named parameters of array types, bound as array literals
+    #[serde(default)]
+    pub param_casts: Vec<(String, String)>,
 }
 
 /// How parameters are passed to the function.
@@ -39,16 +42,28 @@ impl CallPlan {
         let qi = routine.qualified_identifier();
 
         let params = extract_call_params(request, routine)?;
+        check_required_params(&params, routine)?;
 
         let returns_scalar = !routine.return_type.is_set_returning()
             && routine.return_type.type_name().map(|t| !t.contains("record")).unwrap_or(true);
 
+        let param_casts = match &params {
+            CallParams::Named(params) => params
+                .iter()
+                .filter_map(|(name, _)| routine.find_param(name))
+                .filter(|p| p.is_array())
+                .map(|p| (p.name.clone(), p.param_type.clone()))
+                .collect(),
+            _ => vec![],
+        };
+
         Ok(Self {
             function: qi,
             params,
             returns_scalar,
             returns_set: routine.return_type.is_set_returning(),
             volatility: format!("{:?}", routine.volatility),
+            param_casts,
         })
     }
 
@@ -59,7 +74,7 @@ impl CallPlan {
 }
 
 /// Extract call parameters from request.
-fn extract_call_params(request: &ApiRequest, _routine: &Routine) -> Result<CallParams> {
+fn extract_call_params(request: &ApiRequest, routine: &Routine) -> Result<CallParams> {
     // Check for JSON body first
     if let Some(payload) = &request.payload {
         match payload {
@@ -78,6 +93,11 @@ fn extract_call_params(request: &ApiRequest, _routine: &Routine) -> Result<CallP
                                 let value = match v {
                                     serde_json::Value::String(s) => s,
                                     serde_json::Value::Null => String::new(),
+                                    serde_json::Value::Array(items)
+                                        if routine.find_param(&k).is_some_and(|p| p.is_array()) =>
+                                    {
+                                        pg_array_literal(&items)
+                                    }
                                     other => other.to_string(),
                                 };
                                 (k, value)
@@ -105,19 +125,80 @@ fn extract_call_params(request: &ApiRequest, _routine: &Routine) -> Result<CallP
         }
     }
 
-    // Fall back to query parameters
-    if !request.query_params.params.is_empty() {
-        return Ok(CallParams::Named(request.query_params.params.clone()));
+    // Fall back to query parameters that name a function argument; the rest
+    // filter the result
+    let params = query_arguments(request, routine);
+    if !params.is_empty() {
+        return Ok(CallParams::Named(params));
     }
 
     // No parameters
     Ok(CallParams::None)
 }
 
+/// Format a JSON array as a PostgreSQL array literal, e.g. `{"1","2"}`.
+///
+/// Elements are quoted so their text is taken as is; nested arrays become
+/// dimensions and JSON nulls become NULL.
+fn pg_array_literal(items: &[serde_json::Value]) -> String {
+    let elements: Vec<String> = items
+        .iter()
+        .map(|item| match item {
+            serde_json::Value::Null => "NULL".to_string(),
+            serde_json::Value::Array(inner) => pg_array_literal(inner),
+            serde_json::Value::String(s) => quote_array_element(s),
+            other => quote_array_element(&other.to_string()),
+        })
+        .collect();
+    format!("{{{}}}", elements.join(","))
+}
+
+/// Double-quote an array element, escaping quotes and backslashes.
+fn quote_array_element(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Ensure every parameter without a default is supplied.
+///
+/// An empty body or query string calls the function with all defaults.
+fn check_required_params(params: &CallParams, routine: &Routine) -> Result<()> {
+    let supplied: Vec<&str> = match params {
+        CallParams::Named(params) => params.iter().map(|(name, _)| name.as_str()).collect(),
+        CallParams::None => vec![],
+        // Positional and single-argument calls are matched by PostgreSQL
+        CallParams::Positional(_) | CallParams::SingleObject(_) => return Ok(()),
+    };
+    match routine
+        .params
+        .iter()
+        .find(|p| p.required && !supplied.contains(&p.name.as_str()))
+    {
+        Some(missing) => Err(Error::MissingParameter(format!(
+            "{} (required by {})",
+            missing.name, routine.name
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Query string parameters passed to the function as arguments.
+pub fn query_arguments(request: &ApiRequest, routine: &Routine) -> Vec<(String, String)> {
+    if request.payload.is_some() {
+        return vec![];
+    }
+    request
+        .query_params
+        .params
+        .iter()
+        .filter(|(name, _)| routine.find_param(name).is_some())
+        .cloned()
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::schema_cache::{FuncVolatility, RetType};
+    use crate::schema_cache::{FuncVolatility, RetType, RoutineParam};
 
     fn make_routine() -> Routine {
         Routine {
@@ -154,4 +235,84 @@ mod tests {
         let plan = CallPlan::from_request(&request, &routine).unwrap();
         assert!(!plan.has_params());
     }
+
+    fn param(name: &str, required: bool) -> RoutineParam {
+        RoutineParam {
+            name: name.into(),
+            param_type: "integer".into(),
+            type_max_length: "integer".into(),
+            required,
+            variadic: false,
+        }
+    }
+
+    #[test]
+    fn test_empty_body_uses_all_defaults() {
+        let routine = Routine {
+            params: vec![param("lim", false), param("off", false)],
+            ..make_routine()
+        };
+
+        // A POST with an empty body has no payload
+        let plan = CallPlan::from_request(&ApiRequest::default(), &routine).unwrap();
+        assert!(!plan.has_params());
+
+        let sql = crate::query::QueryBuilder::build_call(&plan).unwrap();
+        assert_eq!(sql.sql(), "SELECT * FROM \"public\".\"get_users\"()");
+    }
+
+    #[test]
+    fn test_array_param_binds_array_literal() {
+        let routine = Routine {
+            params: vec![
+                RoutineParam { param_type: "integer[]".into(), ..param("ids", true) },
+                RoutineParam { param_type: "text[][]".into(), ..param("tags", false) },
+                param("lim", false),
+            ],
+            ..make_routine()
+        };
+        let request = ApiRequest {
+            payload: Some(Payload::ProcessedJson {
+                raw: bytes::Bytes::from(r#"{"ids": [1, 2, null], "tags": [["a \"b\"", "c"]], "lim": 5}"#),
+                keys: ["ids", "tags", "lim"].iter().map(|k| k.to_string()).collect(),
+            }),
+            ..Default::default()
+        };
+
+        let plan = CallPlan::from_request(&request, &routine).unwrap();
+        let (sql, params) = crate::query::QueryBuilder::build_call(&plan).unwrap().build();
+        assert_eq!(
+            sql,
+            "SELECT * FROM \"public\".\"get_users\"(\"ids\" => $1::integer[], \"lim\" => $2, \"tags\" => $3::text[][])"
+        );
+        assert_eq!(
+            params,
+            vec![
+                postrust_sql::SqlParam::Text(r#"{"1","2",NULL}"#.into()),
+                postrust_sql::SqlParam::Text("5".into()),
+                postrust_sql::SqlParam::Text(r#"{{"a \"b\"","c"}}"#.into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_required_param() {
+        let routine = Routine {
+            params: vec![param("id", true), param("lim", false)],
+            ..make_routine()
+        };
+
+        let err = CallPlan::from_request(&ApiRequest::default(), &routine).unwrap_err();
+        assert!(matches!(err, Error::MissingParameter(ref m) if m.starts_with("id")), "{:?}", err);
+
+        let request = ApiRequest {
+            payload: Some(Payload::ProcessedJson {
+                raw: bytes::Bytes::from(r#"{"lim": 5}"#),
+                keys: ["lim".to_string()].into_iter().collect(),
+            }),
+            ..Default::default()
+        };
+        let err = CallPlan::from_request(&request, &routine).unwrap_err();
+        assert!(matches!(err, Error::MissingParameter(_)), "{:?}", err);
+    }
 }
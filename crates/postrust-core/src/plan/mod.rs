@@ -9,16 +9,28 @@ mod call_plan;
 mod types;
 
 pub use read_plan::{ReadPlan, ReadPlanTree};
-pub use mutate_plan::MutatePlan;
+pub use mutate_plan::{MutatePlan, NestedInsert};
 pub use call_plan::{CallPlan, CallParams};
 pub use types::*;
 
 use crate::api_request::{
-    Action, ApiRequest, DbAction,
-    QualifiedIdentifier,
+    Action, ApiRequest, AppliedPreferences, DbAction, PreferRepresentation, Preferences,
+    QualifiedIdentifier, Range,
 };
-use crate::error::{Error, Result};
+use crate::error::Result;
 use crate::schema_cache::SchemaCache;
+use http::StatusCode;
+use serde_json::Value;
+
+/// Column an upsert returns to tell inserted rows (`xmax = 0`) from updated ones.
+pub const INSERTED_FLAG: &str = "pgrst_inserted";
+
+/// Column reads and representations return each row's ETag in: the `md5` of
+/// the row as text, which `If-Match` is compared against.
+pub const ETAG_COLUMN: &str = "pgrst_etag";
+
+/// CTE a mutation runs as when its returned rows are shaped by a read plan.
+pub const MUTATION_RESULT: &str = "pgrst_mutation_result";
 
 /// The execution plan for an API request.
 #[derive(Clone, Debug)]
@@ -83,19 +95,39 @@ fn create_db_plan(
     action: &DbAction,
     schema_cache: &SchemaCache,
 ) -> Result<DbActionPlan> {
+    // A negative offset counts back from the total of an ordered table read
+    if request.top_level_range.is_from_end() {
+        if !matches!(action, DbAction::RelationRead { .. }) {
+            return Err(crate::error::Error::InvalidQueryParam(
+                "a negative offset only applies to reads of tables and views".into(),
+            ));
+        }
+        if !request.query_params.order.iter().any(|(path, _)| path.is_empty()) {
+            return Err(crate::error::Error::InvalidQueryParam(
+                "a negative offset counts from the end of an order; add order=".into(),
+            ));
+        }
+    }
+
     match action {
         DbAction::RelationRead { qi, .. } => {
             let table = schema_cache.require_table(qi)?;
-            let read_plan = ReadPlan::from_request(request, table, schema_cache)?;
-            Ok(DbActionPlan::Read(ReadPlanTree::leaf(read_plan)))
+            let read_tree = ReadPlanTree::from_request(request, table, schema_cache)?;
+            Ok(DbActionPlan::Read(read_tree))
         }
 
         DbAction::RelationMut { qi, mutation } => {
             let table = schema_cache.require_table(qi)?;
-            let mutate_plan = MutatePlan::from_request(request, table, mutation)?;
+            let mutate_plan = MutatePlan::from_request(request, table, mutation, schema_cache)?;
 
             let read_plan = if request.preferences.representation.needs_body() {
-                let rp = ReadPlan::for_mutation(request, table, schema_cache)?;
+                // The representation may be resolved in the Accept-Profile schema
+                let read_table = match &request.representation_schema {
+                    Some(schema) => schema_cache
+                        .require_table(&QualifiedIdentifier::new(schema, &qi.name))?,
+                    None => table,
+                };
+                let rp = ReadPlan::for_mutation(request, read_table, schema_cache)?;
                 Some(ReadPlanTree::leaf(rp))
             } else {
                 None
@@ -108,19 +140,25 @@ fn create_db_plan(
         }
 
         DbAction::Routine { qi, invoke_method: _ } => {
-            let routines = schema_cache
-                .get_routines(qi)
-                .ok_or_else(|| Error::FunctionNotFound(qi.to_string()))?;
-
-            let routine = routines
-                .first()
-                .ok_or_else(|| Error::FunctionNotFound(qi.to_string()))?;
+            let routines = schema_cache.require_routines(qi)?;
+            let routine = &routines[0];
 
             let call_plan = CallPlan::from_request(request, routine)?;
 
+            // select, filters and order shape the result when the function
+            // returns a known table type
+            let arguments = call_plan::query_arguments(request, routine);
+            let read_plan = match routine.return_type_qi() {
+                Some(ret_qi) if shapes_call_result(request, &arguments) => schema_cache
+                    .get_table(&ret_qi)
+                    .map(|table| ReadPlanTree::for_call(request, table, &arguments, schema_cache))
+                    .transpose()?,
+                _ => None,
+            };
+
             Ok(DbActionPlan::Call {
                 call: call_plan,
-                read: None,
+                read: read_plan,
             })
         }
 
@@ -131,6 +169,165 @@ fn create_db_plan(
     }
 }
 
+/// Check whether the query string does more than pass function arguments.
+fn shapes_call_result(request: &ApiRequest, arguments: &[(String, String)]) -> bool {
+    let params = &request.query_params;
+    !params.select.is_empty()
+        || !params.order.is_empty()
+        || !params.logic.is_empty()
+        || request.top_level_range != Range::default()
+        || params
+            .filters_root
+            .iter()
+            .any(|f| !arguments.iter().any(|(name, _)| *name == f.field.name))
+}
+
+impl DbActionPlan {
+    /// Work out which of the requested preferences this plan honored.
+    ///
+    /// `counted` tells whether a total count was actually computed. The
+    /// transaction end and time zone are settled while executing, so they
+    /// are left for the caller to fill in.
+    pub fn applied_preferences(&self, prefs: &Preferences, counted: bool) -> AppliedPreferences {
+        let mut applied = AppliedPreferences {
+            count: prefs.count.clone().filter(|_| counted),
+            handling: prefs.handling.clone(),
+            ..Default::default()
+        };
+
+        if let Self::MutateRead { mutate, .. } = self {
+            if prefs.representation != PreferRepresentation::None {
+                applied.representation = Some(prefs.representation.clone());
+            }
+
+            match mutate {
+                MutatePlan::Insert { on_conflict, .. } => {
+                    if prefs.resolution.is_some() {
+                        applied.resolution = on_conflict.as_ref().map(|(res, _)| res.clone());
+                    }
+                    applied.missing = prefs.missing.clone();
+                }
+                MutatePlan::Update { bulk_key, .. } => {
+                    applied.missing = prefs.missing.clone();
+                    applied.bulk_update = !bulk_key.is_empty();
+                }
+                MutatePlan::Delete { .. } => {}
+            }
+        }
+
+        applied
+    }
+}
+
+impl DbActionPlan {
+    /// Check request preconditions against the number of affected rows.
+    ///
+    /// A conditional update (`If-Match`) that touched no rows means the ETag
+    /// no longer matched, which is a 412 rather than an empty success.
+    pub fn check_preconditions(&self, affected_rows: usize) -> Result<()> {
+        match self {
+            Self::MutateRead {
+                mutate: MutatePlan::Update { if_match: Some(etag), target, .. },
+                ..
+            } if affected_rows == 0 => Err(crate::error::Error::PreconditionFailed(format!(
+                "no row in '{}' matches the ETag \"{}\"",
+                target.name, etag
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Status and Location for the rows an insert returned.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InsertOutcome {
+    pub status: StatusCode,
+    pub location: Option<String>,
+}
+
+impl DbActionPlan {
+    /// Work out the response to an insert from the rows it returned.
+    ///
+    /// Upserts return [`INSERTED_FLAG`] per row, which is stripped here. The
+    /// status is 201 unless every returned row was an update, and only a
+    /// single inserted row gets a Location.
+    pub fn insert_outcome(&self, rows: &mut [Value]) -> Option<InsertOutcome> {
+        let Self::MutateRead {
+            mutate: MutatePlan::Insert { target, on_conflict, pk_cols, .. },
+            ..
+        } = self
+        else {
+            return None;
+        };
+
+        let inserted: Vec<bool> = rows
+            .iter_mut()
+            .map(|row| match row.as_object_mut().and_then(|obj| obj.remove(INSERTED_FLAG)) {
+                Some(Value::Bool(flag)) => flag,
+                _ => true,
+            })
+            .collect();
+
+        let all_updated = on_conflict.is_some() && !inserted.is_empty() && !inserted.contains(&true);
+        let location = match (&*rows, inserted.as_slice()) {
+            ([row], [true]) => pk_location(target, pk_cols, row),
+            _ => None,
+        };
+
+        Some(InsertOutcome {
+            status: if all_updated { StatusCode::OK } else { StatusCode::CREATED },
+            location,
+        })
+    }
+}
+
+/// Strip [`ETAG_COLUMN`] from every row, returning the ETag when there is
+/// exactly one.
+pub fn take_etag(rows: &mut [Value]) -> Option<String> {
+    let etags: Vec<Option<Value>> = rows
+        .iter_mut()
+        .map(|row| row.as_object_mut().and_then(|obj| obj.remove(ETAG_COLUMN)))
+        .collect();
+    match etags.as_slice() {
+        [Some(Value::String(etag))] => Some(etag.clone()),
+        _ => None,
+    }
+}
+
+/// Characters left unescaped in a Location filter: RFC 3986 unreserved.
+const LOCATION_ESCAPE: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// `/table?pk=eq.value`, relative to the API root, for a row that carries
+/// every primary key column.
+///
+/// Composite keys become one filter per column, in primary key order, with
+/// names and values percent-encoded.
+fn pk_location(target: &QualifiedIdentifier, pk_cols: &[String], row: &Value) -> Option<String> {
+    if pk_cols.is_empty() {
+        return None;
+    }
+    let filters = pk_cols
+        .iter()
+        .map(|col| {
+            let value = match row.get(col)? {
+                Value::Null => return None,
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            Some(format!(
+                "{}=eq.{}",
+                percent_encoding::utf8_percent_encode(col, LOCATION_ESCAPE),
+                percent_encoding::utf8_percent_encode(&value, LOCATION_ESCAPE)
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(format!("/{}?{}", target.name, filters.join("&")))
+}
+
 impl crate::api_request::PreferRepresentation {
     /// Check if response body is needed.
     pub fn needs_body(&self) -> bool {
@@ -155,4 +352,485 @@ mod tests {
             _ => panic!("Expected RelationInfo"),
         }
     }
+
+    #[test]
+    fn test_applied_preferences_count_only_when_computed() {
+        let plan = DbActionPlan::Read(ReadPlanTree::empty());
+        let prefs = Preferences {
+            count: Some(crate::api_request::PreferCount::Exact),
+            ..Default::default()
+        };
+
+        assert_eq!(plan.applied_preferences(&prefs, false), AppliedPreferences::default());
+        assert_eq!(
+            plan.applied_preferences(&prefs, true).count,
+            Some(crate::api_request::PreferCount::Exact)
+        );
+    }
+
+    #[test]
+    fn test_applied_preferences_for_upsert() {
+        use crate::api_request::PreferResolution;
+
+        let plan = DbActionPlan::MutateRead {
+            mutate: MutatePlan::Insert {
+                target: QualifiedIdentifier::new("public", "users"),
+                columns: vec![],
+                body: None,
+                on_conflict: Some((PreferResolution::IgnoreDuplicates, vec!["id".into()])),
+                where_clauses: vec![],
+                returning: vec![],
+                pk_cols: vec!["id".into()],
+                apply_defaults: true,
+                nested: vec![],
+                binary_body: false,
+                raw_json: false,
+            },
+            read: None,
+        };
+        let prefs = Preferences {
+            resolution: Some(PreferResolution::IgnoreDuplicates),
+            representation: PreferRepresentation::Full,
+            timezone: Some("UTC".into()),
+            ..Default::default()
+        };
+
+        let applied = plan.applied_preferences(&prefs, false);
+        assert_eq!(
+            crate::api_request::preferences::preference_applied(&applied).as_deref(),
+            Some("resolution=ignore-duplicates, return=representation")
+        );
+    }
+
+    #[test]
+    fn test_applied_preferences_report_handling() {
+        use crate::api_request::PreferHandling;
+
+        let plan = DbActionPlan::Read(ReadPlanTree::empty());
+        let prefs = Preferences {
+            handling: Some(PreferHandling::Strict),
+            ..Default::default()
+        };
+
+        let applied = plan.applied_preferences(&prefs, false);
+        assert_eq!(
+            crate::api_request::preferences::preference_applied(&applied).as_deref(),
+            Some("handling=strict")
+        );
+    }
+
+    fn upsert_plan() -> DbActionPlan {
+        DbActionPlan::MutateRead {
+            mutate: MutatePlan::Insert {
+                target: QualifiedIdentifier::new("public", "users"),
+                columns: vec![],
+                body: None,
+                on_conflict: Some((
+                    crate::api_request::PreferResolution::MergeDuplicates,
+                    vec!["id".into()],
+                )),
+                where_clauses: vec![],
+                returning: vec!["id".into()],
+                pk_cols: vec!["id".into()],
+                apply_defaults: true,
+                nested: vec![],
+                binary_body: false,
+                raw_json: false,
+            },
+            read: None,
+        }
+    }
+
+    #[test]
+    fn test_upsert_that_inserted_is_created() {
+        let mut rows = vec![serde_json::json!({"id": 7, "pgrst_inserted": true})];
+        let outcome = upsert_plan().insert_outcome(&mut rows).unwrap();
+
+        assert_eq!(outcome.status, StatusCode::CREATED);
+        assert_eq!(outcome.location.as_deref(), Some("/users?id=eq.7"));
+        assert_eq!(rows, vec![serde_json::json!({"id": 7})]);
+    }
+
+    #[test]
+    fn test_composite_key_location() {
+        let target = QualifiedIdentifier::new("public", "enrollments");
+        let pk_cols = vec!["student id".to_string(), "course".to_string()];
+        let row = serde_json::json!({"course": "math&art/101", "student id": 42, "grade": "A"});
+
+        assert_eq!(
+            pk_location(&target, &pk_cols, &row).as_deref(),
+            Some("/enrollments?student%20id=eq.42&course=eq.math%26art%2F101")
+        );
+
+        // Every key column is needed
+        let row = serde_json::json!({"student id": 42, "course": null});
+        assert_eq!(pk_location(&target, &pk_cols, &row), None);
+    }
+
+    #[test]
+    fn test_upsert_that_only_updated_is_ok() {
+        let mut rows = vec![
+            serde_json::json!({"id": 1, "pgrst_inserted": false}),
+            serde_json::json!({"id": 2, "pgrst_inserted": false}),
+        ];
+        let outcome = upsert_plan().insert_outcome(&mut rows).unwrap();
+
+        assert_eq!(outcome.status, StatusCode::OK);
+        assert_eq!(outcome.location, None);
+        assert!(rows.iter().all(|r| r.get("pgrst_inserted").is_none()));
+
+        let mut single = vec![serde_json::json!({"id": 1, "pgrst_inserted": false})];
+        assert_eq!(upsert_plan().insert_outcome(&mut single).unwrap().location, None);
+        assert!(DbActionPlan::Read(ReadPlanTree::empty()).insert_outcome(&mut []).is_none());
+    }
+
+    #[test]
+    fn test_mutation_representation_uses_accept_profile_schema() {
+        use crate::api_request::{DbAction, Mutation};
+        use crate::schema_cache::{Column, Table};
+        use indexmap::IndexMap;
+        use std::collections::HashMap;
+
+        let items = |schema: &str, cols: &[&str]| {
+            let mut columns = IndexMap::new();
+            for (i, name) in cols.iter().enumerate() {
+                columns.insert(
+                    name.to_string(),
+                    Column {
+                        name: name.to_string(),
+                        nullable: true,
+                        data_type: "text".into(),
+                        nominal_type: "text".into(),
+                        is_pk: *name == "id",
+                        position: i as i32 + 1,
+                        ..Default::default()
+                    },
+                );
+            }
+            Table {
+                schema: schema.into(),
+                name: "items".into(),
+                description: None,
+                is_view: false,
+                insertable: true,
+                updatable: true,
+                deletable: true,
+                pk_cols: vec!["id".into()],
+                columns,
+            }
+        };
+
+        let mut tables = HashMap::new();
+        tables.insert(QualifiedIdentifier::new("v1", "items"), items("v1", &["id", "name"]));
+        tables.insert(
+            QualifiedIdentifier::new("v2", "items"),
+            items("v2", &["id", "name", "label"]),
+        );
+        let cache = SchemaCache {
+            tables,
+            pg_version: 150000,
+            schemas: vec!["v1".into(), "v2".into()],
+            ..Default::default()
+        };
+
+        let request = ApiRequest {
+            action: Action::Db(DbAction::RelationMut {
+                qi: QualifiedIdentifier::new("v2", "items"),
+                mutation: Mutation::Delete,
+            }),
+            schema: "v2".into(),
+            representation_schema: Some("v1".into()),
+            preferences: Preferences {
+                representation: PreferRepresentation::Full,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let plan = create_action_plan(&request, &cache).unwrap();
+        match plan {
+            ActionPlan::Db(DbActionPlan::MutateRead { mutate, read: Some(read) }) => {
+                assert_eq!(mutate.target().schema, "v2");
+                assert_eq!(read.root.from.schema, "v1");
+                assert_eq!(read.root.select.len(), 2);
+            }
+            other => panic!("Expected mutation with read plan, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_filtered_delete_returns_selected_columns() {
+        use crate::api_request::{parse_query_params, DbAction, Mutation};
+
+        let request = ApiRequest {
+            action: Action::Db(DbAction::RelationMut {
+                qi: QualifiedIdentifier::new("public", "users"),
+                mutation: Mutation::Delete,
+            }),
+            schema: "public".into(),
+            query_params: parse_query_params("name=eq.Bob&select=id,email&order=id").unwrap(),
+            preferences: Preferences {
+                representation: PreferRepresentation::Full,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let plan = create_action_plan(&request, &get_users_cache()).unwrap();
+
+        let (sql, params) = crate::query::build_query(&plan, None).unwrap().build_main();
+        assert_eq!(
+            sql,
+            "WITH \"pgrst_mutation_result\" AS (DELETE FROM \"public\".\"users\" WHERE \"name\" = $1 \
+             RETURNING \"id\", \"name\", \"email\") \
+             SELECT \"id\", \"email\" FROM \"pgrst_mutation_result\" ORDER BY \"id\""
+        );
+        assert_eq!(params, vec![postrust_sql::SqlParam::Text("Bob".into())]);
+    }
+
+    #[test]
+    fn test_upsert_returns_selected_columns() {
+        use crate::api_request::{parse_query_params, DbAction, MediaType, Mutation, PreferResolution};
+
+        let body = bytes::Bytes::from_static(br#"[{"id": "7", "name": "Ada", "email": "ada@example.com"}]"#);
+        let request = ApiRequest {
+            action: Action::Db(DbAction::RelationMut {
+                qi: QualifiedIdentifier::new("public", "users"),
+                mutation: Mutation::Create,
+            }),
+            schema: "public".into(),
+            payload: crate::api_request::payload::parse_payload(body, &MediaType::ApplicationJson, None)
+                .unwrap(),
+            query_params: parse_query_params("select=name&name=neq.Bob").unwrap(),
+            preferences: Preferences {
+                representation: PreferRepresentation::Full,
+                resolution: Some(PreferResolution::MergeDuplicates),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let plan = create_action_plan(&request, &get_users_cache()).unwrap();
+
+        let (sql, _) = crate::query::build_query(&plan, None).unwrap().build_main();
+        assert!(sql.starts_with("WITH \"pgrst_mutation_result\" AS (INSERT INTO \"public\".\"users\""), "{}", sql);
+        assert!(
+            sql.ends_with(
+                "RETURNING \"id\", \"name\", \"email\", md5(CAST(\"users\" AS text)) AS \"pgrst_etag\", \
+                 xmax = 0 AS \"pgrst_inserted\") \
+                 SELECT \"name\", \"pgrst_inserted\", \"pgrst_etag\" FROM \"pgrst_mutation_result\" \
+                 WHERE \"name\" <> $2"
+            ),
+            "{}",
+            sql
+        );
+
+        // The flag sets the status, and it and the ETag are stripped from the rows
+        let ActionPlan::Db(db_plan) = plan else { panic!("Expected a database plan") };
+        let mut rows = vec![serde_json::json!({"name": "Ada", "pgrst_inserted": false, "pgrst_etag": "9e107d9d"})];
+        let outcome = db_plan.insert_outcome(&mut rows).unwrap();
+        assert_eq!(outcome.status, StatusCode::OK);
+        assert_eq!(take_etag(&mut rows).as_deref(), Some("9e107d9d"));
+        assert_eq!(rows, vec![serde_json::json!({"name": "Ada"})]);
+    }
+
+    #[test]
+    fn test_take_etag_only_for_a_single_row() {
+        let mut rows = vec![serde_json::json!({"id": 1, "pgrst_etag": "a"})];
+        assert_eq!(take_etag(&mut rows).as_deref(), Some("a"));
+        assert_eq!(rows, vec![serde_json::json!({"id": 1})]);
+
+        let mut rows = vec![
+            serde_json::json!({"id": 1, "pgrst_etag": "a"}),
+            serde_json::json!({"id": 2, "pgrst_etag": "b"}),
+        ];
+        assert_eq!(take_etag(&mut rows), None);
+        assert_eq!(rows, vec![serde_json::json!({"id": 1}), serde_json::json!({"id": 2})]);
+    }
+
+    #[test]
+    fn test_negative_offset_needs_an_ordered_read() {
+        use crate::api_request::{parse_query_params, DbAction, Mutation};
+
+        let read = |query: &str| ApiRequest {
+            action: Action::Db(DbAction::RelationRead {
+                qi: QualifiedIdentifier::new("public", "users"),
+                headers_only: false,
+            }),
+            schema: "public".into(),
+            query_params: parse_query_params(query).unwrap(),
+            top_level_range: Range::new(-10, None),
+            ..Default::default()
+        };
+        assert!(create_action_plan(&read("order=id"), &get_users_cache()).is_ok());
+
+        let err = create_action_plan(&read("select=id"), &get_users_cache()).unwrap_err();
+        assert!(matches!(err, crate::error::Error::InvalidQueryParam(_)), "{:?}", err);
+
+        let delete = ApiRequest {
+            action: Action::Db(DbAction::RelationMut {
+                qi: QualifiedIdentifier::new("public", "users"),
+                mutation: Mutation::Delete,
+            }),
+            ..read("order=id")
+        };
+        let err = create_action_plan(&delete, &get_users_cache()).unwrap_err();
+        assert!(matches!(err, crate::error::Error::InvalidQueryParam(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_stale_if_match_fails_precondition() {
+        let plan = DbActionPlan::MutateRead {
+            mutate: MutatePlan::Update {
+                target: QualifiedIdentifier::new("public", "users"),
+                columns: vec![],
+                body: None,
+                where_clauses: vec![],
+                returning: vec!["id".into()],
+                apply_defaults: false,
+                if_match: Some("abc123".into()),
+                bulk_key: vec![],
+            },
+            read: None,
+        };
+
+        // Matching version: the row was updated
+        assert!(plan.check_preconditions(1).is_ok());
+
+        // Stale version: nothing matched
+        let err = plan.check_preconditions(0).unwrap_err();
+        assert_eq!(err.status_code(), http::StatusCode::PRECONDITION_FAILED);
+    }
+
+    fn get_users_cache() -> SchemaCache {
+        use crate::schema_cache::{Column, FuncVolatility, RetType, Routine, RoutineParam, Table};
+        use indexmap::IndexMap;
+        use std::collections::HashMap;
+
+        let mut columns = IndexMap::new();
+        for (i, name) in ["id", "name", "email"].iter().enumerate() {
+            columns.insert(
+                name.to_string(),
+                Column {
+                    name: name.to_string(),
+                    nullable: true,
+                    data_type: "text".into(),
+                    nominal_type: "text".into(),
+                    is_pk: *name == "id",
+                    position: i as i32 + 1,
+                    ..Default::default()
+                },
+            );
+        }
+        let users = Table {
+            schema: "public".into(),
+            name: "users".into(),
+            description: None,
+            is_view: false,
+            insertable: true,
+            updatable: true,
+            deletable: true,
+            pk_cols: vec!["id".into()],
+            columns,
+        };
+        let routine = |name: &str, params: Vec<RoutineParam>| Routine {
+            schema: "public".into(),
+            name: name.into(),
+            description: None,
+            params,
+            return_type: RetType::SetOf("users".into()),
+            volatility: FuncVolatility::Stable,
+            has_variadic: false,
+            isolation_level: None,
+            settings: vec![],
+            is_procedure: false,
+        };
+        let q = RoutineParam {
+            name: "q".into(),
+            param_type: "text".into(),
+            type_max_length: "text".into(),
+            required: true,
+            variadic: false,
+        };
+
+        SchemaCache {
+            tables: HashMap::from([(QualifiedIdentifier::new("public", "users"), users)]),
+            routines: HashMap::from([
+                (
+                    QualifiedIdentifier::new("public", "get_users"),
+                    vec![routine("get_users", vec![])],
+                ),
+                (
+                    QualifiedIdentifier::new("public", "search"),
+                    vec![routine("search", vec![q])],
+                ),
+            ]),
+            pg_version: 150000,
+            schemas: vec!["public".into()],
+            ..Default::default()
+        }
+    }
+
+    fn rpc_request(function: &str, query: &str) -> ApiRequest {
+        use crate::api_request::{parse_routine_query_params, InvokeMethod};
+
+        ApiRequest {
+            action: Action::Db(DbAction::Routine {
+                qi: QualifiedIdentifier::new("public", function),
+                invoke_method: InvokeMethod::Inv,
+            }),
+            query_params: parse_routine_query_params(query).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rpc_select_projects_requested_columns() {
+        let plan = create_action_plan(&rpc_request("get_users", "select=id,name"), &get_users_cache()).unwrap();
+
+        let (sql, _) = crate::query::build_query(&plan, None).unwrap().build_main();
+        assert_eq!(
+            sql,
+            "WITH \"pgrst_call_result\" AS (SELECT * FROM \"public\".\"get_users\"()) \
+             SELECT \"id\", \"name\" FROM \"pgrst_call_result\""
+        );
+    }
+
+    #[test]
+    fn test_rpc_without_select_is_a_plain_call() {
+        let plan = create_action_plan(&rpc_request("get_users", ""), &get_users_cache()).unwrap();
+        match plan {
+            ActionPlan::Db(DbActionPlan::Call { read, .. }) => assert!(read.is_none()),
+            _ => panic!("Expected call plan"),
+        }
+    }
+
+    #[test]
+    fn test_rpc_filters_apply_to_output_rows() {
+        let request = rpc_request("search", "q=hello&name=eq.Bob&order=email");
+        let plan = create_action_plan(&request, &get_users_cache()).unwrap();
+
+        let (sql, params) = crate::query::build_query(&plan, None).unwrap().build_main();
+        assert_eq!(
+            sql,
+            "WITH \"pgrst_call_result\" AS (SELECT * FROM \"public\".\"search\"(\"q\" => $1)) \
+             SELECT \"id\", \"name\", \"email\" FROM \"pgrst_call_result\" \
+             WHERE \"name\" = $2 ORDER BY \"email\""
+        );
+        assert_eq!(
+            params,
+            vec![
+                postrust_sql::SqlParam::Text("hello".into()),
+                postrust_sql::SqlParam::Text("Bob".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rpc_argument_is_not_a_filter() {
+        // `q=eq.x` parses as a filter, but `q` is the function's argument
+        let request = rpc_request("search", "q=eq.x");
+        let plan = create_action_plan(&request, &get_users_cache()).unwrap();
+
+        let (sql, _) = crate::query::build_query(&plan, None).unwrap().build_main();
+        assert_eq!(sql, "SELECT * FROM \"public\".\"search\"(\"q\" => $1)");
+    }
 }
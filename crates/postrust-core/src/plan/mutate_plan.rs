@@ -2,10 +2,10 @@
 
 use super::types::*;
 use crate::api_request::{
-    ApiRequest, Mutation, Payload, PreferResolution, QualifiedIdentifier,
+    ApiRequest, Mutation, Operation, Payload, PreferResolution, QualifiedIdentifier, QuantOperator,
 };
 use crate::error::{Error, Result};
-use crate::schema_cache::Table;
+use crate::schema_cache::{Cardinality, Relationship, SchemaCache, Table};
 use serde::{Deserialize, Serialize};
 
 /// A mutation plan.
@@ -29,6 +29,15 @@ pub enum MutatePlan {
         pk_cols: Vec<String>,
         /// Apply defaults for missing columns
         apply_defaults: bool,
+        /// Related rows embedded in the body
+        #[serde(default)]
+        nested: Vec<NestedInsert>,
+        /// Body is the raw value of the single (bytea) column
+        #[serde(default)]
+        binary_body: bool,
+        /// Body was passed through unparsed (`?columns=`) and is read with `json_to_recordset`
+        #[serde(default)]
+        raw_json: bool,
     },
     /// UPDATE operation
     Update {
@@ -44,6 +53,13 @@ pub enum MutatePlan {
         returning: Vec<String>,
         /// Apply defaults for NULL columns
         apply_defaults: bool,
+        /// ETag the row must still have (`If-Match`)
+        #[serde(default)]
+        if_match: Option<String>,
+        /// Columns matching each row of an array body to the row it updates
+        /// (`Prefer: bulk-update=pk`); empty for a plain update
+        #[serde(default)]
+        bulk_key: Vec<String>,
     },
     /// DELETE operation
     Delete {
@@ -56,17 +72,34 @@ pub enum MutatePlan {
     },
 }
 
+/// Related rows embedded in an insert body (`{"name": .., "books": [..]}`).
+///
+/// The rows are written to the related table in the same statement as the
+/// parent, with the join columns taken from the inserted parent row.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NestedInsert {
+    /// Body key holding the related rows
+    pub key: String,
+    /// Related table
+    pub target: QualifiedIdentifier,
+    /// Columns taken from the embedded rows
+    pub columns: Vec<CoercibleField>,
+    /// (parent column, child column) pairs filled in from the parent row
+    pub join_columns: Vec<(String, String)>,
+}
+
 impl MutatePlan {
     /// Create a mutation plan from an API request.
     pub fn from_request(
         request: &ApiRequest,
         table: &Table,
         mutation: &Mutation,
+        schema_cache: &SchemaCache,
     ) -> Result<Self> {
         let qi = table.qualified_identifier();
 
         match mutation {
-            Mutation::Create => Self::create_insert(request, table, qi),
+            Mutation::Create => Self::create_insert(request, table, qi, schema_cache),
             Mutation::Update => Self::create_update(request, table, qi),
             Mutation::Delete => Self::create_delete(request, table, qi),
             Mutation::SingleUpsert => Self::create_upsert(request, table, qi),
@@ -78,21 +111,54 @@ impl MutatePlan {
         request: &ApiRequest,
         table: &Table,
         qi: QualifiedIdentifier,
+        schema_cache: &SchemaCache,
     ) -> Result<Self> {
-        let columns = get_payload_columns(request, table)?;
-        let body = get_body_bytes(request)?;
+        check_insertable_columns(request, table)?;
+        if let Some(column) = binary_upload_column(request, table)? {
+            return Ok(Self::Insert {
+                target: qi,
+                columns: vec![column],
+                body: get_body_bytes(request)?,
+                on_conflict: None,
+                where_clauses: vec![],
+                returning: get_returning_columns(request, table),
+                pk_cols: table.pk_cols.clone(),
+                apply_defaults: true,
+                nested: vec![],
+                binary_body: true,
+                raw_json: false,
+            });
+        }
+
+        let (columns, nested) = get_insert_columns(request, table, schema_cache)?;
+        let body = get_body_bytes(request)?.map(as_json_array);
         let returning = get_returning_columns(request, table);
-        let apply_defaults = request.preferences.missing == crate::api_request::PreferMissing::ApplyDefaults;
+        let apply_defaults = request.preferences.missing != Some(crate::api_request::PreferMissing::ApplyNulls);
 
-        let on_conflict = request.query_params.on_conflict.as_ref().map(|cols| {
+        // A resolution preference alone upserts on the primary key
+        let conflict_cols = request.query_params.on_conflict.clone().or_else(|| {
+            request
+                .preferences
+                .resolution
+                .as_ref()
+                .filter(|_| !table.pk_cols.is_empty())
+                .map(|_| table.pk_cols.clone())
+        });
+        let on_conflict = conflict_cols.map(|cols| {
             let resolution = request
                 .preferences
                 .resolution
                 .clone()
                 .unwrap_or(PreferResolution::MergeDuplicates);
-            (resolution, cols.clone())
+            (resolution, cols)
         });
 
+        if on_conflict.is_some() && !nested.is_empty() {
+            return Err(Error::EmbeddingError(
+                "nested inserts cannot be combined with on_conflict".into(),
+            ));
+        }
+
         Ok(Self::Insert {
             target: qi,
             columns,
@@ -102,6 +168,9 @@ impl MutatePlan {
             returning,
             pk_cols: table.pk_cols.clone(),
             apply_defaults,
+            nested,
+            binary_body: false,
+            raw_json: is_raw_json(request),
         })
     }
 
@@ -115,7 +184,15 @@ impl MutatePlan {
         let body = get_body_bytes(request)?;
         let where_clauses = build_mutation_where(request, table)?;
         let returning = get_returning_columns(request, table);
-        let apply_defaults = request.preferences.missing == crate::api_request::PreferMissing::ApplyDefaults;
+        let apply_defaults = request.preferences.missing != Some(crate::api_request::PreferMissing::ApplyNulls);
+        let bulk_key = bulk_update_key(request, table, body.as_deref())?;
+
+        // Conditional updates need a row back to tell a stale ETag from success
+        let returning = if request.if_match.is_some() && returning.is_empty() {
+            table.column_names().map(|s| s.to_string()).collect()
+        } else {
+            returning
+        };
 
         Ok(Self::Update {
             target: qi,
@@ -124,6 +201,8 @@ impl MutatePlan {
             where_clauses,
             returning,
             apply_defaults,
+            if_match: request.if_match.clone(),
+            bulk_key,
         })
     }
 
@@ -144,21 +223,43 @@ impl MutatePlan {
     }
 
     /// Create a PUT (upsert) plan.
+    ///
+    /// The body is a single row whose conflict key (the primary key unless
+    /// `on_conflict` names a unique constraint) must equal the URL filters.
     fn create_upsert(
         request: &ApiRequest,
         table: &Table,
         qi: QualifiedIdentifier,
     ) -> Result<Self> {
+        check_insertable_columns(request, table)?;
         let columns = get_payload_columns(request, table)?;
-        let body = get_body_bytes(request)?;
         let returning = get_returning_columns(request, table);
 
-        // Upsert uses PK for conflict
-        let on_conflict = Some((
-            PreferResolution::MergeDuplicates,
-            table.pk_cols.clone(),
+        let conflict_cols = request
+            .query_params
+            .on_conflict
+            .clone()
+            .unwrap_or_else(|| table.pk_cols.clone());
+        if conflict_cols.is_empty() {
+            return Err(Error::InvalidQueryParam(format!(
+                "PUT on {} needs a primary key or on_conflict",
+                table.name
+            )));
+        }
+
+        let row = upsert_row(request)?;
+        check_upsert_key(request, &row, &conflict_cols)?;
+        let body = Some(bytes::Bytes::from(
+            serde_json::to_vec(&[row]).map_err(|e| Error::InvalidBody(e.to_string()))?,
         ));
 
+        let resolution = request
+            .preferences
+            .resolution
+            .clone()
+            .unwrap_or(PreferResolution::MergeDuplicates);
+        let on_conflict = Some((resolution, conflict_cols));
+
         Ok(Self::Insert {
             target: qi,
             columns,
@@ -168,6 +269,9 @@ impl MutatePlan {
             returning,
             pk_cols: table.pk_cols.clone(),
             apply_defaults: true,
+            nested: vec![],
+            binary_body: false,
+            raw_json: is_raw_json(request),
         })
     }
 
@@ -190,6 +294,45 @@ impl MutatePlan {
     }
 }
 
+/// The primary key of a bulk update by key, or empty for a plain update.
+///
+/// Only an array body with `Prefer: bulk-update=pk` is a bulk update. Every
+/// row must hold the whole key and the same columns as the others.
+fn bulk_update_key(request: &ApiRequest, table: &Table, body: Option<&[u8]>) -> Result<Vec<String>> {
+    if !request.preferences.bulk_update {
+        return Ok(vec![]);
+    }
+    let rows = match body.map(serde_json::from_slice::<serde_json::Value>) {
+        Some(Ok(serde_json::Value::Array(rows))) => rows,
+        Some(Err(e)) => return Err(Error::invalid_json(&e)),
+        _ => return Ok(vec![]),
+    };
+    if table.pk_cols.is_empty() {
+        return Err(Error::InvalidBody(format!(
+            "bulk update of '{}' needs a primary key",
+            table.name
+        )));
+    }
+
+    let mut first_keys = None;
+    for row in &rows {
+        let serde_json::Value::Object(row) = row else {
+            return Err(Error::InvalidBody("bulk update rows must be objects".into()));
+        };
+        if let Some(missing) = table.pk_cols.iter().find(|pk| row.get(*pk).is_none_or(|v| v.is_null())) {
+            return Err(Error::InvalidBody(format!(
+                "every row of a bulk update needs its primary key; '{}' is missing",
+                missing
+            )));
+        }
+        let keys: std::collections::BTreeSet<&String> = row.keys().collect();
+        if *first_keys.get_or_insert_with(|| keys.clone()) != keys {
+            return Err(Error::InvalidBody("all rows of a bulk update must have the same keys".into()));
+        }
+    }
+    Ok(table.pk_cols.clone())
+}
+
 /// Get columns from payload.
 fn get_payload_columns(
     request: &ApiRequest,
@@ -198,20 +341,234 @@ fn get_payload_columns(
     let keys = match &request.payload {
         Some(Payload::ProcessedJson { keys, .. }) => keys,
         Some(Payload::ProcessedUrlEncoded { keys, .. }) => keys,
+        _ if is_raw_json(request) => return raw_json_columns(request, table),
         _ => return Ok(vec![]),
     };
 
-    let mut columns = Vec::new();
+    keys.iter().map(|key| payload_column(table, key)).collect()
+}
+
+/// Reject `?columns=` naming a column the database always generates.
+fn check_insertable_columns(request: &ApiRequest, table: &Table) -> Result<()> {
+    let mut generated: Vec<&String> = request
+        .query_params
+        .columns
+        .iter()
+        .flatten()
+        .filter(|name| table.get_column(name).is_some_and(|c| c.generated))
+        .collect();
+    generated.sort();
+    match generated.first() {
+        Some(name) => Err(Error::ColumnNotInsertable(format!("{}.{}", table.name, name))),
+        None => Ok(()),
+    }
+}
+
+/// Whether the body was left unparsed because `?columns=` named its keys.
+fn is_raw_json(request: &ApiRequest) -> bool {
+    matches!(request.payload, Some(Payload::RawJson(_))) && request.query_params.columns.is_some()
+}
+
+/// Columns named by `?columns=`, typed for a `json_to_recordset` column list.
+fn raw_json_columns(request: &ApiRequest, table: &Table) -> Result<Vec<CoercibleField>> {
+    let mut names: Vec<&String> = request.query_params.columns.iter().flatten().collect();
+    names.sort_by_key(|name| table.get_column(name).map(|c| c.position));
+
+    names
+        .into_iter()
+        .map(|name| {
+            let mut field = payload_column(table, name)?;
+            let column = &table.columns[name.as_str()];
+            field.ir_type = column.domain_type().unwrap_or_else(|| column.nominal_type.clone());
+            field.base_type = column.nominal_type.clone();
+            Ok(field)
+        })
+        .collect()
+}
+
+/// Get columns from an insert payload, splitting off keys that embed rows
+/// of a related table.
+fn get_insert_columns(
+    request: &ApiRequest,
+    table: &Table,
+    schema_cache: &SchemaCache,
+) -> Result<(Vec<CoercibleField>, Vec<NestedInsert>)> {
+    let keys = match &request.payload {
+        Some(Payload::ProcessedJson { keys, .. }) => keys,
+        _ => return Ok((get_payload_columns(request, table)?, vec![])),
+    };
 
+    let mut columns = Vec::new();
+    let mut nested_keys = Vec::new();
     for key in keys {
-        let column = table
-            .get_column(key)
-            .ok_or_else(|| Error::UnknownColumn(key.clone()))?;
+        if table.get_column(key).is_some() {
+            columns.push(payload_column(table, key)?);
+        } else {
+            nested_keys.push(key);
+        }
+    }
+
+    if nested_keys.is_empty() {
+        return Ok((columns, vec![]));
+    }
+
+    let body: serde_json::Value = match &request.payload {
+        Some(Payload::ProcessedJson { raw, .. }) => {
+            serde_json::from_slice(raw).map_err(|e| Error::invalid_json(&e))?
+        }
+        _ => serde_json::Value::Null,
+    };
+
+    nested_keys.sort();
+    let nested = nested_keys
+        .into_iter()
+        .map(|key| plan_nested_insert(request, table, key, &body, schema_cache))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((columns, nested))
+}
+
+/// Plan the insert of related rows embedded under `key`.
+fn plan_nested_insert(
+    request: &ApiRequest,
+    table: &Table,
+    key: &str,
+    body: &serde_json::Value,
+    schema_cache: &SchemaCache,
+) -> Result<NestedInsert> {
+    let rel = schema_cache
+        .resolve_relationship(&table.qualified_identifier(), key, None, &request.schema)
+        .map_err(|e| match e {
+            Error::RelationshipNotFound(_) => Error::UnknownColumn(key.to_string()),
+            other => other,
+        })?;
 
-        columns.push(CoercibleField::simple(key, &column.data_type));
+    let join_columns = match rel {
+        Relationship::ForeignKey {
+            cardinality: Cardinality::O2M { columns, .. },
+            ..
+        }
+        | Relationship::ForeignKey {
+            cardinality: Cardinality::O2O { columns, is_parent: true, .. },
+            ..
+        } => columns.clone(),
+        _ => {
+            return Err(Error::EmbeddingError(format!(
+                "cannot insert '{}' together with '{}': only tables referencing '{}' \
+                 can be written in a nested insert",
+                key, table.name, table.name
+            )))
+        }
+    };
+
+    let child = schema_cache.require_table(rel.foreign_table())?;
+
+    // Columns present in any embedded row, minus the ones set from the parent
+    let rows: Vec<&serde_json::Value> = match body {
+        serde_json::Value::Array(arr) => arr.iter().collect(),
+        other => vec![other],
+    };
+    let mut child_keys = std::collections::BTreeSet::new();
+    for row in rows {
+        match row.get(key) {
+            Some(serde_json::Value::Array(items)) => {
+                for item in items {
+                    let obj = item.as_object().ok_or_else(|| {
+                        Error::InvalidBody(format!("'{}' must contain objects", key))
+                    })?;
+                    child_keys.extend(obj.keys().cloned());
+                }
+            }
+            Some(serde_json::Value::Object(obj)) if rel.is_to_one() => {
+                child_keys.extend(obj.keys().cloned());
+            }
+            Some(serde_json::Value::Null) | None => {}
+            Some(_) => {
+                return Err(Error::InvalidBody(format!(
+                    "'{}' must be an array of objects",
+                    key
+                )))
+            }
+        }
     }
 
-    Ok(columns)
+    let columns = child_keys
+        .iter()
+        .filter(|k| !join_columns.iter().any(|(_, child_col)| child_col == *k))
+        .map(|k| payload_column(child, k))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(NestedInsert {
+        key: key.to_string(),
+        target: child.qualified_identifier(),
+        columns,
+        join_columns,
+    })
+}
+
+/// Pick the bytea column an `application/octet-stream` body is written to.
+///
+/// Uses the single column named in `?columns=`, or else the table's only
+/// bytea column. Returns `None` for other content types.
+fn binary_upload_column(request: &ApiRequest, table: &Table) -> Result<Option<CoercibleField>> {
+    if request.content_media_type != crate::api_request::MediaType::OctetStream
+        || !matches!(request.payload, Some(Payload::RawPayload(_)))
+    {
+        return Ok(None);
+    }
+
+    let name = match &request.query_params.columns {
+        Some(cols) if cols.len() == 1 => cols.iter().next().unwrap().clone(),
+        Some(_) => {
+            return Err(Error::InvalidBody(
+                "a binary body can only be written to a single column".into(),
+            ))
+        }
+        None => {
+            let bytea: Vec<&str> = table
+                .columns
+                .values()
+                .filter(|c| c.data_type == "bytea")
+                .map(|c| c.name.as_str())
+                .collect();
+            match bytea.as_slice() {
+                [only] => only.to_string(),
+                [] => {
+                    return Err(Error::InvalidBody(format!(
+                        "'{}' has no bytea column to store a binary body",
+                        table.name
+                    )))
+                }
+                _ => {
+                    return Err(Error::InvalidBody(format!(
+                        "'{}' has several bytea columns; choose one with ?columns=",
+                        table.name
+                    )))
+                }
+            }
+        }
+    };
+
+    let field = payload_column(table, &name)?;
+    if field.base_type != "bytea" {
+        return Err(Error::InvalidBody(format!("column '{}' is not bytea", name)));
+    }
+    Ok(Some(field))
+}
+
+/// Resolve a payload key to a writable column of `table`.
+fn payload_column(table: &Table, key: &str) -> Result<CoercibleField> {
+    let column = table
+        .get_column(key)
+        .ok_or_else(|| Error::UnknownColumn(key.to_string()))?;
+
+    if table.is_view && !column.updatable {
+        return Err(Error::ColumnNotUpdatable(format!("{}.{}", table.name, key)));
+    }
+
+    let mut field = CoercibleField::simple(key, &column.data_type);
+    field.apply_domain(column);
+    Ok(field)
 }
 
 /// Get body as bytes.
@@ -231,6 +588,74 @@ fn get_body_bytes(request: &ApiRequest) -> Result<Option<bytes::Bytes>> {
     }
 }
 
+/// Wrap a single-object JSON body in an array, since inserts read rows with
+/// `json_populate_recordset`/`json_to_recordset`.
+fn as_json_array(body: bytes::Bytes) -> bytes::Bytes {
+    if body.iter().find(|b| !b.is_ascii_whitespace()) != Some(&b'{') {
+        return body;
+    }
+    let mut rows = Vec::with_capacity(body.len() + 2);
+    rows.push(b'[');
+    rows.extend_from_slice(&body);
+    rows.push(b']');
+    rows.into()
+}
+
+/// The single row a PUT body holds, as an object or a one-element array.
+fn upsert_row(request: &ApiRequest) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let body = get_body_bytes(request)?.unwrap_or_default();
+    let value: serde_json::Value =
+        serde_json::from_slice(&body).map_err(|e| Error::invalid_json(&e))?;
+    match value {
+        serde_json::Value::Object(row) => Ok(row),
+        serde_json::Value::Array(mut rows) if rows.len() == 1 => match rows.pop() {
+            Some(serde_json::Value::Object(row)) => Ok(row),
+            _ => Err(Error::InvalidBody("PUT payload must be an object".into())),
+        },
+        _ => Err(Error::InvalidBody("PUT payload must contain a single row".into())),
+    }
+}
+
+/// Require `eq` filters on exactly the conflict columns, matching the row.
+fn check_upsert_key(
+    request: &ApiRequest,
+    row: &serde_json::Map<String, serde_json::Value>,
+    conflict_cols: &[String],
+) -> Result<()> {
+    let filters = &request.query_params.filters_root;
+    let filter_value = |col: &str| {
+        filters.iter().find_map(|f| match &f.op_expr.operation {
+            Operation::Quant { op: QuantOperator::Equal, quantifier: None, value }
+                if f.field.name == col && f.field.json_path.is_empty() && !f.op_expr.negated =>
+            {
+                Some(value)
+            }
+            _ => None,
+        })
+    };
+    if filters.len() != conflict_cols.len() || conflict_cols.iter().any(|c| filter_value(c).is_none()) {
+        return Err(Error::InvalidQueryParam(format!(
+            "PUT filters must include all and only the columns ({}) with eq operators",
+            conflict_cols.join(", ")
+        )));
+    }
+
+    for col in conflict_cols {
+        let matches = match row.get(col) {
+            Some(serde_json::Value::String(s)) => Some(s) == filter_value(col),
+            Some(serde_json::Value::Null) | None => false,
+            Some(other) => filter_value(col) == Some(&other.to_string()),
+        };
+        if !matches {
+            return Err(Error::InvalidBody(format!(
+                "payload value of {} does not match the URL filter",
+                col
+            )));
+        }
+    }
+    Ok(())
+}
+
 /// Get returning columns.
 fn get_returning_columns(request: &ApiRequest, table: &Table) -> Vec<String> {
     if request.preferences.representation.needs_body() {
@@ -262,12 +687,132 @@ fn build_mutation_where(
         )));
     }
 
+    clauses.iter_mut().for_each(|c| c.cast_domains(table));
     Ok(clauses)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::schema_cache::Column;
+    use indexmap::IndexMap;
+
+    fn view_column(name: &str, position: i32, updatable: bool) -> Column {
+        Column {
+            name: name.into(),
+            nullable: true,
+            data_type: "text".into(),
+            nominal_type: "text".into(),
+            position,
+            updatable,
+            ..Default::default()
+        }
+    }
+
+    fn create_test_view() -> Table {
+        let mut columns = IndexMap::new();
+        columns.insert("name".into(), view_column("name", 1, true));
+        columns.insert("name_upper".into(), view_column("name_upper", 2, false));
+
+        Table {
+            schema: "public".into(),
+            name: "users_view".into(),
+            description: None,
+            is_view: true,
+            insertable: true,
+            updatable: true,
+            deletable: true,
+            pk_cols: vec![],
+            columns,
+        }
+    }
+
+    fn empty_cache() -> SchemaCache {
+        SchemaCache {
+            pg_version: 150000,
+            schemas: vec!["public".into()],
+            ..Default::default()
+        }
+    }
+
+    fn table(name: &str, cols: &[&str]) -> Table {
+        let mut columns = IndexMap::new();
+        for (i, col) in cols.iter().enumerate() {
+            columns.insert(col.to_string(), view_column(col, i as i32 + 1, true));
+        }
+        Table {
+            schema: "public".into(),
+            name: name.into(),
+            description: None,
+            is_view: false,
+            insertable: true,
+            updatable: true,
+            deletable: true,
+            pk_cols: vec!["id".into()],
+            columns,
+        }
+    }
+
+    /// authors(id, name) <- books(id, title, author_id)
+    fn authors_books_cache() -> SchemaCache {
+        let authors = QualifiedIdentifier::new("public", "authors");
+        let books = QualifiedIdentifier::new("public", "books");
+        let fk = |table: &QualifiedIdentifier, foreign: &QualifiedIdentifier, cardinality| {
+            Relationship::ForeignKey {
+                table: table.clone(),
+                foreign_table: foreign.clone(),
+                is_self: false,
+                cardinality,
+                table_is_view: false,
+                foreign_table_is_view: false,
+                constraint_name: "books_author_id_fkey".into(),
+            }
+        };
+
+        let mut cache = empty_cache();
+        cache.tables.insert(authors.clone(), table("authors", &["id", "name"]));
+        cache.tables.insert(books.clone(), table("books", &["id", "title", "author_id"]));
+        cache.relationships.insert(
+            (authors.clone(), "public".into()),
+            vec![fk(&authors, &books, Cardinality::O2M {
+                constraint: "books_author_id_fkey".into(),
+                columns: vec![("id".into(), "author_id".into())],
+            })],
+        );
+        cache.relationships.insert(
+            (books.clone(), "public".into()),
+            vec![fk(&books, &authors, Cardinality::M2O {
+                constraint: "books_author_id_fkey".into(),
+                columns: vec![("author_id".into(), "id".into())],
+            })],
+        );
+        cache
+    }
+
+    fn request_with_body(body: serde_json::Value) -> ApiRequest {
+        let raw = bytes::Bytes::from(serde_json::to_vec(&body).unwrap());
+        let payload = crate::api_request::payload::parse_payload(
+            raw,
+            &crate::api_request::MediaType::ApplicationJson,
+            None,
+        )
+        .unwrap();
+        ApiRequest {
+            schema: "public".into(),
+            payload,
+            ..Default::default()
+        }
+    }
+
+    fn request_with_keys(keys: &[&str]) -> ApiRequest {
+        ApiRequest {
+            payload: Some(Payload::ProcessedJson {
+                raw: bytes::Bytes::from_static(b"{}"),
+                keys: keys.iter().map(|k| k.to_string()).collect(),
+            }),
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn test_mutate_plan_target() {
@@ -294,6 +839,9 @@ mod tests {
             returning: vec![],
             pk_cols: vec![],
             apply_defaults: true,
+            nested: vec![],
+            binary_body: false,
+            raw_json: false,
         };
         assert!(insert.has_body());
 
@@ -304,4 +852,320 @@ mod tests {
         };
         assert!(!delete.has_body());
     }
+
+    #[test]
+    fn test_view_passthrough_column_is_writable() {
+        let view = create_test_view();
+        let request = request_with_keys(&["name"]);
+
+        let plan = MutatePlan::from_request(&request, &view, &Mutation::Update, &empty_cache()).unwrap();
+        match plan {
+            MutatePlan::Update { columns, .. } => assert_eq!(columns[0].name, "name"),
+            _ => panic!("Expected update plan"),
+        }
+    }
+
+    #[test]
+    fn test_view_computed_column_is_rejected() {
+        let view = create_test_view();
+        let request = request_with_keys(&["name", "name_upper"]);
+
+        let err = MutatePlan::from_request(&request, &view, &Mutation::Create, &empty_cache()).unwrap_err();
+        assert!(matches!(err, Error::ColumnNotUpdatable(ref c) if c == "users_view.name_upper"));
+        assert_eq!(err.code(), "PGRST111");
+    }
+
+    #[test]
+    fn test_nested_insert_plans_child_rows() {
+        let cache = authors_books_cache();
+        let authors = cache.tables[&QualifiedIdentifier::new("public", "authors")].clone();
+        let request = request_with_body(serde_json::json!({
+            "name": "Ursula",
+            "books": [{"title": "The Dispossessed"}, {"title": "The Lathe of Heaven"}]
+        }));
+
+        let plan = MutatePlan::from_request(&request, &authors, &Mutation::Create, &cache).unwrap();
+        match plan {
+            MutatePlan::Insert { columns, nested, .. } => {
+                assert_eq!(columns.len(), 1);
+                assert_eq!(columns[0].name, "name");
+                assert_eq!(nested.len(), 1);
+                assert_eq!(nested[0].key, "books");
+                assert_eq!(nested[0].target.name, "books");
+                assert_eq!(nested[0].columns.len(), 1);
+                assert_eq!(nested[0].columns[0].name, "title");
+                assert_eq!(nested[0].join_columns, vec![("id".into(), "author_id".into())]);
+            }
+            _ => panic!("Expected insert plan"),
+        }
+    }
+
+    #[test]
+    fn test_nested_insert_rejects_to_one_parent() {
+        let cache = authors_books_cache();
+        let books = cache.tables[&QualifiedIdentifier::new("public", "books")].clone();
+        let request = request_with_body(serde_json::json!({
+            "title": "Dune",
+            "authors": {"name": "Frank"}
+        }));
+
+        let err = MutatePlan::from_request(&request, &books, &Mutation::Create, &cache).unwrap_err();
+        assert!(matches!(err, Error::EmbeddingError(_)));
+    }
+
+    #[test]
+    fn test_nested_insert_unknown_key_is_unknown_column() {
+        let cache = authors_books_cache();
+        let authors = cache.tables[&QualifiedIdentifier::new("public", "authors")].clone();
+        let request = request_with_body(serde_json::json!({"name": "Ursula", "nme": "x"}));
+
+        let err = MutatePlan::from_request(&request, &authors, &Mutation::Create, &cache).unwrap_err();
+        assert!(matches!(err, Error::UnknownColumn(ref c) if c == "nme"));
+    }
+
+    #[test]
+    fn test_octet_stream_insert_targets_bytea_column() {
+        let mut files = table("files", &["id", "name", "data"]);
+        files.columns.get_mut("data").unwrap().data_type = "bytea".into();
+        let request = ApiRequest {
+            content_media_type: crate::api_request::MediaType::OctetStream,
+            payload: Some(Payload::RawPayload(bytes::Bytes::from_static(b"\x00\x01\xff"))),
+            ..Default::default()
+        };
+
+        let plan = MutatePlan::from_request(&request, &files, &Mutation::Create, &empty_cache()).unwrap();
+        match plan {
+            MutatePlan::Insert { columns, body, binary_body, .. } => {
+                assert!(binary_body);
+                assert_eq!(columns.len(), 1);
+                assert_eq!(columns[0].name, "data");
+                assert_eq!(body.unwrap().as_ref(), b"\x00\x01\xff");
+            }
+            _ => panic!("Expected insert plan"),
+        }
+
+        // No bytea column to write to
+        let users = table("users", &["id", "name"]);
+        let err = MutatePlan::from_request(&request, &users, &Mutation::Create, &empty_cache()).unwrap_err();
+        assert!(matches!(err, Error::InvalidBody(_)));
+    }
+
+    #[test]
+    fn test_columns_param_inserts_raw_json() {
+        let mut users = table("users", &["id", "name"]);
+        users.columns.get_mut("id").unwrap().nominal_type = "int4".into();
+        let query_params =
+            crate::api_request::query_params::parse_query_params("columns=name,id").unwrap();
+        let payload = crate::api_request::payload::parse_payload(
+            bytes::Bytes::from_static(br#"[{"id": 1, "name": "a", "extra": true}]"#),
+            &crate::api_request::MediaType::ApplicationJson,
+            query_params.columns.as_ref(),
+        )
+        .unwrap();
+        assert!(matches!(payload, Some(Payload::RawJson(_))));
+        let request = ApiRequest {
+            payload,
+            query_params,
+            ..Default::default()
+        };
+
+        let plan = MutatePlan::from_request(&request, &users, &Mutation::Create, &empty_cache()).unwrap();
+        let names: Vec<&str> = match &plan {
+            MutatePlan::Insert { columns, raw_json, .. } => {
+                assert!(raw_json);
+                columns.iter().map(|c| c.name.as_str()).collect()
+            }
+            _ => panic!("Expected insert plan"),
+        };
+        assert_eq!(names, vec!["id", "name"]);
+
+        let (sql, _) = crate::query::QueryBuilder::build_mutate(&plan).unwrap().build();
+        assert!(sql.contains(
+            "SELECT r.\"id\", r.\"name\" FROM json_to_recordset($1::json) AS r(\"id\" int4, \"name\" text)"
+        ), "{}", sql);
+    }
+
+    #[test]
+    fn test_single_object_insert_is_read_as_one_row() {
+        let users = table("users", &["id", "name"]);
+        let request = request_with_body(serde_json::json!({"id": "5", "name": "a"}));
+
+        let plan = MutatePlan::from_request(&request, &users, &Mutation::Create, &empty_cache()).unwrap();
+        let MutatePlan::Insert { body: Some(body), .. } = &plan else {
+            panic!("Expected insert plan with a body");
+        };
+        assert_eq!(&body[..], br#"[{"id":"5","name":"a"}]"#);
+
+        // Arrays pass through unchanged
+        let request = request_with_body(serde_json::json!([{"id": "5"}, {"id": "6"}]));
+        let plan = MutatePlan::from_request(&request, &users, &Mutation::Create, &empty_cache()).unwrap();
+        let MutatePlan::Insert { body: Some(body), .. } = &plan else {
+            panic!("Expected insert plan with a body");
+        };
+        assert_eq!(&body[..], br#"[{"id":"5"},{"id":"6"}]"#);
+    }
+
+    #[test]
+    fn test_columns_param_rejects_generated_column() {
+        let mut users = table("users", &["id", "name", "name_lower"]);
+        users.columns.get_mut("name_lower").unwrap().generated = true;
+        let insert = |query: &str| {
+            let query_params = crate::api_request::query_params::parse_query_params(query).unwrap();
+            let payload = crate::api_request::payload::parse_payload(
+                bytes::Bytes::from_static(br#"[{"id": 1, "name": "A", "name_lower": "a"}]"#),
+                &crate::api_request::MediaType::ApplicationJson,
+                query_params.columns.as_ref(),
+            )
+            .unwrap();
+            let request = ApiRequest {
+                payload,
+                query_params,
+                ..Default::default()
+            };
+            MutatePlan::from_request(&request, &users, &Mutation::Create, &empty_cache())
+        };
+
+        let err = insert("columns=id,name,name_lower").unwrap_err();
+        assert!(matches!(err, Error::ColumnNotInsertable(ref c) if c == "users.name_lower"), "{:?}", err);
+        assert_eq!(err.status_code(), http::StatusCode::BAD_REQUEST);
+
+        assert!(insert("columns=id,name").is_ok());
+    }
+
+    #[test]
+    fn test_domain_columns_cast_to_domain_on_write() {
+        let mut users = table("users", &["id", "email"]);
+        users.columns.get_mut("email").unwrap().domain =
+            Some(QualifiedIdentifier::new("public", "email_address"));
+        let query_params =
+            crate::api_request::query_params::parse_query_params("columns=email").unwrap();
+        let payload = crate::api_request::payload::parse_payload(
+            bytes::Bytes::from_static(br#"[{"email": "a@b.io"}]"#),
+            &crate::api_request::MediaType::ApplicationJson,
+            query_params.columns.as_ref(),
+        )
+        .unwrap();
+        let request = ApiRequest {
+            payload,
+            query_params,
+            ..Default::default()
+        };
+
+        let plan = MutatePlan::from_request(&request, &users, &Mutation::Create, &empty_cache()).unwrap();
+        let (sql, _) = crate::query::QueryBuilder::build_mutate(&plan).unwrap().build();
+        assert!(sql.contains("AS r(\"email\" \"public\".\"email_address\")"), "{}", sql);
+    }
+
+    fn put(body: serde_json::Value, query: &str, prefer: Option<PreferResolution>) -> ApiRequest {
+        let mut request = request_with_body(body);
+        request.query_params = crate::api_request::query_params::parse_query_params(query).unwrap();
+        request.preferences.resolution = prefer;
+        request
+    }
+
+    #[test]
+    fn test_put_upserts_with_resolution() {
+        let users = table("users", &["id", "email", "name"]);
+
+        let request = put(serde_json::json!({"id": 7, "name": "Ada"}), "id=eq.7", None);
+        let plan = MutatePlan::from_request(&request, &users, &Mutation::SingleUpsert, &empty_cache()).unwrap();
+        let (sql, _) = crate::query::QueryBuilder::build_mutate(&plan).unwrap().build();
+        assert!(sql.contains("ON CONFLICT (\"id\") DO UPDATE SET"), "{}", sql);
+
+        // A unique column other than the key, left untouched on conflict
+        let request = put(
+            serde_json::json!([{"email": "ada@example.com", "name": "Ada"}]),
+            "email=eq.ada@example.com&on_conflict=email",
+            Some(PreferResolution::IgnoreDuplicates),
+        );
+        let plan = MutatePlan::from_request(&request, &users, &Mutation::SingleUpsert, &empty_cache()).unwrap();
+        let (sql, _) = crate::query::QueryBuilder::build_mutate(&plan).unwrap().build();
+        assert!(sql.contains("ON CONFLICT (\"email\") DO NOTHING"), "{}", sql);
+    }
+
+    #[test]
+    fn test_put_rejects_key_mismatch() {
+        let users = table("users", &["id", "name"]);
+        let plan = |request: &ApiRequest| {
+            MutatePlan::from_request(request, &users, &Mutation::SingleUpsert, &empty_cache())
+        };
+
+        let err = plan(&put(serde_json::json!({"id": 8, "name": "Ada"}), "id=eq.7", None)).unwrap_err();
+        assert!(matches!(err, Error::InvalidBody(_)), "{:?}", err);
+
+        let err = plan(&put(serde_json::json!({"name": "Ada"}), "id=eq.7", None)).unwrap_err();
+        assert!(matches!(err, Error::InvalidBody(_)), "{:?}", err);
+
+        let err = plan(&put(serde_json::json!({"id": 7}), "id=gt.7", None)).unwrap_err();
+        assert!(matches!(err, Error::InvalidQueryParam(_)), "{:?}", err);
+
+        let err = plan(&put(serde_json::json!({"id": 7}), "id=eq.7&name=eq.x", None)).unwrap_err();
+        assert!(matches!(err, Error::InvalidQueryParam(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_update_carries_if_match() {
+        let users = table("users", &["id", "name"]);
+        let mut request = request_with_keys(&["name"]);
+        request.if_match = Some("abc123".into());
+
+        let plan = MutatePlan::from_request(&request, &users, &Mutation::Update, &empty_cache()).unwrap();
+        match plan {
+            MutatePlan::Update { if_match, returning, .. } => {
+                assert_eq!(if_match.as_deref(), Some("abc123"));
+                assert!(!returning.is_empty());
+            }
+            _ => panic!("Expected update plan"),
+        }
+    }
+
+    #[test]
+    fn test_array_patch_builds_bulk_update_by_pk() {
+        let users = table("users", &["id", "name"]);
+        let mut request = request_with_body(serde_json::json!([
+            {"id": 1, "name": "Ann"},
+            {"id": 2, "name": null}
+        ]));
+        request.preferences.bulk_update = true;
+
+        let plan = MutatePlan::from_request(&request, &users, &Mutation::Update, &empty_cache()).unwrap();
+        let (sql, params) = crate::query::QueryBuilder::build_mutate(&plan).unwrap().build();
+        assert_eq!(
+            sql,
+            "UPDATE \"public\".\"users\" SET \"name\" = \"pgrst_body\".\"pgrst_2\" \
+             FROM (VALUES ($1::text, $2::text), ($3::text, $4::text)) \
+             AS \"pgrst_body\"(\"pgrst_1\", \"pgrst_2\") \
+             WHERE \"public\".\"users\".\"id\" = \"pgrst_body\".\"pgrst_1\" \
+             RETURNING \"public\".\"users\".\"id\""
+        );
+        assert_eq!(
+            params,
+            vec![
+                postrust_sql::SqlParam::Text("1".into()),
+                postrust_sql::SqlParam::Text("Ann".into()),
+                postrust_sql::SqlParam::Text("2".into()),
+                postrust_sql::SqlParam::Null,
+            ]
+        );
+
+        // Without the preference an array body is a plain update
+        request.preferences.bulk_update = false;
+        match MutatePlan::from_request(&request, &users, &Mutation::Update, &empty_cache()).unwrap() {
+            MutatePlan::Update { bulk_key, .. } => assert!(bulk_key.is_empty()),
+            _ => panic!("Expected update plan"),
+        }
+    }
+
+    #[test]
+    fn test_bulk_update_rows_need_the_pk() {
+        let users = table("users", &["id", "name"]);
+        let mut request = request_with_body(serde_json::json!([
+            {"id": 1, "name": "Ann"},
+            {"name": "Bob"}
+        ]));
+        request.preferences.bulk_update = true;
+
+        let err = MutatePlan::from_request(&request, &users, &Mutation::Update, &empty_cache()).unwrap_err();
+        assert!(matches!(err, Error::InvalidBody(ref msg) if msg.contains("'id'")), "{:?}", err);
+    }
 }
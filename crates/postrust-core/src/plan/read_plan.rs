@@ -2,12 +2,20 @@
 
 use super::types::*;
 use crate::api_request::{
-    ApiRequest, JoinType, QualifiedIdentifier, Range, SelectItem,
+    Action, AggregateFunction, ApiRequest, DbAction, Filter, JoinType, LogicOperator, LogicTree,
+    Mutation, Operation, PreferHandling, QualifiedIdentifier, QuantOperator, Range, SelectItem,
+    SimpleOperator,
 };
 use crate::error::{Error, Result};
-use crate::schema_cache::{Relationship, SchemaCache, Table};
+use crate::schema_cache::{Column, Relationship, SchemaCache, Table};
 use serde::{Deserialize, Serialize};
 
+/// Name the call result is read from when shaping RPC output.
+const CALL_RESULT: &str = "pgrst_call_result";
+
+/// Deepest nesting of embeds, e.g. `parent(parent(...))` on a hierarchy.
+const MAX_EMBED_DEPTH: usize = 16;
+
 /// A read plan for a single table/view.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ReadPlan {
@@ -47,17 +55,23 @@ impl ReadPlan {
         let qi = table.qualified_identifier();
 
         // Build select fields
-        let select = build_select_fields(&request.query_params.select, table)?;
+        check_select_casts(&request.query_params.select, schema_cache)?;
+        let select = build_select_fields(
+            &request.query_params.select,
+            table,
+            &request.query_params.params,
+            request.preferences.handling.as_ref(),
+        )?;
 
         // Build where clauses from filters
-        let where_clauses = build_where_clauses(request, table)?;
-
-        // Build order terms
-        let order = build_order_terms(request, table)?;
+        let where_clauses = build_where_clauses(request, table, &[])?;
 
         // Build relation selects for embedding
         let rel_select = build_relation_selects(&request.query_params.select, table, schema_cache)?;
 
+        // Build order terms
+        let order = build_order_terms(request, table, &rel_select, schema_cache)?;
+
         Ok(Self {
             select,
             from: qi,
@@ -81,11 +95,129 @@ impl ReadPlan {
         schema_cache: &SchemaCache,
     ) -> Result<Self> {
         let mut plan = Self::from_request(request, table, schema_cache)?;
-        // For mutations, we select from the CTE result
-        plan.from_alias = Some("pgrst_mutation_result".to_string());
+        // For mutations, we select from the CTE result; updates and deletes
+        // already applied the filters, inserts leave them to the returned rows
+        plan.from_alias = Some(super::MUTATION_RESULT.to_string());
+        if !matches!(
+            request.action,
+            Action::Db(DbAction::RelationMut { mutation: Mutation::Create | Mutation::SingleUpsert, .. })
+        ) {
+            plan.where_clauses.clear();
+        }
+        plan.range = Range::default();
         Ok(plan)
     }
 
+    /// Create a read plan shaping the result set of an RPC call.
+    ///
+    /// The call runs as the `pgrst_call_result` CTE, so `select`, filters,
+    /// order and range apply to the rows the function returned. Query
+    /// parameters consumed as function `arguments` are not filters.
+    pub fn for_call(
+        request: &ApiRequest,
+        table: &Table,
+        arguments: &[(String, String)],
+        schema_cache: &SchemaCache,
+    ) -> Result<Self> {
+        let where_clauses = build_where_clauses(request, table, &[])?
+            .into_iter()
+            .filter(|clause| match clause {
+                CoercibleLogicTree::Stmt(filter) => {
+                    !arguments.iter().any(|(name, _)| *name == filter.field.name)
+                }
+                _ => true,
+            })
+            .collect();
+
+        let rel_select = build_relation_selects(&request.query_params.select, table, schema_cache)?;
+        check_select_casts(&request.query_params.select, schema_cache)?;
+
+        Ok(Self {
+            select: build_select_fields(
+                &request.query_params.select,
+                table,
+                &request.query_params.params,
+                request.preferences.handling.as_ref(),
+            )?,
+            from: QualifiedIdentifier::new("", CALL_RESULT),
+            from_alias: None,
+            where_clauses,
+            order: build_order_terms(request, table, &rel_select, schema_cache)?,
+            range: request.top_level_range.clone(),
+            rel_name: table.name.clone(),
+            rel_to_parent: None,
+            rel_join_conds: vec![],
+            rel_join_type: None,
+            rel_select,
+            depth: 0,
+        })
+    }
+
+    /// Create the plan for an embedded resource, scoped to its path.
+    ///
+    /// Filters and logic groups addressed to the embed (`posts.id=eq.1`,
+    /// `posts.or=(...)`) go into this plan's WHERE rather than the parent's.
+    /// `path` is the embed's own path, e.g. `["posts", "comments"]`.
+    fn for_embed(
+        request: &ApiRequest,
+        parent: &Table,
+        embed: &RelSelectField,
+        path: &[String],
+        schema_cache: &SchemaCache,
+    ) -> Result<Self> {
+        let rel = schema_cache.resolve_relationship(
+            &parent.qualified_identifier(),
+            &embed.name,
+            embed.hint.as_deref(),
+            &parent.schema,
+        )?;
+        let table = schema_cache.require_table(rel.foreign_table())?;
+
+        let rel_join_conds = rel
+            .join_columns()
+            .into_iter()
+            .map(|(col, fcol)| JoinCondition {
+                left: (parent.qualified_identifier(), col),
+                right: (table.qualified_identifier(), fcol),
+            })
+            .collect();
+
+        // A table embedding itself needs an alias to tell its rows from the
+        // parent's, and a function's rows need one to be referred to at all
+        let from_alias = if matches!(rel, Relationship::Computed { .. }) {
+            Some(format!("pgrst_computed_{}", path.join("_")))
+        } else {
+            (table.qualified_identifier() == parent.qualified_identifier())
+                .then(|| format!("pgrst_self_{}", path.join("_")))
+        };
+
+        check_select_casts(&embed.select, schema_cache)?;
+        Ok(Self {
+            select: build_select_fields(
+                &embed.select,
+                table,
+                &request.query_params.params,
+                request.preferences.handling.as_ref(),
+            )?,
+            from: table.qualified_identifier(),
+            from_alias,
+            where_clauses: build_where_clauses(request, table, path)?,
+            order: vec![],
+            range: request
+                .query_params
+                .ranges
+                .get(&path.join("."))
+                .cloned()
+                .unwrap_or_default(),
+            rel_name: embed.name.clone(),
+            rel_to_parent: Some(rel.clone()),
+            rel_join_conds,
+            rel_join_type: Some(embed.join_type.clone()),
+            rel_select: build_relation_selects(&embed.select, table, schema_cache)?,
+            depth: path.len() as u32,
+        })
+    }
+
     /// Check if this plan has any where clauses.
     pub fn has_where(&self) -> bool {
         !self.where_clauses.is_empty()
@@ -102,11 +234,38 @@ impl ReadPlan {
     }
 }
 
+/// Reject casts to types known not to exist.
+fn check_select_casts(items: &[SelectItem], schema_cache: &SchemaCache) -> Result<()> {
+    for item in items {
+        if let SelectItem::Field { cast, aggregate_cast, .. } = item {
+            for cast in [cast, aggregate_cast].into_iter().flatten() {
+                schema_cache.check_cast(cast)?;
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Build select fields from select items.
+///
+/// `params` holds the query parameters that select items bind by name.
+/// Unknown columns are dropped under `handling=lenient`, reported as
+/// unknown under `handling=strict`, and not found otherwise.
 fn build_select_fields(
     items: &[SelectItem],
     table: &Table,
+    params: &[(String, String)],
+    handling: Option<&PreferHandling>,
 ) -> Result<Vec<CoercibleSelectField>> {
+    let column = |name: &String| -> Result<Option<&Column>> {
+        match (table.get_column(name), handling) {
+            (Some(column), _) => Ok(Some(column)),
+            (None, Some(PreferHandling::Lenient)) => Ok(None),
+            (None, Some(PreferHandling::Strict)) => Err(Error::UnknownColumn(name.clone())),
+            (None, None) => Err(Error::ColumnNotFound(name.clone())),
+        }
+    };
+
     if items.is_empty() {
         // Default: select all columns
         return Ok(table
@@ -120,6 +279,15 @@ fn build_select_fields(
 
     for item in items {
         match item {
+            // `*` expands to every column, alongside any embeds
+            item if item.is_wildcard() => {
+                fields.extend(
+                    table
+                        .columns
+                        .iter()
+                        .map(|(name, col)| CoercibleSelectField::simple(name, &col.data_type)),
+                );
+            }
             SelectItem::Field {
                 field,
                 aggregate,
@@ -127,17 +295,42 @@ fn build_select_fields(
                 cast,
                 alias,
             } => {
-                let column = table
-                    .get_column(&field.name)
-                    .ok_or_else(|| Error::ColumnNotFound(field.name.clone()))?;
+                // `count()` counts rows, not a column
+                let data_type = match aggregate {
+                    Some(AggregateFunction::Count) if field.name == "*" => "bigint",
+                    _ => match column(&field.name)? {
+                        Some(column) => &column.data_type,
+                        None => continue,
+                    },
+                };
 
                 fields.push(CoercibleSelectField {
-                    field: CoercibleField::from_field(field, &column.data_type),
+                    field: CoercibleField::from_field(field, data_type),
                     aggregate: aggregate.clone(),
                     aggregate_cast: aggregate_cast.clone(),
                     cast: cast.clone(),
                     alias: alias.clone(),
+                    headline: None,
+                });
+            }
+            SelectItem::Headline { column: name, function, language, param, alias } => {
+                let Some(col) = column(name)? else {
+                    continue;
+                };
+                let query = params
+                    .iter()
+                    .find(|(name, _)| name == param)
+                    .map(|(_, value)| value.clone())
+                    .ok_or_else(|| Error::MissingParameter(param.clone()))?;
+
+                let mut field = CoercibleSelectField::simple(name, &col.data_type);
+                field.alias = Some(alias.clone().unwrap_or_else(|| "ts_headline".to_string()));
+                field.headline = Some(HeadlineQuery {
+                    function: function.clone(),
+                    language: language.clone(),
+                    query,
                 });
+                fields.push(field);
             }
             // Relations are handled separately
             SelectItem::Relation { .. } | SelectItem::SpreadRelation { .. } => {}
@@ -147,10 +340,12 @@ fn build_select_fields(
     Ok(fields)
 }
 
-/// Build where clauses from request filters.
+/// Build where clauses from the request filters addressed to `path`
+/// (empty for the root resource).
 fn build_where_clauses(
     request: &ApiRequest,
     table: &Table,
+    path: &[String],
 ) -> Result<Vec<CoercibleLogicTree>> {
     let type_resolver = |name: &str| -> String {
         table
@@ -161,45 +356,217 @@ fn build_where_clauses(
 
     let mut clauses = Vec::new();
 
-    // Add root filters
-    for filter in &request.query_params.filters_root {
+    // Add filters
+    let filters: Vec<&crate::api_request::Filter> = if path.is_empty() {
+        request.query_params.filters_root.iter().collect()
+    } else {
+        request
+            .query_params
+            .filters
+            .iter()
+            .filter(|(p, _)| p == path)
+            .map(|(_, f)| f)
+            .collect()
+    };
+    for filter in filters {
+        check_operator(filter, table)?;
         let pg_type = type_resolver(&filter.field.name);
         clauses.push(CoercibleLogicTree::Stmt(CoercibleFilter::from_filter(
             filter, &pg_type,
         )));
     }
 
-    // Add logic trees
-    for (path, tree) in &request.query_params.logic {
-        if path.is_empty() {
-            clauses.push(CoercibleLogicTree::from_logic_tree(tree, type_resolver));
+    // Add logic trees; an `and` group just extends the clauses' implicit AND
+    for (p, tree) in &request.query_params.logic {
+        if p == path {
+            check_logic_operators(tree, table)?;
+            match CoercibleLogicTree::from_logic_tree(tree, type_resolver) {
+                CoercibleLogicTree::Expr { negated: false, op: LogicOperator::And, children } => {
+                    clauses.extend(children)
+                }
+                clause => clauses.push(clause),
+            }
         }
     }
 
+    clauses.iter_mut().for_each(|c| c.cast_domains(table));
     Ok(clauses)
 }
 
+/// Reject operators that don't apply to the filtered column's type.
+///
+/// Array columns compare by containment (`cs`, `cd`, `ov`) or whole-value
+/// equality; ordering, pattern, text search and `in` would compare against
+/// the array itself. Containment needs an array, range or JSON column, and
+/// the positional range operators need a range column.
+fn check_operator(filter: &Filter, table: &Table) -> Result<()> {
+    let Some(column) = table.get_column(&filter.field.name) else {
+        return Ok(());
+    };
+    if !filter.field.json_path.is_empty() {
+        return Ok(());
+    }
+
+    let (sql_op, supported) = match &filter.op_expr.operation {
+        Operation::Simple { op, .. } => {
+            let supported = match op {
+                SimpleOperator::NotEqual => true,
+                SimpleOperator::Contains | SimpleOperator::Contained | SimpleOperator::Overlap => {
+                    column.is_array() || column.is_range() || column.is_json()
+                }
+                _ => column.is_range(),
+            };
+            (op.to_sql(), supported)
+        }
+        Operation::Quant { op, .. } => (
+            op.to_sql(),
+            !column.is_array() || matches!(op, QuantOperator::Equal),
+        ),
+        Operation::In(_) => ("IN", !column.is_array()),
+        Operation::Fts { .. } => ("@@", !column.is_array()),
+        Operation::Is(_) | Operation::IsDistinctFrom(_) => return Ok(()),
+    };
+
+    if supported {
+        return Ok(());
+    }
+    let hint = if column.is_array() {
+        "; use cs, cd or ov to match array elements"
+    } else {
+        ""
+    };
+    Err(Error::InvalidQueryParam(format!(
+        "operator {} does not apply to column '{}' of type {}{}",
+        sql_op, column.name, column.data_type, hint
+    )))
+}
+
+/// Check every filter in a logic tree with [`check_operator`].
+fn check_logic_operators(tree: &LogicTree, table: &Table) -> Result<()> {
+    match tree {
+        LogicTree::Expr { children, .. } => children
+            .iter()
+            .try_for_each(|child| check_logic_operators(child, table)),
+        LogicTree::Stmt(filter) => check_operator(filter, table),
+    }
+}
+
+/// Rewrite an order term naming a select alias (`select=full:name`,
+/// `order=full`) to order by what the alias selects.
+///
+/// Aliased aggregates and headlines have no column underneath, so they are
+/// ordered by their output name, which only works without a JSON path or
+/// cast.
+fn resolve_order_alias(
+    term: &crate::api_request::OrderTerm,
+    select: &[SelectItem],
+) -> Result<Option<crate::api_request::OrderTerm>> {
+    use crate::api_request::OrderTerm;
+    let OrderTerm::Field { field, direction, nulls, cast } = term else {
+        return Ok(None);
+    };
+    let aliased = select.iter().find(|item| match item {
+        SelectItem::Field { alias, .. } | SelectItem::Headline { alias, .. } => alias.as_ref() == Some(&field.name),
+        _ => false,
+    });
+
+    match aliased {
+        Some(SelectItem::Field { field: selected, aggregate: None, cast: selected_cast, .. }) => {
+            let mut resolved = selected.clone();
+            resolved.json_path.extend(field.json_path.iter().cloned());
+            Ok(Some(OrderTerm::Field {
+                field: resolved,
+                direction: direction.clone(),
+                nulls: nulls.clone(),
+                cast: cast.clone().or_else(|| selected_cast.clone()),
+            }))
+        }
+        Some(_) if field.json_path.is_empty() && cast.is_none() => Ok(Some(term.clone())),
+        Some(_) => Err(Error::InvalidQueryParam(format!(
+            "cannot order by a JSON path or cast of '{}', which is not a column",
+            field.name
+        ))),
+        None => Ok(None),
+    }
+}
+
 /// Build order terms from request.
+///
+/// A field term names a column or an alias from the select list. Terms on
+/// an embedded relation (`order=author.name`) must go through a to-one
+/// relationship; ordering by a to-many embed has no single value per parent
+/// row.
 fn build_order_terms(
     request: &ApiRequest,
     table: &Table,
+    rel_select: &[RelSelectField],
+    schema_cache: &SchemaCache,
 ) -> Result<Vec<CoercibleOrderTerm>> {
     let mut terms = Vec::new();
 
     for (path, order_terms) in &request.query_params.order {
-        if path.is_empty() {
-            for term in order_terms {
-                let field_name = match term {
-                    crate::api_request::OrderTerm::Field { field, .. } => &field.name,
-                    crate::api_request::OrderTerm::Relation { field, .. } => &field.name,
-                };
+        if !path.is_empty() {
+            continue;
+        }
+        for term in order_terms {
+            match term {
+                crate::api_request::OrderTerm::Field { field, .. } => {
+                    let term = match resolve_order_alias(term, &request.query_params.select)? {
+                        Some(term) => term,
+                        None if table.get_column(&field.name).is_some() => term.clone(),
+                        None => return Err(Error::ColumnNotFound(field.name.clone())),
+                    };
+                    let crate::api_request::OrderTerm::Field { field, .. } = &term else {
+                        unreachable!("aliases resolve to field terms");
+                    };
+                    let pg_type = table
+                        .get_column(&field.name)
+                        .map(|c| c.data_type.as_str())
+                        .unwrap_or("text");
+                    terms.push(CoercibleOrderTerm::from_order_term(&term, pg_type));
+                }
+                crate::api_request::OrderTerm::Relation { relation, field, .. } => {
+                    // The relation may be addressed by its embed alias
+                    let embed = rel_select.iter().find(|r| r.path_name() == relation);
+                    let rel = schema_cache.resolve_relationship(
+                        &table.qualified_identifier(),
+                        embed.map_or(relation.as_str(), |e| e.name.as_str()),
+                        embed.and_then(|e| e.hint.as_deref()),
+                        &table.schema,
+                    )?;
+                    if !rel.is_to_one() {
+                        return Err(Error::EmbeddingError(format!(
+                            "cannot order by '{}.{}': '{}' is a to-many relationship",
+                            relation, field.name, relation
+                        )));
+                    }
+                    if matches!(rel, Relationship::Computed { .. }) {
+                        return Err(Error::EmbeddingError(format!(
+                            "cannot order by '{}.{}': '{}' is a computed relationship",
+                            relation, field.name, relation
+                        )));
+                    }
 
-                let pg_type = table
-                    .get_column(field_name)
-                    .map(|c| c.data_type.as_str())
-                    .unwrap_or("text");
+                    let foreign = schema_cache.require_table(rel.foreign_table())?;
+                    let pg_type = foreign
+                        .get_column(&field.name)
+                        .map(|c| c.data_type.as_str())
+                        .ok_or_else(|| Error::ColumnNotFound(field.name.clone()))?;
 
-                terms.push(CoercibleOrderTerm::from_order_term(term, pg_type));
+                    let mut coerced = CoercibleOrderTerm::from_order_term(term, pg_type);
+                    coerced.rel_join = Some(OrderRelation {
+                        table: foreign.qualified_identifier(),
+                        join_conds: rel
+                            .join_columns()
+                            .into_iter()
+                            .map(|(col, fcol)| JoinCondition {
+                                left: (table.qualified_identifier(), col),
+                                right: (foreign.qualified_identifier(), fcol),
+                            })
+                            .collect(),
+                    });
+                    terms.push(coerced);
+                }
             }
         }
     }
@@ -220,35 +587,49 @@ fn build_relation_selects(
             SelectItem::Relation {
                 relation,
                 alias,
-                hint: _,
+                hint,
                 join_type,
+                select,
             } => {
-                // Verify relationship exists
-                let _rel = schema_cache
-                    .find_relationship(&table.qualified_identifier(), relation, &table.schema)
-                    .ok_or_else(|| Error::RelationshipNotFound(relation.clone()))?;
+                // Verify relationship exists and is unambiguous
+                let _rel = schema_cache.resolve_relationship(
+                    &table.qualified_identifier(),
+                    relation,
+                    hint.as_deref(),
+                    &table.schema,
+                )?;
 
                 rel_selects.push(RelSelectField {
                     name: relation.clone(),
                     agg_alias: alias.clone().unwrap_or_else(|| format!("pgrst_{}", relation)),
                     join_type: join_type.clone().unwrap_or_default(),
                     is_spread: false,
+                    alias: alias.clone(),
+                    hint: hint.clone(),
+                    select: select.clone(),
                 });
             }
             SelectItem::SpreadRelation {
                 relation,
-                hint: _,
+                hint,
                 join_type,
+                select,
             } => {
-                let _rel = schema_cache
-                    .find_relationship(&table.qualified_identifier(), relation, &table.schema)
-                    .ok_or_else(|| Error::RelationshipNotFound(relation.clone()))?;
+                let _rel = schema_cache.resolve_relationship(
+                    &table.qualified_identifier(),
+                    relation,
+                    hint.as_deref(),
+                    &table.schema,
+                )?;
 
                 rel_selects.push(RelSelectField {
                     name: relation.clone(),
                     agg_alias: format!("pgrst_spread_{}", relation),
                     join_type: join_type.clone().unwrap_or_default(),
                     is_spread: true,
+                    alias: None,
+                    hint: hint.clone(),
+                    select: select.clone(),
                 });
             }
             _ => {}
@@ -289,6 +670,56 @@ impl ReadPlanTree {
         }
     }
 
+    /// Create a tree for a request: the root plan plus one child per embed.
+    pub fn from_request(
+        request: &ApiRequest,
+        table: &Table,
+        schema_cache: &SchemaCache,
+    ) -> Result<Self> {
+        let root = ReadPlan::from_request(request, table, schema_cache)?;
+        Self::with_embeds(root, request, table, &[], schema_cache)
+    }
+
+    /// Create the tree for an RPC whose result rows have the type of `table`.
+    pub fn for_call(
+        request: &ApiRequest,
+        table: &Table,
+        arguments: &[(String, String)],
+        schema_cache: &SchemaCache,
+    ) -> Result<Self> {
+        let root = ReadPlan::for_call(request, table, arguments, schema_cache)?;
+        Self::with_embeds(root, request, table, &[], schema_cache)
+    }
+
+    /// Add one child per embedded resource selected by `root`, recursively.
+    ///
+    /// `path` is the path of `root` itself, empty for the top level.
+    fn with_embeds(
+        root: ReadPlan,
+        request: &ApiRequest,
+        table: &Table,
+        path: &[String],
+        schema_cache: &SchemaCache,
+    ) -> Result<Self> {
+        let mut tree = Self::leaf(root);
+
+        for embed in tree.root.rel_select.clone() {
+            let mut child_path = path.to_vec();
+            child_path.push(embed.path_name().to_string());
+            if child_path.len() > MAX_EMBED_DEPTH {
+                return Err(Error::EmbeddingError(format!(
+                    "embeds are nested more than {} levels deep",
+                    MAX_EMBED_DEPTH
+                )));
+            }
+            let child = ReadPlan::for_embed(request, table, &embed, &child_path, schema_cache)?;
+            let child_table = schema_cache.require_table(&child.from)?;
+            tree.add_child(Self::with_embeds(child, request, child_table, &child_path, schema_cache)?);
+        }
+
+        Ok(tree)
+    }
+
     /// Create a leaf tree (no children).
     pub fn leaf(plan: ReadPlan) -> Self {
         Self {
@@ -307,10 +738,633 @@ impl ReadPlanTree {
 mod tests {
     use super::*;
 
+    use crate::api_request::query_params::parse_query_params;
+    use crate::schema_cache::{Cardinality, Column};
+    use indexmap::IndexMap;
+    use std::collections::HashMap;
+
+    fn table(name: &str, cols: &[&str]) -> Table {
+        let mut columns = IndexMap::new();
+        for (i, col) in cols.iter().enumerate() {
+            columns.insert(
+                col.to_string(),
+                Column {
+                    name: col.to_string(),
+                    nullable: true,
+                    data_type: "text".into(),
+                    nominal_type: "text".into(),
+                    is_pk: *col == "id",
+                    position: i as i32 + 1,
+                    ..Default::default()
+                },
+            );
+        }
+        Table {
+            schema: "public".into(),
+            name: name.into(),
+            description: None,
+            is_view: false,
+            insertable: true,
+            updatable: true,
+            deletable: true,
+            pk_cols: vec!["id".into()],
+            columns,
+        }
+    }
+
+    /// users(id, name) <- posts(id, title, author_id), seen from both sides
+    fn users_posts_cache() -> SchemaCache {
+        let users = QualifiedIdentifier::new("public", "users");
+        let posts = QualifiedIdentifier::new("public", "posts");
+
+        let mut tables = HashMap::new();
+        tables.insert(users.clone(), table("users", &["id", "name"]));
+        tables.insert(posts.clone(), table("posts", &["id", "title", "author_id"]));
+
+        let mut relationships = HashMap::new();
+        relationships.insert(
+            (users.clone(), "public".to_string()),
+            vec![Relationship::ForeignKey {
+                table: users,
+                foreign_table: posts.clone(),
+                is_self: false,
+                cardinality: Cardinality::O2M {
+                    constraint: "posts_author_id_fkey".into(),
+                    columns: vec![("id".into(), "author_id".into())],
+                },
+                table_is_view: false,
+                foreign_table_is_view: false,
+                constraint_name: "posts_author_id_fkey".into(),
+            }],
+        );
+        relationships.insert(
+            (posts.clone(), "public".to_string()),
+            vec![Relationship::ForeignKey {
+                table: posts,
+                foreign_table: QualifiedIdentifier::new("public", "users"),
+                is_self: false,
+                cardinality: Cardinality::M2O {
+                    constraint: "posts_author_id_fkey".into(),
+                    columns: vec![("author_id".into(), "id".into())],
+                },
+                table_is_view: false,
+                foreign_table_is_view: false,
+                constraint_name: "posts_author_id_fkey".into(),
+            }],
+        );
+
+        SchemaCache {
+            tables,
+            relationships,
+            pg_version: 150000,
+            schemas: vec!["public".into()],
+            ..Default::default()
+        }
+    }
+
+    fn plan_tree(table: &str, query: &str) -> Result<ReadPlanTree> {
+        let cache = users_posts_cache();
+        let request = ApiRequest {
+            schema: "public".into(),
+            query_params: parse_query_params(query).unwrap(),
+            ..Default::default()
+        };
+        let table = &cache.tables[&QualifiedIdentifier::new("public", table)];
+        ReadPlanTree::from_request(&request, table, &cache)
+    }
+
+    fn users_tree(query: &str) -> ReadPlanTree {
+        plan_tree("users", query).unwrap()
+    }
+
+    fn tagged_tree(query: &str) -> Result<ReadPlanTree> {
+        let mut posts = table("posts", &["id", "title", "tags"]);
+        let tags = posts.columns.get_mut("tags").unwrap();
+        tags.data_type = "ARRAY".into();
+        tags.nominal_type = "text[]".into();
+        let request = ApiRequest {
+            schema: "public".into(),
+            query_params: parse_query_params(query).unwrap(),
+            ..Default::default()
+        };
+        ReadPlanTree::from_request(&request, &posts, &users_posts_cache())
+    }
+
+    #[test]
+    fn test_embedded_logic_goes_to_child_plan() {
+        let tree = users_tree("select=id,posts(*)&posts.or=(title.eq.a,title.eq.b)&name=eq.x");
+
+        assert_eq!(tree.children.len(), 1);
+        let child = &tree.children[0].root;
+        assert_eq!(child.from.name, "posts");
+        assert_eq!(child.where_clauses.len(), 1);
+        assert!(matches!(
+            &child.where_clauses[0],
+            CoercibleLogicTree::Expr { op: crate::api_request::LogicOperator::Or, children, .. }
+                if children.len() == 2
+        ));
+
+        // Only the root filter stays on the parent
+        assert_eq!(tree.root.where_clauses.len(), 1);
+        assert!(matches!(&tree.root.where_clauses[0], CoercibleLogicTree::Stmt(f) if f.field.name == "name"));
+    }
+
+    #[test]
+    fn test_embedded_filters_and_join_conditions() {
+        let tree = users_tree("select=id,posts(*)&posts.title=eq.a");
+        let child = &tree.children[0].root;
+
+        assert!(tree.root.where_clauses.is_empty());
+        assert!(matches!(&child.where_clauses[0], CoercibleLogicTree::Stmt(f) if f.field.name == "title"));
+        assert_eq!(child.rel_join_conds.len(), 1);
+        assert_eq!(child.rel_join_conds[0].left.1, "id");
+        assert_eq!(child.rel_join_conds[0].right.1, "author_id");
+    }
+
+    #[test]
+    fn test_embedded_limit_uses_lateral_subquery() {
+        let tree = users_tree("select=id,posts(*)&posts.limit=2&posts.offset=1");
+        let (sql, _) = crate::query::QueryBuilder::build_read(&tree).unwrap().build();
+        assert_eq!(
+            sql,
+            "SELECT \"id\", \"pgrst_posts\".\"posts\" AS \"posts\" FROM \"public\".\"users\" \
+             LEFT JOIN LATERAL (SELECT COALESCE(json_agg(\"pgrst_rows\"), '[]'::json) AS \"posts\" FROM (\
+             SELECT \"id\", \"title\", \"author_id\" FROM \"public\".\"posts\" \
+             WHERE \"public\".\"posts\".\"author_id\" = \"public\".\"users\".\"id\" LIMIT 2 OFFSET 1\
+             ) AS \"pgrst_rows\") AS \"pgrst_posts\" ON TRUE"
+        );
+
+        // To-one embeds are objects; an inner join drops parents without a match
+        let tree = plan_tree("posts", "select=title,users!posts_author_id_fkey!inner(*)").unwrap();
+        let (sql, _) = crate::query::QueryBuilder::build_read(&tree).unwrap().build();
+        assert!(sql.contains("LEFT JOIN LATERAL (SELECT row_to_json(\"pgrst_rows\") AS \"users\""), "{}", sql);
+        assert!(sql.ends_with("WHERE \"pgrst_users\".\"users\" IS NOT NULL"), "{}", sql);
+    }
+
+    #[test]
+    fn test_wildcard_with_embed_selects_all_columns_and_relation() {
+        let tree = plan_tree("posts", "select=*,users(name)").unwrap();
+        let columns: Vec<_> = tree.root.select.iter().map(|f| f.field.name.as_str()).collect();
+        assert_eq!(columns, ["id", "title", "author_id"]);
+        assert_eq!(tree.children.len(), 1);
+        let child = &tree.children[0].root;
+        let columns: Vec<_> = child.select.iter().map(|f| f.field.name.as_str()).collect();
+        assert_eq!(columns, ["name"]);
+
+        let (sql, _) = crate::query::QueryBuilder::build_read(&tree).unwrap().build();
+        assert!(sql.starts_with(
+            "SELECT \"id\", \"title\", \"author_id\", \"pgrst_users\".\"users\" AS \"users\" FROM \"public\".\"posts\" \
+             LEFT JOIN LATERAL (SELECT row_to_json(\"pgrst_rows\") AS \"users\" FROM (SELECT \"name\" FROM \"public\".\"users\" \
+             WHERE \"public\".\"users\".\"id\" = \"public\".\"posts\".\"author_id\")"
+        ), "{}", sql);
+    }
+
+    #[test]
+    fn test_nested_embeds_build_nested_plans() {
+        let tree = users_tree("select=name,posts(title,users(name))&posts.limit=1");
+        let posts = &tree.children[0];
+        assert_eq!(posts.root.range.limit, Some(1));
+        assert_eq!(posts.children.len(), 1);
+        assert_eq!(posts.children[0].root.from.name, "users");
+        assert_eq!(posts.children[0].root.depth, 2);
+    }
+
+    /// categories(id, name, parent_id -> categories.id)
+    fn categories_tree(query: &str) -> Result<ReadPlanTree> {
+        let categories = QualifiedIdentifier::new("public", "categories");
+        let fk = |cardinality| Relationship::ForeignKey {
+            table: categories.clone(),
+            foreign_table: categories.clone(),
+            is_self: true,
+            cardinality,
+            table_is_view: false,
+            foreign_table_is_view: false,
+            constraint_name: "categories_parent_id_fkey".into(),
+        };
+        let mut cache = users_posts_cache();
+        cache.tables.insert(categories.clone(), table("categories", &["id", "name", "parent_id"]));
+        cache.relationships.insert(
+            (categories.clone(), "public".to_string()),
+            vec![
+                fk(Cardinality::M2O {
+                    constraint: "categories_parent_id_fkey".into(),
+                    columns: vec![("parent_id".into(), "id".into())],
+                }),
+                fk(Cardinality::O2M {
+                    constraint: "categories_parent_id_fkey".into(),
+                    columns: vec![("id".into(), "parent_id".into())],
+                }),
+            ],
+        );
+
+        let request = ApiRequest {
+            schema: "public".into(),
+            query_params: parse_query_params(query).unwrap(),
+            ..Default::default()
+        };
+        ReadPlanTree::from_request(&request, &cache.tables[&categories], &cache)
+    }
+
+    #[test]
+    fn test_self_referential_embed_in_both_directions() {
+        let tree = categories_tree("select=name,parent(name),children(name)").unwrap();
+        let parent = &tree.children[0].root;
+        assert!(parent.rel_to_parent.as_ref().unwrap().is_to_one());
+        assert_eq!(parent.from_alias.as_deref(), Some("pgrst_self_parent"));
+        let children = &tree.children[1].root;
+        assert!(!children.rel_to_parent.as_ref().unwrap().is_to_one());
+
+        let (sql, _) = crate::query::QueryBuilder::build_read(&tree).unwrap().build();
+        assert!(sql.contains(
+            "SELECT row_to_json(\"pgrst_rows\") AS \"parent\" FROM (SELECT \"name\" \
+             FROM \"public\".\"categories\" AS \"pgrst_self_parent\" \
+             WHERE \"pgrst_self_parent\".\"id\" = \"public\".\"categories\".\"parent_id\")"
+        ), "{}", sql);
+        assert!(sql.contains(
+            "SELECT COALESCE(json_agg(\"pgrst_rows\"), '[]'::json) AS \"children\" FROM (SELECT \"name\" \
+             FROM \"public\".\"categories\" AS \"pgrst_self_children\" \
+             WHERE \"pgrst_self_children\".\"parent_id\" = \"public\".\"categories\".\"id\")"
+        ), "{}", sql);
+
+        // The foreign key column names the parent too, and levels nest
+        let tree = categories_tree("select=name,parent_id(name,parent(name))").unwrap();
+        let grandparent = &tree.children[0].children[0].root;
+        assert_eq!(grandparent.from_alias.as_deref(), Some("pgrst_self_parent_id_parent"));
+        let (sql, _) = crate::query::QueryBuilder::build_read(&tree).unwrap().build();
+        assert!(sql.contains(
+            "WHERE \"pgrst_self_parent_id_parent\".\"id\" = \"pgrst_self_parent_id\".\"parent_id\""
+        ), "{}", sql);
+
+        // The table's own name could mean either direction
+        let err = categories_tree("select=name,categories(name)").unwrap_err();
+        let Error::AmbiguousEmbedding { candidates, .. } = &err else {
+            panic!("Expected an ambiguous embedding, got {:?}", err);
+        };
+        let mut embeds: Vec<&str> = candidates.iter().map(|(embed, _)| embed.as_str()).collect();
+        embeds.sort();
+        assert_eq!(embeds, vec!["children", "parent"]);
+
+        let deep = format!("select=name,{}{}", "parent(".repeat(MAX_EMBED_DEPTH + 1), ")".repeat(MAX_EMBED_DEPTH + 1));
+        let err = categories_tree(&deep.replace("parent()", "parent(name)")).unwrap_err();
+        assert!(matches!(err, Error::EmbeddingError(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_computed_relationship_embeds_through_function() {
+        let users = QualifiedIdentifier::new("public", "users");
+        let mut cache = users_posts_cache();
+        cache.relationships.get_mut(&(users.clone(), "public".to_string())).unwrap().push(Relationship::Computed {
+            function: QualifiedIdentifier::new("public", "recent_posts"),
+            table: users.clone(),
+            foreign_table: QualifiedIdentifier::new("public", "posts"),
+            table_alias: users.clone(),
+            to_one: false,
+            is_self: false,
+        });
+        let request = ApiRequest {
+            schema: "public".into(),
+            query_params: parse_query_params("select=name,recent_posts(title)&recent_posts.title=eq.a").unwrap(),
+            ..Default::default()
+        };
+        let tree = ReadPlanTree::from_request(&request, &cache.tables[&users], &cache).unwrap();
+        let child = &tree.children[0].root;
+        assert_eq!(child.from.name, "posts");
+        assert!(child.rel_join_conds.is_empty());
+
+        let (sql, params) = crate::query::QueryBuilder::build_read(&tree).unwrap().build();
+        assert_eq!(
+            sql,
+            "SELECT \"name\", \"pgrst_recent_posts\".\"recent_posts\" AS \"recent_posts\" FROM \"public\".\"users\" \
+             LEFT JOIN LATERAL (SELECT COALESCE(json_agg(\"pgrst_rows\"), '[]'::json) AS \"recent_posts\" FROM (SELECT \"title\" \
+             FROM \"public\".\"recent_posts\"(\"public\".\"users\".*) AS \"pgrst_computed_recent_posts\" \
+             WHERE \"title\" = $1) AS \"pgrst_rows\") AS \"pgrst_recent_posts\" ON TRUE"
+        );
+        assert_eq!(params.len(), 1);
+
+        // The posts table itself is still reached through the foreign key
+        let request = ApiRequest {
+            schema: "public".into(),
+            query_params: parse_query_params("select=name,posts(title)").unwrap(),
+            ..Default::default()
+        };
+        let tree = ReadPlanTree::from_request(&request, &cache.tables[&users], &cache).unwrap();
+        assert!(!tree.children[0].root.rel_join_conds.is_empty());
+    }
+
+    #[test]
+    fn test_empty_embeds_are_empty_array_or_null() {
+        // No posts: the aggregate over no rows is coalesced to []
+        let (sql, _) = crate::query::QueryBuilder::build_read(&users_tree("select=id,posts(id)")).unwrap().build();
+        assert!(
+            sql.contains("(SELECT COALESCE(json_agg(\"pgrst_rows\"), '[]'::json) AS \"posts\" FROM ("),
+            "{}",
+            sql
+        );
+
+        // No author: the lateral subquery has no row, so the left join leaves NULL
+        let (sql, _) = crate::query::QueryBuilder::build_read(&plan_tree("posts", "select=id,users(id)").unwrap())
+            .unwrap()
+            .build();
+        assert!(
+            sql.contains("LEFT JOIN LATERAL (SELECT row_to_json(\"pgrst_rows\") AS \"users\" FROM ("),
+            "{}",
+            sql
+        );
+        assert!(!sql.contains("COALESCE"), "{}", sql);
+    }
+
+    #[test]
+    fn test_spread_aggregate_is_flattened_into_parent() {
+        let tree = users_tree("select=name,...posts(total:count(),max(title))");
+        assert_eq!(tree.children.len(), 1);
+
+        let (sql, _) = crate::query::QueryBuilder::build_read(&tree).unwrap().build();
+        assert_eq!(
+            sql,
+            "SELECT \"name\", \"pgrst_posts\".* FROM \"public\".\"users\" \
+             LEFT JOIN LATERAL (SELECT COUNT(*) AS \"total\", MAX(\"title\") FROM \"public\".\"posts\" \
+             WHERE \"public\".\"posts\".\"author_id\" = \"public\".\"users\".\"id\") AS \"pgrst_posts\" ON TRUE"
+        );
+
+        // Plain columns next to an aggregate are grouped by
+        let tree = plan_tree("posts", "select=author_id,count()").unwrap();
+        let (sql, _) = crate::query::QueryBuilder::build_read(&tree).unwrap().build();
+        assert_eq!(sql, "SELECT \"author_id\", COUNT(*) FROM \"public\".\"posts\" GROUP BY \"author_id\"");
+    }
+
+    #[test]
+    fn test_aliased_embed_uses_alias_as_key() {
+        let tree = plan_tree("posts", "select=title,writer:users(name)&writer.name=eq.a").unwrap();
+        assert_eq!(tree.root.rel_select[0].name, "users");
+        assert_eq!(tree.root.rel_select[0].path_name(), "writer");
+        // Embed filters address the embed by its alias
+        assert_eq!(tree.children[0].root.where_clauses.len(), 1);
+
+        let (sql, _) = crate::query::QueryBuilder::build_read(&tree).unwrap().build();
+        assert!(sql.starts_with(
+            "SELECT \"title\", \"pgrst_writer\".\"writer\" AS \"writer\" FROM \"public\".\"posts\" \
+             LEFT JOIN LATERAL (SELECT row_to_json(\"pgrst_rows\") AS \"writer\" FROM (SELECT \"name\" FROM \"public\".\"users\""
+        ), "{}", sql);
+    }
+
+    #[test]
+    fn test_unknown_select_column_under_strict_and_lenient_handling() {
+        let cache = users_posts_cache();
+        let table = &cache.tables[&QualifiedIdentifier::new("public", "users")];
+        let plan = |handling| {
+            let mut request = ApiRequest {
+                schema: "public".into(),
+                query_params: parse_query_params("select=id,bogus,posts(title,nope)").unwrap(),
+                ..Default::default()
+            };
+            request.preferences.handling = handling;
+            ReadPlanTree::from_request(&request, table, &cache)
+        };
+
+        let err = plan(Some(PreferHandling::Strict)).unwrap_err();
+        assert!(matches!(err, Error::UnknownColumn(ref c) if c == "bogus"), "{:?}", err);
+        assert_eq!(err.status_code(), http::StatusCode::BAD_REQUEST);
+
+        let tree = plan(Some(PreferHandling::Lenient)).unwrap();
+        let columns: Vec<_> = tree.root.select.iter().map(|f| f.field.name.as_str()).collect();
+        assert_eq!(columns, ["id"]);
+        let columns: Vec<_> = tree.children[0].root.select.iter().map(|f| f.field.name.as_str()).collect();
+        assert_eq!(columns, ["title"]);
+
+        // Without a preference the column is not found
+        assert!(matches!(plan(None).unwrap_err(), Error::ColumnNotFound(_)));
+    }
+
     #[test]
     fn test_read_plan_tree_empty() {
         let tree = ReadPlanTree::empty();
         assert!(tree.root.select.is_empty());
         assert!(tree.children.is_empty());
     }
+
+    #[test]
+    fn test_order_by_select_alias() {
+        let tree = users_tree("select=id,full:name&order=full.desc");
+        assert_eq!(tree.root.order[0].field.name, "name");
+        let (sql, _) = crate::query::QueryBuilder::build_read(&tree).unwrap().build();
+        assert_eq!(sql, "SELECT \"id\", \"name\" AS \"full\" FROM \"public\".\"users\" ORDER BY \"name\" DESC");
+
+        // The alias's cast carries over
+        let tree = users_tree("select=n:name::int&order=n");
+        assert_eq!(tree.root.order[0].field.name, "name");
+        assert_eq!(tree.root.order[0].cast.as_deref(), Some("int"));
+
+        // An aggregate is ordered by its output name
+        let tree = plan_tree("posts", "select=author_id,total:count()&order=total.desc").unwrap();
+        let (sql, _) = crate::query::QueryBuilder::build_read(&tree).unwrap().build();
+        assert!(sql.ends_with("GROUP BY \"author_id\" ORDER BY \"total\" DESC"), "{}", sql);
+
+        // Neither a column nor an alias
+        let err = plan_tree("users", "select=id,full:name&order=nickname").unwrap_err();
+        assert!(matches!(err, Error::ColumnNotFound(ref c) if c == "nickname"), "{:?}", err);
+    }
+
+    #[test]
+    fn test_order_by_to_one_embedded_column() {
+        let tree = plan_tree("posts", "order=users.name.desc,id").unwrap();
+        let order = &tree.root.order;
+
+        assert_eq!(order.len(), 2);
+        assert_eq!(order[0].relation.as_deref(), Some("users"));
+        assert_eq!(order[0].field.name, "name");
+        let rel = order[0].rel_join.as_ref().unwrap();
+        assert_eq!(rel.table.name, "users");
+        assert_eq!(rel.join_conds[0].left.1, "author_id");
+        assert_eq!(rel.join_conds[0].right.1, "id");
+        assert!(order[1].rel_join.is_none());
+
+        let (sql, _) = crate::query::QueryBuilder::build_read(&tree).unwrap().build();
+        assert!(sql.ends_with(
+            "ORDER BY (SELECT \"users\".\"name\" FROM \"public\".\"users\" AS \"users\" \
+             WHERE \"users\".\"id\" = \"public\".\"posts\".\"author_id\") DESC, \"id\""
+        ), "{}", sql);
+    }
+
+    #[test]
+    fn test_array_column_operators() {
+        let tree = tagged_tree("tags=cs.{a,b}&or=(tags.ov.{c},tags.eq.{})").unwrap();
+        assert_eq!(tree.root.where_clauses.len(), 2);
+
+        let err = tagged_tree("tags=gt.1").unwrap_err();
+        assert!(matches!(&err, Error::InvalidQueryParam(msg) if msg.contains("use cs")), "{:?}", err);
+        assert!(tagged_tree("tags=in.(a,b)").is_err());
+        assert!(tagged_tree("or=(id.eq.1,tags.like.a*)").is_err());
+    }
+
+    fn where_sql(table: Table, query: &str) -> (String, Vec<postrust_sql::SqlParam>) {
+        let request = ApiRequest {
+            schema: "public".into(),
+            query_params: parse_query_params(query).unwrap(),
+            ..Default::default()
+        };
+        let tree = ReadPlanTree::from_request(&request, &table, &users_posts_cache()).unwrap();
+        let plan = crate::plan::ActionPlan::Db(crate::plan::DbActionPlan::Read(tree));
+        let (sql, params) = crate::query::build_query(&plan, None).unwrap().build_main();
+        let clause = sql.split_once(" WHERE ").map(|(_, w)| w.to_string()).unwrap_or_default();
+        (clause, params)
+    }
+
+    #[test]
+    fn test_or_mixes_columns_and_operators() {
+        let people = table("people", &["id", "age", "status"]);
+
+        let (clause, params) = where_sql(people.clone(), "or=(age.lt.18,age.gt.65,status.eq.banned)");
+        assert_eq!(clause, "(\"age\" < $1 OR \"age\" > $2 OR \"status\" = $3)");
+        assert_eq!(
+            params,
+            vec![
+                postrust_sql::SqlParam::Text("18".into()),
+                postrust_sql::SqlParam::Text("65".into()),
+                postrust_sql::SqlParam::Text("banned".into()),
+            ]
+        );
+
+        // Nested groups keep their own parentheses next to top-level filters
+        let (clause, params) = where_sql(
+            people.clone(),
+            "id=gt.0&or=(status.eq.banned,and(age.gte.18,status.not.eq.minor))",
+        );
+        assert_eq!(
+            clause,
+            "\"id\" > $1 AND (\"status\" = $2 OR (\"age\" >= $3 AND NOT \"status\" = $4))"
+        );
+        assert_eq!(params.len(), 4);
+
+        // A group of the same operator adds nothing, so it is flattened
+        let (clause, _) = where_sql(people.clone(), "or=(age.lt.18,or(age.gt.65,status.eq.banned))");
+        assert_eq!(clause, "(\"age\" < $1 OR \"age\" > $2 OR \"status\" = $3)");
+
+        let (clause, _) = where_sql(people, "not.or=(age.lt.18,status.eq.banned)");
+        assert_eq!(clause, "NOT (\"age\" < $1 OR \"status\" = $2)");
+    }
+
+    #[test]
+    fn test_and_group_composes_with_top_level_filters() {
+        let grades = table("grades", &["id", "grade", "student"]);
+
+        let (clause, params) = where_sql(
+            grades.clone(),
+            "and=(grade.gte.90,student.eq.true)&grade=lte.100&id=not.eq.7",
+        );
+        assert_eq!(clause, "\"grade\" <= $1 AND NOT \"id\" = $2 AND \"grade\" >= $3 AND \"student\" = $4");
+        assert_eq!(params.len(), 4);
+
+        // Repeated columns are an implicit AND; an `or` group stays grouped
+        let (clause, _) = where_sql(
+            grades.clone(),
+            "grade=gte.90&grade=lte.100&or=(student.eq.true,id.lt.5)&and=(id.gt.1)",
+        );
+        assert_eq!(
+            clause,
+            "\"grade\" >= $1 AND \"grade\" <= $2 AND \"id\" > $3 AND (\"student\" = $4 OR \"id\" < $5)"
+        );
+
+        // A negated group keeps its parentheses
+        let (clause, _) = where_sql(grades, "not.and=(grade.gte.90,student.eq.true)&grade=gt.0");
+        assert_eq!(clause, "\"grade\" > $1 AND NOT (\"grade\" >= $2 AND \"student\" = $3)");
+    }
+
+    #[test]
+    fn test_containment_on_scalar_column_is_rejected() {
+        assert!(tagged_tree("title=cs.{a}").is_err());
+        assert!(tagged_tree("title=sl.[1,2)").is_err());
+        assert!(tagged_tree("title=gt.a").is_ok());
+    }
+
+    #[test]
+    fn test_headline_binds_search_text() {
+        let tree = plan_tree(
+            "posts",
+            "select=id,hl:ts_headline(title,websearch_to_tsquery('english',$q))&q=cats' or 1=1",
+        )
+        .unwrap();
+        assert!(tree.root.where_clauses.is_empty());
+
+        let plan = crate::plan::ActionPlan::Db(crate::plan::DbActionPlan::Read(tree));
+        let (sql, params) = crate::query::build_query(&plan, None).unwrap().build_main();
+        assert!(sql.contains(
+            "ts_headline($1::regconfig, \"title\", websearch_to_tsquery($2::regconfig, $3::text)) AS \"hl\""
+        ), "{}", sql);
+        assert!(!sql.contains("cats"), "{}", sql);
+        assert_eq!(params[2], postrust_sql::SqlParam::Text("cats' or 1=1".into()));
+
+        // The bound parameter must be supplied
+        let err = plan_tree("posts", "select=ts_headline(title,to_tsquery($q))").unwrap_err();
+        assert!(matches!(err, Error::MissingParameter(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_domain_column_filters_cast_to_domain() {
+        let mut users = table("users", &["id", "email"]);
+        users.columns.get_mut("email").unwrap().domain =
+            Some(QualifiedIdentifier::new("public", "email_address"));
+        let request = ApiRequest {
+            schema: "public".into(),
+            query_params: parse_query_params(
+                "email=eq.a@b.io&or=(email.in.(c@d.io),email.like.*@x.io)",
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        let tree = ReadPlanTree::from_request(&request, &users, &users_posts_cache()).unwrap();
+
+        let plan = crate::plan::ActionPlan::Db(crate::plan::DbActionPlan::Read(tree));
+        let (sql, _) = crate::query::build_query(&plan, None).unwrap().build_main();
+        assert!(sql.contains("\"email\" = $1::\"public\".\"email_address\""), "{}", sql);
+        assert!(sql.contains("\"email\" IN ($2::\"public\".\"email_address\")"), "{}", sql);
+        // Patterns aren't values of the domain
+        assert!(sql.contains("\"email\" LIKE $3"), "{}", sql);
+        assert!(!sql.contains("$3::"), "{}", sql);
+    }
+
+    #[test]
+    fn test_select_casts_timestamptz_to_date() {
+        let mut events = table("events", &["id", "created"]);
+        events.columns.get_mut("created").unwrap().data_type = "timestamp with time zone".into();
+        let request = ApiRequest {
+            schema: "public".into(),
+            query_params: parse_query_params("select=id,created::date,created::date:day,id::numeric(10,2):total").unwrap(),
+            ..Default::default()
+        };
+        let tree = ReadPlanTree::from_request(&request, &events, &users_posts_cache()).unwrap();
+
+        let plan = crate::plan::ActionPlan::Db(crate::plan::DbActionPlan::Read(tree));
+        let (sql, _) = crate::query::build_query(&plan, None).unwrap().build_main();
+        assert!(sql.contains("\"created\"::date,"), "{}", sql);
+        assert!(sql.contains("\"created\"::date AS \"day\""), "{}", sql);
+        assert!(sql.contains("\"id\"::numeric(10,2) AS \"total\""), "{}", sql);
+    }
+
+    #[test]
+    fn test_select_cast_to_unknown_schema_type_is_rejected() {
+        let mut cache = users_posts_cache();
+        cache.types.insert(QualifiedIdentifier::new("public", "mood"));
+
+        let request = ApiRequest {
+            schema: "public".into(),
+            query_params: parse_query_params("select=name::public.mood").unwrap(),
+            ..Default::default()
+        };
+        let users = table("users", &["id", "name"]);
+        assert!(ReadPlanTree::from_request(&request, &users, &cache).is_ok());
+
+        let request = ApiRequest {
+            schema: "public".into(),
+            query_params: parse_query_params("select=name::public.feeling").unwrap(),
+            ..Default::default()
+        };
+        let err = ReadPlanTree::from_request(&request, &users, &cache).unwrap_err();
+        assert!(matches!(err, Error::InvalidQueryParam(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_order_by_to_many_embed_is_rejected() {
+        let err = plan_tree("users", "order=posts.title").unwrap_err();
+        assert!(matches!(err, Error::EmbeddingError(_)), "{:?}", err);
+    }
 }
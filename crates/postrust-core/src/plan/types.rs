@@ -4,9 +4,10 @@
 //! proper SQL generation with type coercion.
 
 use crate::api_request::{
-    AggregateFunction, Field, Filter, JoinType, JsonPath, LogicOperator,
-    LogicTree, OpExpr, OrderDirection, OrderNulls, OrderTerm, QualifiedIdentifier,
+    AggregateFunction, Field, Filter, FtsOperator, JoinType, JsonPath, LogicOperator,
+    LogicTree, OpExpr, OrderDirection, OrderNulls, OrderTerm, QualifiedIdentifier, SelectItem,
 };
+use crate::schema_cache::{Column, Table};
 use serde::{Deserialize, Serialize};
 
 /// A field with type coercion information.
@@ -63,6 +64,18 @@ impl CoercibleField {
             full_row: false,
         }
     }
+
+    /// Cast values to the column's domain, keeping `base_type` as is.
+    pub fn apply_domain(&mut self, column: &Column) {
+        if let Some(domain) = column.domain_type() {
+            self.ir_type = domain;
+        }
+    }
+
+    /// The domain that values compared to this field are cast to.
+    pub fn domain_cast(&self) -> Option<&str> {
+        (self.ir_type != self.base_type && self.json_path.is_empty()).then_some(self.ir_type.as_str())
+    }
 }
 
 /// A select field with coercion and aggregation.
@@ -78,6 +91,20 @@ pub struct CoercibleSelectField {
     pub cast: Option<String>,
     /// Output alias
     pub alias: Option<String>,
+    /// Select `ts_headline` over the field instead of the field itself
+    #[serde(default)]
+    pub headline: Option<HeadlineQuery>,
+}
+
+/// The search a `ts_headline` column highlights.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HeadlineQuery {
+    /// Function that turns the text into a tsquery
+    pub function: FtsOperator,
+    /// Text search configuration
+    pub language: Option<String>,
+    /// Search text, bound as a parameter
+    pub query: String,
 }
 
 impl CoercibleSelectField {
@@ -89,6 +116,7 @@ impl CoercibleSelectField {
             aggregate_cast: None,
             cast: None,
             alias: None,
+            headline: None,
         }
     }
 
@@ -100,6 +128,7 @@ impl CoercibleSelectField {
             aggregate_cast: None,
             cast: None,
             alias: Some(alias.to_string()),
+            headline: None,
         }
     }
 }
@@ -162,6 +191,21 @@ impl CoercibleLogicTree {
             }
         }
     }
+
+    /// Cast filter values on domain-typed columns of `table` to the domain.
+    pub fn cast_domains(&mut self, table: &Table) {
+        match self {
+            Self::Expr { children, .. } => {
+                children.iter_mut().for_each(|c| c.cast_domains(table));
+            }
+            Self::Stmt(filter) => {
+                if let Some(column) = table.get_column(&filter.field.name) {
+                    filter.field.apply_domain(column);
+                }
+            }
+            Self::NullEmbed { .. } => {}
+        }
+    }
 }
 
 /// An ORDER BY term with coercion.
@@ -175,23 +219,42 @@ pub struct CoercibleOrderTerm {
     pub nulls: Option<OrderNulls>,
     /// Relation (for embedded ordering)
     pub relation: Option<String>,
+    /// How to reach the to-one relation the field belongs to
+    #[serde(default)]
+    pub rel_join: Option<OrderRelation>,
+    /// Cast applied to the ordered value
+    #[serde(default)]
+    pub cast: Option<String>,
+}
+
+/// A to-one relation the parent is ordered through.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OrderRelation {
+    /// Related table
+    pub table: QualifiedIdentifier,
+    /// Conditions joining it to the parent row
+    pub join_conds: Vec<JoinCondition>,
 }
 
 impl CoercibleOrderTerm {
     /// Create from an order term with type info.
     pub fn from_order_term(term: &OrderTerm, pg_type: &str) -> Self {
         match term {
-            OrderTerm::Field { field, direction, nulls } => Self {
+            OrderTerm::Field { field, direction, nulls, cast } => Self {
                 field: CoercibleField::from_field(field, pg_type),
                 direction: direction.clone(),
                 nulls: nulls.clone(),
                 relation: None,
+                rel_join: None,
+                cast: cast.clone(),
             },
             OrderTerm::Relation { relation, field, direction, nulls } => Self {
                 field: CoercibleField::from_field(field, pg_type),
                 direction: direction.clone(),
                 nulls: nulls.clone(),
                 relation: Some(relation.clone()),
+                rel_join: None,
+                cast: None,
             },
         }
     }
@@ -217,6 +280,20 @@ pub struct RelSelectField {
     pub join_type: JoinType,
     /// Whether this is a spread relation
     pub is_spread: bool,
+    /// Alias given in the select (`alias:relation(...)`)
+    pub alias: Option<String>,
+    /// Disambiguation hint (`relation!hint`)
+    pub hint: Option<String>,
+    /// Select list of the embedded resource
+    pub select: Vec<SelectItem>,
+}
+
+impl RelSelectField {
+    /// Name used to address this embed in query parameters
+    /// (`posts.id=eq.1`): the alias when given, else the relation name.
+    pub fn path_name(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
 }
 
 #[cfg(test)]
@@ -1,12 +1,16 @@
 //! Query builder implementation.
 
-use crate::error::Result;
+use super::{CountKind, CountQuery};
+use crate::api_request::{JoinType, PreferCount};
+use crate::error::{Error, Result};
 use crate::plan::{
     CallPlan, CallParams, CoercibleFilter, CoercibleLogicTree, CoercibleOrderTerm,
-    CoercibleSelectField, MutatePlan, ReadPlan, ReadPlanTree,
+    CoercibleField, CoercibleSelectField, MutatePlan, NestedInsert, OrderRelation, ReadPlan,
+    ReadPlanTree, RelSelectField, ETAG_COLUMN, INSERTED_FLAG, MUTATION_RESULT,
 };
+use crate::schema_cache::Relationship;
 use postrust_sql::{
-    escape_ident, from_qi, DeleteBuilder, InsertBuilder, OrderExpr, SelectBuilder,
+    escape_ident, from_qi, quote_literal, DeleteBuilder, InsertBuilder, OrderExpr, SelectBuilder,
     SqlFragment, SqlParam, UpdateBuilder,
 };
 
@@ -16,11 +20,118 @@ pub struct QueryBuilder;
 impl QueryBuilder {
     /// Build a SELECT query from a read plan tree.
     pub fn build_read(tree: &ReadPlanTree) -> Result<SqlFragment> {
-        Self::build_read_plan(&tree.root)
+        Ok(Self::build_read_tree(tree)?.build())
     }
 
-    /// Build a SELECT query from a read plan.
-    fn build_read_plan(plan: &ReadPlan) -> Result<SqlFragment> {
+    /// Build a SELECT that also returns each row's ETag in [`ETAG_COLUMN`].
+    ///
+    /// Aggregated rows stand for no single row of the relation, so they get
+    /// none.
+    pub fn build_tagged_read(tree: &ReadPlanTree) -> Result<SqlFragment> {
+        let plan = &tree.root;
+        let mut builder = Self::build_read_tree(tree)?;
+        if plan.select.iter().any(|field| field.aggregate.is_some()) {
+            return Ok(builder.build());
+        }
+        if plan.select.is_empty() && plan.rel_select.is_empty() {
+            builder = builder.all_columns();
+        }
+        let row = plan.from_alias.as_deref().unwrap_or(&plan.from.name);
+        Ok(builder.column_raw(SqlFragment::raw(Self::etag_column(row))).build())
+    }
+
+    /// Build the SELECT for a plan and its embedded resources.
+    ///
+    /// Each embed is a `LEFT JOIN LATERAL` subquery correlated with the
+    /// parent row, so its filters, limit and offset apply per parent.
+    fn build_read_tree(tree: &ReadPlanTree) -> Result<SelectBuilder> {
+        let mut builder = Self::build_read_plan(&tree.root)?;
+        let parent = Self::plan_ref(&tree.root);
+
+        for (embed, child) in tree.root.rel_select.iter().zip(&tree.children) {
+            let name = embed.path_name();
+            let alias = format!("pgrst_{}", name);
+            if embed.is_spread {
+                builder = builder
+                    .left_join_lateral(Self::build_embed_rows(child, &parent)?.build(), &alias, "TRUE")
+                    .column_raw(SqlFragment::raw(format!("{}.*", escape_ident(&alias))));
+                continue;
+            }
+            let column = format!("{}.{}", escape_ident(&alias), escape_ident(name));
+
+            builder = builder
+                .left_join_lateral(Self::build_embed(child, &parent, embed)?, &alias, "TRUE")
+                .column_raw(SqlFragment::raw(format!("{} AS {}", column, escape_ident(name))));
+            if embed.join_type == JoinType::Inner {
+                builder = builder.where_raw(SqlFragment::raw(format!("{} IS NOT NULL", column)));
+            }
+        }
+
+        Ok(builder)
+    }
+
+    /// Build the lateral subquery producing an embed's JSON.
+    ///
+    /// To-one embeds yield an object, or NULL when no row matches; to-many
+    /// embeds an array, `[]` when empty. An inner to-many embed yields NULL
+    /// rather than `[]` when no child matches, so the parent row can be
+    /// filtered out.
+    fn build_embed(child: &ReadPlanTree, parent: &str, embed: &RelSelectField) -> Result<SqlFragment> {
+        let plan = &child.root;
+        let rows = Self::build_embed_rows(child, parent)?;
+
+        let to_one = plan.rel_to_parent.as_ref().is_some_and(|rel| rel.is_to_one());
+        let json = match (to_one, &embed.join_type) {
+            (true, _) => "row_to_json(\"pgrst_rows\")",
+            (false, JoinType::Inner) => "json_agg(\"pgrst_rows\")",
+            (false, JoinType::Left) => "COALESCE(json_agg(\"pgrst_rows\"), '[]'::json)",
+        };
+
+        let mut frag = SqlFragment::raw(format!("SELECT {} AS {} FROM (", json, escape_ident(embed.path_name())));
+        frag.append(rows.build());
+        frag.push(") AS \"pgrst_rows\"");
+        Ok(frag)
+    }
+
+    /// Build the rows of an embed, correlated with the parent row.
+    ///
+    /// A spread embed joins these rows directly, so its columns (or its
+    /// aggregates, computed per parent) land in the parent row. A computed
+    /// relationship reads its rows from the function called with the
+    /// parent row.
+    fn build_embed_rows(child: &ReadPlanTree, parent: &str) -> Result<SelectBuilder> {
+        let plan = &child.root;
+        let child_ref = Self::plan_ref(plan);
+        let mut rows = Self::build_read_tree(child)?;
+        if let Some(Relationship::Computed { function, .. }) = &plan.rel_to_parent {
+            let function = postrust_sql::identifier::QualifiedIdentifier::new(&function.schema, &function.name);
+            rows = rows.from_raw(SqlFragment::raw(format!("{}({}.*) AS {}", from_qi(&function), parent, child_ref)));
+        }
+        for cond in &plan.rel_join_conds {
+            rows = rows.where_raw(SqlFragment::raw(format!(
+                "{}.{} = {}.{}",
+                child_ref,
+                escape_ident(&cond.right.1),
+                parent,
+                escape_ident(&cond.left.1)
+            )));
+        }
+        Ok(rows)
+    }
+
+    /// How columns of a plan's source are qualified.
+    fn plan_ref(plan: &ReadPlan) -> String {
+        match &plan.from_alias {
+            Some(alias) => escape_ident(alias),
+            None => from_qi(&postrust_sql::identifier::QualifiedIdentifier::new(
+                &plan.from.schema,
+                &plan.from.name,
+            )),
+        }
+    }
+
+    /// Build the SELECT for a read plan, without its embeds.
+    fn build_read_plan(plan: &ReadPlan) -> Result<SelectBuilder> {
         let mut builder = SelectBuilder::new();
 
         // FROM clause
@@ -48,10 +159,23 @@ impl QueryBuilder {
             builder = builder.where_raw(expr);
         }
 
+        // GROUP BY the plain columns selected next to aggregates
+        if plan.select.iter().any(|field| field.aggregate.is_some()) {
+            for field in plan.select.iter().filter(|f| f.aggregate.is_none() && f.headline.is_none()) {
+                builder = builder.group_by(&field.field.name);
+            }
+        }
+
         // ORDER BY
+        let parent = Self::plan_ref(plan);
         for term in &plan.order {
-            let order = Self::build_order_term(term);
-            builder = builder.order_by(order);
+            builder = match &term.rel_join {
+                Some(rel) => builder.order_by_raw(Self::build_relation_order_term(term, rel, &parent)),
+                None if !term.field.json_path.is_empty() || term.cast.is_some() => {
+                    builder.order_by_raw(Self::build_expr_order_term(term))
+                }
+                None => builder.order_by(Self::build_order_term(term)),
+            };
         }
 
         // LIMIT/OFFSET
@@ -62,13 +186,169 @@ impl QueryBuilder {
             builder = builder.offset(plan.range.offset);
         }
 
+        Ok(builder)
+    }
+
+    /// Build a count query for a read plan tree.
+    ///
+    /// `Exact` counts every matching row. `Planned` uses the planner's row
+    /// estimate from `EXPLAIN`. `Estimated` reads `pg_class.reltuples` when
+    /// the request is unfiltered and falls back to the planner otherwise, or
+    /// when the relation has no statistics (never analyzed, or a view).
+    pub fn build_count(tree: &ReadPlanTree, count: &PreferCount) -> Result<CountQuery> {
+        let plan = &tree.root;
+        let filtered = !plan.where_clauses.is_empty() || Self::inner_embeds(tree).next().is_some();
+
+        let kind = match count {
+            PreferCount::Exact => CountKind::Exact,
+            PreferCount::Planned => CountKind::Planner,
+            PreferCount::Estimated if !filtered => CountKind::Statistics,
+            PreferCount::Estimated => CountKind::Planner,
+        };
+
+        let query = match kind {
+            CountKind::Exact | CountKind::Planner => CountQuery {
+                sql: Self::count_rows(Self::build_count_source(tree)?, kind),
+                kind,
+                fallback: None,
+            },
+            CountKind::Statistics => {
+                let qi = postrust_sql::identifier::QualifiedIdentifier::new(
+                    &plan.from.schema,
+                    &plan.from.name,
+                );
+                // NULL unless the relation stores rows and has been analyzed
+                let mut frag = SqlFragment::raw(
+                    "SELECT CASE WHEN c.reltuples >= 0 AND c.relkind IN ('r', 'p', 'm') \
+                     THEN c.reltuples::bigint END AS total \
+                     FROM pg_catalog.pg_class c WHERE c.oid = ",
+                );
+                frag.push_param(from_qi(&qi));
+                frag.push("::regclass");
+                CountQuery {
+                    sql: frag,
+                    kind,
+                    fallback: Some(Self::count_rows(Self::build_count_source(tree)?, CountKind::Planner)),
+                }
+            }
+        };
+
+        Ok(query)
+    }
+
+    /// Build a count query for the rows a set-returning RPC returns.
+    ///
+    /// The call is counted as a subquery, filtered by the read plan shaping
+    /// its result if any. The output has no table statistics, so estimates
+    /// come from the planner.
+    pub fn build_call_count(
+        plan: &CallPlan,
+        tree: Option<&ReadPlanTree>,
+        count: &PreferCount,
+    ) -> Result<CountQuery> {
+        let source = match tree {
+            Some(tree) => {
+                let mut frag = SqlFragment::new();
+                frag.push("WITH ");
+                frag.push(&escape_ident(&tree.root.from.name));
+                frag.push(" AS (");
+                frag.append(Self::build_call(plan)?);
+                frag.push(") ");
+                frag.append(Self::build_count_source(tree)?);
+                frag
+            }
+            None => Self::build_call(plan)?,
+        };
+        let kind = match count {
+            PreferCount::Exact => CountKind::Exact,
+            PreferCount::Planned | PreferCount::Estimated => CountKind::Planner,
+        };
+        Ok(CountQuery { sql: Self::count_rows(source, kind), kind, fallback: None })
+    }
+
+    /// Count the rows of `source` exactly, or estimate them with `EXPLAIN`.
+    fn count_rows(source: SqlFragment, kind: CountKind) -> SqlFragment {
+        if kind == CountKind::Exact {
+            let mut frag = SqlFragment::raw("SELECT pg_catalog.count(*) AS total FROM (");
+            frag.append(source);
+            frag.push(") _postrust_count");
+            frag
+        } else {
+            let mut frag = SqlFragment::raw("EXPLAIN (FORMAT JSON) ");
+            frag.append(source);
+            frag
+        }
+    }
+
+    /// Build the row source counted by `build_count` (no ordering or paging).
+    ///
+    /// Rows without a match for an `!inner` embed are left out, as in the read.
+    fn build_count_source(tree: &ReadPlanTree) -> Result<SqlFragment> {
+        let plan = &tree.root;
+        let qi = postrust_sql::identifier::QualifiedIdentifier::new(
+            &plan.from.schema,
+            &plan.from.name,
+        );
+        let mut builder = SelectBuilder::new().column_raw(SqlFragment::raw("1"));
+        builder = match &plan.from_alias {
+            Some(alias) => builder.from_table_as(&qi, alias),
+            None => builder.from_table(&qi),
+        };
+
+        for clause in &plan.where_clauses {
+            let expr = Self::build_logic_tree(clause)?;
+            builder = builder.where_raw(expr);
+        }
+
+        let parent = Self::plan_ref(plan);
+        for child in Self::inner_embeds(tree) {
+            let mut exists = SqlFragment::raw("EXISTS (");
+            exists.append(Self::build_embed_rows(child, &parent)?.build());
+            exists.push(")");
+            builder = builder.where_raw(exists);
+        }
+
         Ok(builder.build())
     }
 
+    /// The embedded trees a parent row needs a match in (`!inner`, not spread).
+    fn inner_embeds(tree: &ReadPlanTree) -> impl Iterator<Item = &ReadPlanTree> {
+        tree.root
+            .rel_select
+            .iter()
+            .zip(&tree.children)
+            .filter(|(embed, _)| embed.join_type == JoinType::Inner && !embed.is_spread)
+            .map(|(_, child)| child)
+    }
+
     /// Build a SELECT field.
     fn build_select_field(field: &CoercibleSelectField) -> Result<SqlFragment> {
         let mut frag = SqlFragment::new();
 
+        // ts_headline([config, ]col, fn([config, ]$q)), with the search text bound
+        if let Some(headline) = &field.headline {
+            frag.push("ts_headline(");
+            if let Some(lang) = &headline.language {
+                frag.push_typed_param(lang.clone(), "regconfig");
+                frag.push(", ");
+            }
+            frag.push(&escape_ident(&field.field.name));
+            frag.push(", ");
+            frag.push(headline.function.to_function());
+            frag.push("(");
+            if let Some(lang) = &headline.language {
+                frag.push_typed_param(lang.clone(), "regconfig");
+                frag.push(", ");
+            }
+            frag.push_typed_param(headline.query.clone(), "text");
+            frag.push("))");
+            if let Some(alias) = &field.alias {
+                frag.push(" AS ");
+                frag.push(&escape_ident(alias));
+            }
+            return Ok(frag);
+        }
+
         // Aggregate function
         if let Some(agg) = &field.aggregate {
             frag.push(agg.to_sql());
@@ -76,11 +356,19 @@ impl QueryBuilder {
         }
 
         // Column name with JSON path
-        frag.push(&escape_ident(&field.field.name));
+        if field.field.name == "*" {
+            frag.push("*");
+        } else {
+            frag.push(&escape_ident(&field.field.name));
+        }
 
         // Close aggregate
         if field.aggregate.is_some() {
             frag.push(")");
+            if let Some(cast) = &field.aggregate_cast {
+                frag.push("::");
+                frag.push(cast);
+            }
         }
 
         // Cast
@@ -107,9 +395,12 @@ impl QueryBuilder {
                     crate::api_request::LogicOperator::Or => " OR ",
                 };
 
-                let child_frags: Result<Vec<_>> = children
-                    .iter()
-                    .map(|c| Self::build_logic_tree(c))
+                // `or(a, or(b, c))` is `(a OR b OR c)`
+                let mut flat = Vec::new();
+                Self::flatten_logic(op, children, &mut flat);
+                let child_frags: Result<Vec<_>> = flat
+                    .into_iter()
+                    .map(Self::build_logic_tree)
                     .collect();
 
                 let mut combined = SqlFragment::join(sep, child_frags?).parens();
@@ -136,17 +427,45 @@ impl QueryBuilder {
         }
     }
 
+    /// Collect `children`, inlining non-negated groups of the same operator.
+    fn flatten_logic<'a>(
+        op: &crate::api_request::LogicOperator,
+        children: &'a [CoercibleLogicTree],
+        out: &mut Vec<&'a CoercibleLogicTree>,
+    ) {
+        for child in children {
+            match child {
+                CoercibleLogicTree::Expr { negated: false, op: child_op, children } if child_op == op => {
+                    Self::flatten_logic(op, children, out);
+                }
+                other => out.push(other),
+            }
+        }
+    }
+
     /// Build a filter expression.
     fn build_filter(filter: &CoercibleFilter) -> Result<SqlFragment> {
         let mut frag = SqlFragment::new();
 
+        // Negation applies to the whole comparison: `NOT "col" = $1`, except
+        // for IS, which has its own negated form: `"col" IS NOT NULL`
+        let negated = filter.op_expr.negated;
+        let is = matches!(filter.op_expr.operation, crate::api_request::Operation::Is(_));
+        if negated && !is {
+            frag.push("NOT ");
+        }
+
         // Column name
         frag.push(&escape_ident(&filter.field.name));
 
-        // Handle negation
-        if filter.op_expr.negated {
-            frag.push(" NOT");
-        }
+        // Comparisons against a domain column cast the value to the domain
+        let domain = filter.field.domain_cast();
+        let push_value = |frag: &mut SqlFragment, value: &String, comparison: bool| {
+            match domain.filter(|_| comparison) {
+                Some(domain) => frag.push_typed_param(value.clone(), domain),
+                None => frag.push_param(value.clone()),
+            };
+        };
 
         // Operation
         match &filter.op_expr.operation {
@@ -154,7 +473,7 @@ impl QueryBuilder {
                 frag.push(" ");
                 frag.push(op.to_sql());
                 frag.push(" ");
-                frag.push_param(value.clone());
+                push_value(&mut frag, value, *op == crate::api_request::SimpleOperator::NotEqual);
             }
             crate::api_request::Operation::Quant { op, quantifier, value } => {
                 frag.push(" ");
@@ -168,7 +487,9 @@ impl QueryBuilder {
                     frag.push_param(value.clone());
                     frag.push(")");
                 } else {
-                    frag.push_param(value.clone());
+                    use crate::api_request::QuantOperator::*;
+                    let comparison = matches!(op, Equal | GreaterThanEqual | GreaterThan | LessThanEqual | LessThan);
+                    push_value(&mut frag, value, comparison);
                 }
             }
             crate::api_request::Operation::In(values) => {
@@ -177,17 +498,17 @@ impl QueryBuilder {
                     if i > 0 {
                         frag.push(", ");
                     }
-                    frag.push_param(v.clone());
+                    push_value(&mut frag, v, true);
                 }
                 frag.push(")");
             }
             crate::api_request::Operation::Is(is_val) => {
-                frag.push(" IS ");
+                frag.push(if negated { " IS NOT " } else { " IS " });
                 frag.push(is_val.to_sql());
             }
             crate::api_request::Operation::IsDistinctFrom(value) => {
                 frag.push(" IS DISTINCT FROM ");
-                frag.push_param(value.clone());
+                push_value(&mut frag, value, true);
             }
             crate::api_request::Operation::Fts { op, language, value } => {
                 frag.push(" @@ ");
@@ -226,8 +547,100 @@ impl QueryBuilder {
         order
     }
 
+    /// Order by a JSON path and/or cast, e.g. `("data"->>'priority')::int DESC`.
+    fn build_expr_order_term(term: &CoercibleOrderTerm) -> SqlFragment {
+        let mut frag = SqlFragment::new();
+        frag.push("(");
+        frag.push(&escape_ident(&term.field.name));
+        for op in &term.field.json_path {
+            let (arrow, operand) = match op {
+                crate::api_request::JsonOperation::Arrow(operand) => ("->", operand),
+                crate::api_request::JsonOperation::DoubleArrow(operand) => ("->>", operand),
+            };
+            frag.push(arrow);
+            match operand {
+                crate::api_request::JsonOperand::Key(key) => frag.push(&quote_literal(key)),
+                crate::api_request::JsonOperand::Idx(idx) => frag.push(&idx.to_string()),
+            };
+        }
+        frag.push(")");
+        if let Some(cast) = &term.cast {
+            frag.push("::");
+            frag.push(cast);
+        }
+        Self::push_order_modifiers(&mut frag, term);
+        frag
+    }
+
+    /// Append ASC/DESC and NULLS FIRST/LAST.
+    fn push_order_modifiers(frag: &mut SqlFragment, term: &CoercibleOrderTerm) {
+        match term.direction {
+            Some(crate::api_request::OrderDirection::Asc) => frag.push(" ASC"),
+            Some(crate::api_request::OrderDirection::Desc) => frag.push(" DESC"),
+            None => &mut *frag,
+        };
+        match term.nulls {
+            Some(crate::api_request::OrderNulls::First) => frag.push(" NULLS FIRST"),
+            Some(crate::api_request::OrderNulls::Last) => frag.push(" NULLS LAST"),
+            None => &mut *frag,
+        };
+    }
+
+    /// Order by a column of a to-one relation.
+    ///
+    /// The related row is looked up in a correlated subquery, so the
+    /// parent's own columns stay unambiguous.
+    fn build_relation_order_term(
+        term: &CoercibleOrderTerm,
+        rel: &OrderRelation,
+        parent: &str,
+    ) -> SqlFragment {
+        let alias = escape_ident(term.relation.as_deref().unwrap_or(&rel.table.name));
+        let conds: Vec<String> = rel
+            .join_conds
+            .iter()
+            .map(|cond| {
+                format!(
+                    "{}.{} = {}.{}",
+                    alias,
+                    escape_ident(&cond.right.1),
+                    parent,
+                    escape_ident(&cond.left.1)
+                )
+            })
+            .collect();
+
+        let mut frag = SqlFragment::new();
+        frag.push("(SELECT ");
+        frag.push(&alias);
+        frag.push(".");
+        frag.push(&escape_ident(&term.field.name));
+        frag.push(" FROM ");
+        frag.push(&from_qi(&postrust_sql::identifier::QualifiedIdentifier::new(
+            &rel.table.schema,
+            &rel.table.name,
+        )));
+        frag.push(" AS ");
+        frag.push(&alias);
+        frag.push(" WHERE ");
+        frag.push(&conds.join(" AND "));
+        frag.push(")");
+        Self::push_order_modifiers(&mut frag, term);
+        frag
+    }
+
     /// Build a mutation query.
     pub fn build_mutate(plan: &MutatePlan) -> Result<SqlFragment> {
+        Self::build_mutate_returning(plan, false)
+    }
+
+    /// Build a mutation whose returned rows also carry their ETag in
+    /// [`ETAG_COLUMN`], when it returns any columns. Deleted rows get none.
+    pub fn build_tagged_mutate(plan: &MutatePlan) -> Result<SqlFragment> {
+        Self::build_mutate_returning(plan, true)
+    }
+
+    fn build_mutate_returning(plan: &MutatePlan, etag: bool) -> Result<SqlFragment> {
         match plan {
             MutatePlan::Insert {
                 target,
@@ -235,12 +648,20 @@ impl QueryBuilder {
                 body,
                 on_conflict,
                 returning,
+                nested,
+                binary_body,
+                raw_json,
                 ..
             } => {
                 let qi = postrust_sql::identifier::QualifiedIdentifier::new(
                     &target.schema,
                     &target.name,
                 );
+                let etag = etag && !returning.is_empty();
+
+                if !nested.is_empty() {
+                    return Self::build_nested_insert(&qi, columns, body.as_ref(), returning, etag, nested);
+                }
 
                 let mut builder = InsertBuilder::new().into_table(&qi);
 
@@ -248,17 +669,46 @@ impl QueryBuilder {
                 let col_names: Vec<String> = columns.iter().map(|c| c.name.clone()).collect();
                 builder = builder.columns(col_names);
 
-                // For bulk insert, we'd use json_populate_recordset
-                // For now, simplified single-row insert
+                // Raw octet-stream body: bind the bytes as the column value
+                if *binary_body {
+                    let bytes = body.as_ref().map(|b| b.to_vec()).unwrap_or_default();
+                    builder = builder.values(vec![SqlParam::Bytes(bytes)]);
+                    for col in returning {
+                        builder = builder.returning(col);
+                    }
+                    if etag {
+                        builder = builder.returning_raw(SqlFragment::raw(Self::etag_column(&target.name)));
+                    }
+                    return Ok(builder.build());
+                }
+
                 if let Some(body_bytes) = body {
-                    // This would be expanded with proper JSON handling
                     let body_str = String::from_utf8_lossy(body_bytes);
-                    let mut frag = SqlFragment::new();
-                    frag.push("SELECT * FROM json_populate_recordset(NULL::");
-                    frag.push(&from_qi(&qi));
-                    frag.push(", ");
-                    frag.push_param(body_str.to_string());
-                    frag.push("::json)");
+                    let populate_fn = if *raw_json {
+                        "json_to_recordset"
+                    } else {
+                        "json_populate_recordset"
+                    };
+                    let mut frag = Self::build_insert_select(
+                        &qi,
+                        columns,
+                        populate_fn,
+                        body_str.to_string(),
+                        None,
+                    );
+                    Self::push_on_conflict(&mut frag, on_conflict.as_ref(), columns);
+
+                    let mut returning: Vec<String> = returning.iter().map(|c| escape_ident(c)).collect();
+                    if etag {
+                        returning.push(Self::etag_column(&target.name));
+                    }
+                    if on_conflict.is_some() {
+                        returning.push(format!("xmax = 0 AS {}", escape_ident(INSERTED_FLAG)));
+                    }
+                    if !returning.is_empty() {
+                        frag.push(" RETURNING ");
+                        frag.push(&returning.join(", "));
+                    }
                     return Ok(frag);
                 }
 
@@ -287,6 +737,9 @@ impl QueryBuilder {
                 for col in returning {
                     builder = builder.returning(col);
                 }
+                if etag {
+                    builder = builder.returning_raw(SqlFragment::raw(Self::etag_column(&target.name)));
+                }
 
                 Ok(builder.build())
             }
@@ -297,6 +750,8 @@ impl QueryBuilder {
                 body,
                 where_clauses,
                 returning,
+                if_match,
+                bulk_key,
                 ..
             } => {
                 let qi = postrust_sql::identifier::QualifiedIdentifier::new(
@@ -304,6 +759,26 @@ impl QueryBuilder {
                     &target.name,
                 );
 
+                if !bulk_key.is_empty() {
+                    let body = body.as_ref().ok_or_else(|| Error::InvalidBody("bulk update requires a body".into()))?;
+                    let mut frag = Self::build_bulk_update(&qi, columns, body, bulk_key)?;
+                    for clause in where_clauses {
+                        frag.push(" AND ");
+                        frag.append(Self::build_logic_tree(clause)?);
+                    }
+                    if !returning.is_empty() {
+                        let table = from_qi(&qi);
+                        let mut cols: Vec<String> =
+                            returning.iter().map(|c| format!("{}.{}", table, escape_ident(c))).collect();
+                        if etag {
+                            cols.push(Self::etag_column(&target.name));
+                        }
+                        frag.push(" RETURNING ");
+                        frag.push(&cols.join(", "));
+                    }
+                    return Ok(frag);
+                }
+
                 let builder = UpdateBuilder::new().table(&qi);
 
                 // SET columns from body
@@ -329,14 +804,16 @@ impl QueryBuilder {
                     }
 
                     // WHERE
-                    if !where_clauses.is_empty() {
+                    let mut predicates = where_clauses
+                        .iter()
+                        .map(Self::build_logic_tree)
+                        .collect::<Result<Vec<_>>>()?;
+                    if let Some(etag) = if_match.as_deref().filter(|e| *e != "*") {
+                        predicates.push(Self::build_etag_match(&target.name, etag));
+                    }
+                    if !predicates.is_empty() {
                         frag.push(" WHERE ");
-                        for (i, clause) in where_clauses.iter().enumerate() {
-                            if i > 0 {
-                                frag.push(" AND ");
-                            }
-                            frag.append(Self::build_logic_tree(clause)?);
-                        }
+                        frag.append(SqlFragment::join(" AND ", predicates));
                     }
 
                     // RETURNING
@@ -348,6 +825,10 @@ impl QueryBuilder {
                             }
                             frag.push(&escape_ident(col));
                         }
+                        if etag {
+                            frag.push(", ");
+                            frag.push(&Self::etag_column(&target.name));
+                        }
                     }
 
                     return Ok(frag);
@@ -384,6 +865,326 @@ impl QueryBuilder {
         }
     }
 
+    /// `UPDATE ... FROM (VALUES ...)`: each body row updates the row with
+    /// its key.
+    ///
+    /// Key columns come first in the values list, followed by the columns
+    /// to set, every value bound as text and cast to the column type.
+    fn build_bulk_update(
+        qi: &postrust_sql::identifier::QualifiedIdentifier,
+        columns: &[CoercibleField],
+        body: &bytes::Bytes,
+        key: &[String],
+    ) -> Result<SqlFragment> {
+        let rows: Vec<serde_json::Map<String, serde_json::Value>> =
+            serde_json::from_slice(body).map_err(|e| Error::InvalidBody(e.to_string()))?;
+        let set_cols: Vec<&CoercibleField> = columns.iter().filter(|c| !key.contains(&c.name)).collect();
+        let key_cols: Vec<CoercibleField> = key
+            .iter()
+            .map(|k| {
+                columns
+                    .iter()
+                    .find(|c| &c.name == k)
+                    .cloned()
+                    .unwrap_or_else(|| CoercibleField::simple(k.as_str(), "text"))
+            })
+            .collect();
+        if set_cols.is_empty() {
+            return Err(Error::InvalidBody("bulk update rows have no columns to set".into()));
+        }
+        let value_cols: Vec<&CoercibleField> = key_cols.iter().chain(set_cols.iter().copied()).collect();
+        let alias = |i: usize| escape_ident(&format!("pgrst_{}", i + 1));
+        let table = from_qi(qi);
+
+        let mut frag = SqlFragment::new();
+        frag.push("UPDATE ");
+        frag.push(&table);
+        frag.push(" SET ");
+        let sets: Vec<String> = set_cols
+            .iter()
+            .enumerate()
+            .map(|(i, c)| format!("{} = \"pgrst_body\".{}", escape_ident(&c.name), alias(key.len() + i)))
+            .collect();
+        frag.push(&sets.join(", "));
+
+        frag.push(" FROM (VALUES ");
+        for (r, row) in rows.iter().enumerate() {
+            if r > 0 {
+                frag.push(", ");
+            }
+            frag.push("(");
+            for (i, col) in value_cols.iter().enumerate() {
+                if i > 0 {
+                    frag.push(", ");
+                }
+                match row.get(&col.name) {
+                    None | Some(serde_json::Value::Null) => frag.push_param(SqlParam::Null),
+                    Some(serde_json::Value::String(s)) => frag.push_param(s.clone()),
+                    Some(v) => frag.push_param(v.to_string()),
+                };
+                frag.push("::");
+                frag.push(&col.ir_type);
+            }
+            frag.push(")");
+        }
+        let aliases: Vec<String> = (0..value_cols.len()).map(alias).collect();
+        frag.push(") AS \"pgrst_body\"(");
+        frag.push(&aliases.join(", "));
+        frag.push(") WHERE ");
+        let matches: Vec<String> = key
+            .iter()
+            .enumerate()
+            .map(|(i, k)| format!("{}.{} = \"pgrst_body\".{}", table, escape_ident(k), alias(i)))
+            .collect();
+        frag.push(&matches.join(" AND "));
+        Ok(frag)
+    }
+
+    /// `md5(CAST(<row> AS text))`: the ETag of a row, from its text form.
+    fn etag_expr(row: &str) -> String {
+        format!("md5(CAST({} AS text))", escape_ident(row))
+    }
+
+    /// The ETag of a row, selected as [`ETAG_COLUMN`].
+    fn etag_column(row: &str) -> String {
+        format!("{} AS {}", Self::etag_expr(row), escape_ident(ETAG_COLUMN))
+    }
+
+    /// `md5(CAST(<table> AS text)) = $n`: the row still has the ETag the
+    /// client read.
+    fn build_etag_match(table: &str, etag: &str) -> SqlFragment {
+        let mut frag = SqlFragment::new();
+        frag.push(&Self::etag_expr(table));
+        frag.push(" = ");
+        frag.push_param(etag.to_string());
+        frag
+    }
+
+    /// Build an insert that also writes related rows embedded in the body.
+    ///
+    /// Each parent row gets its own data-modifying CTE, followed by one CTE
+    /// per embedded relationship that joins against it for the key values.
+    /// Everything runs as a single statement, so it commits or fails as one.
+    fn build_nested_insert(
+        qi: &postrust_sql::identifier::QualifiedIdentifier,
+        columns: &[CoercibleField],
+        body: Option<&bytes::Bytes>,
+        returning: &[String],
+        etag: bool,
+        nested: &[NestedInsert],
+    ) -> Result<SqlFragment> {
+        let body = body.ok_or_else(|| Error::InvalidBody("nested insert requires a body".into()))?;
+        let value: serde_json::Value =
+            serde_json::from_slice(body).map_err(|e| Error::InvalidBody(e.to_string()))?;
+        let rows: Vec<&serde_json::Value> = match &value {
+            serde_json::Value::Array(arr) => arr.iter().collect(),
+            other => vec![other],
+        };
+        if rows.is_empty() {
+            return Err(Error::InvalidBody("nested insert requires at least one row".into()));
+        }
+
+        let mut frag = SqlFragment::new();
+        let mut parents = Vec::new();
+        frag.push("WITH ");
+
+        for (i, row) in rows.iter().enumerate() {
+            let parent = escape_ident(&format!("pgrst_parent_{}", i));
+            if i > 0 {
+                frag.push(", ");
+            }
+            frag.push(&parent);
+            frag.push(" AS (");
+            frag.append(Self::build_insert_select(
+                qi,
+                columns,
+                "json_populate_record",
+                row.to_string(),
+                None,
+            ));
+            frag.push(" RETURNING *)");
+
+            for (j, n) in nested.iter().enumerate() {
+                let children = match row.get(&n.key) {
+                    Some(serde_json::Value::Array(items)) if !items.is_empty() => {
+                        serde_json::Value::Array(items.clone())
+                    }
+                    Some(obj @ serde_json::Value::Object(_)) => {
+                        serde_json::Value::Array(vec![obj.clone()])
+                    }
+                    _ => continue,
+                };
+
+                let child_qi = postrust_sql::identifier::QualifiedIdentifier::new(
+                    &n.target.schema,
+                    &n.target.name,
+                );
+                frag.push(", ");
+                frag.push(&escape_ident(&format!("pgrst_nested_{}_{}", i, j)));
+                frag.push(" AS (");
+                frag.append(Self::build_insert_select(
+                    &child_qi,
+                    &n.columns,
+                    "json_populate_recordset",
+                    children.to_string(),
+                    Some((&parent, &n.join_columns)),
+                ));
+                frag.push(" RETURNING 1)");
+            }
+
+            parents.push(parent);
+        }
+
+        let select_cols = if returning.is_empty() {
+            "*".to_string()
+        } else {
+            returning.iter().map(|c| escape_ident(c)).collect::<Vec<_>>().join(", ")
+        };
+        // Each parent CTE returns the whole inserted row, so it tags the row
+        let selects: Vec<String> = parents
+            .iter()
+            .enumerate()
+            .map(|(i, p)| match etag {
+                true => format!(
+                    "SELECT {}, {} FROM {}",
+                    select_cols,
+                    Self::etag_column(&format!("pgrst_parent_{}", i)),
+                    p
+                ),
+                false => format!("SELECT {} FROM {}", select_cols, p),
+            })
+            .collect();
+        frag.push(" ");
+        frag.push(&selects.join(" UNION ALL "));
+
+        Ok(frag)
+    }
+
+    /// Append `ON CONFLICT` for an upsert, merging every inserted column.
+    fn push_on_conflict(
+        frag: &mut SqlFragment,
+        on_conflict: Option<&(crate::api_request::PreferResolution, Vec<String>)>,
+        columns: &[CoercibleField],
+    ) {
+        let Some((resolution, conflict_cols)) = on_conflict else {
+            return;
+        };
+        let conflict_cols: Vec<String> = conflict_cols.iter().map(|c| escape_ident(c)).collect();
+        frag.push(" ON CONFLICT (");
+        frag.push(&conflict_cols.join(", "));
+        frag.push(")");
+        match resolution {
+            crate::api_request::PreferResolution::MergeDuplicates if !columns.is_empty() => {
+                let set: Vec<String> = columns
+                    .iter()
+                    .map(|c| format!("{0} = EXCLUDED.{0}", escape_ident(&c.name)))
+                    .collect();
+                frag.push(" DO UPDATE SET ");
+                frag.push(&set.join(", "));
+            }
+            _ => {
+                frag.push(" DO NOTHING");
+            }
+        }
+    }
+
+    /// `INSERT INTO target (cols) SELECT cols FROM <populate>(NULL::target, $n::json)`,
+    /// optionally joined to a parent CTE that supplies the join columns.
+    ///
+    /// `json_to_recordset` takes no row type, so the record is described by
+    /// a column definition list built from the columns' types instead.
+    fn build_insert_select(
+        qi: &postrust_sql::identifier::QualifiedIdentifier,
+        columns: &[CoercibleField],
+        populate_fn: &str,
+        json: String,
+        parent: Option<(&str, &[(String, String)])>,
+    ) -> SqlFragment {
+        let mut targets: Vec<String> = columns.iter().map(|c| escape_ident(&c.name)).collect();
+        let mut values: Vec<String> = columns
+            .iter()
+            .map(|c| format!("r.{}", escape_ident(&c.name)))
+            .collect();
+        if let Some((_, join_columns)) = parent {
+            for (parent_col, child_col) in join_columns {
+                targets.push(escape_ident(child_col));
+                values.push(format!("p.{}", escape_ident(parent_col)));
+            }
+        }
+
+        let mut frag = SqlFragment::new();
+        frag.push("INSERT INTO ");
+        frag.push(&from_qi(qi));
+        if targets.is_empty() {
+            frag.push(" DEFAULT VALUES");
+            return frag;
+        }
+        frag.push(" (");
+        frag.push(&targets.join(", "));
+        frag.push(") SELECT ");
+        frag.push(&values.join(", "));
+        frag.push(" FROM ");
+        frag.push(populate_fn);
+        if populate_fn == "json_to_recordset" {
+            let definitions: Vec<String> = columns
+                .iter()
+                .map(|c| format!("{} {}", escape_ident(&c.name), c.ir_type))
+                .collect();
+            frag.push("(");
+            frag.push_param(json);
+            frag.push("::json) AS r(");
+            frag.push(&definitions.join(", "));
+            frag.push(")");
+        } else {
+            frag.push("(NULL::");
+            frag.push(&from_qi(qi));
+            frag.push(", ");
+            frag.push_param(json);
+            frag.push("::json) r");
+        }
+        if let Some((parent, _)) = parent {
+            frag.push(", ");
+            frag.push(parent);
+            frag.push(" p");
+        }
+        frag
+    }
+
+    /// Build an RPC call whose result is shaped by a read plan.
+    ///
+    /// The call becomes a CTE named after the read plan's source, and the
+    /// read selects from it.
+    pub fn build_call_read(plan: &CallPlan, tree: &ReadPlanTree) -> Result<SqlFragment> {
+        let mut frag = SqlFragment::new();
+        frag.push("WITH ");
+        frag.push(&escape_ident(&tree.root.from.name));
+        frag.push(" AS (");
+        frag.append(Self::build_call(plan)?);
+        frag.push(") ");
+        frag.append(Self::build_read(tree)?);
+        Ok(frag)
+    }
+
+    /// Build a mutation whose returned rows are shaped by a read plan.
+    ///
+    /// The mutation becomes the [`MUTATION_RESULT`] CTE, and the read's
+    /// `select` and order apply to the rows it returned. The CTE carries
+    /// [`ETAG_COLUMN`] for the read to select.
+    pub fn build_mutate_read(plan: &MutatePlan, tree: &ReadPlanTree) -> Result<SqlFragment> {
+        let mut read = tree.clone();
+        read.root.from = crate::api_request::QualifiedIdentifier::new("", MUTATION_RESULT);
+        read.root.from_alias = None;
+
+        let mut frag = SqlFragment::new();
+        frag.push("WITH ");
+        frag.push(&escape_ident(MUTATION_RESULT));
+        frag.push(" AS (");
+        frag.append(Self::build_tagged_mutate(plan)?);
+        frag.push(") ");
+        frag.append(Self::build_read(&read)?);
+        Ok(frag)
+    }
+
     /// Build an RPC call query.
     pub fn build_call(plan: &CallPlan) -> Result<SqlFragment> {
         let qi = postrust_sql::identifier::QualifiedIdentifier::new(
@@ -405,6 +1206,10 @@ impl QueryBuilder {
                     frag.push(&escape_ident(name));
                     frag.push(" => ");
                     frag.push_param(SqlParam::Text(value.clone()));
+                    if let Some((_, cast)) = plan.param_casts.iter().find(|(param, _)| param == name) {
+                        frag.push("::");
+                        frag.push(cast);
+                    }
                 }
             }
             CallParams::Positional(values) => {
@@ -0,0 +1,161 @@
+//! Request-scoped GUC statements.
+//!
+//! Exposes request data to SQL the same way PostgREST does, via
+//! `current_setting('request.headers', true)` and friends.
+
+use crate::api_request::ApiRequest;
+use indexmap::IndexMap;
+use postrust_sql::{escape_ident, quote_literal};
+
+/// Build `SET LOCAL` statements for the request method, path, headers and cookies.
+///
+/// When `header_allowlist` is given, only the listed headers (matched
+/// case-insensitively) are exposed in `request.headers`.
+pub fn build_request_gucs(
+    request: &ApiRequest,
+    header_allowlist: Option<&[String]>,
+) -> Vec<String> {
+    vec![
+        set_local("request.method", &request.method),
+        set_local("request.path", &request.path),
+        set_local_json("request.headers", &exposed_headers(request, header_allowlist)),
+        set_local_json("request.cookies", &request.cookies),
+    ]
+}
+
+/// The headers exposed to SQL in `request.headers`.
+pub fn exposed_headers<'a>(
+    request: &'a ApiRequest,
+    header_allowlist: Option<&[String]>,
+) -> IndexMap<&'a str, &'a str> {
+    request
+        .headers
+        .iter()
+        .filter(|(name, _)| match header_allowlist {
+            Some(allowed) => allowed.iter().any(|a| a.eq_ignore_ascii_case(name)),
+            None => true,
+        })
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect()
+}
+
+/// Build the `SET LOCAL application_name` statement shown in `pg_stat_activity`.
+///
+/// Names the role and, when the client sent an `X-Request-Id`, the request.
+pub fn build_application_name(request: &ApiRequest, role: &str) -> String {
+    let name = match request.headers.get("x-request-id") {
+        Some(id) => format!("postrust/{} {}", role, id),
+        None => format!("postrust/{}", role),
+    };
+    set_local("application_name", &name)
+}
+
+/// Build the `SET LOCAL search_path` statement for a request.
+///
+/// The exposed schema comes first so unqualified names resolve against it,
+/// followed by `db_extra_search_path` and finally `pg_temp`, which keeps
+/// temporary objects from shadowing anything above.
+pub fn build_search_path(schema: &str, extra: &[String]) -> String {
+    let mut path = vec![escape_ident(schema)];
+    for s in extra {
+        if s != schema && s != "pg_temp" {
+            path.push(escape_ident(s));
+        }
+    }
+    path.push("pg_temp".to_string());
+
+    format!("SET LOCAL search_path = {}", path.join(", "))
+}
+
+/// Build a `SET LOCAL` statement with a quoted text value.
+fn set_local(name: &str, value: &str) -> String {
+    format!("SET LOCAL {} = {}", name, quote_literal(value))
+}
+
+/// Build a `SET LOCAL` statement with a JSON-encoded value.
+fn set_local_json(name: &str, value: &impl serde::Serialize) -> String {
+    let json = serde_json::to_string(value).unwrap_or_else(|_| "{}".to_string());
+    set_local(name, &json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_with_headers() -> ApiRequest {
+        ApiRequest {
+            method: "PATCH".into(),
+            path: "/projects".into(),
+            headers: [("user-agent", "curl/8.0"), ("authorization", "Bearer secret")]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            cookies: [("session".to_string(), "it's".to_string())].into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_headers_guc_json() {
+        let stmts = build_request_gucs(&request_with_headers(), None);
+
+        assert_eq!(
+            stmts[2],
+            r#"SET LOCAL request.headers = '{"user-agent":"curl/8.0","authorization":"Bearer secret"}'"#
+        );
+    }
+
+    #[test]
+    fn test_headers_guc_allowlist() {
+        let allowed = vec!["User-Agent".to_string()];
+        let stmts = build_request_gucs(&request_with_headers(), Some(&allowed));
+
+        assert_eq!(stmts[2], r#"SET LOCAL request.headers = '{"user-agent":"curl/8.0"}'"#);
+        assert!(!stmts[2].contains("authorization"));
+    }
+
+    #[test]
+    fn test_cookies_guc_quoting() {
+        let stmts = build_request_gucs(&request_with_headers(), None);
+
+        assert_eq!(stmts[3], r#"SET LOCAL request.cookies = '{"session":"it''s"}'"#);
+    }
+
+    #[test]
+    fn test_method_and_path_gucs() {
+        let stmts = build_request_gucs(&request_with_headers(), None);
+
+        assert_eq!(stmts[0], "SET LOCAL request.method = 'PATCH'");
+        assert_eq!(stmts[1], "SET LOCAL request.path = '/projects'");
+    }
+
+    #[test]
+    fn test_search_path_puts_exposed_schema_first() {
+        let extra = vec!["extensions".to_string(), "public".to_string()];
+        assert_eq!(
+            build_search_path("api", &extra),
+            "SET LOCAL search_path = \"api\", \"extensions\", \"public\", pg_temp"
+        );
+    }
+
+    #[test]
+    fn test_application_name_names_role_and_request() {
+        let mut request = request_with_headers();
+        assert_eq!(
+            build_application_name(&request, "web_user"),
+            "SET LOCAL application_name = 'postrust/web_user'"
+        );
+
+        request.headers.insert("x-request-id".into(), "7f3a-01".into());
+        assert_eq!(
+            build_application_name(&request, "web_user"),
+            "SET LOCAL application_name = 'postrust/web_user 7f3a-01'"
+        );
+    }
+
+    #[test]
+    fn test_search_path_skips_duplicates() {
+        let extra = vec!["api".to_string(), "pg_temp".to_string()];
+        assert_eq!(build_search_path("api", &extra), "SET LOCAL search_path = \"api\", pg_temp");
+    }
+}
@@ -0,0 +1,70 @@
+//! Post-request hook.
+//!
+//! With `db_post_request` set, the response rows are passed to the function
+//! as a JSON array inside the request's transaction, and a non-null return
+//! becomes the response body.
+
+use postrust_sql::escape_ident;
+use serde_json::Value;
+
+/// Build the `SELECT` calling the post-request function on `$1`.
+///
+/// An unqualified function name resolves against the request schema.
+pub fn build_post_request(function: &str, schema: &str) -> String {
+    let (schema, name) = function.split_once('.').unwrap_or((schema, function));
+    format!(
+        "SELECT {}.{}($1::json)",
+        escape_ident(schema),
+        escape_ident(name)
+    )
+}
+
+/// Replace `rows` with the post-request function's output.
+///
+/// A returned array becomes the rows; any other value is the single row.
+/// A null return keeps the original rows.
+pub fn apply_post_request(rows: Vec<Value>, output: Option<Value>) -> Vec<Value> {
+    match output {
+        None | Some(Value::Null) => rows,
+        Some(Value::Array(items)) => items,
+        Some(other) => vec![other],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_build_post_request() {
+        assert_eq!(
+            build_post_request("audit", "api"),
+            "SELECT \"api\".\"audit\"($1::json)"
+        );
+        assert_eq!(
+            build_post_request("hooks.enrich", "api"),
+            "SELECT \"hooks\".\"enrich\"($1::json)"
+        );
+    }
+
+    #[test]
+    fn test_output_replaces_body() {
+        let rows = vec![json!({"id": 1})];
+        assert_eq!(
+            apply_post_request(rows.clone(), Some(json!([{"id": 1, "seen": true}]))),
+            vec![json!({"id": 1, "seen": true})]
+        );
+        assert_eq!(
+            apply_post_request(rows, Some(json!({"count": 1}))),
+            vec![json!({"count": 1})]
+        );
+    }
+
+    #[test]
+    fn test_null_output_keeps_body() {
+        let rows = vec![json!({"id": 1})];
+        assert_eq!(apply_post_request(rows.clone(), Some(Value::Null)), rows);
+        assert_eq!(apply_post_request(rows.clone(), None), rows);
+    }
+}
@@ -3,11 +3,16 @@
 //! This module converts execution plans into parameterized SQL queries.
 
 mod builder;
+mod guc;
+mod hook;
 
 pub use builder::QueryBuilder;
+pub use guc::{build_application_name, build_request_gucs, build_search_path, exposed_headers};
+pub use hook::{apply_post_request, build_post_request};
 
+use crate::api_request::PreferCount;
 use crate::error::Result;
-use crate::plan::{ActionPlan, DbActionPlan};
+use crate::plan::{ActionPlan, CoercibleSelectField, DbActionPlan, MutatePlan, ETAG_COLUMN, INSERTED_FLAG};
 use postrust_sql::{SqlFragment, SqlParam};
 
 /// Build SQL from an action plan.
@@ -32,25 +37,101 @@ fn build_db_query(plan: &DbActionPlan, role: Option<&str>) -> Result<MainQuery>
 
     match plan {
         DbActionPlan::Read(read_tree) => {
-            query.main = QueryBuilder::build_read(read_tree)?;
+            query.main = QueryBuilder::build_tagged_read(read_tree)?;
         }
+        // Deleted rows can only be read back through RETURNING
+        DbActionPlan::MutateRead { mutate: mutate @ MutatePlan::Delete { .. }, read: Some(read_tree) } => {
+            query.main = QueryBuilder::build_mutate_read(mutate, read_tree)?;
+        }
+        // Upserts return their merged rows shaped by the read, keeping the
+        // inserted flag that decides the response status, and the ETag
+        DbActionPlan::MutateRead {
+            mutate: mutate @ MutatePlan::Insert { on_conflict: Some(_), .. },
+            read: Some(read_tree),
+        } => {
+            let mut read = read_tree.clone();
+            read.root.select.push(CoercibleSelectField::simple(INSERTED_FLAG, "boolean"));
+            read.root.select.push(CoercibleSelectField::simple(ETAG_COLUMN, "text"));
+            query.main = QueryBuilder::build_mutate_read(mutate, &read)?;
+        }
+        // Only a representation carries the ETag; without one the RETURNING
+        // list is just the primary key for Location
         DbActionPlan::MutateRead { mutate, read } => {
-            query.main = QueryBuilder::build_mutate(mutate)?;
+            query.main = match read {
+                Some(_) => QueryBuilder::build_tagged_mutate(mutate)?,
+                None => QueryBuilder::build_mutate(mutate)?,
+            };
             if let Some(read_tree) = read {
                 query.read = Some(QueryBuilder::build_read(read_tree)?);
             }
         }
         DbActionPlan::Call { call, read } => {
-            query.main = QueryBuilder::build_call(call)?;
-            if let Some(read_tree) = read {
-                query.read = Some(QueryBuilder::build_read(read_tree)?);
-            }
+            query.main = match read {
+                Some(read_tree) => QueryBuilder::build_call_read(call, read_tree)?,
+                None => QueryBuilder::build_call(call)?,
+            };
         }
     }
 
     Ok(query)
 }
 
+/// Build the total-count query for a read plan or a set-returning call.
+///
+/// Counting a call runs the function a second time, so volatile functions
+/// are not counted.
+pub fn build_count_query(plan: &ActionPlan, count: &PreferCount) -> Result<Option<CountQuery>> {
+    match plan {
+        ActionPlan::Db(DbActionPlan::Read(read_tree)) => {
+            QueryBuilder::build_count(read_tree, count).map(Some)
+        }
+        ActionPlan::Db(DbActionPlan::Call { call, read })
+            if call.returns_set && call.volatility != "Volatile" =>
+        {
+            QueryBuilder::build_call_count(call, read.as_ref(), count).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// How a count query obtains its total.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CountKind {
+    /// `count(*)` over the filtered relation
+    Exact,
+    /// `pg_class.reltuples` table statistics
+    Statistics,
+    /// Row estimate from `EXPLAIN (FORMAT JSON)`
+    Planner,
+}
+
+/// A query producing the total row count of a read.
+#[derive(Clone, Debug)]
+pub struct CountQuery {
+    /// Count SQL
+    pub sql: SqlFragment,
+    /// How the count is computed
+    pub kind: CountKind,
+    /// Planner estimate to run when `sql` yields no total (no statistics)
+    pub fallback: Option<SqlFragment>,
+}
+
+impl CountQuery {
+    /// Check if the count is an approximation.
+    pub fn is_estimate(&self) -> bool {
+        self.kind != CountKind::Exact
+    }
+}
+
+/// Extract the top-level row estimate from `EXPLAIN (FORMAT JSON)` output.
+pub fn explain_row_estimate(plan: &serde_json::Value) -> Option<i64> {
+    plan.get(0)?
+        .get("Plan")?
+        .get("Plan Rows")?
+        .as_f64()
+        .map(|rows| rows as i64)
+}
+
 /// A complete query with setup and main statement.
 #[derive(Clone, Debug, Default)]
 pub struct MainQuery {
@@ -61,7 +142,7 @@ pub struct MainQuery {
     /// Read query (for mutations with RETURNING)
     pub read: Option<SqlFragment>,
     /// Count query (for pagination)
-    pub count: Option<SqlFragment>,
+    pub count: Option<CountQuery>,
 }
 
 impl MainQuery {
@@ -85,3 +166,337 @@ impl MainQuery {
         self.main.build()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_request::QualifiedIdentifier;
+    use crate::plan::{CoercibleLogicTree, ReadPlanTree};
+
+    fn users_read(filtered: bool) -> ActionPlan {
+        let mut tree = ReadPlanTree::empty();
+        tree.root.from = QualifiedIdentifier::new("public", "users");
+        if filtered {
+            tree.root.where_clauses.push(CoercibleLogicTree::NullEmbed {
+                negated: true,
+                field_name: "email".into(),
+            });
+        }
+        ActionPlan::Db(DbActionPlan::Read(tree))
+    }
+
+    #[test]
+    fn test_exact_count_query() {
+        let count = build_count_query(&users_read(true), &PreferCount::Exact)
+            .unwrap()
+            .unwrap();
+        assert!(!count.is_estimate());
+
+        let (sql, _) = count.sql.build();
+        assert!(sql.starts_with("SELECT pg_catalog.count(*) AS total FROM (SELECT 1 FROM"));
+        assert!(sql.contains("\"email\" IS NOT NULL"));
+    }
+
+    #[test]
+    fn test_estimated_count_uses_statistics_when_unfiltered() {
+        let count = build_count_query(&users_read(false), &PreferCount::Estimated)
+            .unwrap()
+            .unwrap();
+        assert_eq!(count.kind, CountKind::Statistics);
+        assert!(count.is_estimate());
+
+        let (sql, params) = count.sql.build();
+        assert!(sql.contains("reltuples"));
+        assert!(!sql.contains("pg_catalog.greatest"));
+        assert!(sql.ends_with("$1::regclass"));
+        assert_eq!(params.len(), 1);
+
+        // Relations without statistics fall back to the planner
+        let (fallback, _) = count.fallback.unwrap().build();
+        assert!(fallback.starts_with("EXPLAIN (FORMAT JSON) SELECT 1 FROM"), "{}", fallback);
+    }
+
+    #[test]
+    fn test_read_returns_row_etag() {
+        let (sql, _) = build_query(&users_read(false), None).unwrap().build_main();
+        assert_eq!(
+            sql,
+            "SELECT *, md5(CAST(\"users\" AS text)) AS \"pgrst_etag\" FROM \"public\".\"users\""
+        );
+
+        // Aggregates stand for no single row
+        let mut tree = ReadPlanTree::empty();
+        tree.root.from = QualifiedIdentifier::new("public", "users");
+        let mut count = CoercibleSelectField::simple("*", "bigint");
+        count.aggregate = Some(crate::api_request::AggregateFunction::Count);
+        tree.root.select.push(count);
+        let (sql, _) = build_query(&ActionPlan::Db(DbActionPlan::Read(tree)), None)
+            .unwrap()
+            .build_main();
+        assert!(!sql.contains("pgrst_etag"), "{}", sql);
+    }
+
+    #[test]
+    fn test_estimated_count_falls_back_to_explain_when_filtered() {
+        let count = build_count_query(&users_read(true), &PreferCount::Estimated)
+            .unwrap()
+            .unwrap();
+        assert_eq!(count.kind, CountKind::Planner);
+
+        let (sql, _) = count.sql.build();
+        assert!(sql.starts_with("EXPLAIN (FORMAT JSON) SELECT 1 FROM"));
+    }
+
+    #[test]
+    fn test_call_count_wraps_function_output() {
+        use crate::plan::{CallParams, CallPlan};
+
+        let call = CallPlan {
+            function: QualifiedIdentifier::new("api", "search_users"),
+            params: CallParams::Named(vec![("q".into(), "ann".into())]),
+            returns_scalar: false,
+            returns_set: true,
+            volatility: "Stable".into(),
+            param_casts: vec![],
+        };
+        let plan = ActionPlan::Db(DbActionPlan::Call { call: call.clone(), read: None });
+        let count = build_count_query(&plan, &PreferCount::Exact).unwrap().unwrap();
+        let (sql, params) = count.sql.build();
+        assert_eq!(
+            sql,
+            "SELECT pg_catalog.count(*) AS total FROM (\
+             SELECT * FROM \"api\".\"search_users\"(\"q\" => $1)) _postrust_count"
+        );
+        assert_eq!(params, vec![SqlParam::Text("ann".into())]);
+
+        // Filters on the output are counted too
+        let mut tree = ReadPlanTree::empty();
+        tree.root.from = QualifiedIdentifier::new("", "pgrst_call_result");
+        tree.root.where_clauses.push(CoercibleLogicTree::NullEmbed {
+            negated: true,
+            field_name: "email".into(),
+        });
+        let plan = ActionPlan::Db(DbActionPlan::Call { call: call.clone(), read: Some(tree) });
+        let count = build_count_query(&plan, &PreferCount::Planned).unwrap().unwrap();
+        assert_eq!(count.kind, CountKind::Planner);
+        let (sql, _) = count.sql.build();
+        assert!(sql.starts_with("EXPLAIN (FORMAT JSON) WITH \"pgrst_call_result\" AS (SELECT * FROM"), "{}", sql);
+        assert!(sql.ends_with("\"email\" IS NOT NULL"), "{}", sql);
+
+        // Volatile functions would run twice, and scalars have nothing to count
+        let volatile = CallPlan { volatility: "Volatile".into(), ..call.clone() };
+        let plan = ActionPlan::Db(DbActionPlan::Call { call: volatile, read: None });
+        assert!(build_count_query(&plan, &PreferCount::Exact).unwrap().is_none());
+        let scalar = CallPlan { returns_set: false, ..call };
+        let plan = ActionPlan::Db(DbActionPlan::Call { call: scalar, read: None });
+        assert!(build_count_query(&plan, &PreferCount::Exact).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_explain_row_estimate() {
+        let plan = serde_json::json!([{"Plan": {"Node Type": "Seq Scan", "Plan Rows": 1234}}]);
+        assert_eq!(explain_row_estimate(&plan), Some(1234));
+        assert_eq!(explain_row_estimate(&serde_json::json!([])), None);
+    }
+
+    #[test]
+    fn test_nested_insert_query() {
+        use crate::plan::{CoercibleField, MutatePlan, NestedInsert};
+
+        let body = serde_json::json!({"name": "Ursula", "books": [{"title": "The Dispossessed"}]});
+        let plan = ActionPlan::Db(DbActionPlan::MutateRead {
+            mutate: MutatePlan::Insert {
+                target: QualifiedIdentifier::new("public", "authors"),
+                columns: vec![CoercibleField::simple("name", "text")],
+                body: Some(bytes::Bytes::from(body.to_string())),
+                on_conflict: None,
+                where_clauses: vec![],
+                returning: vec!["id".into()],
+                pk_cols: vec!["id".into()],
+                apply_defaults: true,
+                nested: vec![NestedInsert {
+                    key: "books".into(),
+                    target: QualifiedIdentifier::new("public", "books"),
+                    columns: vec![CoercibleField::simple("title", "text")],
+                    join_columns: vec![("id".into(), "author_id".into())],
+                }],
+                binary_body: false,
+                raw_json: false,
+            },
+            read: None,
+        });
+
+        let (sql, params) = build_query(&plan, None).unwrap().build_main();
+        assert_eq!(
+            sql,
+            "WITH \"pgrst_parent_0\" AS (INSERT INTO \"public\".\"authors\" (\"name\") \
+             SELECT r.\"name\" FROM json_populate_record(NULL::\"public\".\"authors\", $1::json) r \
+             RETURNING *), \
+             \"pgrst_nested_0_0\" AS (INSERT INTO \"public\".\"books\" (\"title\", \"author_id\") \
+             SELECT r.\"title\", p.\"id\" FROM json_populate_recordset(NULL::\"public\".\"books\", $2::json) r, \
+             \"pgrst_parent_0\" p RETURNING 1) \
+             SELECT \"id\" FROM \"pgrst_parent_0\""
+        );
+        assert_eq!(params.len(), 2);
+    }
+
+    #[test]
+    fn test_upsert_flags_inserted_rows() {
+        use crate::api_request::PreferResolution;
+        use crate::plan::{CoercibleField, MutatePlan};
+
+        let plan = ActionPlan::Db(DbActionPlan::MutateRead {
+            mutate: MutatePlan::Insert {
+                target: QualifiedIdentifier::new("public", "users"),
+                columns: vec![
+                    CoercibleField::simple("id", "integer"),
+                    CoercibleField::simple("name", "text"),
+                ],
+                body: Some(bytes::Bytes::from_static(br#"[{"id":1,"name":"a"}]"#)),
+                on_conflict: Some((PreferResolution::MergeDuplicates, vec!["id".into()])),
+                where_clauses: vec![],
+                returning: vec!["id".into()],
+                pk_cols: vec!["id".into()],
+                apply_defaults: true,
+                nested: vec![],
+                binary_body: false,
+                raw_json: false,
+            },
+            read: None,
+        });
+
+        let (sql, _) = build_query(&plan, None).unwrap().build_main();
+        assert_eq!(
+            sql,
+            "INSERT INTO \"public\".\"users\" (\"id\", \"name\") \
+             SELECT r.\"id\", r.\"name\" FROM json_populate_recordset(NULL::\"public\".\"users\", $1::json) r \
+             ON CONFLICT (\"id\") DO UPDATE SET \"id\" = EXCLUDED.\"id\", \"name\" = EXCLUDED.\"name\" \
+             RETURNING \"id\", xmax = 0 AS \"pgrst_inserted\""
+        );
+    }
+
+    #[test]
+    fn test_order_by_json_path() {
+        use crate::plan::CoercibleOrderTerm;
+
+        let params = crate::api_request::query_params::parse_query_params(
+            "order=data->>priority,data->>priority::int.desc.nullslast",
+        )
+        .unwrap();
+        let mut tree = ReadPlanTree::empty();
+        tree.root.from = QualifiedIdentifier::new("public", "tasks");
+        for term in &params.order[0].1 {
+            tree.root.order.push(CoercibleOrderTerm::from_order_term(term, "jsonb"));
+        }
+
+        let (sql, _) = build_query(&ActionPlan::Db(DbActionPlan::Read(tree)), None)
+            .unwrap()
+            .build_main();
+        assert!(
+            sql.contains("ORDER BY (\"data\"->>'priority'), (\"data\"->>'priority')::int DESC NULLS LAST"),
+            "{}",
+            sql
+        );
+    }
+
+    #[test]
+    fn test_is_filters_and_their_negation() {
+        use crate::plan::CoercibleFilter;
+
+        let cases = [
+            ("flag=is.true", "\"flag\" IS TRUE"),
+            ("flag=is.false", "\"flag\" IS FALSE"),
+            ("flag=is.unknown", "\"flag\" IS UNKNOWN"),
+            ("flag=is.null", "\"flag\" IS NULL"),
+            ("flag=not.is.true", "\"flag\" IS NOT TRUE"),
+            ("flag=not.is.false", "\"flag\" IS NOT FALSE"),
+            ("flag=not.is.unknown", "\"flag\" IS NOT UNKNOWN"),
+            ("flag=not.is.null", "\"flag\" IS NOT NULL"),
+        ];
+        for (query, expected) in cases {
+            let params = crate::api_request::query_params::parse_query_params(query).unwrap();
+            let mut tree = ReadPlanTree::empty();
+            tree.root.from = QualifiedIdentifier::new("public", "users");
+            tree.root
+                .where_clauses
+                .push(CoercibleLogicTree::Stmt(CoercibleFilter::from_filter(&params.filters_root[0], "bool")));
+
+            let (sql, params) = build_query(&ActionPlan::Db(DbActionPlan::Read(tree)), None)
+                .unwrap()
+                .build_main();
+            assert!(sql.contains(&format!("WHERE {}", expected)), "{}: {}", query, sql);
+            assert!(params.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_repeated_filters_are_anded() {
+        use crate::plan::CoercibleFilter;
+
+        let params = crate::api_request::query_params::parse_query_params("id=gt.1&id=lt.9").unwrap();
+        let mut tree = ReadPlanTree::empty();
+        tree.root.from = QualifiedIdentifier::new("public", "users");
+        for filter in &params.filters_root {
+            tree.root
+                .where_clauses
+                .push(CoercibleLogicTree::Stmt(CoercibleFilter::from_filter(filter, "int4")));
+        }
+
+        let (sql, params) = build_query(&ActionPlan::Db(DbActionPlan::Read(tree)), None)
+            .unwrap()
+            .build_main();
+        assert!(sql.contains("\"id\" > $1"), "{}", sql);
+        assert!(sql.contains(" AND "), "{}", sql);
+        assert!(sql.contains("\"id\" < $2"), "{}", sql);
+        assert_eq!(params.len(), 2);
+    }
+
+    #[test]
+    fn test_binary_insert_binds_raw_bytes() {
+        use crate::plan::{CoercibleField, MutatePlan};
+
+        let plan = ActionPlan::Db(DbActionPlan::MutateRead {
+            mutate: MutatePlan::Insert {
+                target: QualifiedIdentifier::new("public", "files"),
+                columns: vec![CoercibleField::simple("data", "bytea")],
+                body: Some(bytes::Bytes::from_static(b"\x00\x01\xff")),
+                on_conflict: None,
+                where_clauses: vec![],
+                returning: vec!["id".into()],
+                pk_cols: vec!["id".into()],
+                apply_defaults: true,
+                nested: vec![],
+                binary_body: true,
+                raw_json: false,
+            },
+            read: None,
+        });
+
+        let (sql, params) = build_query(&plan, None).unwrap().build_main();
+        assert!(sql.starts_with("INSERT INTO \"public\".\"files\" (\"data\") VALUES ($1"), "{}", sql);
+        assert_eq!(params, vec![SqlParam::Bytes(vec![0x00, 0x01, 0xff])]);
+    }
+
+    #[test]
+    fn test_update_with_if_match_adds_etag_predicate() {
+        use crate::plan::{CoercibleField, MutatePlan};
+
+        let plan = ActionPlan::Db(DbActionPlan::MutateRead {
+            mutate: MutatePlan::Update {
+                target: QualifiedIdentifier::new("public", "users"),
+                columns: vec![CoercibleField::simple("name", "text")],
+                body: Some(bytes::Bytes::from_static(br#"{"name":"Bob"}"#)),
+                where_clauses: vec![],
+                returning: vec!["id".into()],
+                apply_defaults: false,
+                if_match: Some("abc123".into()),
+                bulk_key: vec![],
+            },
+            read: None,
+        });
+
+        let (sql, params) = build_query(&plan, None).unwrap().build_main();
+        assert!(sql.contains("WHERE md5(CAST(\"users\" AS text)) = $3"), "{}", sql);
+        assert_eq!(params[2], SqlParam::Text("abc123".into()));
+    }
+}
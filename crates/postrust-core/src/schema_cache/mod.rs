@@ -7,31 +7,78 @@ mod table;
 mod relationship;
 mod routine;
 mod queries;
+mod names;
 
 pub use table::{Table, Column, ColumnMap, TablesMap};
-pub use relationship::{Relationship, Cardinality, Junction, RelationshipsMap};
-pub use routine::{Routine, RoutineParam, RetType, FuncVolatility, RoutineMap};
+pub use relationship::{
+    add_view_relationships, Cardinality, Junction, Relationship, RelationshipsMap,
+    ViewColumnSource,
+};
+pub use routine::{Routine, RoutineFilter, RoutineParam, RetType, FuncVolatility, RoutineMap};
+pub use names::NameIndex;
 
 use crate::api_request::QualifiedIdentifier;
 use crate::error::{Error, Result};
+use serde::{Serialize, Serializer};
 use sqlx::PgPool;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 use tracing::info;
 
 /// Cached PostgreSQL schema metadata.
-#[derive(Clone, Debug)]
+///
+/// Serializes (for `--dump-schema`) with maps keyed by `schema.name` and
+/// in sorted order, so dumps can be diffed.
+#[derive(Clone, Debug, Default, Serialize)]
 pub struct SchemaCache {
     /// Tables and views by qualified identifier.
+    #[serde(serialize_with = "by_name")]
     pub tables: TablesMap,
     /// Relationships between tables.
+    #[serde(serialize_with = "relationships_by_name")]
     pub relationships: RelationshipsMap,
     /// Stored functions/procedures.
+    #[serde(serialize_with = "by_name")]
     pub routines: RoutineMap,
     /// Valid timezone names.
+    #[serde(serialize_with = "sorted")]
     pub timezones: HashSet<String>,
+    /// User-defined types (composite, domain, enum, range) in the exposed schemas.
+    #[serde(serialize_with = "sorted")]
+    pub types: HashSet<QualifiedIdentifier>,
     /// PostgreSQL version.
     pub pg_version: i32,
+    /// Exposed schemas this cache was loaded for.
+    pub schemas: Vec<String>,
+    /// Lowercased names, when names resolve case-insensitively.
+    #[serde(skip)]
+    pub name_index: Option<NameIndex>,
+}
+
+fn by_name<V: Serialize, S: Serializer>(
+    map: &HashMap<QualifiedIdentifier, V>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    let sorted: BTreeMap<String, &V> = map.iter().map(|(qi, v)| (qi.to_string(), v)).collect();
+    sorted.serialize(serializer)
+}
+
+/// Keyed by table, then by the schema the relationships are seen from.
+fn relationships_by_name<S: Serializer>(
+    map: &RelationshipsMap,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    let mut sorted: BTreeMap<String, BTreeMap<&str, &Vec<Relationship>>> = BTreeMap::new();
+    for ((qi, schema), rels) in map {
+        sorted.entry(qi.to_string()).or_default().insert(schema, rels);
+    }
+    sorted.serialize(serializer)
+}
+
+fn sorted<T: ToString, S: Serializer>(set: &HashSet<T>, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    let mut names: Vec<String> = set.iter().map(T::to_string).collect();
+    names.sort();
+    names.serialize(serializer)
 }
 
 impl SchemaCache {
@@ -59,12 +106,18 @@ impl SchemaCache {
         let timezones = queries::load_timezones(pool).await?;
         info!("Loaded {} timezones", timezones.len());
 
+        // Load user-defined type names
+        let types = queries::load_types(pool, schemas).await?;
+
         Ok(Self {
             tables,
             relationships,
             routines,
             timezones,
+            types,
             pg_version,
+            schemas: schemas.to_vec(),
+            name_index: None,
         })
     }
 
@@ -75,8 +128,11 @@ impl SchemaCache {
 
     /// Get a table, returning an error if not found.
     pub fn require_table(&self, qi: &QualifiedIdentifier) -> Result<&Table> {
-        self.get_table(qi)
-            .ok_or_else(|| Error::TableNotFound(qi.to_string()))
+        self.get_table(qi).ok_or_else(|| Error::TableNotFound {
+            name: qi.to_string(),
+            suggestion: closest_name(qi, self.tables.keys()),
+            schemas: self.schemas.clone(),
+        })
     }
 
     /// Get relationships for a table.
@@ -89,11 +145,51 @@ impl SchemaCache {
         self.routines.get(qi)
     }
 
+    /// Get the overloads of a routine, returning an error if not found.
+    pub fn require_routines(&self, qi: &QualifiedIdentifier) -> Result<&Vec<Routine>> {
+        self.get_routines(qi)
+            .filter(|routines| !routines.is_empty())
+            .ok_or_else(|| Error::FunctionNotFound {
+                name: qi.to_string(),
+                suggestion: closest_name(qi, self.routines.keys()),
+                schemas: self.schemas.clone(),
+            })
+    }
+
+    /// Drop the routines the filter does not expose, so requests for them
+    /// find no function.
+    pub fn with_routine_filter(mut self, filter: &RoutineFilter) -> Self {
+        for routines in self.routines.values_mut() {
+            routines.retain(|routine| filter.exposes(routine));
+        }
+        self.routines.retain(|_, routines| !routines.is_empty());
+        self
+    }
+
     /// Check if a timezone is valid.
     pub fn is_valid_timezone(&self, tz: &str) -> bool {
         self.timezones.contains(tz)
     }
 
+    /// Check that the target of a `::type` cast exists.
+    ///
+    /// Only names qualified with an exposed schema can be verified; built-in
+    /// and other types are left for PostgreSQL to reject.
+    pub fn check_cast(&self, cast: &str) -> Result<()> {
+        let base = cast.split(['(', '[']).next().unwrap_or(cast);
+        if let Some((schema, name)) = base.split_once('.') {
+            if self.schemas.iter().any(|s| s == schema)
+                && !self.types.contains(&QualifiedIdentifier::new(schema, name))
+            {
+                return Err(Error::InvalidQueryParam(format!(
+                    "unknown type '{}' in cast",
+                    base
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Get a summary of the cached schema.
     pub fn summary(&self) -> String {
         format!(
@@ -123,6 +219,94 @@ impl SchemaCache {
                 }
             })
     }
+
+    /// Resolve the relationship for an embed, applying the optional hint.
+    ///
+    /// Errors when no relationship matches, or when several do and the hint
+    /// does not narrow them down to one.
+    pub fn resolve_relationship(
+        &self,
+        from: &QualifiedIdentifier,
+        to_name: &str,
+        hint: Option<&str>,
+        schema: &str,
+    ) -> Result<&Relationship> {
+        let named: Vec<&Relationship> = self
+            .get_relationships(from, schema)
+            .into_iter()
+            .flatten()
+            .filter(|r| r.matches_name(to_name))
+            .collect();
+        let candidates: Vec<&Relationship> = named
+            .iter()
+            .copied()
+            .filter(|r| hint.is_none_or(|h| r.matches_hint(h)))
+            .collect();
+
+        match candidates.as_slice() {
+            [] => Err(Error::RelationshipNotFound(to_name.to_string())),
+            [rel] => Ok(rel),
+            many => Err(Error::AmbiguousEmbedding {
+                from: from.name.clone(),
+                to: to_name.to_string(),
+                candidates: many
+                    .iter()
+                    .map(|rel| (disambiguated_embed(rel, to_name, &named), rel.describe()))
+                    .collect(),
+            }),
+        }
+    }
+}
+
+/// The embed that selects `rel` alone among the relationships `named` by
+/// the same name: `name!hint` with the first hint matching no other, or
+/// else the relationship's own name.
+fn disambiguated_embed(rel: &Relationship, to_name: &str, named: &[&Relationship]) -> String {
+    let hints = rel.hints();
+    let unique = hints
+        .iter()
+        .find(|hint| named.iter().filter(|r| r.matches_hint(hint)).count() == 1);
+    match (unique, rel.self_name()) {
+        (Some(hint), _) => format!("{}!{}", to_name, hint),
+        // Both directions of a self-reference share their constraint
+        (None, Some(name)) => name,
+        (None, None) => format!("{}!{}", to_name, hints[0]),
+    }
+}
+
+/// Find the known name closest to `target`, for "did you mean" hints.
+///
+/// Names in the target's schema are preferred on ties. Matches more than
+/// two edits away (or a third of the name's length, for long names) are
+/// not suggested.
+fn closest_name<'a>(
+    target: &QualifiedIdentifier,
+    candidates: impl Iterator<Item = &'a QualifiedIdentifier>,
+) -> Option<String> {
+    let max_distance = (target.name.chars().count() / 3).max(2);
+
+    candidates
+        .map(|qi| (levenshtein(&target.name, &qi.name), qi.schema != target.schema, qi))
+        .filter(|(distance, _, _)| *distance <= max_distance)
+        .min_by(|a, b| (a.0, a.1, &a.2.name).cmp(&(b.0, b.1, &b.2.name)))
+        .map(|(_, _, qi)| qi.to_string())
+}
+
+/// Levenshtein edit distance between two strings.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+
+    prev[b.len()]
 }
 
 /// Thread-safe schema cache wrapper.
@@ -168,3 +352,191 @@ impl Default for SchemaCacheRef {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn fk(table: &str, foreign: &str, constraint: &str, column: &str) -> Relationship {
+        Relationship::ForeignKey {
+            table: QualifiedIdentifier::new("public", table),
+            foreign_table: QualifiedIdentifier::new("public", foreign),
+            is_self: false,
+            cardinality: Cardinality::M2O {
+                constraint: constraint.into(),
+                columns: vec![(column.into(), "id".into())],
+            },
+            table_is_view: false,
+            foreign_table_is_view: false,
+            constraint_name: constraint.into(),
+        }
+    }
+
+    fn cache_with(rels: Vec<Relationship>) -> SchemaCache {
+        let mut relationships = HashMap::new();
+        relationships.insert(
+            (QualifiedIdentifier::new("public", "messages"), "public".to_string()),
+            rels,
+        );
+        SchemaCache {
+            relationships,
+            pg_version: 150000,
+            schemas: vec!["public".into()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("users", "users"), 0);
+        assert_eq!(levenshtein("usrs", "users"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_unknown_table_suggests_closest() {
+        let mut cache = cache_with(vec![]);
+        for name in ["users", "user_roles", "projects"] {
+            let qi = QualifiedIdentifier::new("public", name);
+            cache.tables.insert(
+                qi,
+                Table {
+                    schema: "public".into(),
+                    name: name.into(),
+                    description: None,
+                    is_view: false,
+                    insertable: true,
+                    updatable: true,
+                    deletable: true,
+                    pk_cols: vec![],
+                    columns: ColumnMap::new(),
+                },
+            );
+        }
+
+        let err = cache
+            .require_table(&QualifiedIdentifier::new("public", "usres"))
+            .unwrap_err();
+        assert_eq!(err.status_code(), http::StatusCode::NOT_FOUND);
+
+        let json = err.to_json();
+        assert_eq!(json["code"], "PGRST301");
+        assert_eq!(json["hint"], "Perhaps you meant the table 'public.users'");
+        assert!(json["details"].as_str().unwrap().contains("exposed schemas: public"));
+
+        // Nothing close enough: fall back to the generic hint
+        let err = cache
+            .require_table(&QualifiedIdentifier::new("public", "invoices"))
+            .unwrap_err();
+        assert_eq!(err.to_json()["hint"], "Check the table name and schema");
+    }
+
+    #[test]
+    fn test_dump_is_json_with_tables_by_name() {
+        let mut cache = cache_with(vec![fk("messages", "users", "messages_sender_fkey", "sender_id")]);
+        let users = Table {
+            schema: "public".into(),
+            name: "users".into(),
+            description: None,
+            is_view: false,
+            insertable: true,
+            updatable: true,
+            deletable: true,
+            pk_cols: vec!["id".into()],
+            columns: ColumnMap::new(),
+        };
+        cache.tables.insert(users.qualified_identifier(), users);
+        cache.timezones = ["UTC".to_string(), "Europe/Paris".to_string()].into();
+
+        let dump: serde_json::Value = serde_json::from_str(&serde_json::to_string(&cache).unwrap()).unwrap();
+        assert_eq!(dump["tables"]["public.users"]["pk_cols"], serde_json::json!(["id"]));
+        assert!(dump["relationships"]["public.messages"]["public"].is_array());
+        assert_eq!(dump["timezones"], serde_json::json!(["Europe/Paris", "UTC"]));
+        assert_eq!(dump["pg_version"], 150000);
+        assert!(dump.get("name_index").is_none());
+    }
+
+    #[test]
+    fn test_filtered_routines_are_not_found() {
+        let routine = |name: &str, volatility: FuncVolatility| Routine {
+            schema: "public".into(),
+            name: name.into(),
+            description: None,
+            params: vec![],
+            return_type: RetType::Void,
+            volatility,
+            has_variadic: false,
+            isolation_level: None,
+            settings: vec![],
+            is_procedure: false,
+        };
+        let mut cache = cache_with(vec![]);
+        let routines = [("search", FuncVolatility::Stable), ("reset", FuncVolatility::Volatile)];
+        for (name, volatility) in routines {
+            let qi = QualifiedIdentifier::new("public", name);
+            cache.routines.insert(qi, vec![routine(name, volatility)]);
+        }
+
+        let filter = RoutineFilter {
+            volatility: Some(vec![FuncVolatility::Stable]),
+            ..Default::default()
+        };
+        let cache = cache.with_routine_filter(&filter);
+        assert!(cache.require_routines(&QualifiedIdentifier::new("public", "search")).is_ok());
+
+        let err = cache.require_routines(&QualifiedIdentifier::new("public", "reset")).unwrap_err();
+        assert!(matches!(err, Error::FunctionNotFound { .. }), "{:?}", err);
+        assert_eq!(err.status_code(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_resolve_relationship_unique() {
+        let cache = cache_with(vec![fk("messages", "users", "messages_sender_fkey", "sender_id")]);
+        let messages = QualifiedIdentifier::new("public", "messages");
+
+        let rel = cache.resolve_relationship(&messages, "users", None, "public").unwrap();
+        assert_eq!(rel.foreign_table().name, "users");
+
+        let err = cache.resolve_relationship(&messages, "teams", None, "public").unwrap_err();
+        assert!(matches!(err, Error::RelationshipNotFound(_)));
+    }
+
+    #[test]
+    fn test_resolve_relationship_ambiguous_requires_hint() {
+        let cache = cache_with(vec![
+            fk("messages", "users", "messages_sender_fkey", "sender_id"),
+            fk("messages", "users", "messages_receiver_fkey", "receiver_id"),
+        ]);
+        let messages = QualifiedIdentifier::new("public", "messages");
+
+        let err = cache.resolve_relationship(&messages, "users", None, "public").unwrap_err();
+        assert_eq!(err.status_code(), http::StatusCode::MULTIPLE_CHOICES);
+        let Error::AmbiguousEmbedding { candidates, .. } = &err else {
+            panic!("Expected an ambiguous embedding, got {:?}", err);
+        };
+        assert_eq!(
+            candidates,
+            &vec![
+                (
+                    "users!messages_sender_fkey".to_string(),
+                    "messages(sender_id) -> users(id) using messages_sender_fkey".to_string()
+                ),
+                (
+                    "users!messages_receiver_fkey".to_string(),
+                    "messages(receiver_id) -> users(id) using messages_receiver_fkey".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            err.to_json()["hint"],
+            "Try changing 'users' to one of: 'users!messages_sender_fkey', 'users!messages_receiver_fkey'"
+        );
+
+        let rel = cache
+            .resolve_relationship(&messages, "users", Some("receiver_id"), "public")
+            .unwrap();
+        assert_eq!(rel.join_columns(), vec![("receiver_id".into(), "id".into())]);
+    }
+}
@@ -0,0 +1,240 @@
+//! Case-insensitive resolution of table and column names.
+
+use super::SchemaCache;
+use crate::api_request::{Action, ApiRequest, DbAction, LogicTree, OrderTerm, QualifiedIdentifier, SelectItem};
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+
+/// Lowercased names mapped to the exact names they may stand for.
+#[derive(Clone, Debug, Default)]
+pub struct NameIndex {
+    tables: HashMap<(String, String), Vec<String>>,
+    columns: HashMap<QualifiedIdentifier, HashMap<String, Vec<String>>>,
+}
+
+impl NameIndex {
+    /// Index every table and column of the cache.
+    pub fn new(cache: &SchemaCache) -> Self {
+        let mut index = Self::default();
+        for (qi, table) in &cache.tables {
+            index
+                .tables
+                .entry((qi.schema.clone(), qi.name.to_lowercase()))
+                .or_default()
+                .push(qi.name.clone());
+            let columns = index.columns.entry(qi.clone()).or_default();
+            for name in table.columns.keys() {
+                columns.entry(name.to_lowercase()).or_default().push(name.clone());
+            }
+        }
+        for names in index.tables.values_mut() {
+            names.sort();
+        }
+        for names in index.columns.values_mut().flat_map(|c| c.values_mut()) {
+            names.sort();
+        }
+        index
+    }
+}
+
+/// Pick the exact name `name` refers to; an exact match always wins.
+fn resolve<'a>(candidates: Option<&'a Vec<String>>, name: &str, kind: &str) -> Result<Option<&'a String>> {
+    let Some(candidates) = candidates else {
+        return Ok(None);
+    };
+    if let Some(exact) = candidates.iter().find(|c| *c == name) {
+        return Ok(Some(exact));
+    }
+    match candidates.as_slice() {
+        [only] => Ok(Some(only)),
+        many => Err(Error::AmbiguousRequest(format!(
+            "{} '{}' matches {}",
+            kind,
+            name,
+            many.join(", ")
+        ))),
+    }
+}
+
+impl SchemaCache {
+    /// Build the index used by [`SchemaCache::resolve_names`].
+    pub fn with_case_insensitive_names(mut self) -> Self {
+        self.name_index = Some(NameIndex::new(&self));
+        self
+    }
+
+    /// Rewrite the request's table name and its top-level column names to
+    /// the exact case used in the database.
+    ///
+    /// Does nothing unless case-insensitive names are enabled. Names that
+    /// match several identifiers differing only in case are rejected.
+    pub fn resolve_names(&self, request: &mut ApiRequest) -> Result<()> {
+        let Some(index) = &self.name_index else {
+            return Ok(());
+        };
+
+        let qi = match &mut request.action {
+            Action::Db(DbAction::RelationRead { qi, .. })
+            | Action::Db(DbAction::RelationMut { qi, .. })
+            | Action::RelationInfo(qi) => qi,
+            _ => return Ok(()),
+        };
+        let key = (qi.schema.clone(), qi.name.to_lowercase());
+        if let Some(name) = resolve(index.tables.get(&key), &qi.name, "table")? {
+            qi.name = name.clone();
+        }
+        let Some(columns) = index.columns.get(qi) else {
+            return Ok(());
+        };
+        let column = |name: &mut String| -> Result<()> {
+            if let Some(exact) = resolve(columns.get(&name.to_lowercase()), name, "column")? {
+                *name = exact.clone();
+            }
+            Ok(())
+        };
+
+        let params = &mut request.query_params;
+        for item in &mut params.select {
+            match item {
+                SelectItem::Field { field, .. } => column(&mut field.name)?,
+                SelectItem::Headline { column: name, .. } => column(name)?,
+                SelectItem::Relation { .. } | SelectItem::SpreadRelation { .. } => {}
+            }
+        }
+        for filter in &mut params.filters_root {
+            column(&mut filter.field.name)?;
+        }
+        params.filter_fields = params.filters_root.iter().map(|f| f.field.name.clone()).collect();
+        for (_, tree) in params.logic.iter_mut().filter(|(path, _)| path.is_empty()) {
+            resolve_tree(tree, &column)?;
+        }
+        for (_, terms) in params.order.iter_mut().filter(|(path, _)| path.is_empty()) {
+            for term in terms {
+                if let OrderTerm::Field { field, .. } = term {
+                    column(&mut field.name)?;
+                }
+            }
+        }
+        if let Some(names) = params.columns.take() {
+            let mut resolved = std::collections::HashSet::new();
+            for mut name in names {
+                column(&mut name)?;
+                resolved.insert(name);
+            }
+            params.columns = Some(resolved);
+        }
+        for name in params.on_conflict.iter_mut().flatten() {
+            column(name)?;
+        }
+        Ok(())
+    }
+}
+
+fn resolve_tree(tree: &mut LogicTree, column: &impl Fn(&mut String) -> Result<()>) -> Result<()> {
+    match tree {
+        LogicTree::Expr { children, .. } => children.iter_mut().try_for_each(|c| resolve_tree(c, column)),
+        LogicTree::Stmt(filter) => column(&mut filter.field.name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_request::query_params::parse_query_params;
+    use crate::schema_cache::{Column, Table};
+    use indexmap::IndexMap;
+
+    fn cache(tables: &[(&str, &[&str])]) -> SchemaCache {
+        let tables = tables
+            .iter()
+            .map(|(name, cols)| {
+                let columns: IndexMap<String, Column> = cols
+                    .iter()
+                    .enumerate()
+                    .map(|(i, col)| {
+                        let column = Column {
+                            name: col.to_string(),
+                            nullable: true,
+                            data_type: "text".into(),
+                            nominal_type: "text".into(),
+                            position: i as i32 + 1,
+                            ..Default::default()
+                        };
+                        (col.to_string(), column)
+                    })
+                    .collect();
+                let table = Table {
+                    schema: "public".into(),
+                    name: name.to_string(),
+                    description: None,
+                    is_view: false,
+                    insertable: true,
+                    updatable: true,
+                    deletable: true,
+                    pk_cols: vec![],
+                    columns,
+                };
+                (table.qualified_identifier(), table)
+            })
+            .collect();
+        SchemaCache {
+            tables,
+            pg_version: 150000,
+            schemas: vec!["public".into()],
+            ..Default::default()
+        }
+        .with_case_insensitive_names()
+    }
+
+    fn read(table: &str, query: &str) -> ApiRequest {
+        ApiRequest {
+            action: Action::Db(DbAction::RelationRead {
+                qi: QualifiedIdentifier::new("public", table),
+                headers_only: false,
+            }),
+            query_params: parse_query_params(query).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lowercase_names_resolve_to_exact_case() {
+        let cache = cache(&[("UserAccounts", &["Id", "EmailAddress"])]);
+        let mut request = read("useraccounts", "select=id,emailaddress&emailaddress=eq.a&order=id.desc");
+
+        cache.resolve_names(&mut request).unwrap();
+        assert!(matches!(
+            &request.action,
+            Action::Db(DbAction::RelationRead { qi, .. }) if qi.name == "UserAccounts"
+        ));
+        let params = &request.query_params;
+        assert!(matches!(&params.select[1], SelectItem::Field { field, .. } if field.name == "EmailAddress"));
+        assert_eq!(params.filters_root[0].field.name, "EmailAddress");
+        assert!(params.filter_fields.contains("EmailAddress"));
+        assert!(matches!(&params.order[0].1[0], OrderTerm::Field { field, .. } if field.name == "Id"));
+    }
+
+    #[test]
+    fn test_ambiguous_names_are_rejected() {
+        let cache = cache(&[("Users", &["id"]), ("USERS", &["id"]), ("items", &["Name", "NAME"])]);
+
+        let err = cache.resolve_names(&mut read("users", "")).unwrap_err();
+        assert!(matches!(err, Error::AmbiguousRequest(_)), "{:?}", err);
+
+        // An exact match is never ambiguous
+        cache.resolve_names(&mut read("USERS", "")).unwrap();
+
+        let err = cache.resolve_names(&mut read("items", "name=eq.x")).unwrap_err();
+        assert!(matches!(err, Error::AmbiguousRequest(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_exact_case_without_index() {
+        let mut cache = cache(&[("Users", &["Id"])]);
+        cache.name_index = None;
+
+        let mut request = read("users", "id=eq.1");
+        cache.resolve_names(&mut request).unwrap();
+        assert_eq!(request.query_params.filters_root[0].field.name, "id");
+    }
+}
@@ -1,8 +1,10 @@
 //! SQL queries for schema introspection.
 
 use super::table::{Column, ColumnMap, Table, TablesMap};
-use super::relationship::{Cardinality, Relationship, RelationshipsMap};
-use super::routine::{FuncVolatility, RetType, Routine, RoutineMap};
+use super::relationship::{
+    self, Cardinality, Relationship, RelationshipsMap, ViewColumnSource,
+};
+use super::routine::{FuncVolatility, RetType, Routine, RoutineMap, RoutineParam};
 use crate::api_request::QualifiedIdentifier;
 use crate::error::{Error, Result};
 use indexmap::IndexMap;
@@ -113,8 +115,13 @@ async fn load_columns(
             c.is_nullable,
             c.data_type,
             c.udt_name,
+            c.domain_schema,
+            c.domain_name,
             c.character_maximum_length,
             c.column_default,
+            c.is_updatable,
+            c.is_generated,
+            c.identity_generation,
             pg_catalog.col_description(
                 (quote_ident(c.table_schema) || '.' || quote_ident(c.table_name))::regclass,
                 c.ordinal_position
@@ -122,14 +129,25 @@ async fn load_columns(
             CASE WHEN e.enumtypid IS NOT NULL
                  THEN array_agg(e.enumlabel ORDER BY e.enumsortorder)
                  ELSE ARRAY[]::text[]
-            END as enum_values
+            END as enum_values,
+            ARRAY(
+                SELECT pg_get_constraintdef(con.oid)
+                FROM pg_constraint con
+                JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = con.conkey[1]
+                WHERE con.contype = 'c'
+                  AND con.conrelid = (quote_ident(c.table_schema) || '.' || quote_ident(c.table_name))::regclass
+                  AND cardinality(con.conkey) = 1
+                  AND a.attname = c.column_name
+                ORDER BY con.conname
+            ) as checks
         FROM information_schema.columns c
         LEFT JOIN pg_type t ON t.typname = c.udt_name
         LEFT JOIN pg_enum e ON e.enumtypid = t.oid
         WHERE c.table_schema = $1 AND c.table_name = $2
         GROUP BY c.table_schema, c.table_name, c.column_name, c.ordinal_position, c.is_nullable,
-                 c.data_type, c.udt_name, c.character_maximum_length,
-                 c.column_default, t.oid, e.enumtypid
+                 c.data_type, c.udt_name, c.domain_schema, c.domain_name, c.character_maximum_length,
+                 c.column_default, c.is_updatable, c.is_generated, c.identity_generation,
+                 t.oid, e.enumtypid
         ORDER BY c.ordinal_position
         "#,
     )
@@ -146,7 +164,13 @@ async fn load_columns(
         let udt_name: String = row.get("udt_name");
         let max_len: Option<i32> = row.get("character_maximum_length");
         let enum_values: Vec<String> = row.get("enum_values");
+        let checks: Vec<String> = row.get("checks");
         let position: i32 = row.get("ordinal_position");
+        let is_updatable: String = row.get("is_updatable");
+        let domain_schema: Option<String> = row.get("domain_schema");
+        let domain_name: Option<String> = row.get("domain_name");
+        let is_generated: Option<String> = row.get("is_generated");
+        let identity_generation: Option<String> = row.get("identity_generation");
 
         let column = Column {
             name: name.clone(),
@@ -157,8 +181,13 @@ async fn load_columns(
             max_len,
             default: row.get("column_default"),
             enum_values,
+            checks,
             is_pk: pk_cols.contains(&name),
             position,
+            updatable: is_updatable == "YES",
+            domain: domain_schema.zip(domain_name).map(|(s, n)| QualifiedIdentifier::new(s, n)),
+            generated: is_generated.as_deref() == Some("ALWAYS")
+                || identity_generation.as_deref() == Some("ALWAYS"),
         };
 
         columns.insert(name, column);
@@ -273,7 +302,7 @@ pub async fn load_relationships(pool: &PgPool, schemas: &[String]) -> Result<Rel
         } else {
             Cardinality::O2M {
                 constraint: constraint_name.clone(),
-                columns: column_pairs,
+                columns: reverse_columns,
             }
         };
 
@@ -293,9 +322,136 @@ pub async fn load_relationships(pool: &PgPool, schemas: &[String]) -> Result<Rel
             .push(reverse_rel);
     }
 
+    let view_sources = load_view_sources(pool, schemas).await?;
+    relationship::add_view_relationships(&mut relationships, &view_sources);
+
+    for rel in load_computed_relationships(pool, schemas).await? {
+        let Relationship::Computed { function, table, .. } = &rel else {
+            continue;
+        };
+        relationships
+            .entry((table.clone(), function.schema.clone()))
+            .or_default()
+            .push(rel);
+    }
+
     Ok(relationships)
 }
 
+/// Load computed relationships: functions taking a single row of one table
+/// and returning rows of another (or the same) table.
+async fn load_computed_relationships(pool: &PgPool, schemas: &[String]) -> Result<Vec<Relationship>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            pn.nspname as function_schema,
+            p.proname as function_name,
+            tn.nspname as table_schema,
+            t.relname as table_name,
+            fn.nspname as foreign_table_schema,
+            f.relname as foreign_table_name,
+            NOT p.proretset as to_one
+        FROM pg_proc p
+        JOIN pg_namespace pn ON pn.oid = p.pronamespace
+        JOIN pg_type arg ON arg.oid = p.proargtypes[0]
+        JOIN pg_class t ON t.oid = arg.typrelid
+        JOIN pg_namespace tn ON tn.oid = t.relnamespace
+        JOIN pg_type ret ON ret.oid = p.prorettype
+        JOIN pg_class f ON f.oid = ret.typrelid
+        JOIN pg_namespace fn ON fn.oid = f.relnamespace
+        WHERE p.pronargs = 1
+          AND p.prokind = 'f'
+          AND t.relkind IN ('r', 'v', 'm', 'f', 'p')
+          AND f.relkind IN ('r', 'v', 'm', 'f', 'p')
+          AND pn.nspname = ANY($1)
+        "#,
+    )
+    .bind(schemas)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| Error::SchemaCacheLoadFailed(e.to_string()))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let table = QualifiedIdentifier::new(
+                row.get::<String, _>("table_schema"),
+                row.get::<String, _>("table_name"),
+            );
+            let foreign_table = QualifiedIdentifier::new(
+                row.get::<String, _>("foreign_table_schema"),
+                row.get::<String, _>("foreign_table_name"),
+            );
+            Relationship::Computed {
+                function: QualifiedIdentifier::new(
+                    row.get::<String, _>("function_schema"),
+                    row.get::<String, _>("function_name"),
+                ),
+                is_self: table == foreign_table,
+                table_alias: table.clone(),
+                table,
+                foreign_table,
+                to_one: row.get("to_one"),
+            }
+        })
+        .collect())
+}
+
+/// Load base table columns that views pass through unchanged.
+///
+/// Uses the view rewrite rule's `pg_depend` entries; a base column counts
+/// as passed through when the view exposes a column with the same name.
+async fn load_view_sources(pool: &PgPool, schemas: &[String]) -> Result<Vec<ViewColumnSource>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT DISTINCT
+            vn.nspname as view_schema,
+            v.relname as view_name,
+            tn.nspname as table_schema,
+            t.relname as table_name,
+            ta.attname as column_name
+        FROM pg_rewrite r
+        JOIN pg_class v ON v.oid = r.ev_class AND v.relkind IN ('v', 'm')
+        JOIN pg_namespace vn ON vn.oid = v.relnamespace
+        JOIN pg_depend d ON d.objid = r.oid
+            AND d.classid = 'pg_rewrite'::regclass
+            AND d.refclassid = 'pg_class'::regclass
+            AND d.refobjsubid > 0
+        JOIN pg_class t ON t.oid = d.refobjid AND t.oid <> v.oid
+        JOIN pg_namespace tn ON tn.oid = t.relnamespace
+        JOIN pg_attribute ta ON ta.attrelid = t.oid AND ta.attnum = d.refobjsubid
+        JOIN pg_attribute va ON va.attrelid = v.oid
+            AND va.attname = ta.attname
+            AND va.attnum > 0
+            AND NOT va.attisdropped
+        WHERE vn.nspname = ANY($1)
+        "#,
+    )
+    .bind(schemas)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| Error::SchemaCacheLoadFailed(e.to_string()))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let column: String = row.get("column_name");
+            ViewColumnSource {
+                view: QualifiedIdentifier::new(
+                    row.get::<String, _>("view_schema"),
+                    row.get::<String, _>("view_name"),
+                ),
+                view_column: column.clone(),
+                table: QualifiedIdentifier::new(
+                    row.get::<String, _>("table_schema"),
+                    row.get::<String, _>("table_name"),
+                ),
+                table_column: column,
+            }
+        })
+        .collect())
+}
+
 /// Load stored functions.
 pub async fn load_routines(pool: &PgPool, schemas: &[String]) -> Result<RoutineMap> {
     let mut routines: RoutineMap = HashMap::new();
@@ -314,7 +470,25 @@ pub async fn load_routines(pool: &PgPool, schemas: &[String]) -> Result<RoutineM
                 WHEN p.proretset THEN 'SETOF ' || pg_catalog.format_type(p.prorettype, NULL)
                 ELSE pg_catalog.format_type(p.prorettype, NULL)
             END as return_type,
-            p.proretset as returns_set
+            p.proretset as returns_set,
+            COALESCE((
+                SELECT json_agg(json_build_object(
+                    'name', COALESCE(args.name, ''),
+                    'param_type', pg_catalog.format_type(args.type, NULL),
+                    'type_max_length', pg_catalog.format_type(args.type, NULL),
+                    'required', args.idx <= p.pronargs - p.pronargdefaults,
+                    'variadic', COALESCE(args.mode, 'i') = 'v'
+                ) ORDER BY args.idx)
+                FROM (
+                    SELECT a.type, a.name, a.mode, row_number() OVER (ORDER BY a.ord) AS idx
+                    FROM unnest(
+                        COALESCE(p.proallargtypes, p.proargtypes::oid[]),
+                        p.proargnames,
+                        p.proargmodes
+                    ) WITH ORDINALITY AS a(type, name, mode, ord)
+                    WHERE COALESCE(a.mode, 'i') IN ('i', 'b', 'v')
+                ) args
+            ), '[]') as params
         FROM pg_proc p
         JOIN pg_namespace n ON n.oid = p.pronamespace
         WHERE n.nspname = ANY($1)
@@ -348,7 +522,7 @@ pub async fn load_routines(pool: &PgPool, schemas: &[String]) -> Result<RoutineM
             schema,
             name,
             description: row.get("description"),
-            params: vec![], // Simplified - full implementation would parse args
+            params: row.get::<sqlx::types::Json<Vec<RoutineParam>>, _>("params").0,
             return_type,
             volatility: FuncVolatility::from_char(volatility.chars().next().unwrap_or('v')),
             has_variadic: row.get("has_variadic"),
@@ -363,6 +537,29 @@ pub async fn load_routines(pool: &PgPool, schemas: &[String]) -> Result<RoutineM
     Ok(routines)
 }
 
+/// Load the names of user-defined types in the given schemas.
+pub async fn load_types(pool: &PgPool, schemas: &[String]) -> Result<HashSet<QualifiedIdentifier>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT n.nspname AS schema, t.typname AS name
+        FROM pg_type t
+        JOIN pg_namespace n ON n.oid = t.typnamespace
+        WHERE n.nspname = ANY($1)
+          AND t.typtype IN ('b', 'c', 'd', 'e', 'r')
+          AND t.typelem = 0
+        "#,
+    )
+    .bind(schemas)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| Error::SchemaCacheLoadFailed(e.to_string()))?;
+
+    Ok(rows
+        .iter()
+        .map(|r| QualifiedIdentifier::new(r.get::<String, _>("schema"), r.get::<String, _>("name")))
+        .collect())
+}
+
 /// Load valid timezone names.
 pub async fn load_timezones(pool: &PgPool) -> Result<HashSet<String>> {
     let rows = sqlx::query("SELECT name FROM pg_timezone_names")
@@ -68,6 +68,108 @@ impl Relationship {
             Self::Computed { .. } => vec![],
         }
     }
+
+    /// Check if an embed names this relationship by its foreign table.
+    ///
+    /// A table referencing itself is its own foreign table in both
+    /// directions, so those directions also have names of their own: the
+    /// referenced row by the foreign key column (`parent_id`, or `parent`
+    /// without the `_id` suffix), and the referencing rows as `children`.
+    /// A computed relationship is named by its function.
+    pub fn matches_name(&self, name: &str) -> bool {
+        if let Self::Computed { function, .. } = self {
+            return function.name == name;
+        }
+        if self.foreign_table().name == name {
+            return true;
+        }
+        let Self::ForeignKey { is_self: true, cardinality, .. } = self else {
+            return false;
+        };
+        match cardinality {
+            Cardinality::M2O { columns, .. } | Cardinality::O2O { columns, is_parent: false, .. } => {
+                match columns.as_slice() {
+                    [(col, _)] => col == name || col.strip_suffix("_id") == Some(name),
+                    _ => false,
+                }
+            }
+            Cardinality::O2M { .. } | Cardinality::O2O { is_parent: true, .. } => name == "children",
+            Cardinality::M2M(_) => false,
+        }
+    }
+
+    /// Check if an embed hint (`table!hint(...)`) selects this relationship.
+    ///
+    /// A hint matches the constraint name, the foreign table name, or a join
+    /// column on either side.
+    pub fn matches_hint(&self, hint: &str) -> bool {
+        match self {
+            Self::ForeignKey {
+                foreign_table,
+                constraint_name,
+                ..
+            } => {
+                constraint_name == hint
+                    || foreign_table.name == hint
+                    || self
+                        .join_columns()
+                        .iter()
+                        .any(|(col, fcol)| col == hint || fcol == hint)
+            }
+            Self::Computed { function, .. } => function.name == hint,
+        }
+    }
+
+    /// Hints that could select this relationship, most specific first: the
+    /// constraint (or function) name, then this table's join columns.
+    pub fn hints(&self) -> Vec<String> {
+        match self {
+            Self::ForeignKey { constraint_name, .. } => std::iter::once(constraint_name.clone())
+                .chain(self.join_columns().into_iter().map(|(col, _)| col))
+                .collect(),
+            Self::Computed { function, .. } => vec![function.name.clone()],
+        }
+    }
+
+    /// The name of its own a self-referential direction can be embedded by:
+    /// the foreign key column without `_id` for the referenced row, and
+    /// `children` for the referencing rows.
+    pub fn self_name(&self) -> Option<String> {
+        let Self::ForeignKey { is_self: true, cardinality, .. } = self else {
+            return None;
+        };
+        match cardinality {
+            Cardinality::M2O { columns, .. } | Cardinality::O2O { columns, is_parent: false, .. } => {
+                match columns.as_slice() {
+                    [(col, _)] => Some(col.strip_suffix("_id").unwrap_or(col).to_string()),
+                    _ => None,
+                }
+            }
+            Cardinality::O2M { .. } | Cardinality::O2O { is_parent: true, .. } => Some("children".into()),
+            Cardinality::M2M(_) => None,
+        }
+    }
+
+    /// Describe the relationship for error details, e.g.
+    /// `messages(sender_id) -> users(id) using messages_sender_fkey`.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::ForeignKey { table, foreign_table, constraint_name, .. } => {
+                let (cols, fcols): (Vec<String>, Vec<String>) = self.join_columns().into_iter().unzip();
+                format!(
+                    "{}({}) -> {}({}) using {}",
+                    table.name,
+                    cols.join(", "),
+                    foreign_table.name,
+                    fcols.join(", "),
+                    constraint_name
+                )
+            }
+            Self::Computed { function, table, foreign_table, .. } => {
+                format!("{}({}) -> {} using {}", function.name, table.name, foreign_table.name, function)
+            }
+        }
+    }
 }
 
 /// Relationship cardinality.
@@ -105,6 +207,54 @@ impl Cardinality {
         }
     }
 
+    /// Rename join columns, returning `None` if a column has no mapping.
+    ///
+    /// `left` maps columns of the relationship's own table, `right` those of
+    /// the foreign table. Many-to-many relationships are not remapped.
+    fn map_columns(
+        &self,
+        left: Option<&HashMap<&str, &str>>,
+        right: Option<&HashMap<&str, &str>>,
+    ) -> Option<Self> {
+        let map = |columns: &[(String, String)]| -> Option<Vec<(String, String)>> {
+            columns
+                .iter()
+                .map(|(col, fcol)| {
+                    let col = match left {
+                        Some(m) => m.get(col.as_str())?.to_string(),
+                        None => col.clone(),
+                    };
+                    let fcol = match right {
+                        Some(m) => m.get(fcol.as_str())?.to_string(),
+                        None => fcol.clone(),
+                    };
+                    Some((col, fcol))
+                })
+                .collect()
+        };
+
+        match self {
+            Self::O2M { constraint, columns } => Some(Self::O2M {
+                constraint: constraint.clone(),
+                columns: map(columns)?,
+            }),
+            Self::M2O { constraint, columns } => Some(Self::M2O {
+                constraint: constraint.clone(),
+                columns: map(columns)?,
+            }),
+            Self::O2O {
+                constraint,
+                columns,
+                is_parent,
+            } => Some(Self::O2O {
+                constraint: constraint.clone(),
+                columns: map(columns)?,
+                is_parent: *is_parent,
+            }),
+            Self::M2M(_) => None,
+        }
+    }
+
     /// Get the constraint name.
     pub fn constraint_name(&self) -> &str {
         match self {
@@ -146,6 +296,106 @@ impl Junction {
 /// Map of (table, schema) to relationships.
 pub type RelationshipsMap = HashMap<(QualifiedIdentifier, String), Vec<Relationship>>;
 
+/// A view column that passes a base table column through unchanged.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ViewColumnSource {
+    /// The view
+    pub view: QualifiedIdentifier,
+    /// Column name in the view
+    pub view_column: String,
+    /// The base table
+    pub table: QualifiedIdentifier,
+    /// Column name in the base table
+    pub table_column: String,
+}
+
+/// A view over a base table, with its base column -> view column mapping.
+type ViewColumns<'a> = (&'a QualifiedIdentifier, HashMap<&'a str, &'a str>);
+
+/// Infer relationships for views from the foreign keys of their base tables.
+///
+/// A base table relationship is copied onto a view when the view exposes
+/// every join column on that side. Copies keep the original constraint name,
+/// so a view built on several related tables may yield several candidates
+/// for the same target; those need an embed hint to disambiguate.
+pub fn add_view_relationships(
+    relationships: &mut RelationshipsMap,
+    sources: &[ViewColumnSource],
+) {
+    let mut views_by_table: HashMap<&QualifiedIdentifier, Vec<ViewColumns>> = HashMap::new();
+    for src in sources {
+        let views = views_by_table.entry(&src.table).or_default();
+        match views.iter_mut().find(|(view, _)| *view == &src.view) {
+            Some((_, columns)) => {
+                columns.insert(&src.table_column, &src.view_column);
+            }
+            None => views.push((
+                &src.view,
+                HashMap::from([(src.table_column.as_str(), src.view_column.as_str())]),
+            )),
+        }
+    }
+
+    let mut inferred: Vec<((QualifiedIdentifier, String), Relationship)> = Vec::new();
+
+    for rels in relationships.values() {
+        for rel in rels {
+            let Relationship::ForeignKey {
+                table,
+                foreign_table,
+                cardinality,
+                table_is_view,
+                foreign_table_is_view,
+                constraint_name,
+                ..
+            } = rel
+            else {
+                continue;
+            };
+
+            // View over the source table: view -> foreign table
+            for (view, columns) in views_by_table.get(table).into_iter().flatten() {
+                if let Some(cardinality) = cardinality.map_columns(Some(columns), None) {
+                    inferred.push((
+                        ((*view).clone(), view.schema.clone()),
+                        Relationship::ForeignKey {
+                            table: (*view).clone(),
+                            foreign_table: foreign_table.clone(),
+                            is_self: false,
+                            cardinality,
+                            table_is_view: true,
+                            foreign_table_is_view: *foreign_table_is_view,
+                            constraint_name: constraint_name.clone(),
+                        },
+                    ));
+                }
+            }
+
+            // View over the foreign table: source table -> view
+            for (view, columns) in views_by_table.get(foreign_table).into_iter().flatten() {
+                if let Some(cardinality) = cardinality.map_columns(None, Some(columns)) {
+                    inferred.push((
+                        (table.clone(), table.schema.clone()),
+                        Relationship::ForeignKey {
+                            table: table.clone(),
+                            foreign_table: (*view).clone(),
+                            is_self: false,
+                            cardinality,
+                            table_is_view: *table_is_view,
+                            foreign_table_is_view: true,
+                            constraint_name: constraint_name.clone(),
+                        },
+                    ));
+                }
+            }
+        }
+    }
+
+    for (key, rel) in inferred {
+        relationships.entry(key).or_default().push(rel);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -180,4 +430,90 @@ mod tests {
         assert_eq!(cols.len(), 1);
         assert_eq!(cols[0], ("id".into(), "user_id".into()));
     }
+
+    fn orders_users_fk() -> RelationshipsMap {
+        let orders = QualifiedIdentifier::new("public", "orders");
+        let users = QualifiedIdentifier::new("public", "users");
+        let mut rels = RelationshipsMap::new();
+        rels.insert(
+            (orders.clone(), "public".into()),
+            vec![Relationship::ForeignKey {
+                table: orders.clone(),
+                foreign_table: users.clone(),
+                is_self: false,
+                cardinality: Cardinality::M2O {
+                    constraint: "orders_user_id_fkey".into(),
+                    columns: vec![("user_id".into(), "id".into())],
+                },
+                table_is_view: false,
+                foreign_table_is_view: false,
+                constraint_name: "orders_user_id_fkey".into(),
+            }],
+        );
+        rels.insert(
+            (users.clone(), "public".into()),
+            vec![Relationship::ForeignKey {
+                table: users,
+                foreign_table: orders,
+                is_self: false,
+                cardinality: Cardinality::O2M {
+                    constraint: "orders_user_id_fkey".into(),
+                    columns: vec![("id".into(), "user_id".into())],
+                },
+                table_is_view: false,
+                foreign_table_is_view: false,
+                constraint_name: "orders_user_id_fkey".into(),
+            }],
+        );
+        rels
+    }
+
+    fn source(view: &str, table: &str, column: &str) -> ViewColumnSource {
+        ViewColumnSource {
+            view: QualifiedIdentifier::new("public", view),
+            view_column: column.into(),
+            table: QualifiedIdentifier::new("public", table),
+            table_column: column.into(),
+        }
+    }
+
+    #[test]
+    fn test_view_inherits_base_table_relationship() {
+        let mut rels = orders_users_fk();
+        add_view_relationships(
+            &mut rels,
+            &[source("recent_orders", "orders", "id"), source("recent_orders", "orders", "user_id")],
+        );
+
+        let view = QualifiedIdentifier::new("public", "recent_orders");
+        let view_rels = &rels[&(view.clone(), "public".to_string())];
+        assert_eq!(view_rels.len(), 1);
+        assert_eq!(view_rels[0].foreign_table().name, "users");
+        assert!(view_rels[0].is_to_one());
+        assert!(matches!(view_rels[0], Relationship::ForeignKey { table_is_view: true, .. }));
+
+        // And the base table can embed the view
+        let users = QualifiedIdentifier::new("public", "users");
+        let users_rels = &rels[&(users, "public".to_string())];
+        assert!(users_rels.iter().any(|r| r.foreign_table() == &view && !r.is_to_one()));
+    }
+
+    #[test]
+    fn test_view_without_join_column_gets_no_relationship() {
+        let mut rels = orders_users_fk();
+        add_view_relationships(&mut rels, &[source("order_totals", "orders", "id")]);
+
+        let view = QualifiedIdentifier::new("public", "order_totals");
+        assert!(!rels.contains_key(&(view, "public".to_string())));
+    }
+
+    #[test]
+    fn test_matches_hint() {
+        let rels = orders_users_fk();
+        let rel = &rels[&(QualifiedIdentifier::new("public", "orders"), "public".to_string())][0];
+
+        assert!(rel.matches_hint("orders_user_id_fkey"));
+        assert!(rel.matches_hint("user_id"));
+        assert!(!rel.matches_hint("customer_id"));
+    }
 }
@@ -49,6 +49,19 @@ impl Routine {
     pub fn find_param(&self, name: &str) -> Option<&RoutineParam> {
         self.params.iter().find(|p| p.name == name)
     }
+
+    /// The named composite type this function returns, if any.
+    ///
+    /// Unqualified type names are resolved in the function's own schema.
+    pub fn return_type_qi(&self) -> Option<QualifiedIdentifier> {
+        let type_name = self.return_type.type_name()?;
+        Some(match type_name.split_once('.') {
+            Some((schema, name)) => {
+                QualifiedIdentifier::new(schema.trim_matches('"'), name.trim_matches('"'))
+            }
+            None => QualifiedIdentifier::new(&self.schema, type_name.trim_matches('"')),
+        })
+    }
 }
 
 /// A function parameter.
@@ -66,6 +79,13 @@ pub struct RoutineParam {
     pub variadic: bool,
 }
 
+impl RoutineParam {
+    /// Check if the parameter takes an array, e.g. `integer[]`.
+    pub fn is_array(&self) -> bool {
+        self.param_type.ends_with("[]")
+    }
+}
+
 /// Function return type.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum RetType {
@@ -115,6 +135,52 @@ impl FuncVolatility {
             _ => Self::Volatile,
         }
     }
+
+    /// Parse the SQL keyword, e.g. `stable`, in any case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "immutable" => Some(Self::Immutable),
+            "stable" => Some(Self::Stable),
+            "volatile" => Some(Self::Volatile),
+            _ => None,
+        }
+    }
+}
+
+/// Which routines the API exposes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RoutineFilter {
+    /// Schemas whose routines are exposed (all when `None`)
+    pub schemas: Option<Vec<String>>,
+    /// Hidden routines as `name` or `schema.name`, where `*` matches any characters
+    pub hidden: Vec<String>,
+    /// Volatilities exposed (all when `None`)
+    pub volatility: Option<Vec<FuncVolatility>>,
+}
+
+impl RoutineFilter {
+    /// Check if a routine passes every rule.
+    pub fn exposes(&self, routine: &Routine) -> bool {
+        let qualified = format!("{}.{}", routine.schema, routine.name);
+        self.schemas.as_ref().is_none_or(|s| s.contains(&routine.schema))
+            && self.volatility.as_ref().is_none_or(|v| v.contains(&routine.volatility))
+            && !self
+                .hidden
+                .iter()
+                .any(|pattern| glob_match(pattern, &routine.name) || glob_match(pattern, &qualified))
+    }
+}
+
+/// Match `text` against a pattern where `*` stands for any run of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => text.strip_prefix(prefix).is_some_and(|text| {
+            (0..=text.len())
+                .filter(|i| text.is_char_boundary(*i))
+                .any(|i| glob_match(rest, &text[i..]))
+        }),
+    }
 }
 
 /// Map of qualified identifier to routines (overloaded functions share name).
@@ -145,6 +211,34 @@ mod tests {
         assert!(!routine.is_safe_for_get());
     }
 
+    #[test]
+    fn test_routine_filter() {
+        let routine = |schema: &str, name: &str, volatility: FuncVolatility| Routine {
+            schema: schema.into(),
+            name: name.into(),
+            description: None,
+            params: vec![],
+            return_type: RetType::Void,
+            volatility,
+            has_variadic: false,
+            isolation_level: None,
+            settings: vec![],
+            is_procedure: false,
+        };
+        let filter = RoutineFilter {
+            schemas: Some(vec!["api".into()]),
+            hidden: vec!["_*".into(), "api.*_internal".into()],
+            volatility: Some(vec![FuncVolatility::Immutable, FuncVolatility::Stable]),
+        };
+
+        assert!(filter.exposes(&routine("api", "search", FuncVolatility::Stable)));
+        assert!(!filter.exposes(&routine("api", "reset", FuncVolatility::Volatile)));
+        assert!(!filter.exposes(&routine("private", "search", FuncVolatility::Stable)));
+        assert!(!filter.exposes(&routine("api", "_helper", FuncVolatility::Stable)));
+        assert!(!filter.exposes(&routine("api", "stats_internal", FuncVolatility::Stable)));
+        assert!(RoutineFilter::default().exposes(&routine("private", "_x", FuncVolatility::Volatile)));
+    }
+
     #[test]
     fn test_ret_type_is_set_returning() {
         assert!(!RetType::Single("text".into()).is_set_returning());
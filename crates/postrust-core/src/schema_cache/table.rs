@@ -74,10 +74,45 @@ pub struct Column {
     pub default: Option<String>,
     /// Enum values (for enum types)
     pub enum_values: Vec<String>,
+    /// CHECK constraint definitions that involve only this column
+    #[serde(default)]
+    pub checks: Vec<String>,
     /// Whether this is part of the primary key
     pub is_pk: bool,
     /// Column position (1-based)
     pub position: i32,
+    /// Whether the column can be written (false for computed view columns)
+    pub updatable: bool,
+    /// Domain the column is declared with; `data_type` is its base type
+    #[serde(default)]
+    pub domain: Option<QualifiedIdentifier>,
+    /// Whether the database always computes the value (generated columns
+    /// and `GENERATED ALWAYS` identities), so it cannot be inserted
+    #[serde(default)]
+    pub generated: bool,
+}
+
+impl Default for Column {
+    /// An unnamed, writable column with no constraints, for building up
+    /// with struct update syntax.
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            description: None,
+            nullable: false,
+            data_type: String::new(),
+            nominal_type: String::new(),
+            max_len: None,
+            default: None,
+            enum_values: vec![],
+            checks: vec![],
+            is_pk: false,
+            position: 0,
+            updatable: true,
+            domain: None,
+            generated: false,
+        }
+    }
 }
 
 impl Column {
@@ -101,13 +136,23 @@ impl Column {
 
     /// Check if this is an array type.
     pub fn is_array(&self) -> bool {
-        self.data_type.starts_with('_') || self.data_type.ends_with("[]")
+        self.data_type == "ARRAY"
+            || self.data_type.starts_with('_')
+            || self.data_type.ends_with("[]")
     }
 
     /// Check if this is a range type.
     pub fn is_range(&self) -> bool {
         self.data_type.ends_with("range")
     }
+
+    /// The quoted domain name that values written or compared to this
+    /// column are cast to, so the domain's constraints apply.
+    pub fn domain_type(&self) -> Option<String> {
+        self.domain.as_ref().map(|d| {
+            format!("{}.{}", postrust_sql::escape_ident(&d.schema), postrust_sql::escape_ident(&d.name))
+        })
+    }
 }
 
 /// Map of column name to column.
@@ -143,43 +188,31 @@ mod tests {
     fn test_column_is_auto() {
         let col1 = Column {
             name: "id".into(),
-            description: None,
-            nullable: false,
             data_type: "integer".into(),
             nominal_type: "integer".into(),
-            max_len: None,
             default: Some("nextval('users_id_seq'::regclass)".into()),
-            enum_values: vec![],
             is_pk: true,
             position: 1,
+            ..Default::default()
         };
         assert!(col1.is_auto());
 
         let col2 = Column {
             name: "uuid".into(),
-            description: None,
-            nullable: false,
             data_type: "uuid".into(),
             nominal_type: "uuid".into(),
-            max_len: None,
             default: Some("gen_random_uuid()".into()),
-            enum_values: vec![],
-            is_pk: false,
             position: 2,
+            ..Default::default()
         };
         assert!(col2.is_auto());
 
         let col3 = Column {
             name: "name".into(),
-            description: None,
-            nullable: false,
             data_type: "text".into(),
             nominal_type: "text".into(),
-            max_len: None,
-            default: None,
-            enum_values: vec![],
-            is_pk: false,
             position: 3,
+            ..Default::default()
         };
         assert!(!col3.is_auto());
     }
@@ -5,6 +5,7 @@ use postrust_core::schema_cache::SchemaCacheRef;
 use sqlx::PgPool;
 
 /// Context available to all GraphQL resolvers.
+#[derive(Clone)]
 pub struct GraphQLContext {
     /// Database connection pool.
     pub pool: PgPool,
@@ -12,6 +13,8 @@ pub struct GraphQLContext {
     pub schema_cache: SchemaCacheRef,
     /// Authentication result with role and claims.
     pub auth: AuthResult,
+    /// Database schema chosen with `Accept-Profile`, if any.
+    pub profile: Option<String>,
 }
 
 impl GraphQLContext {
@@ -21,9 +24,16 @@ impl GraphQLContext {
             pool,
             schema_cache,
             auth,
+            profile: None,
         }
     }
 
+    /// Run in the given database schema's namespace.
+    pub fn with_profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
     /// Get the current role.
     pub fn role(&self) -> &str {
         &self.auth.role
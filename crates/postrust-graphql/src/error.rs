@@ -20,6 +20,9 @@ pub enum GraphQLError {
     #[error("Type mapping error: {0}")]
     TypeMapping(String),
 
+    #[error("Unacceptable schema: {schema} (acceptable profiles: {})", .acceptable.join(", "))]
+    UnacceptableSchema { schema: String, acceptable: Vec<String> },
+
     #[error("Authentication required")]
     AuthenticationRequired,
 
@@ -8,20 +8,25 @@ use crate::error::GraphQLError;
 use crate::schema::object::TableObjectType;
 use crate::schema::{build_schema, GeneratedSchema, MutationType, SchemaConfig};
 use crate::subscription::{
-    generate_subscription_fields, NotifyBroker, SubscriptionField as SubField, TableChangePayload,
+    create_notify_trigger_sql_with_limit, generate_subscription_fields, NotifyBroker,
+    SubscriptionField as SubField, TableChangePayload,
 };
 use async_graphql::dynamic::*;
 use async_graphql::Value;
-use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use async_graphql::{BatchRequest, BatchResponse};
+use async_graphql_axum::{GraphQLBatchRequest, GraphQLResponse};
 use axum::extract::State;
 use axum::response::IntoResponse;
 use futures::stream::StreamExt;
+use postrust_auth::{AuthResult, JwtConfig};
 use postrust_core::schema_cache::SchemaCache;
-use sqlx::PgPool;
+use sqlx::{Executor, PgPool};
 use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
-use tracing::{debug, info, trace};
+use tracing::{debug, info, trace, warn};
 
 /// GraphQL execution state shared across requests.
 pub struct GraphQLState {
@@ -33,6 +38,9 @@ pub struct GraphQLState {
     pub generated_schema: GeneratedSchema,
     /// async-graphql Schema (built dynamically)
     pub schema: Schema,
+    /// One schema per exposed database schema, chosen with `Accept-Profile`
+    /// when more than one is exposed
+    pub profiles: HashMap<String, Schema>,
     /// Schema configuration
     pub config: SchemaConfig,
     /// Subscription fields
@@ -50,7 +58,7 @@ impl GraphQLState {
     ) -> Result<Self, GraphQLError> {
         let generated_schema = build_schema(&schema_cache, &config);
         let subscription_fields = if config.enable_subscriptions {
-            generate_subscription_fields(&schema_cache, &generated_schema)
+            generate_subscription_fields(&schema_cache, &generated_schema, &config)
         } else {
             Vec::new()
         };
@@ -62,13 +70,16 @@ impl GraphQLState {
             } else {
                 None
             },
+            config.disable_introspection,
         )?;
+        let profiles = build_profiles(&schema_cache, &config)?;
 
         Ok(Self {
             pool: pool.clone(),
             schema_cache,
             generated_schema,
             schema,
+            profiles,
             config,
             subscription_fields,
             broker: Arc::new(RwLock::new(None)),
@@ -79,7 +90,7 @@ impl GraphQLState {
     pub fn rebuild(&mut self) -> Result<(), GraphQLError> {
         self.generated_schema = build_schema(&self.schema_cache, &self.config);
         self.subscription_fields = if self.config.enable_subscriptions {
-            generate_subscription_fields(&self.schema_cache, &self.generated_schema)
+            generate_subscription_fields(&self.schema_cache, &self.generated_schema, &self.config)
         } else {
             Vec::new()
         };
@@ -91,10 +102,27 @@ impl GraphQLState {
             } else {
                 None
             },
+            self.config.disable_introspection,
         )?;
+        self.profiles = build_profiles(&self.schema_cache, &self.config)?;
         Ok(())
     }
 
+    /// The schema to run a request in: the one for `profile` when given,
+    /// else the schema of everything exposed.
+    pub fn schema_for(&self, profile: Option<&str>) -> Result<&Schema, GraphQLError> {
+        let Some(profile) = profile else {
+            return Ok(&self.schema);
+        };
+        if !self.config.is_schema_exposed(profile) {
+            return Err(GraphQLError::UnacceptableSchema {
+                schema: profile.to_string(),
+                acceptable: self.config.exposed_schemas.clone(),
+            });
+        }
+        Ok(self.profiles.get(profile).unwrap_or(&self.schema))
+    }
+
     /// Initialize the subscription broker.
     ///
     /// This should be called after creating the state to enable subscriptions.
@@ -127,6 +155,40 @@ impl GraphQLState {
         Ok(())
     }
 
+    /// Create the notify trigger of every subscribed table.
+    ///
+    /// Tables the connecting role may not add triggers to are skipped with a
+    /// warning. Returns the number of triggers installed.
+    pub async fn install_notify_triggers(&self) -> Result<usize, crate::subscription::BrokerError> {
+        let mut installed = 0;
+        for field in &self.subscription_fields {
+            let allowed: bool = sqlx::query_scalar(
+                "SELECT has_table_privilege(format('%I.%I', $1::text, $2::text), 'TRIGGER') \
+                 AND has_schema_privilege($1::text, 'CREATE')",
+            )
+            .bind(&field.schema_name)
+            .bind(&field.table_name)
+            .fetch_one(&self.pool)
+            .await?;
+            if !allowed {
+                warn!(
+                    "Skipping notify trigger on {}.{}: missing TRIGGER or CREATE privilege",
+                    field.schema_name, field.table_name
+                );
+                continue;
+            }
+
+            let sql = create_notify_trigger_sql_with_limit(
+                &field.schema_name,
+                &field.table_name,
+                self.config.notify_max_payload,
+            );
+            self.pool.execute(sql.as_str()).await?;
+            installed += 1;
+        }
+        Ok(installed)
+    }
+
     /// Stop the subscription broker.
     pub async fn stop_subscriptions(&self) {
         let broker_guard = self.broker.read().await;
@@ -142,45 +204,155 @@ impl GraphQLState {
 }
 
 /// Handle a GraphQL request.
+///
+/// A JSON array body is executed as a batch and answered with an array of
+/// responses in the same order.
 pub async fn graphql_handler(
     State(state): State<Arc<GraphQLState>>,
     ctx: GraphQLContext,
-    req: GraphQLRequest,
+    req: GraphQLBatchRequest,
 ) -> GraphQLResponse {
-    let request = req
-        .into_inner()
+    execute_batch(&state, ctx, req.into_inner()).await.into()
+}
+
+/// Execute a single or batched request, sharing one auth context across
+/// every operation.
+pub async fn execute_batch(
+    state: &GraphQLState,
+    ctx: GraphQLContext,
+    request: BatchRequest,
+) -> BatchResponse {
+    let schema = match state.schema_for(ctx.profile.as_deref()) {
+        Ok(schema) => schema,
+        Err(e) => {
+            let error = || async_graphql::Response::from_errors(vec![async_graphql::ServerError::new(e.to_string(), None)]);
+            return match request {
+                BatchRequest::Single(_) => BatchResponse::Single(error()),
+                BatchRequest::Batch(requests) => BatchResponse::Batch(requests.iter().map(|_| error()).collect()),
+            };
+        }
+    };
+    let request = request
         .data(ctx)
         .data(state.pool.clone())
         .data(Arc::clone(&state.broker));
-    state.schema.execute(request).await.into()
+    match request {
+        BatchRequest::Single(request) => BatchResponse::Single(schema.execute(request).await),
+        BatchRequest::Batch(requests) => BatchResponse::Batch(
+            futures::future::join_all(requests.into_iter().map(|r| schema.execute(r))).await,
+        ),
+    }
+}
+
+/// Build a schema per exposed database schema, each unprefixed and without
+/// subscriptions, which stay on the combined schema.
+fn build_profiles(
+    schema_cache: &SchemaCache,
+    config: &SchemaConfig,
+) -> Result<HashMap<String, Schema>, GraphQLError> {
+    if config.exposed_schemas.len() < 2 {
+        return Ok(HashMap::new());
+    }
+    config
+        .exposed_schemas
+        .iter()
+        .map(|name| {
+            let profile_config = SchemaConfig {
+                exposed_schemas: vec![name.clone()],
+                enable_subscriptions: false,
+                ..config.clone()
+            };
+            let generated = build_schema(schema_cache, &profile_config);
+            let schema = build_dynamic_schema(&generated, schema_cache, None, config.disable_introspection)?;
+            Ok((name.clone(), schema))
+        })
+        .collect()
 }
 
 /// Handle GraphQL WebSocket subscription upgrade.
 ///
 /// This should be called with a WebSocket upgrade request to enable
-/// GraphQL subscriptions over WebSocket.
+/// GraphQL subscriptions over WebSocket, once the caller has authenticated
+/// the upgrade request as `auth`. A token in the `connection_init` payload
+/// takes its place; rows refetched for the session are read as that role.
 pub async fn graphql_ws_handler(
-    State(state): State<Arc<GraphQLState>>,
+    state: &GraphQLState,
+    auth: AuthResult,
+    jwt_config: JwtConfig,
     protocol: async_graphql_axum::GraphQLProtocol,
     ws: axum::extract::WebSocketUpgrade,
 ) -> impl IntoResponse {
     let schema = state.schema.clone();
     let pool = state.pool.clone();
     let broker = Arc::clone(&state.broker);
+    let schema_cache = Arc::clone(&state.schema_cache);
+    let context_pool = pool.clone();
+    let context = move |auth| {
+        GraphQLContext::new(
+            context_pool.clone(),
+            postrust_core::schema_cache::SchemaCacheRef::from_static((*schema_cache).clone()),
+            auth,
+        )
+    };
+    let keepalive_timeout = state.config.ws_keepalive_timeout;
+    let max_lifetime = state.config.ws_max_lifetime;
 
     ws.protocols(["graphql-transport-ws", "graphql-ws"])
         .on_upgrade(move |socket| async move {
             let mut data = async_graphql::Data::default();
+            data.insert(context(auth));
             data.insert(pool);
             data.insert(broker);
 
-            async_graphql_axum::GraphQLWebSocket::new(socket, schema, protocol)
+            let session = async_graphql_axum::GraphQLWebSocket::new(socket, schema, protocol)
                 .with_data(data)
-                .serve()
-                .await
+                .on_connection_init(move |payload| async move {
+                    let mut data = async_graphql::Data::default();
+                    if let Some(auth) = connection_init_auth(&payload, &jwt_config)? {
+                        data.insert(context(auth));
+                    }
+                    Ok(data)
+                })
+                .keepalive_timeout(keepalive_timeout)
+                .serve();
+            if !serve_within_lifetime(session, max_lifetime).await {
+                debug!("Closing GraphQL WebSocket after its maximum lifetime");
+            }
         })
 }
 
+/// Authenticate the `authorization` entry of a `connection_init` payload.
+///
+/// Returns `None` when the payload carries no token. An invalid token
+/// refuses the connection rather than falling back to the anonymous role.
+fn connection_init_auth(
+    payload: &serde_json::Value,
+    jwt_config: &JwtConfig,
+) -> Result<Option<AuthResult>, async_graphql::Error> {
+    let header = ["authorization", "Authorization"]
+        .iter()
+        .find_map(|key| payload.get(key).and_then(|v| v.as_str()));
+    match header {
+        Some(header) => postrust_auth::authenticate(Some(header), jwt_config)
+            .map(Some)
+            .map_err(|e| async_graphql::Error::new(e.to_string())),
+        None => Ok(None),
+    }
+}
+
+/// Run a WebSocket session, dropping it once `max_lifetime` has passed.
+///
+/// Returns whether the session ended on its own.
+async fn serve_within_lifetime(session: impl Future<Output = ()>, max_lifetime: Option<Duration>) -> bool {
+    match max_lifetime {
+        Some(lifetime) => tokio::time::timeout(lifetime, session).await.is_ok(),
+        None => {
+            session.await;
+            true
+        }
+    }
+}
+
 /// Handle GraphQL playground request.
 pub async fn graphql_playground() -> impl axum::response::IntoResponse {
     axum::response::Html(async_graphql::http::playground_source(
@@ -194,11 +366,13 @@ fn build_dynamic_schema(
     generated: &GeneratedSchema,
     _schema_cache: &SchemaCache,
     subscription_fields: Option<&[SubField]>,
+    disable_introspection: bool,
 ) -> Result<Schema, GraphQLError> {
     // Create object types for each table
     let mut object_types: HashMap<String, Object> = HashMap::new();
 
     for (type_name, obj) in &generated.object_types {
+        obj.check_field_names().map_err(GraphQLError::SchemaError)?;
         let table_obj = create_object_type(obj);
         object_types.insert(type_name.clone(), table_obj);
     }
@@ -253,6 +427,10 @@ fn build_dynamic_schema(
     // Register input types
     builder = register_filter_input_types(builder);
 
+    if disable_introspection {
+        builder = builder.disable_introspection();
+    }
+
     builder
         .finish()
         .map_err(|e| GraphQLError::SchemaError(e.to_string()))
@@ -267,19 +445,19 @@ fn create_object_type(obj: &TableObjectType) -> Object {
     }
 
     for field in &obj.fields {
-        let field_name = field.name.clone();
+        let column = field.column.clone();
         let field_type = graphql_type_ref(&field.type_string());
 
         // Create field with resolver that extracts from parent async_graphql::Value
         // The query resolver stores rows as FieldValue::value(Value::Object)
         // so we use as_value() to get the Value and extract fields from the Object
         let gql_field = Field::new(&field.name, field_type, move |ctx| {
-            let field_name = field_name.clone();
+            let column = column.clone();
             FieldFuture::new(async move {
                 // Get the parent value as async_graphql::Value using as_value()
                 if let Some(Value::Object(map)) = ctx.parent_value.as_value() {
                     // Convert field name to async_graphql::Name for lookup
-                    let key = async_graphql::Name::new(&field_name);
+                    let key = async_graphql::Name::new(&column);
                     if let Some(val) = map.get(&key) {
                         return Ok(Some(FieldValue::value(val.clone())));
                     }
@@ -307,16 +485,18 @@ fn create_query_type(generated: &GeneratedSchema) -> Object {
     let mut query = Object::new("Query");
 
     for field in &generated.query_fields {
+        let schema = field.schema.clone();
         let table_name = field.table_name.clone();
         let type_name = field.type_name.clone();
         let is_by_pk = field.is_by_pk;
         let return_type = graphql_type_ref(&field.return_type);
 
         let mut gql_field = Field::new(&field.name, return_type, move |ctx| {
+            let schema = schema.clone();
             let table_name = table_name.clone();
             let type_name = type_name.clone();
             FieldFuture::new(async move {
-                resolve_query(&ctx, &table_name, &type_name, is_by_pk).await
+                resolve_query(&ctx, &schema, &table_name, &type_name, is_by_pk).await
             })
         });
 
@@ -357,14 +537,27 @@ fn create_mutation_type(generated: &GeneratedSchema) -> Object {
     let mut mutation = Object::new("Mutation");
 
     for field in &generated.mutation_fields {
+        let schema = field.schema.clone();
         let table_name = field.table_name.clone();
         let mutation_type = field.mutation_type;
         let return_type = graphql_type_ref(&field.return_type);
+        let hidden = Arc::new(generated.hidden_columns(&field.schema, &field.table_name).to_vec());
+        let renamed = Arc::new(
+            generated
+                .object_types
+                .values()
+                .find(|o| o.table.schema == field.schema && o.table.name == field.table_name)
+                .map(|o| o.renamed_columns())
+                .unwrap_or_default(),
+        );
 
         let mut gql_field = Field::new(&field.name, return_type, move |ctx| {
+            let schema = schema.clone();
             let table_name = table_name.clone();
+            let hidden = Arc::clone(&hidden);
+            let renamed = Arc::clone(&renamed);
             FieldFuture::new(async move {
-                resolve_mutation(&ctx, &table_name, mutation_type).await
+                resolve_mutation(&ctx, &schema, &table_name, mutation_type, &hidden, &renamed).await
             })
         });
 
@@ -418,23 +611,29 @@ fn create_subscription_type(fields: &[SubField]) -> Subscription {
                     .subscribe(&channel_name)
                     .await
                     .map_err(|e| async_graphql::Error::new(format!("Subscription error: {}", e)))?;
+                let pool = ctx.data::<PgPool>()?.clone();
+                let auth = ctx.data::<GraphQLContext>()?.auth.clone();
 
                 // Transform notification stream to GraphQL values
                 // Use FieldValue::value() so field resolvers can use as_value()
-                let value_stream = stream.filter_map(|notification| async move {
-                    match TableChangePayload::from_payload(&notification.payload) {
-                        Ok(payload) => {
-                            if let Some(data) = payload.data() {
-                                // Convert to async_graphql::Value so field resolvers can extract fields
-                                Some(Ok(FieldValue::value(json_to_value(data.clone()))))
-                            } else {
-                                None
+                let value_stream = stream.filter_map(move |notification| {
+                    let pool = pool.clone();
+                    let auth = auth.clone();
+                    async move {
+                        let payload = match TableChangePayload::from_payload(&notification.payload) {
+                            Ok(payload) => payload,
+                            Err(e) => {
+                                debug!("Failed to parse notification payload: {}", e);
+                                return None;
                             }
-                        }
-                        Err(e) => {
-                            debug!("Failed to parse notification payload: {}", e);
-                            None
-                        }
+                        };
+                        let data = if payload.needs_refetch() {
+                            refetch_row(&pool, &auth, &payload).await?
+                        } else {
+                            payload.data()?.clone()
+                        };
+                        // Convert to async_graphql::Value so field resolvers can extract fields
+                        Some(Ok(FieldValue::value(json_to_value(data))))
                     }
                 });
 
@@ -454,9 +653,21 @@ fn create_subscription_type(fields: &[SubField]) -> Subscription {
     subscription
 }
 
+/// Fetch a row whose notification carried only its primary key.
+async fn refetch_row(pool: &PgPool, auth: &AuthResult, payload: &TableChangePayload) -> Option<serde_json::Value> {
+    match payload.refetch(pool, auth).await {
+        Ok(row) => row,
+        Err(e) => {
+            warn!("Failed to refetch {}.{} as {}: {}", payload.schema, payload.table, auth.role, e);
+            None
+        }
+    }
+}
+
 /// Resolve a query field.
 async fn resolve_query<'a>(
     ctx: &ResolverContext<'a>,
+    schema: &str,
     table_name: &str,
     _type_name: &str,
     is_by_pk: bool,
@@ -481,8 +692,9 @@ async fn resolve_query<'a>(
 
     // Build simple query
     let mut sql = format!(
-        "SELECT row_to_json(t) FROM (SELECT * FROM public.{}) t",
-        table_name
+        "SELECT row_to_json(t) FROM (SELECT * FROM {}.{}) t",
+        postrust_sql::escape_ident(schema),
+        postrust_sql::escape_ident(table_name)
     );
 
     if let Some(limit) = limit {
@@ -513,8 +725,11 @@ async fn resolve_query<'a>(
 /// Resolve a mutation field.
 async fn resolve_mutation<'a>(
     ctx: &ResolverContext<'a>,
+    schema: &str,
     table_name: &str,
     mutation_type: MutationType,
+    hidden: &[String],
+    renamed: &HashMap<String, String>,
 ) -> Result<Option<FieldValue<'a>>, async_graphql::Error> {
     let pool = ctx.data::<PgPool>()?;
     let gql_ctx = ctx.data::<GraphQLContext>()?;
@@ -527,41 +742,81 @@ async fn resolve_mutation<'a>(
                 .args
                 .try_get("objects")
                 .ok()
-                .map(|v| accessor_to_json(&v))
+                .map(|v| rename_to_columns(accessor_to_json(&v), renamed))
                 .unwrap_or_else(|| serde_json::Value::Array(vec![]));
+            reject_hidden_columns(&objects, hidden)?;
 
-            execute_insert(pool, table_name, gql_ctx.role(), objects, mutation_type).await?
+            execute_insert(pool, schema, table_name, gql_ctx.role(), objects, mutation_type).await?
         }
         MutationType::Update | MutationType::UpdateByPk => {
             let set_value = ctx
                 .args
                 .try_get("set")
                 .ok()
-                .map(|v| accessor_to_json(&v))
+                .map(|v| rename_to_columns(accessor_to_json(&v), renamed))
                 .unwrap_or_else(|| serde_json::json!({}));
 
             let where_clause = ctx
                 .args
                 .try_get("where")
                 .ok()
-                .map(|v| accessor_to_json(&v));
+                .map(|v| rename_to_columns(accessor_to_json(&v), renamed));
+            reject_hidden_columns(&set_value, hidden)?;
+            if let Some(where_clause) = &where_clause {
+                reject_hidden_columns(where_clause, hidden)?;
+            }
 
-            execute_update(pool, table_name, gql_ctx.role(), set_value, where_clause, mutation_type).await?
+            execute_update(pool, schema, table_name, gql_ctx.role(), set_value, where_clause, mutation_type).await?
         }
         MutationType::Delete | MutationType::DeleteByPk => {
             let where_clause = ctx
                 .args
                 .try_get("where")
                 .ok()
-                .map(|v| accessor_to_json(&v));
+                .map(|v| rename_to_columns(accessor_to_json(&v), renamed));
+            if let Some(where_clause) = &where_clause {
+                reject_hidden_columns(where_clause, hidden)?;
+            }
 
-            execute_delete(pool, table_name, gql_ctx.role(), where_clause, mutation_type).await?
+            execute_delete(pool, schema, table_name, gql_ctx.role(), where_clause, mutation_type).await?
         }
     };
 
     Ok(result)
 }
 
+/// Rewrite `@name`-renamed field names in mutation arguments to their columns.
+fn rename_to_columns(value: serde_json::Value, renamed: &HashMap<String, String>) -> serde_json::Value {
+    match value {
+        serde_json::Value::Array(items) if !renamed.is_empty() => serde_json::Value::Array(
+            items.into_iter().map(|v| rename_to_columns(v, renamed)).collect(),
+        ),
+        serde_json::Value::Object(map) if !renamed.is_empty() => serde_json::Value::Object(
+            map.into_iter()
+                .map(|(k, v)| (renamed.get(&k).cloned().unwrap_or(k), v))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// Reject mutation arguments naming a hidden column, as if it didn't exist.
+fn reject_hidden_columns(
+    value: &serde_json::Value,
+    hidden: &[String],
+) -> Result<(), async_graphql::Error> {
+    let objects = match value {
+        serde_json::Value::Array(items) => items.iter().collect(),
+        other => vec![other],
+    };
+    for object in objects.into_iter().filter_map(|o| o.as_object()) {
+        if let Some(column) = object.keys().find(|k| hidden.contains(k)) {
+            return Err(async_graphql::Error::new(format!("Column '{}' does not exist", column)));
+        }
+    }
+    Ok(())
+}
+
 /// Execute a SQL query and return results as serde_json::Value.
 /// We keep data as serde_json::Value so field resolvers can use try_downcast_ref.
 async fn execute_query(
@@ -596,6 +851,7 @@ async fn execute_query(
 /// Execute an insert mutation.
 async fn execute_insert<'a>(
     pool: &PgPool,
+    schema: &str,
     table_name: &str,
     role: &str,
     objects: serde_json::Value,
@@ -632,7 +888,8 @@ async fn execute_insert<'a>(
             let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("${}", i)).collect();
 
             let sql = format!(
-                "INSERT INTO public.{} ({}) VALUES ({}) RETURNING row_to_json(public.{}.*)",
+                "INSERT INTO {}.{} ({}) VALUES ({}) RETURNING row_to_json({}.*)",
+                postrust_sql::escape_ident(schema),
                 postrust_sql::escape_ident(table_name),
                 columns.iter().map(|c| postrust_sql::escape_ident(c)).collect::<Vec<_>>().join(", "),
                 placeholders.join(", "),
@@ -694,6 +951,7 @@ fn bind_json_value<'q>(
 /// Execute an update mutation.
 async fn execute_update<'a>(
     pool: &PgPool,
+    schema: &str,
     table_name: &str,
     role: &str,
     set_value: serde_json::Value,
@@ -732,7 +990,8 @@ async fn execute_update<'a>(
     let (where_sql, where_values) = build_where_clause(where_clause.as_ref(), param_idx)?;
 
     let sql = format!(
-        "UPDATE public.{} SET {} {} RETURNING row_to_json(public.{}.*)",
+        "UPDATE {}.{} SET {} {} RETURNING row_to_json({}.*)",
+        postrust_sql::escape_ident(schema),
         postrust_sql::escape_ident(table_name),
         set_parts.join(", "),
         where_sql,
@@ -776,6 +1035,7 @@ async fn execute_update<'a>(
 /// Execute a delete mutation.
 async fn execute_delete<'a>(
     pool: &PgPool,
+    schema: &str,
     table_name: &str,
     role: &str,
     where_clause: Option<serde_json::Value>,
@@ -796,7 +1056,8 @@ async fn execute_delete<'a>(
     let (where_sql, where_values) = build_where_clause(where_clause.as_ref(), 1)?;
 
     let sql = format!(
-        "DELETE FROM public.{} {} RETURNING row_to_json(public.{}.*)",
+        "DELETE FROM {}.{} {} RETURNING row_to_json({}.*)",
+        postrust_sql::escape_ident(schema),
         postrust_sql::escape_ident(table_name),
         where_sql,
         postrust_sql::escape_ident(table_name)
@@ -1099,7 +1360,7 @@ mod tests {
     use super::*;
     use indexmap::IndexMap;
     use postrust_core::schema_cache::{Column, Table};
-    use std::collections::{HashMap, HashSet};
+    use std::collections::HashMap;
 
     fn create_test_table(name: &str) -> Table {
         let mut columns = IndexMap::new();
@@ -1107,30 +1368,22 @@ mod tests {
             "id".into(),
             Column {
                 name: "id".into(),
-                description: None,
-                nullable: false,
                 data_type: "integer".into(),
                 nominal_type: "int4".into(),
-                max_len: None,
                 default: Some("nextval('id_seq')".into()),
-                enum_values: vec![],
                 is_pk: true,
                 position: 1,
+                ..Default::default()
             },
         );
         columns.insert(
             "name".into(),
             Column {
                 name: "name".into(),
-                description: None,
-                nullable: false,
                 data_type: "text".into(),
                 nominal_type: "text".into(),
-                max_len: None,
-                default: None,
-                enum_values: vec![],
-                is_pk: false,
                 position: 2,
+                ..Default::default()
             },
         );
 
@@ -1154,10 +1407,9 @@ mod tests {
 
         SchemaCache {
             tables,
-            relationships: HashMap::new(),
-            routines: HashMap::new(),
-            timezones: HashSet::new(),
             pg_version: 150000,
+            schemas: vec!["public".into()],
+            ..Default::default()
         }
     }
 
@@ -1277,13 +1529,232 @@ mod tests {
         let config = SchemaConfig::default();
         let generated = build_schema(&cache, &config);
 
-        let result = build_dynamic_schema(&generated, &cache, None);
+        let result = build_dynamic_schema(&generated, &cache, None, false);
         if let Err(ref e) = result {
             eprintln!("Schema build error: {:?}", e);
         }
         assert!(result.is_ok(), "Schema build failed: {:?}", result.err());
     }
 
+    #[tokio::test]
+    async fn test_introspection_can_be_disabled() {
+        let cache = create_test_schema_cache();
+        let generated = build_schema(&cache, &SchemaConfig::default());
+        let introspection = "{ __schema { queryType { name } } }";
+
+        let schema = build_dynamic_schema(&generated, &cache, None, false).unwrap();
+        let response = schema.execute(introspection).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            serde_json::json!({"__schema": {"queryType": {"name": "Query"}}})
+        );
+
+        let schema = build_dynamic_schema(&generated, &cache, None, true).unwrap();
+        let response = schema.execute(introspection).await;
+        assert!(!response.errors.is_empty(), "introspection should be rejected");
+
+        // Regular fields still resolve
+        let response = schema.execute("{ _schema }").await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            serde_json::json!({"_schema": "Postrust GraphQL Schema"})
+        );
+    }
+
+    #[test]
+    fn test_renamed_fields_map_to_columns() {
+        let renamed = HashMap::from([("emailAddress".to_string(), "email".to_string())]);
+
+        let objects = serde_json::json!([{"emailAddress": "a@b.c", "name": "a"}]);
+        assert_eq!(
+            rename_to_columns(objects, &renamed),
+            serde_json::json!([{"email": "a@b.c", "name": "a"}])
+        );
+
+        let filter = serde_json::json!({"emailAddress": {"eq": "a@b.c"}});
+        assert_eq!(
+            rename_to_columns(filter, &renamed),
+            serde_json::json!({"email": {"eq": "a@b.c"}})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_field_names_fail_schema_build() {
+        let mut cache = create_test_schema_cache();
+        for table in cache.tables.values_mut() {
+            table.columns["id"].description = Some("@name name".into());
+        }
+        let pool = PgPool::connect_lazy("postgres://localhost/postrust").unwrap();
+        let result = GraphQLState::new(pool, Arc::new(cache), SchemaConfig::default());
+        assert!(matches!(result, Err(GraphQLError::SchemaError(_))));
+    }
+
+    #[test]
+    fn test_hidden_columns_rejected_in_mutation_arguments() {
+        let hidden = vec!["password_hash".to_string()];
+
+        let objects = serde_json::json!([{"name": "a"}, {"name": "b", "password_hash": "x"}]);
+        let err = reject_hidden_columns(&objects, &hidden).unwrap_err();
+        assert_eq!(err.message, "Column 'password_hash' does not exist");
+
+        let filter = serde_json::json!({"password_hash": {"eq": "x"}});
+        assert!(reject_hidden_columns(&filter, &hidden).is_err());
+
+        let filter = serde_json::json!({"name": {"eq": "x"}});
+        assert!(reject_hidden_columns(&filter, &hidden).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_init_subscriptions_disabled_is_noop() {
+        // A lazy pool never connects, so any LISTEN attempt would fail
+        let pool = PgPool::connect_lazy("postgres://localhost/postrust").unwrap();
+        let state = GraphQLState::new(
+            pool,
+            Arc::new(create_test_schema_cache()),
+            SchemaConfig::default(),
+        )
+        .unwrap();
+
+        state.init_subscriptions().await.unwrap();
+        assert!(state.subscription_fields.is_empty());
+        assert!(state.broker.read().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_batch_returns_responses_in_order() {
+        let pool = PgPool::connect_lazy("postgres://localhost/postrust").unwrap();
+        let state = GraphQLState::new(
+            pool.clone(),
+            Arc::new(create_test_schema_cache()),
+            SchemaConfig::default(),
+        )
+        .unwrap();
+        let ctx = GraphQLContext::new(
+            pool,
+            postrust_core::schema_cache::SchemaCacheRef::from_static(create_test_schema_cache()),
+            postrust_auth::AuthResult {
+                role: "anon".into(),
+                claims: HashMap::new(),
+            },
+        );
+
+        let batch = BatchRequest::Batch(vec![
+            async_graphql::Request::new("{ first: _schema }"),
+            async_graphql::Request::new("{ second: _schema }"),
+        ]);
+        let BatchResponse::Batch(responses) = execute_batch(&state, ctx.clone(), batch).await else {
+            panic!("expected a batch response");
+        };
+        assert_eq!(responses.len(), 2);
+        assert_eq!(
+            responses[0].data.clone().into_json().unwrap(),
+            serde_json::json!({"first": "Postrust GraphQL Schema"})
+        );
+        assert_eq!(
+            responses[1].data.clone().into_json().unwrap(),
+            serde_json::json!({"second": "Postrust GraphQL Schema"})
+        );
+
+        // A single operation keeps the single-response shape
+        let single = BatchRequest::Single(async_graphql::Request::new("{ _schema }"));
+        assert!(matches!(
+            execute_batch(&state, ctx, single).await,
+            BatchResponse::Single(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_accept_profile_selects_schema_types() {
+        let mut cache = create_test_schema_cache();
+        let mut accounts = create_test_table("accounts");
+        accounts.schema = "auth".into();
+        cache.tables.insert(accounts.qualified_identifier(), accounts);
+
+        let pool = PgPool::connect_lazy("postgres://localhost/postrust").unwrap();
+        let config = SchemaConfig::new().with_schemas(vec!["public".into(), "auth".into()]);
+        let state = GraphQLState::new(pool.clone(), Arc::new(cache.clone()), config).unwrap();
+        let run = |profile: Option<&str>| {
+            let ctx = GraphQLContext::new(
+                pool.clone(),
+                postrust_core::schema_cache::SchemaCacheRef::from_static(cache.clone()),
+                postrust_auth::AuthResult {
+                    role: "anon".into(),
+                    claims: HashMap::new(),
+                },
+            )
+            .with_profile(profile.map(String::from));
+            let query = "{ users: __type(name: \"Users\") { name } accounts: __type(name: \"Accounts\") { name } }";
+            let state = &state;
+            async move {
+                let BatchResponse::Single(response) =
+                    execute_batch(state, ctx, BatchRequest::Single(async_graphql::Request::new(query))).await
+                else {
+                    panic!("expected a single response");
+                };
+                response
+            }
+        };
+
+        let both = run(None).await.data.into_json().unwrap();
+        assert_eq!(both, serde_json::json!({"users": {"name": "Users"}, "accounts": {"name": "Accounts"}}));
+        let auth = run(Some("auth")).await.data.into_json().unwrap();
+        assert_eq!(auth, serde_json::json!({"users": null, "accounts": {"name": "Accounts"}}));
+        let public = run(Some("public")).await.data.into_json().unwrap();
+        assert_eq!(public, serde_json::json!({"users": {"name": "Users"}, "accounts": null}));
+
+        let response = run(Some("internal")).await;
+        assert_eq!(response.errors[0].message, "Unacceptable schema: internal (acceptable profiles: public, auth)");
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_timeout_closes_idle_socket() {
+        use async_graphql::http::{WebSocket, WsMessage};
+
+        let cache = create_test_schema_cache();
+        let config = SchemaConfig {
+            ws_keepalive_timeout: Some(Duration::from_millis(20)),
+            ..SchemaConfig::default()
+        };
+        let generated = build_schema(&cache, &config);
+        let schema = build_dynamic_schema(&generated, &cache, None, false).unwrap();
+
+        // The client initializes the connection and then goes quiet
+        let client = futures::stream::iter(vec![r#"{"type":"connection_init"}"#.to_string()])
+            .chain(futures::stream::pending());
+        let mut socket = WebSocket::new(schema, client, async_graphql::http::WebSocketProtocols::GraphQLWS)
+            .keepalive_timeout(config.ws_keepalive_timeout);
+
+        assert!(matches!(socket.next().await, Some(WsMessage::Text(ack)) if ack.contains("connection_ack")));
+        let closed = tokio::time::timeout(Duration::from_secs(5), socket.next()).await.unwrap();
+        assert!(matches!(closed, Some(WsMessage::Close(3008, _))), "{:?}", closed);
+    }
+
+    #[test]
+    fn test_connection_init_auth() {
+        let config = JwtConfig {
+            secret: Some("a-secret-that-is-at-least-32-characters".into()),
+            anon_role: Some("web_anon".into()),
+            ..Default::default()
+        };
+
+        // Without a token the upgrade request's role stays in place
+        assert!(connection_init_auth(&serde_json::json!({}), &config).unwrap().is_none());
+
+        // A bad token closes the connection instead of running as anon
+        let payload = serde_json::json!({"authorization": "Bearer not-a-jwt"});
+        assert!(connection_init_auth(&payload, &config).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_lifetime_ends_session() {
+        let ended = serve_within_lifetime(futures::future::pending(), Some(Duration::from_secs(60))).await;
+        assert!(!ended);
+        assert!(serve_within_lifetime(async {}, Some(Duration::from_secs(60))).await);
+        assert!(serve_within_lifetime(async {}, None).await);
+    }
+
     #[test]
     fn test_create_object_type() {
         let table = create_test_table("users");
@@ -1360,11 +1831,11 @@ mod tests {
         let generated = build_schema(&cache, &config);
 
         // Generate subscription fields
-        let sub_fields = generate_subscription_fields(&cache, &generated);
+        let sub_fields = generate_subscription_fields(&cache, &generated, &config);
         assert!(!sub_fields.is_empty(), "Should have subscription fields");
 
         // Build schema with subscriptions
-        let result = build_dynamic_schema(&generated, &cache, Some(&sub_fields));
+        let result = build_dynamic_schema(&generated, &cache, Some(&sub_fields), false);
         assert!(result.is_ok(), "Schema with subscriptions should build");
     }
 
@@ -1374,7 +1845,7 @@ mod tests {
         let config = SchemaConfig::default();
         let generated = build_schema(&cache, &config);
 
-        let fields = generate_subscription_fields(&cache, &generated);
+        let fields = generate_subscription_fields(&cache, &generated, &config);
 
         // Should have one subscription field for the users table
         assert_eq!(fields.len(), 1);
@@ -1383,6 +1854,26 @@ mod tests {
         assert_eq!(fields[0].channel_name(), "postrust_public_users");
     }
 
+    #[test]
+    fn test_subscription_fields_follow_allowlist() {
+        let cache = create_test_schema_cache();
+        let generated = build_schema(&cache, &SchemaConfig::default());
+
+        let config = SchemaConfig::default().with_subscription_tables(vec!["public.users".into()]);
+        let fields = generate_subscription_fields(&cache, &generated, &config);
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].table_name, "users");
+
+        let config = SchemaConfig::default().with_subscription_tables(vec!["orders".into()]);
+        assert!(generate_subscription_fields(&cache, &generated, &config).is_empty());
+
+        // The denylist wins over the allowlist
+        let config = SchemaConfig::default()
+            .with_subscription_tables(vec!["users".into()])
+            .with_subscription_exclude(vec!["users".into()]);
+        assert!(generate_subscription_fields(&cache, &generated, &config).is_empty());
+    }
+
     #[test]
     fn test_create_subscription_type() {
         use crate::subscription::SubscriptionField as SubField;
@@ -286,14 +286,12 @@ mod tests {
             Column {
                 name: "id".into(),
                 description: Some("Primary key".into()),
-                nullable: false,
                 data_type: "integer".into(),
                 nominal_type: "int4".into(),
-                max_len: None,
                 default: Some("nextval('users_id_seq')".into()),
-                enum_values: vec![],
                 is_pk: true,
                 position: 1,
+                ..Default::default()
             },
         );
         columns.insert(
@@ -301,44 +299,32 @@ mod tests {
             Column {
                 name: "name".into(),
                 description: Some("User name".into()),
-                nullable: false,
                 data_type: "text".into(),
                 nominal_type: "text".into(),
-                max_len: None,
-                default: None,
-                enum_values: vec![],
-                is_pk: false,
                 position: 2,
+                ..Default::default()
             },
         );
         columns.insert(
             "email".into(),
             Column {
                 name: "email".into(),
-                description: None,
                 nullable: true,
                 data_type: "text".into(),
                 nominal_type: "text".into(),
-                max_len: None,
-                default: None,
-                enum_values: vec![],
-                is_pk: false,
                 position: 3,
+                ..Default::default()
             },
         );
         columns.insert(
             "created_at".into(),
             Column {
                 name: "created_at".into(),
-                description: None,
-                nullable: false,
                 data_type: "timestamptz".into(),
                 nominal_type: "timestamptz".into(),
-                max_len: None,
                 default: Some("now()".into()),
-                enum_values: vec![],
-                is_pk: false,
                 position: 4,
+                ..Default::default()
             },
         );
 
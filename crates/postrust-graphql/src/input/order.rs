@@ -89,6 +89,7 @@ impl OrderByField {
             field: Field::simple(&self.field),
             direction: Some(self.direction.into()),
             nulls: self.nulls.map(|n| n.into()),
+            cast: None,
         }
     }
 }
@@ -263,6 +264,7 @@ mod tests {
                 field,
                 direction,
                 nulls,
+                ..
             } => {
                 assert_eq!(field.name, "name");
                 assert!(matches!(direction, Some(CoreOrderDirection::Desc)));
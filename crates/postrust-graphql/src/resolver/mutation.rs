@@ -316,6 +316,9 @@ pub fn build_insert_plan(args: &InsertArgs, table: &Table) -> MutatePlan {
         returning,
         pk_cols: table.pk_cols.clone(),
         apply_defaults: true,
+        nested: vec![],
+        binary_body: false,
+        raw_json: false,
     }
 }
 
@@ -337,6 +340,8 @@ pub fn build_update_plan(args: &UpdateArgs, table: &Table) -> MutatePlan {
         where_clauses,
         returning,
         apply_defaults: false,
+        if_match: None,
+        bulk_key: vec![],
     }
 }
 
@@ -371,45 +376,33 @@ mod tests {
             "id".into(),
             Column {
                 name: "id".into(),
-                description: None,
-                nullable: false,
                 data_type: "integer".into(),
                 nominal_type: "int4".into(),
-                max_len: None,
                 default: Some("nextval('users_id_seq')".into()),
-                enum_values: vec![],
                 is_pk: true,
                 position: 1,
+                ..Default::default()
             },
         );
         columns.insert(
             "name".into(),
             Column {
                 name: "name".into(),
-                description: None,
-                nullable: false,
                 data_type: "text".into(),
                 nominal_type: "text".into(),
-                max_len: None,
-                default: None,
-                enum_values: vec![],
-                is_pk: false,
                 position: 2,
+                ..Default::default()
             },
         );
         columns.insert(
             "email".into(),
             Column {
                 name: "email".into(),
-                description: None,
                 nullable: true,
                 data_type: "text".into(),
                 nominal_type: "text".into(),
-                max_len: None,
-                default: None,
-                enum_values: vec![],
-                is_pk: false,
                 position: 3,
+                ..Default::default()
             },
         );
 
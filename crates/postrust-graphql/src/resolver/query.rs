@@ -363,60 +363,44 @@ mod tests {
             "id".into(),
             Column {
                 name: "id".into(),
-                description: None,
-                nullable: false,
                 data_type: "integer".into(),
                 nominal_type: "int4".into(),
-                max_len: None,
                 default: Some("nextval('users_id_seq')".into()),
-                enum_values: vec![],
                 is_pk: true,
                 position: 1,
+                ..Default::default()
             },
         );
         columns.insert(
             "name".into(),
             Column {
                 name: "name".into(),
-                description: None,
-                nullable: false,
                 data_type: "text".into(),
                 nominal_type: "text".into(),
-                max_len: None,
-                default: None,
-                enum_values: vec![],
-                is_pk: false,
                 position: 2,
+                ..Default::default()
             },
         );
         columns.insert(
             "email".into(),
             Column {
                 name: "email".into(),
-                description: None,
                 nullable: true,
                 data_type: "text".into(),
                 nominal_type: "text".into(),
-                max_len: None,
-                default: None,
-                enum_values: vec![],
-                is_pk: false,
                 position: 3,
+                ..Default::default()
             },
         );
         columns.insert(
             "age".into(),
             Column {
                 name: "age".into(),
-                description: None,
                 nullable: true,
                 data_type: "integer".into(),
                 nominal_type: "int4".into(),
-                max_len: None,
-                default: None,
-                enum_values: vec![],
-                is_pk: false,
                 position: 4,
+                ..Default::default()
             },
         );
 
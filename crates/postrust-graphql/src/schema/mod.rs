@@ -10,7 +10,9 @@ use crate::input::mutation::{is_deletable, is_insertable, is_updatable};
 use crate::schema::object::{to_camel_case, to_pascal_case, TableObjectType};
 use crate::schema::relationship::RelationshipField;
 use postrust_core::schema_cache::{SchemaCache, Table};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::time::Duration;
 
 /// Configuration for schema generation.
 #[derive(Debug, Clone)]
@@ -27,6 +29,23 @@ pub struct SchemaConfig {
     pub query_suffix: Option<String>,
     /// Whether to use camelCase for field names
     pub use_camel_case: bool,
+    /// Prefix type and field names with their schema (e.g., "PublicUsers",
+    /// "authUsers") when more than one schema is exposed
+    pub prefix_schema_names: bool,
+    /// Reject `__schema`/`__type` introspection queries (for production)
+    pub disable_introspection: bool,
+    /// Close WebSocket connections that send nothing (not even a ping) for this long
+    pub ws_keepalive_timeout: Option<Duration>,
+    /// Close WebSocket connections after this long regardless of activity
+    pub ws_max_lifetime: Option<Duration>,
+    /// Notify payloads larger than this many bytes carry only the primary key
+    pub notify_max_payload: usize,
+    /// Tables (`table` or `schema.table`) that get subscriptions; all when `None`
+    pub subscription_tables: Option<Vec<String>>,
+    /// Tables (`table` or `schema.table`) that never get subscriptions
+    pub subscription_exclude: Vec<String>,
+    /// Columns (`table.column` or `schema.table.column`) left out of the schema
+    pub hidden_columns: Vec<String>,
 }
 
 impl Default for SchemaConfig {
@@ -38,6 +57,14 @@ impl Default for SchemaConfig {
             query_prefix: None,
             query_suffix: None,
             use_camel_case: true,
+            prefix_schema_names: false,
+            disable_introspection: false,
+            ws_keepalive_timeout: None,
+            ws_max_lifetime: None,
+            notify_max_payload: crate::subscription::MAX_NOTIFY_PAYLOAD,
+            subscription_tables: None,
+            subscription_exclude: Vec::new(),
+            hidden_columns: Vec::new(),
         }
     }
 }
@@ -66,10 +93,90 @@ impl SchemaConfig {
         self
     }
 
+    /// Limit subscriptions to the given tables (`table` or `schema.table`).
+    pub fn with_subscription_tables(mut self, tables: Vec<String>) -> Self {
+        self.subscription_tables = Some(tables);
+        self
+    }
+
+    /// Exclude the given tables (`table` or `schema.table`) from subscriptions.
+    pub fn with_subscription_exclude(mut self, tables: Vec<String>) -> Self {
+        self.subscription_exclude = tables;
+        self
+    }
+
+    /// Whether a table gets a subscription field; the denylist wins.
+    pub fn subscribes_to(&self, schema: &str, table: &str) -> bool {
+        let qualified = format!("{}.{}", schema, table);
+        let listed = |names: &[String]| names.iter().any(|n| *n == table || *n == qualified);
+        !listed(&self.subscription_exclude)
+            && self.subscription_tables.as_deref().is_none_or(listed)
+    }
+
+    /// Hide the given columns (`table.column` or `schema.table.column`).
+    pub fn with_hidden_columns(mut self, columns: Vec<String>) -> Self {
+        self.hidden_columns = columns;
+        self
+    }
+
+    /// Whether a column is left out of the schema.
+    pub fn is_column_hidden(&self, schema: &str, table: &str, column: &str) -> bool {
+        let short = format!("{}.{}", table, column);
+        let qualified = format!("{}.{}.{}", schema, table, column);
+        self.hidden_columns.iter().any(|c| *c == short || *c == qualified)
+    }
+
+    /// The table without its hidden columns.
+    pub fn visible_table<'a>(&self, table: &'a Table) -> Cow<'a, Table> {
+        if !table
+            .columns
+            .keys()
+            .any(|c| self.is_column_hidden(&table.schema, &table.name, c))
+        {
+            return Cow::Borrowed(table);
+        }
+        let mut visible = table.clone();
+        visible
+            .columns
+            .retain(|c, _| !self.is_column_hidden(&table.schema, &table.name, c));
+        Cow::Owned(visible)
+    }
+
+    /// Enable or disable schema-prefixed names.
+    pub fn with_schema_prefix(mut self, enable: bool) -> Self {
+        self.prefix_schema_names = enable;
+        self
+    }
+
+    /// Enable or disable introspection queries.
+    pub fn with_introspection(mut self, enable: bool) -> Self {
+        self.disable_introspection = !enable;
+        self
+    }
+
     /// Check if a schema is exposed.
     pub fn is_schema_exposed(&self, schema: &str) -> bool {
         self.exposed_schemas.iter().any(|s| s == schema)
     }
+
+    /// Whether names are schema-prefixed: enabled and more than one schema exposed.
+    pub fn prefixes_schemas(&self) -> bool {
+        self.prefix_schema_names && self.exposed_schemas.len() > 1
+    }
+
+    /// Name a table's GraphQL type and fields derive from (`auth_users` when prefixed).
+    pub fn base_name(&self, schema: &str, table: &str) -> String {
+        if self.prefixes_schemas() {
+            format!("{}_{}", schema, table)
+        } else {
+            table.to_string()
+        }
+    }
+
+    /// GraphQL object type name for a table.
+    pub fn type_name(&self, schema: &str, table: &str) -> String {
+        to_pascal_case(&self.base_name(schema, table))
+    }
 }
 
 /// Represents a generated GraphQL schema.
@@ -83,9 +190,19 @@ pub struct GeneratedSchema {
     pub mutation_fields: Vec<MutationField>,
     /// Relationship fields for each type
     pub relationship_fields: HashMap<String, Vec<RelationshipField>>,
+    /// Hidden columns of each `(schema, table)` that has any
+    pub hidden_columns: HashMap<(String, String), Vec<String>>,
 }
 
 impl GeneratedSchema {
+    /// Columns of a table left out of the schema.
+    pub fn hidden_columns(&self, schema: &str, table: &str) -> &[String] {
+        self.hidden_columns
+            .get(&(schema.to_string(), table.to_string()))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
     /// Get an object type by name.
     pub fn get_object_type(&self, name: &str) -> Option<&TableObjectType> {
         self.object_types.get(name)
@@ -125,6 +242,8 @@ impl GeneratedSchema {
 pub struct QueryField {
     /// Field name (e.g., "users")
     pub name: String,
+    /// Schema of the table
+    pub schema: String,
     /// Table name
     pub table_name: String,
     /// GraphQL object type name (e.g., "Users")
@@ -142,11 +261,12 @@ pub struct QueryField {
 impl QueryField {
     /// Create a list query field (e.g., users).
     pub fn list(table: &Table, config: &SchemaConfig) -> Self {
-        let type_name = to_pascal_case(&table.name);
+        let base = config.base_name(&table.schema, &table.name);
+        let type_name = to_pascal_case(&base);
         let field_name = if config.use_camel_case {
-            to_camel_case(&table.name)
+            to_camel_case(&base)
         } else {
-            table.name.clone()
+            base
         };
 
         let name = match (&config.query_prefix, &config.query_suffix) {
@@ -160,6 +280,7 @@ impl QueryField {
 
         Self {
             name,
+            schema: table.schema.clone(),
             table_name: table.name.clone(),
             type_name: type_name.clone(),
             return_type: format!("[{}!]!", type_name),
@@ -175,8 +296,9 @@ impl QueryField {
             return None;
         }
 
-        let type_name = to_pascal_case(&table.name);
-        let singular = singularize(&table.name);
+        let base = config.base_name(&table.schema, &table.name);
+        let type_name = to_pascal_case(&base);
+        let singular = singularize(&base);
         let field_name = if config.use_camel_case {
             format!("{}ByPk", to_camel_case(&singular))
         } else {
@@ -185,6 +307,7 @@ impl QueryField {
 
         Some(Self {
             name: field_name,
+            schema: table.schema.clone(),
             table_name: table.name.clone(),
             type_name: type_name.clone(),
             return_type: type_name,
@@ -200,6 +323,8 @@ impl QueryField {
 pub struct MutationField {
     /// Field name (e.g., "insertUsers")
     pub name: String,
+    /// Schema of the table
+    pub schema: String,
     /// Table name
     pub table_name: String,
     /// Mutation type
@@ -234,19 +359,21 @@ impl MutationField {
             return vec![];
         }
 
-        let type_name = to_pascal_case(&table.name);
-        let singular = singularize(&table.name);
+        let base = config.base_name(&table.schema, &table.name);
+        let type_name = to_pascal_case(&base);
+        let singular = singularize(&base);
 
         let mut fields = vec![];
 
         // insert_users (batch insert)
         let name = if config.use_camel_case {
-            format!("insert{}", to_pascal_case(&table.name))
+            format!("insert{}", to_pascal_case(&base))
         } else {
-            format!("insert_{}", table.name)
+            format!("insert_{}", base)
         };
         fields.push(Self {
             name,
+            schema: table.schema.clone(),
             table_name: table.name.clone(),
             mutation_type: MutationType::Insert,
             return_type: format!("[{}!]!", type_name),
@@ -261,6 +388,7 @@ impl MutationField {
         };
         fields.push(Self {
             name,
+            schema: table.schema.clone(),
             table_name: table.name.clone(),
             mutation_type: MutationType::InsertOne,
             return_type: type_name.clone(),
@@ -276,19 +404,21 @@ impl MutationField {
             return vec![];
         }
 
-        let type_name = to_pascal_case(&table.name);
-        let singular = singularize(&table.name);
+        let base = config.base_name(&table.schema, &table.name);
+        let type_name = to_pascal_case(&base);
+        let singular = singularize(&base);
 
         let mut fields = vec![];
 
         // update_users (batch update)
         let name = if config.use_camel_case {
-            format!("update{}", to_pascal_case(&table.name))
+            format!("update{}", to_pascal_case(&base))
         } else {
-            format!("update_{}", table.name)
+            format!("update_{}", base)
         };
         fields.push(Self {
             name,
+            schema: table.schema.clone(),
             table_name: table.name.clone(),
             mutation_type: MutationType::Update,
             return_type: format!("[{}!]!", type_name),
@@ -304,6 +434,7 @@ impl MutationField {
             };
             fields.push(Self {
                 name,
+                schema: table.schema.clone(),
                 table_name: table.name.clone(),
                 mutation_type: MutationType::UpdateByPk,
                 return_type: type_name,
@@ -320,19 +451,21 @@ impl MutationField {
             return vec![];
         }
 
-        let type_name = to_pascal_case(&table.name);
-        let singular = singularize(&table.name);
+        let base = config.base_name(&table.schema, &table.name);
+        let type_name = to_pascal_case(&base);
+        let singular = singularize(&base);
 
         let mut fields = vec![];
 
         // delete_users (batch delete)
         let name = if config.use_camel_case {
-            format!("delete{}", to_pascal_case(&table.name))
+            format!("delete{}", to_pascal_case(&base))
         } else {
-            format!("delete_{}", table.name)
+            format!("delete_{}", base)
         };
         fields.push(Self {
             name,
+            schema: table.schema.clone(),
             table_name: table.name.clone(),
             mutation_type: MutationType::Delete,
             return_type: format!("[{}!]!", type_name),
@@ -348,6 +481,7 @@ impl MutationField {
             };
             fields.push(Self {
                 name,
+                schema: table.schema.clone(),
                 table_name: table.name.clone(),
                 mutation_type: MutationType::DeleteByPk,
                 return_type: type_name,
@@ -365,6 +499,7 @@ pub fn build_schema(schema_cache: &SchemaCache, config: &SchemaConfig) -> Genera
     let mut query_fields = Vec::new();
     let mut mutation_fields = Vec::new();
     let mut relationship_fields = HashMap::new();
+    let mut hidden_columns = HashMap::new();
 
     // Process each table in the schema cache
     for table in schema_cache.tables.values() {
@@ -373,8 +508,20 @@ pub fn build_schema(schema_cache: &SchemaCache, config: &SchemaConfig) -> Genera
             continue;
         }
 
+        let hidden: Vec<String> = table
+            .columns
+            .keys()
+            .filter(|c| config.is_column_hidden(&table.schema, &table.name, c))
+            .cloned()
+            .collect();
+        if !hidden.is_empty() {
+            hidden_columns.insert((table.schema.clone(), table.name.clone()), hidden);
+        }
+        let table = &*config.visible_table(table);
+
         // Create object type
-        let obj_type = TableObjectType::from_table(table);
+        let mut obj_type = TableObjectType::from_table(table);
+        obj_type.name = config.type_name(&table.schema, &table.name);
         let type_name = obj_type.name.clone();
 
         // Add query fields
@@ -396,7 +543,12 @@ pub fn build_schema(schema_cache: &SchemaCache, config: &SchemaConfig) -> Genera
             .map(|relationships| {
                 relationships
                     .iter()
-                    .map(|r| RelationshipField::from_relationship(r))
+                    .map(|r| {
+                        let mut field = RelationshipField::from_relationship(r);
+                        let foreign = r.foreign_table();
+                        field.target_type = config.type_name(&foreign.schema, &foreign.name);
+                        field
+                    })
                     .collect()
             })
             .unwrap_or_default();
@@ -413,6 +565,7 @@ pub fn build_schema(schema_cache: &SchemaCache, config: &SchemaConfig) -> Genera
         query_fields,
         mutation_fields,
         relationship_fields,
+        hidden_columns,
     }
 }
 
@@ -444,30 +597,22 @@ mod tests {
             "id".into(),
             Column {
                 name: "id".into(),
-                description: None,
-                nullable: false,
                 data_type: "integer".into(),
                 nominal_type: "int4".into(),
-                max_len: None,
                 default: Some("nextval('id_seq')".into()),
-                enum_values: vec![],
                 is_pk: true,
                 position: 1,
+                ..Default::default()
             },
         );
         columns.insert(
             "name".into(),
             Column {
                 name: "name".into(),
-                description: None,
-                nullable: false,
                 data_type: "text".into(),
                 nominal_type: "text".into(),
-                max_len: None,
-                default: None,
-                enum_values: vec![],
-                is_pk: false,
                 position: 2,
+                ..Default::default()
             },
         );
 
@@ -485,7 +630,7 @@ mod tests {
     }
 
     fn create_test_schema_cache() -> SchemaCache {
-        use std::collections::{HashMap, HashSet};
+        use std::collections::HashMap;
 
         let mut tables = HashMap::new();
 
@@ -499,10 +644,9 @@ mod tests {
 
         SchemaCache {
             tables,
-            relationships: HashMap::new(),
-            routines: HashMap::new(),
-            timezones: HashSet::new(),
             pg_version: 150000,
+            schemas: vec!["public".into()],
+            ..Default::default()
         }
     }
 
@@ -528,6 +672,30 @@ mod tests {
         assert!(!config.is_schema_exposed("private"));
     }
 
+    #[test]
+    fn test_hidden_columns_are_left_out() {
+        use crate::input::mutation::{InsertInput, UpdateInput};
+
+        let cache = create_test_schema_cache();
+        let config = SchemaConfig::new().with_hidden_columns(vec!["users.name".into()]);
+        let generated = build_schema(&cache, &config);
+
+        let users = generated.get_object_type("Users").unwrap();
+        assert!(users.fields.iter().all(|f| f.name != "name"));
+        assert!(users.fields.iter().any(|f| f.name == "id"));
+        assert_eq!(generated.hidden_columns("public", "users"), ["name".to_string()]);
+
+        let table = cache.tables.values().find(|t| t.name == "users").unwrap();
+        let visible = config.visible_table(table);
+        assert!(InsertInput::from_table(&visible).fields.iter().all(|f| f.name != "name"));
+        assert!(UpdateInput::from_table(&visible).fields.iter().all(|f| f.name != "name"));
+
+        // Other tables keep their columns
+        let posts = generated.get_object_type("Posts").unwrap();
+        assert!(posts.fields.iter().any(|f| f.name == "name"));
+        assert!(generated.hidden_columns("public", "posts").is_empty());
+    }
+
     #[test]
     fn test_schema_config_mutations_disabled() {
         let config = SchemaConfig::new().with_mutations(false);
@@ -788,6 +956,43 @@ mod tests {
         assert!(schema.get_object_type("Secrets").is_none());
     }
 
+    #[test]
+    fn test_build_schema_prefixes_schema_names() {
+        let mut cache = create_test_schema_cache();
+        let mut auth_users = create_test_table("users", true, true, true);
+        auth_users.schema = "auth".into();
+        cache.tables.insert(auth_users.qualified_identifier(), auth_users);
+
+        let config = SchemaConfig::new()
+            .with_schemas(vec!["public".to_string(), "auth".to_string()])
+            .with_schema_prefix(true);
+        let schema = build_schema(&cache, &config);
+
+        assert_eq!(schema.object_types.len(), 4);
+        assert_eq!(schema.get_object_type("PublicUsers").unwrap().table.schema, "public");
+        assert_eq!(schema.get_object_type("AuthUsers").unwrap().table.schema, "auth");
+        assert!(schema.get_object_type("Users").is_none());
+
+        let names: Vec<&str> = schema.query_fields.iter().map(|f| f.name.as_str()).collect();
+        assert!(names.contains(&"publicUsers"));
+        assert!(names.contains(&"authUsers"));
+        assert!(names.contains(&"authUserByPk"));
+        let auth_list = schema.query_fields.iter().find(|f| f.name == "authUsers").unwrap();
+        assert_eq!(auth_list.schema, "auth");
+        assert_eq!(auth_list.return_type, "[AuthUsers!]!");
+        assert!(schema.mutation_fields.iter().any(|f| f.name == "insertAuthUsers"));
+    }
+
+    #[test]
+    fn test_schema_prefix_needs_multiple_schemas() {
+        let cache = create_test_schema_cache();
+        let config = SchemaConfig::new().with_schema_prefix(true);
+        let schema = build_schema(&cache, &config);
+
+        assert!(!config.prefixes_schemas());
+        assert!(schema.get_object_type("Users").is_some());
+    }
+
     // ============================================================================
     // GeneratedSchema Tests
     // ============================================================================
@@ -2,12 +2,15 @@
 
 use crate::types::{pg_type_to_graphql, GraphQLType};
 use postrust_core::schema_cache::{Column, Table};
+use std::collections::{HashMap, HashSet};
 
 /// Represents a GraphQL field derived from a database column.
 #[derive(Debug, Clone)]
 pub struct GraphQLField {
-    /// Field name (same as column name).
+    /// Field name: the column name, unless overridden by an `@name` comment directive.
     pub name: String,
+    /// Column the field reads from.
+    pub column: String,
     /// Field description from column comment.
     pub description: Option<String>,
     /// GraphQL type for this field.
@@ -24,9 +27,15 @@ impl GraphQLField {
         let graphql_type = pg_type_to_graphql(&column.nominal_type);
         let nullable = column.nullable && !column.is_pk;
 
+        let (name, description) = match column.description.as_deref() {
+            Some(comment) => parse_name_directive(comment),
+            None => (None, None),
+        };
+
         Self {
-            name: column.name.clone(),
-            description: column.description.clone(),
+            name: name.unwrap_or_else(|| column.name.clone()),
+            column: column.name.clone(),
+            description,
             graphql_type,
             nullable,
             is_pk: column.is_pk,
@@ -101,6 +110,45 @@ impl TableObjectType {
     pub fn pk_fields(&self) -> Vec<&GraphQLField> {
         self.fields.iter().filter(|f| f.is_pk).collect()
     }
+
+    /// Renamed fields, mapped to their columns.
+    pub fn renamed_columns(&self) -> HashMap<String, String> {
+        self.fields
+            .iter()
+            .filter(|f| f.name != f.column)
+            .map(|f| (f.name.clone(), f.column.clone()))
+            .collect()
+    }
+
+    /// Check that no two fields share a name, e.g. after an `@name` override.
+    pub fn check_field_names(&self) -> Result<(), String> {
+        let mut seen = HashSet::new();
+        match self.fields.iter().find(|f| !seen.insert(f.name.as_str())) {
+            Some(field) => Err(format!(
+                "Field '{}' of type '{}' is defined twice; check the @name comments on table '{}'",
+                field.name, self.name, self.table.name
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Split an `@name <field>` directive out of a column comment.
+///
+/// Returns the field name and the comment without the directive line.
+fn parse_name_directive(comment: &str) -> (Option<String>, Option<String>) {
+    let mut name = None;
+    let mut lines = Vec::new();
+    for line in comment.lines() {
+        match line.trim().strip_prefix("@name") {
+            Some(rest) if name.is_none() && rest.starts_with(char::is_whitespace) => {
+                name = rest.split_whitespace().next().map(str::to_string);
+            }
+            _ => lines.push(line),
+        }
+    }
+    let description = lines.join("\n").trim().to_string();
+    (name, (!description.is_empty()).then_some(description))
 }
 
 /// Convert a snake_case string to PascalCase.
@@ -141,14 +189,12 @@ mod tests {
             Column {
                 name: "id".into(),
                 description: Some("Primary key".into()),
-                nullable: false,
                 data_type: "integer".into(),
                 nominal_type: "int4".into(),
-                max_len: None,
                 default: Some("nextval('users_id_seq')".into()),
-                enum_values: vec![],
                 is_pk: true,
                 position: 1,
+                ..Default::default()
             },
         );
         columns.insert(
@@ -156,29 +202,21 @@ mod tests {
             Column {
                 name: "name".into(),
                 description: Some("User name".into()),
-                nullable: false,
                 data_type: "text".into(),
                 nominal_type: "text".into(),
-                max_len: None,
-                default: None,
-                enum_values: vec![],
-                is_pk: false,
                 position: 2,
+                ..Default::default()
             },
         );
         columns.insert(
             "email".into(),
             Column {
                 name: "email".into(),
-                description: None,
                 nullable: true,
                 data_type: "text".into(),
                 nominal_type: "text".into(),
-                max_len: None,
-                default: None,
-                enum_values: vec![],
-                is_pk: false,
                 position: 3,
+                ..Default::default()
             },
         );
         columns.insert(
@@ -189,11 +227,8 @@ mod tests {
                 nullable: true,
                 data_type: "jsonb".into(),
                 nominal_type: "jsonb".into(),
-                max_len: None,
-                default: None,
-                enum_values: vec![],
-                is_pk: false,
                 position: 4,
+                ..Default::default()
             },
         );
 
@@ -308,6 +343,38 @@ mod tests {
         assert_eq!(email_field.type_string(), "String"); // Nullable
     }
 
+    #[test]
+    fn test_name_directive_renames_field() {
+        let mut table = create_test_table();
+        table.columns["email"].description = Some("Contact address\n@name emailAddress".into());
+        let obj = TableObjectType::from_table(&table);
+
+        let field = obj.get_field("emailAddress").unwrap();
+        assert_eq!(field.column, "email");
+        assert_eq!(field.description, Some("Contact address".into()));
+        assert!(!obj.has_field("email"));
+        assert_eq!(
+            obj.renamed_columns(),
+            HashMap::from([("emailAddress".to_string(), "email".to_string())])
+        );
+        assert!(obj.check_field_names().is_ok());
+
+        // A directive alone leaves no description
+        table.columns["email"].description = Some("@name mail".into());
+        let obj = TableObjectType::from_table(&table);
+        assert_eq!(obj.get_field("mail").unwrap().description, None);
+    }
+
+    #[test]
+    fn test_name_directive_collision() {
+        let mut table = create_test_table();
+        table.columns["email"].description = Some("@name name".into());
+        let obj = TableObjectType::from_table(&table);
+
+        let err = obj.check_field_names().unwrap_err();
+        assert!(err.contains("Field 'name' of type 'Users' is defined twice"), "{}", err);
+    }
+
     #[test]
     fn test_pk_fields() {
         let table = create_test_table();
@@ -4,6 +4,7 @@
 //! and broadcasts them to GraphQL subscription clients.
 
 use futures::stream::{Stream, StreamExt};
+use postrust_sql::{escape_ident, quote_literal};
 use sqlx::postgres::PgListener;
 use sqlx::PgPool;
 use std::collections::HashMap;
@@ -320,11 +321,26 @@ pub fn table_channel_name(schema: &str, table: &str) -> String {
     format!("postrust_{}_{}", schema, table)
 }
 
+/// Largest NOTIFY payload PostgreSQL accepts, in bytes.
+pub const MAX_NOTIFY_PAYLOAD: usize = 7999;
+
 /// Generate SQL to create a notification trigger for a table.
+///
+/// The SQL is idempotent: the function is replaced and the trigger dropped
+/// and recreated, so it can run on every startup.
 pub fn create_notify_trigger_sql(schema: &str, table: &str) -> String {
-    let channel = table_channel_name(schema, table);
-    let trigger_name = format!("postrust_notify_{}_{}", schema, table);
-    let function_name = format!("postrust_notify_{}_{}_fn", schema, table);
+    create_notify_trigger_sql_with_limit(schema, table, MAX_NOTIFY_PAYLOAD)
+}
+
+/// Like [`create_notify_trigger_sql`], but rows whose payload exceeds
+/// `max_payload` bytes are sent as their primary key only, for the server
+/// to refetch.
+pub fn create_notify_trigger_sql_with_limit(schema: &str, table: &str, max_payload: usize) -> String {
+    let channel = quote_literal(&table_channel_name(schema, table));
+    let trigger_name = escape_ident(&format!("postrust_notify_{}_{}", schema, table));
+    let function_name = escape_ident(&format!("postrust_notify_{}_{}_fn", schema, table));
+    let schema = escape_ident(schema);
+    let table = escape_ident(table);
 
     format!(
         r#"
@@ -333,6 +349,7 @@ CREATE OR REPLACE FUNCTION {schema}.{function_name}()
 RETURNS TRIGGER AS $$
 DECLARE
     payload jsonb;
+    pk jsonb;
 BEGIN
     IF TG_OP = 'DELETE' THEN
         payload := jsonb_build_object(
@@ -358,7 +375,21 @@ BEGIN
         );
     END IF;
 
-    PERFORM pg_notify('{channel}', payload::text);
+    IF octet_length(payload::text) > {max_payload} THEN
+        SELECT jsonb_object_agg(a.attname, to_jsonb(COALESCE(NEW, OLD)) -> a.attname)
+        INTO pk
+        FROM pg_index i
+        JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey)
+        WHERE i.indrelid = TG_RELID AND i.indisprimary;
+        payload := jsonb_build_object(
+            'operation', TG_OP,
+            'table', TG_TABLE_NAME,
+            'schema', TG_TABLE_SCHEMA,
+            'pk', pk
+        );
+    END IF;
+
+    PERFORM pg_notify({channel}, payload::text);
 
     RETURN COALESCE(NEW, OLD);
 END;
@@ -375,14 +406,17 @@ CREATE TRIGGER {trigger_name}
         table = table,
         channel = channel,
         function_name = function_name,
-        trigger_name = trigger_name
+        trigger_name = trigger_name,
+        max_payload = max_payload
     )
 }
 
 /// Generate SQL to drop a notification trigger for a table.
 pub fn drop_notify_trigger_sql(schema: &str, table: &str) -> String {
-    let trigger_name = format!("postrust_notify_{}_{}", schema, table);
-    let function_name = format!("postrust_notify_{}_{}_fn", schema, table);
+    let trigger_name = escape_ident(&format!("postrust_notify_{}_{}", schema, table));
+    let function_name = escape_ident(&format!("postrust_notify_{}_{}_fn", schema, table));
+    let schema = escape_ident(schema);
+    let table = escape_ident(table);
 
     format!(
         r#"
@@ -422,6 +456,34 @@ mod tests {
         assert!(sql.contains("postrust_public_users"));
     }
 
+    #[test]
+    fn test_create_notify_trigger_sql_is_idempotent() {
+        let sql = create_notify_trigger_sql("public", "users");
+        let drop = sql.find("DROP TRIGGER IF EXISTS \"postrust_notify_public_users\" ON \"public\".\"users\"");
+        let create = sql.find("CREATE TRIGGER \"postrust_notify_public_users\"");
+        assert!(drop.is_some() && create.is_some() && drop < create);
+        assert!(sql.contains("CREATE OR REPLACE FUNCTION \"public\".\"postrust_notify_public_users_fn\"()"));
+        assert!(!sql.contains("CREATE FUNCTION"));
+    }
+
+    #[test]
+    fn test_oversized_payload_falls_back_to_pk() {
+        let sql = create_notify_trigger_sql_with_limit("public", "docs", 4000);
+        assert!(sql.contains("IF octet_length(payload::text) > 4000 THEN"));
+        assert!(sql.contains("i.indisprimary"));
+        assert!(sql.contains("'pk', pk"));
+
+        let sql = create_notify_trigger_sql("public", "docs");
+        assert!(sql.contains(&format!("> {} THEN", MAX_NOTIFY_PAYLOAD)));
+    }
+
+    #[test]
+    fn test_create_notify_trigger_sql_quotes_names() {
+        let sql = create_notify_trigger_sql("public", "Order Items");
+        assert!(sql.contains("ON \"public\".\"Order Items\""));
+        assert!(sql.contains("pg_notify('postrust_public_Order Items', payload::text)"));
+    }
+
     #[test]
     fn test_drop_notify_trigger_sql() {
         let sql = drop_notify_trigger_sql("public", "users");
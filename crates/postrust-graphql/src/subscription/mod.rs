@@ -33,12 +33,16 @@
 pub mod broker;
 
 pub use broker::{
-    create_notify_trigger_sql, drop_notify_trigger_sql, table_channel_name, BrokerError,
-    NotifyBroker, PgNotification,
+    create_notify_trigger_sql, create_notify_trigger_sql_with_limit, drop_notify_trigger_sql,
+    table_channel_name, BrokerError, NotifyBroker, PgNotification, MAX_NOTIFY_PAYLOAD,
 };
 
-use crate::schema::GeneratedSchema;
+use crate::schema::object::to_pascal_case;
+use crate::schema::{GeneratedSchema, SchemaConfig};
+use postrust_auth::AuthResult;
 use postrust_core::schema_cache::SchemaCache;
+use postrust_sql::escape_ident;
+use sqlx::PgPool;
 
 /// A subscription field in the GraphQL schema.
 #[derive(Debug, Clone)]
@@ -73,10 +77,11 @@ impl SubscriptionField {
     }
 }
 
-/// Generate subscription fields for all tables in the schema.
+/// Generate subscription fields for the tables `config` subscribes to.
 pub fn generate_subscription_fields(
     _schema_cache: &SchemaCache,
     generated: &GeneratedSchema,
+    config: &SchemaConfig,
 ) -> Vec<SubscriptionField> {
     let mut fields = Vec::new();
 
@@ -84,12 +89,13 @@ pub fn generate_subscription_fields(
         let table = &obj_type.table;
 
         // Only create subscriptions for tables, not views (views can be added later)
-        if !table.is_view {
-            fields.push(SubscriptionField::for_table(
-                &table.schema,
-                &table.name,
-                type_name,
-            ));
+        if !table.is_view && config.subscribes_to(&table.schema, &table.name) {
+            let mut field = SubscriptionField::for_table(&table.schema, &table.name, type_name);
+            // Schema-prefixed types get a schema-prefixed field to match
+            if *type_name != to_pascal_case(&table.name) {
+                field.name = to_camel_case(&format!("{}_{}", table.schema, table.name));
+            }
+            fields.push(field);
         }
     }
 
@@ -132,6 +138,9 @@ pub struct TableChangePayload {
     /// The new row data (for INSERT and UPDATE)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub new: Option<serde_json::Value>,
+    /// Primary key of a row too large to send in full
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pk: Option<serde_json::Value>,
 }
 
 impl TableChangePayload {
@@ -143,13 +152,66 @@ impl TableChangePayload {
     /// Get the data to return to the client.
     ///
     /// For INSERT and UPDATE, returns the new row.
-    /// For DELETE, returns the old row.
+    /// For DELETE, returns the old row, or just its primary key if the row
+    /// was too large to send.
     pub fn data(&self) -> Option<&serde_json::Value> {
         match self.operation.as_str() {
-            "DELETE" => self.old.as_ref(),
+            "DELETE" => self.old.as_ref().or(self.pk.as_ref()),
             _ => self.new.as_ref(),
         }
     }
+
+    /// Whether the row was sent as its primary key only and must be refetched.
+    pub fn needs_refetch(&self) -> bool {
+        self.pk.is_some() && self.data().is_none()
+    }
+
+    /// SQL selecting the changed row as JSON, with the `pk` object bound to `$1`.
+    pub fn refetch_sql(&self) -> Option<String> {
+        let pk = self.pk.as_ref()?.as_object()?;
+        if pk.is_empty() {
+            return None;
+        }
+        let columns = pk
+            .keys()
+            .map(|k| escape_ident(k))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let table = format!("{}.{}", escape_ident(&self.schema), escape_ident(&self.table));
+        Some(format!(
+            "SELECT to_jsonb(t) FROM {table} t WHERE ({columns}) = \
+             (SELECT {columns} FROM jsonb_populate_record(NULL::{table}, $1::jsonb))"
+        ))
+    }
+
+    /// Fetch the changed row as the subscriber, so its role's grants and
+    /// row-level security apply as they would to a query.
+    pub async fn refetch(&self, pool: &PgPool, auth: &AuthResult) -> Result<Option<serde_json::Value>, sqlx::Error> {
+        let Some(sql) = self.refetch_sql() else {
+            return Ok(None);
+        };
+        let mut tx = pool.begin().await?;
+        sqlx::query(&format!("SET LOCAL ROLE {}", escape_ident(&auth.role)))
+            .execute(&mut *tx)
+            .await?;
+        for (key, value) in &auth.claims {
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            sqlx::query("SELECT set_config($1, $2, true)")
+                .bind(format!("request.jwt.claims.{}", key))
+                .bind(value)
+                .execute(&mut *tx)
+                .await?;
+        }
+        let row = sqlx::query_scalar::<_, serde_json::Value>(&sql)
+            .bind(self.pk.clone())
+            .fetch_optional(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(row)
+    }
 }
 
 #[cfg(test)]
@@ -187,6 +249,31 @@ mod tests {
         assert!(payload.old.is_none());
     }
 
+    #[test]
+    fn test_pk_only_payload_is_refetched() {
+        let json = r#"{
+            "operation": "UPDATE",
+            "table": "documents",
+            "schema": "public",
+            "pk": {"id": 7, "tenant": "acme"}
+        }"#;
+
+        let payload = TableChangePayload::from_payload(json).unwrap();
+        assert!(payload.data().is_none());
+        assert!(payload.needs_refetch());
+        assert_eq!(
+            payload.refetch_sql().unwrap(),
+            "SELECT to_jsonb(t) FROM \"public\".\"documents\" t WHERE (\"id\", \"tenant\") = \
+             (SELECT \"id\", \"tenant\" FROM jsonb_populate_record(NULL::\"public\".\"documents\", $1::jsonb))"
+        );
+
+        // A deleted row can't be refetched; its key is all there is
+        let json = r#"{"operation": "DELETE", "table": "documents", "schema": "public", "pk": {"id": 7}}"#;
+        let payload = TableChangePayload::from_payload(json).unwrap();
+        assert!(!payload.needs_refetch());
+        assert_eq!(payload.data(), Some(&serde_json::json!({"id": 7})));
+    }
+
     #[test]
     fn test_table_change_payload_data() {
         let insert_payload = TableChangePayload {
@@ -195,6 +282,7 @@ mod tests {
             schema: "public".to_string(),
             old: None,
             new: Some(serde_json::json!({"id": 1})),
+            pk: None,
         };
         assert!(insert_payload.data().is_some());
 
@@ -204,6 +292,7 @@ mod tests {
             schema: "public".to_string(),
             old: Some(serde_json::json!({"id": 1})),
             new: None,
+            pk: None,
         };
         assert!(delete_payload.data().is_some());
     }
@@ -6,6 +6,7 @@
 //! Set DATABASE_URL environment variable to your test database connection string.
 
 use futures::StreamExt;
+use postrust_auth::AuthResult;
 use postrust_graphql::subscription::{
     create_notify_trigger_sql, drop_notify_trigger_sql, table_channel_name, NotifyBroker,
     TableChangePayload,
@@ -503,3 +504,58 @@ async fn test_trigger_sql_is_valid() {
         .await
         .expect("Failed to drop table");
 }
+
+#[tokio::test]
+#[ignore] // Requires running PostgreSQL database
+async fn test_refetch_runs_as_subscriber() {
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&get_database_url())
+        .await
+        .expect("Failed to connect to database");
+
+    let test_table = unique_table_name();
+    setup_test_table(&pool, &test_table).await.expect("Failed to setup test table");
+    pool.execute(
+        format!(
+            "DO $$ BEGIN CREATE ROLE postrust_sub_reader NOLOGIN; EXCEPTION WHEN duplicate_object THEN NULL; END $$;
+             GRANT USAGE ON SCHEMA {0} TO postrust_sub_reader;
+             GRANT SELECT ON {0}.{1} TO postrust_sub_reader;
+             ALTER TABLE {0}.{1} ENABLE ROW LEVEL SECURITY;
+             CREATE POLICY own_rows ON {0}.{1}
+                 USING (name = current_setting('request.jwt.claims.sub', true));
+             INSERT INTO {0}.{1} (id, name) VALUES (1, 'alice'), (2, 'bob');",
+            TEST_SCHEMA, test_table
+        )
+        .as_str(),
+    )
+    .await
+    .expect("Failed to set up row level security");
+
+    let payload = |id: i32| TableChangePayload {
+        operation: "UPDATE".into(),
+        table: test_table.clone(),
+        schema: TEST_SCHEMA.into(),
+        old: None,
+        new: None,
+        pk: Some(serde_json::json!({ "id": id })),
+    };
+    let auth = |role: &str| AuthResult {
+        role: role.into(),
+        claims: [("sub".to_string(), serde_json::json!("alice"))].into_iter().collect(),
+    };
+
+    // Only the rows the subscriber's policy lets it see come back
+    let own = payload(1).refetch(&pool, &auth("postrust_sub_reader")).await.unwrap();
+    assert_eq!(own.unwrap()["name"], "alice");
+    let other = payload(2).refetch(&pool, &auth("postrust_sub_reader")).await.unwrap();
+    assert!(other.is_none());
+
+    // A role without SELECT is refused rather than read as the server
+    pool.execute("DO $$ BEGIN CREATE ROLE postrust_sub_nobody NOLOGIN; EXCEPTION WHEN duplicate_object THEN NULL; END $$;")
+        .await
+        .unwrap();
+    assert!(payload(1).refetch(&pool, &auth("postrust_sub_nobody")).await.is_err());
+
+    cleanup_test_table(&pool, &test_table).await.expect("Failed to cleanup");
+}
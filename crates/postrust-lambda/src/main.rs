@@ -45,9 +45,13 @@ async fn handler(event: Request) -> Result<Response<Body>, Error> {
     let schema_cache = SCHEMA_CACHE
         .get_or_init(|| async {
             info!("Loading schema cache");
-            let cache = postrust_core::SchemaCache::load(pool, &config.db_schemas)
+            let mut cache = postrust_core::SchemaCache::load(pool, &config.db_schemas)
                 .await
-                .expect("Failed to load schema cache");
+                .expect("Failed to load schema cache")
+                .with_routine_filter(&config.routine_filter());
+            if config.db_case_insensitive_names {
+                cache = cache.with_case_insensitive_names();
+            }
             Arc::new(RwLock::new(cache))
         })
         .await;
@@ -70,10 +74,12 @@ async fn process_lambda_request(
 ) -> Result<Response<Body>, postrust_core::Error> {
     let jwt_config = postrust_auth::JwtConfig {
         secret: config.jwt_secret.clone(),
+        secret_previous: config.jwt_secret_previous.clone(),
         secret_is_base64: config.jwt_secret_is_base64,
         audience: config.jwt_aud.clone(),
         role_claim_key: config.jwt_role_claim_key.clone(),
         anon_role: config.db_anon_role.clone(),
+        role_allowlist: config.jwt_role_allowlist.clone(),
     };
 
     // Extract auth header
@@ -83,8 +89,12 @@ async fn process_lambda_request(
         .and_then(|v| v.to_str().ok());
 
     // Authenticate
-    let auth_result = postrust_auth::authenticate(auth_header, &jwt_config)
-        .map_err(|e| postrust_core::Error::InvalidJwt(e.to_string()))?;
+    let auth_result = postrust_auth::authenticate(auth_header, &jwt_config).map_err(|e| match e {
+        postrust_auth::JwtError::RoleNotAllowed(role) => {
+            postrust_core::Error::InsufficientPermissions(format!("role \"{}\" is not allowed", role))
+        }
+        other => postrust_core::Error::InvalidJwt(other.to_string()),
+    })?;
 
     debug!("Authenticated as role: {}", auth_result.role);
 
@@ -114,18 +124,26 @@ async fn process_lambda_request(
         config.default_schema(),
         &config.db_schemas,
     )?;
+    config.query_limits().check(&api_request.query_params)?;
+
+    // Enforce the configured page size
+    api_request.top_level_range = api_request
+        .top_level_range
+        .bounded(config.default_limit, config.db_max_rows);
 
     // Parse payload
     if !body_bytes.is_empty() {
         let payload = postrust_core::api_request::payload::parse_payload(
             body_bytes,
             &api_request.content_media_type,
+            api_request.query_params.columns.as_ref(),
         )?;
         api_request.payload = payload;
     }
 
     // Get schema cache
     let cache = schema_cache.read().await;
+    cache.resolve_names(&mut api_request)?;
 
     // Create execution plan
     let plan = postrust_core::create_action_plan(&api_request, &cache)?;
@@ -147,10 +165,13 @@ async fn process_lambda_request(
     let rows = sqlx::query(&sql)
         .fetch_all(pool)
         .await
-        .map_err(|e| postrust_core::Error::Internal(e.to_string()))?;
+        .map_err(|e| match e {
+            sqlx::Error::PoolTimedOut => postrust_core::Error::PoolExhausted,
+            other => postrust_core::Error::Internal(other.to_string()),
+        })?;
 
     // Convert to JSON
-    let json_rows: Vec<serde_json::Value> = rows
+    let mut json_rows: Vec<serde_json::Value> = rows
         .iter()
         .map(|row| {
             use sqlx::{Column, Row};
@@ -166,22 +187,29 @@ async fn process_lambda_request(
         })
         .collect();
 
+    let etag = postrust_core::plan::take_etag(&mut json_rows);
     let body = serde_json::to_string(&json_rows).unwrap_or_else(|_| "[]".to_string());
 
-    Ok(Response::builder()
+    let mut builder = Response::builder()
         .status(200)
-        .header("content-type", "application/json")
-        .body(Body::from(body))
-        .unwrap())
+        .header("content-type", "application/json");
+    if let Some(etag) = etag {
+        builder = builder.header("etag", format!("\"{}\"", etag));
+    }
+
+    Ok(builder.body(Body::from(body)).unwrap())
 }
 
 fn error_response(error: postrust_core::Error) -> Response<Body> {
     let status = error.status_code().as_u16();
     let body = serde_json::to_string(&error.to_json()).unwrap_or_else(|_| "{}".to_string());
 
-    Response::builder()
+    let mut builder = Response::builder()
         .status(status)
-        .header("content-type", "application/json")
-        .body(Body::from(body))
-        .unwrap()
+        .header("content-type", "application/json");
+    if let Some(seconds) = error.retry_after() {
+        builder = builder.header("retry-after", seconds);
+    }
+
+    builder.body(Body::from(body)).unwrap()
 }
@@ -1,6 +1,7 @@
 //! Response header building.
 
 use http::{HeaderMap, HeaderValue};
+use postrust_core::api_request::AppliedPreferences;
 use postrust_core::ApiRequest;
 use std::fmt;
 
@@ -15,6 +16,8 @@ pub struct ContentRange {
     pub total: Option<i64>,
     /// Unit name
     pub unit: String,
+    /// Whether the total is an estimate rather than an exact count
+    pub estimated: bool,
 }
 
 impl ContentRange {
@@ -25,11 +28,28 @@ impl ContentRange {
             end,
             total,
             unit: "items".to_string(),
+            estimated: false,
         }
     }
 
+    /// Mark the total as an estimate (`count=planned` / `count=estimated`).
+    pub fn estimated(mut self) -> Self {
+        self.estimated = true;
+        self
+    }
+
+    /// Range reported for a mutation: `*/affected`.
+    pub fn affected(rows: usize) -> Self {
+        Self::new(0, -1, Some(rows as i64))
+    }
+
     /// Create from offset, limit, and total.
+    ///
+    /// An empty page has its end before its start and is shown as `*/total`.
     pub fn from_pagination(offset: i64, limit: Option<i64>, count: i64, total: Option<i64>) -> Self {
+        if count == 0 {
+            return Self::new(offset, offset - 1, total);
+        }
         let end = match limit {
             Some(l) => (offset + l - 1).min(offset + count - 1).max(offset),
             None => offset + count - 1,
@@ -41,7 +61,17 @@ impl ContentRange {
 
 impl fmt::Display for ContentRange {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.end < self.start {
+            return match self.total {
+                Some(total) if self.estimated => write!(f, "{} */~{}", self.unit, total),
+                Some(total) => write!(f, "{} */{}", self.unit, total),
+                None => write!(f, "{} */*", self.unit),
+            };
+        }
         match self.total {
+            Some(total) if self.estimated => {
+                write!(f, "{} {}-{}/~{}", self.unit, self.start, self.end, total)
+            }
             Some(total) => write!(f, "{} {}-{}/{}", self.unit, self.start, self.end, total),
             None => write!(f, "{} {}-{}/*", self.unit, self.start, self.end),
         }
@@ -54,6 +84,7 @@ pub fn build_response_headers(
     content_type: &str,
     content_range: Option<&ContentRange>,
     location: Option<&str>,
+    applied: &AppliedPreferences,
 ) -> HeaderMap {
     let mut headers = HeaderMap::new();
 
@@ -78,7 +109,7 @@ pub fn build_response_headers(
 
     // Content-Profile
     if request.negotiated_by_profile {
-        if let Ok(v) = HeaderValue::from_str(&request.schema) {
+        if let Ok(v) = HeaderValue::from_str(request.response_schema()) {
             headers.insert(
                 http::header::HeaderName::from_static("content-profile"),
                 v,
@@ -87,7 +118,7 @@ pub fn build_response_headers(
     }
 
     // Preference-Applied
-    if let Some(applied) = postrust_core::api_request::preferences::preference_applied(&request.preferences) {
+    if let Some(applied) = postrust_core::api_request::preferences::preference_applied(applied) {
         if let Ok(v) = HeaderValue::from_str(&applied) {
             headers.insert(
                 http::header::HeaderName::from_static("preference-applied"),
@@ -99,6 +130,48 @@ pub fn build_response_headers(
     headers
 }
 
+/// `Link` header pointing at the next and previous pages of a read.
+///
+/// The links repeat `path` and `query` with `limit` and `offset` replaced.
+/// The page size is `limit`, or the size of this page when unlimited. `next`
+/// is left out on the last page; without a total, a short page is the last.
+pub fn pagination_links(path: &str, query: Option<&str>, range: &ContentRange, limit: Option<i64>) -> Option<String> {
+    let returned = (range.end - range.start + 1).max(0);
+    let size = limit.unwrap_or(returned);
+    if size <= 0 {
+        return None;
+    }
+
+    let kept: Vec<&str> = query
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or("");
+            !pair.is_empty() && key != "limit" && key != "offset"
+        })
+        .collect();
+    let link = |offset: i64, rel: &str| {
+        let mut pairs = kept.clone();
+        let page = format!("limit={}&offset={}", size, offset);
+        pairs.push(&page);
+        format!("<{}?{}>; rel=\"{}\"", path, pairs.join("&"), rel)
+    };
+
+    let next = range.start + size;
+    let has_next = match range.total {
+        Some(total) => next < total,
+        None => returned == size,
+    };
+    let mut links = Vec::new();
+    if has_next {
+        links.push(link(next, "next"));
+    }
+    if range.start > 0 {
+        links.push(link((range.start - size).max(0), "prev"));
+    }
+    (!links.is_empty()).then(|| links.join(", "))
+}
+
 /// Parse GUC headers from database response.
 pub fn parse_guc_headers(guc_headers: &str) -> Vec<(String, String)> {
     // Format: "header1: value1\nheader2: value2"
@@ -126,6 +199,16 @@ mod tests {
         assert_eq!(range.to_string(), "items 10-19/*");
     }
 
+    #[test]
+    fn test_content_range_display_estimated() {
+        let range = ContentRange::new(0, 9, Some(1000)).estimated();
+        assert_eq!(range.to_string(), "items 0-9/~1000");
+
+        // Nothing to mark without a total
+        let range = ContentRange::new(0, 9, None).estimated();
+        assert_eq!(range.to_string(), "items 0-9/*");
+    }
+
     #[test]
     fn test_content_range_from_pagination() {
         // First page of 10
@@ -137,6 +220,46 @@ mod tests {
         let range = ContentRange::from_pagination(90, Some(10), 5, Some(95));
         assert_eq!(range.start, 90);
         assert_eq!(range.end, 94);
+
+        // limit=0 only reports the total
+        let range = ContentRange::from_pagination(0, Some(0), 0, Some(42));
+        assert_eq!(range.to_string(), "items */42");
+    }
+
+    #[test]
+    fn test_content_range_affected_rows() {
+        assert_eq!(ContentRange::affected(3).to_string(), "items */3");
+        assert_eq!(ContentRange::affected(0).to_string(), "items */0");
+    }
+
+    #[test]
+    fn test_pagination_links_middle_page() {
+        let range = ContentRange::from_pagination(20, Some(10), 10, Some(100));
+        let links = pagination_links("/items", Some("select=id&limit=10&offset=20"), &range, Some(10));
+        assert_eq!(
+            links.unwrap(),
+            "</items?select=id&limit=10&offset=30>; rel=\"next\", \
+             </items?select=id&limit=10&offset=10>; rel=\"prev\""
+        );
+    }
+
+    #[test]
+    fn test_pagination_links_last_page() {
+        // The last page only links back
+        let range = ContentRange::from_pagination(90, Some(10), 5, Some(95));
+        let links = pagination_links("/items", Some("offset=90&limit=10"), &range, Some(10));
+        assert_eq!(links.unwrap(), "</items?limit=10&offset=80>; rel=\"prev\"");
+
+        // Without a total, a short page is the last one
+        let range = ContentRange::from_pagination(0, Some(10), 4, None);
+        assert_eq!(pagination_links("/items", None, &range, Some(10)), None);
+
+        // A full page may have more after it
+        let range = ContentRange::from_pagination(0, Some(10), 10, None);
+        assert_eq!(
+            pagination_links("/items", None, &range, Some(10)).unwrap(),
+            "</items?limit=10&offset=10>; rel=\"next\""
+        );
     }
 
     #[test]
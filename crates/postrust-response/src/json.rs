@@ -2,27 +2,71 @@
 
 use super::FormatError;
 use bytes::Bytes;
+use indexmap::IndexMap;
 
-/// Format rows as a JSON array.
-pub fn format_json_response(rows: &[serde_json::Value]) -> Result<Bytes, FormatError> {
-    let json = serde_json::to_vec(rows)?;
+/// Format rows as a JSON array, indented when `pretty` (`?pretty`).
+pub fn format_json_response(rows: &[serde_json::Value], pretty: bool) -> Result<Bytes, FormatError> {
+    to_json(rows, pretty)
+}
+
+/// Serialize compactly, or indented for reading in a terminal.
+pub(crate) fn to_json<T: serde::Serialize + ?Sized>(value: &T, pretty: bool) -> Result<Bytes, FormatError> {
+    let json = if pretty {
+        serde_json::to_vec_pretty(value)?
+    } else {
+        serde_json::to_vec(value)?
+    };
     Ok(Bytes::from(json))
 }
 
+/// Format rows as newline-delimited JSON, one compact object per line.
+pub fn format_ndjson_response(rows: &[serde_json::Value]) -> Result<Bytes, FormatError> {
+    let mut output = Vec::new();
+    for row in rows {
+        serde_json::to_writer(&mut output, row)?;
+        output.push(b'\n');
+    }
+    Ok(Bytes::from(output))
+}
+
 /// Format a single row as JSON object.
 pub fn format_json_object(row: &serde_json::Value) -> Result<Bytes, FormatError> {
     let json = serde_json::to_vec(row)?;
     Ok(Bytes::from(json))
 }
 
-/// Format rows with nulls stripped (for vnd.pgrst.array+json).
-pub fn format_json_strip_nulls(rows: &[serde_json::Value]) -> Result<Bytes, FormatError> {
+/// Format rows with null-valued keys stripped (`nulls=stripped`).
+pub fn format_json_strip_nulls(rows: &[serde_json::Value], pretty: bool) -> Result<Bytes, FormatError> {
     let stripped: Vec<serde_json::Value> = rows
         .iter()
         .map(|row| strip_nulls(row.clone()))
         .collect();
-    let json = serde_json::to_vec(&stripped)?;
-    Ok(Bytes::from(json))
+    to_json(&stripped, pretty)
+}
+
+/// Format rows as one object keyed by primary key, in row order.
+///
+/// Composite keys join their values with `,`: `{"1,a": {...}}`.
+pub fn format_keyed_json(rows: &[serde_json::Value], key: &[String], pretty: bool) -> Result<Bytes, FormatError> {
+    if key.is_empty() {
+        return Err(FormatError::NotKeyable("resource has no primary key".into()));
+    }
+    let mut keyed = IndexMap::with_capacity(rows.len());
+    for row in rows {
+        let parts = key
+            .iter()
+            .map(|col| match row.get(col) {
+                Some(serde_json::Value::String(s)) => Ok(s.clone()),
+                Some(serde_json::Value::Null) | None => Err(FormatError::NotKeyable(format!(
+                    "primary key column '{}' is not selected",
+                    col
+                ))),
+                Some(other) => Ok(other.to_string()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        keyed.insert(parts.join(","), row);
+    }
+    to_json(&keyed, pretty)
 }
 
 /// Recursively strip null values from a JSON value.
@@ -59,13 +103,33 @@ mod tests {
             json!({"id": 2, "name": "Bob"}),
         ];
 
-        let result = format_json_response(&rows).unwrap();
+        let result = format_json_response(&rows, false).unwrap();
         let parsed: Vec<serde_json::Value> = serde_json::from_slice(&result).unwrap();
 
         assert_eq!(parsed.len(), 2);
         assert_eq!(parsed[0]["name"], "Alice");
     }
 
+    #[test]
+    fn test_format_ndjson_response() {
+        let rows = vec![
+            json!({"id": 1, "name": "Alice"}),
+            json!({"id": 2, "tags": ["a", "b"]}),
+        ];
+
+        let result = format_ndjson_response(&rows).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&result).unwrap(),
+            "{\"id\":1,\"name\":\"Alice\"}\n{\"id\":2,\"tags\":[\"a\",\"b\"]}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_ndjson_empty() {
+        let result = format_ndjson_response(&[]).unwrap();
+        assert!(result.is_empty());
+    }
+
     #[test]
     fn test_format_json_object() {
         let row = json!({"id": 1, "name": "Alice"});
@@ -96,10 +160,55 @@ mod tests {
         assert!(stripped["nested"].get("b").is_none());
     }
 
+    #[test]
+    fn test_keyed_output_matches_array_output() {
+        let rows = vec![json!({"id": 2, "name": "Bob"}), json!({"id": 1, "name": "Alice"})];
+
+        let array = format_json_response(&rows, false).unwrap();
+        assert_eq!(&array[..], br#"[{"id":2,"name":"Bob"},{"id":1,"name":"Alice"}]"#);
+
+        let keyed = format_keyed_json(&rows, &["id".into()], false).unwrap();
+        assert_eq!(&keyed[..], br#"{"2":{"id":2,"name":"Bob"},"1":{"id":1,"name":"Alice"}}"#);
+
+        let parsed: serde_json::Value = serde_json::from_slice(&keyed).unwrap();
+        for row in &rows {
+            assert_eq!(&parsed[row["id"].to_string()], row);
+        }
+    }
+
+    #[test]
+    fn test_keyed_output_with_composite_key() {
+        let rows = vec![json!({"order_id": 7, "sku": "a-1", "qty": 2})];
+        let key = vec!["order_id".to_string(), "sku".to_string()];
+
+        let keyed = format_keyed_json(&rows, &key, false).unwrap();
+        assert_eq!(&keyed[..], br#"{"7,a-1":{"order_id":7,"qty":2,"sku":"a-1"}}"#);
+
+        // Both key columns must be selected
+        let err = format_keyed_json(&[json!({"order_id": 7})], &key, false).unwrap_err();
+        assert!(matches!(err, FormatError::NotKeyable(_)));
+        let err = format_keyed_json(&rows, &[], false).unwrap_err();
+        assert!(matches!(err, FormatError::NotKeyable(_)));
+    }
+
     #[test]
     fn test_format_empty_array() {
         let rows: Vec<serde_json::Value> = vec![];
-        let result = format_json_response(&rows).unwrap();
+        let result = format_json_response(&rows, false).unwrap();
         assert_eq!(&result[..], b"[]");
     }
+
+    #[test]
+    fn test_format_json_pretty() {
+        let rows = vec![json!({"id": 1, "tags": ["a"]})];
+
+        let compact = format_json_response(&rows, false).unwrap();
+        assert_eq!(&compact[..], br#"[{"id":1,"tags":["a"]}]"#);
+
+        let pretty = format_json_response(&rows, true).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&pretty).unwrap(),
+            "[\n  {\n    \"id\": 1,\n    \"tags\": [\n      \"a\"\n    ]\n  }\n]"
+        );
+    }
 }
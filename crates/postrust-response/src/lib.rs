@@ -4,12 +4,15 @@
 
 mod json;
 mod headers;
+mod xml;
 
-pub use json::format_json_response;
-pub use headers::{build_response_headers, ContentRange};
+pub use json::{format_json_response, format_json_strip_nulls, format_keyed_json, format_ndjson_response};
+pub use headers::{build_response_headers, pagination_links, ContentRange};
+pub use xml::{format_xml_response, XmlOptions};
 
 use http::{HeaderMap, HeaderValue, StatusCode};
-use postrust_core::{ActionPlan, ApiRequest, MediaType, PreferRepresentation};
+use postrust_core::api_request::{AppliedPreferences, DbAction};
+use postrust_core::{Action, ActionPlan, ApiRequest, MediaType, PreferRepresentation};
 use serde::Serialize;
 
 /// A formatted HTTP response.
@@ -41,7 +44,7 @@ impl Response {
         Ok(response)
     }
 
-    /// Create an empty response.
+    /// Create an empty response, without a Content-Type.
     pub fn empty(status: StatusCode) -> Self {
         Self::new(status, bytes::Bytes::new())
     }
@@ -70,6 +73,26 @@ impl Response {
     pub fn set_location(&mut self, location: &str) {
         self.set_header("location", location);
     }
+
+    /// Set Content-Length from the buffered body.
+    ///
+    /// 204 and 1xx responses carry neither a body nor a Content-Length.
+    pub fn set_content_length(&mut self) {
+        if self.status == StatusCode::NO_CONTENT || self.status.is_informational() {
+            self.headers.remove(http::header::CONTENT_LENGTH);
+        } else {
+            self.headers
+                .insert(http::header::CONTENT_LENGTH, HeaderValue::from(self.body.len()));
+        }
+    }
+
+    /// Drop the body and Content-Type of a 204 response.
+    fn strip_no_content(&mut self) {
+        if self.status == StatusCode::NO_CONTENT {
+            self.body = bytes::Bytes::new();
+            self.headers.remove(http::header::CONTENT_TYPE);
+        }
+    }
 }
 
 /// Format a query result as a response.
@@ -83,38 +106,90 @@ pub fn format_response(
         .cloned()
         .unwrap_or(MediaType::ApplicationJson);
 
-    match &media_type {
+    let pretty = request.query_params.pretty;
+    let mut response = match &media_type {
+        // The API root serves the OpenAPI document itself, not rows
+        _ if matches!(request.action, Action::Db(DbAction::SchemaRead { .. })) => {
+            let spec = result.rows.first().cloned().unwrap_or(serde_json::Value::Null);
+            let mut response = Response::new(result.status, json::to_json(&spec, pretty)?);
+            response.set_content_type("application/openapi+json; charset=utf-8");
+            add_common_headers(&mut response, request, result);
+            response
+        }
         MediaType::ApplicationJson => {
-            let body = format_json_response(&result.rows)?;
+            let body = format_json_response(&result.rows, pretty)?;
             let mut response = Response::new(result.status, body);
             response.set_content_type("application/json; charset=utf-8");
             add_common_headers(&mut response, request, result);
-            Ok(response)
+            response
         }
-        MediaType::TextCsv => {
-            // CSV formatting would go here
-            let body = format_csv_response(&result.rows)?;
+        MediaType::TextCsv { null } => {
+            let body = format_csv_response(&result.rows, null.as_deref().unwrap_or(""))?;
             let mut response = Response::new(result.status, body);
             response.set_content_type("text/csv; charset=utf-8");
             add_common_headers(&mut response, request, result);
-            Ok(response)
+            response
         }
-        MediaType::SingularJson { nullable } => {
-            let body = format_singular_json(&result.rows, *nullable)?;
+        MediaType::TextXml => {
+            let body = format_xml_response(&result.rows, &XmlOptions::default())?;
+            let mut response = Response::new(result.status, body);
+            response.set_content_type("text/xml; charset=utf-8");
+            add_common_headers(&mut response, request, result);
+            response
+        }
+        MediaType::NdJson => {
+            let body = format_ndjson_response(&result.rows)?;
+            let mut response = Response::new(result.status, body);
+            response.set_content_type("application/x-ndjson; charset=utf-8");
+            add_common_headers(&mut response, request, result);
+            response
+        }
+        MediaType::ArrayJsonStrip => {
+            let body = format_json_strip_nulls(&result.rows, pretty)?;
+            let mut response = Response::new(result.status, body);
+            response.set_content_type("application/vnd.pgrst.array+json; nulls=stripped; charset=utf-8");
+            add_common_headers(&mut response, request, result);
+            response
+        }
+        MediaType::KeyedJson => {
+            let body = format_keyed_json(&result.rows, &result.primary_key, pretty)?;
+            let mut response = Response::new(result.status, body);
+            response.set_content_type("application/json; keyed=true; charset=utf-8");
+            add_common_headers(&mut response, request, result);
+            response
+        }
+        MediaType::SingularJson { nullable, scalar } => {
+            let body = format_singular_json(&result.rows, *nullable, *scalar, pretty)?;
             let mut response = Response::new(result.status, body);
             response.set_content_type("application/vnd.pgrst.object+json; charset=utf-8");
             add_common_headers(&mut response, request, result);
-            Ok(response)
+            response
         }
         _ => {
             // Default to JSON
-            let body = format_json_response(&result.rows)?;
+            let body = format_json_response(&result.rows, pretty)?;
             let mut response = Response::new(result.status, body);
             response.set_content_type("application/json; charset=utf-8");
             add_common_headers(&mut response, request, result);
-            Ok(response)
+            response
         }
+    };
+
+    // return=headers-only: the mutation ran, but only its headers are sent
+    if request.preferences.representation == PreferRepresentation::HeadersOnly
+        && matches!(request.action, Action::Db(DbAction::RelationMut { .. }))
+    {
+        response.status = StatusCode::NO_CONTENT;
     }
+    response.strip_no_content();
+
+    // HEAD: same status and headers as GET, including the length, no body
+    response.set_content_length();
+    if request.action.is_headers_only() {
+        response.body = bytes::Bytes::new();
+    }
+
+    Ok(response)
 }
 
 /// Add common response headers.
@@ -129,29 +204,50 @@ fn add_common_headers(response: &mut Response, request: &ApiRequest, result: &Qu
         response.set_location(location);
     }
 
+    // ETag of a single row, for If-Match
+    if let Some(etag) = &result.etag {
+        response.set_header("etag", &format!("\"{}\"", etag));
+    }
+
     // Preference-Applied
-    if let Some(applied) = postrust_core::api_request::preferences::preference_applied(&request.preferences) {
+    if let Some(applied) = postrust_core::api_request::preferences::preference_applied(&result.applied_preferences) {
         response.set_header("preference-applied", &applied);
     }
 
+    // Warning for ignored preferences (handling=strict)
+    if let Some(warning) = postrust_core::api_request::preferences::invalid_preferences_warning(&request.preferences) {
+        response.set_header("warning", &warning);
+    }
+
     // Content-Profile
     if request.negotiated_by_profile {
-        response.set_header("content-profile", &request.schema);
+        response.set_header("content-profile", request.response_schema());
     }
 }
 
 /// Format singular JSON (single object or null).
-fn format_singular_json(rows: &[serde_json::Value], nullable: bool) -> Result<bytes::Bytes, FormatError> {
-    match rows.len() {
-        0 if nullable => Ok(bytes::Bytes::from_static(b"null")),
-        0 => Err(FormatError::NotFound),
-        1 => Ok(bytes::Bytes::from(serde_json::to_vec(&rows[0])?)),
+///
+/// With `scalar`, a row with exactly one field is sent as that field's
+/// value, e.g. `42` rather than `{"count":42}`.
+fn format_singular_json(
+    rows: &[serde_json::Value],
+    nullable: bool,
+    scalar: bool,
+    pretty: bool,
+) -> Result<bytes::Bytes, FormatError> {
+    match rows {
+        [] if nullable => Ok(bytes::Bytes::from_static(b"null")),
+        [] => Err(FormatError::NotFound),
+        [serde_json::Value::Object(row)] if scalar && row.len() == 1 => {
+            json::to_json(row.values().next().unwrap(), pretty)
+        }
+        [row] => json::to_json(row, pretty),
         _ => Err(FormatError::MultipleRows),
     }
 }
 
-/// Format CSV response.
-fn format_csv_response(rows: &[serde_json::Value]) -> Result<bytes::Bytes, FormatError> {
+/// Format CSV response, writing `null` for NULL fields.
+fn format_csv_response(rows: &[serde_json::Value], null: &str) -> Result<bytes::Bytes, FormatError> {
     if rows.is_empty() {
         return Ok(bytes::Bytes::new());
     }
@@ -173,7 +269,7 @@ fn format_csv_response(rows: &[serde_json::Value]) -> Result<bytes::Bytes, Forma
                         .map(|h| {
                             row_map
                                 .get(*h)
-                                .map(|v| csv_escape(v))
+                                .map(|v| csv_escape(v, null))
                                 .unwrap_or_default()
                         })
                         .collect();
@@ -188,16 +284,19 @@ fn format_csv_response(rows: &[serde_json::Value]) -> Result<bytes::Bytes, Forma
 }
 
 /// Escape a value for CSV.
-fn csv_escape(value: &serde_json::Value) -> String {
+///
+/// A string equal to a non-empty `null` sentinel is quoted so it still reads
+/// back as text.
+fn csv_escape(value: &serde_json::Value, null: &str) -> String {
     match value {
         serde_json::Value::String(s) => {
-            if s.contains(',') || s.contains('"') || s.contains('\n') {
+            if s.contains(',') || s.contains('"') || s.contains('\n') || (!null.is_empty() && s == null) {
                 format!("\"{}\"", s.replace('"', "\"\""))
             } else {
                 s.clone()
             }
         }
-        serde_json::Value::Null => String::new(),
+        serde_json::Value::Null => null.to_string(),
         other => other.to_string(),
     }
 }
@@ -215,10 +314,16 @@ pub struct QueryResult {
     pub content_range: Option<ContentRange>,
     /// Location header (for POST)
     pub location: Option<String>,
+    /// ETag of the single row returned
+    pub etag: Option<String>,
     /// Custom headers from GUC
     pub guc_headers: Option<String>,
     /// Custom status from GUC
     pub guc_status: Option<String>,
+    /// Preferences honored during execution
+    pub applied_preferences: AppliedPreferences,
+    /// Primary key columns of the resource (for `keyed=true`)
+    pub primary_key: Vec<String>,
 }
 
 /// Response formatting error.
@@ -232,6 +337,9 @@ pub enum FormatError {
 
     #[error("Multiple rows returned for singular response")]
     MultipleRows,
+
+    #[error("Rows cannot be keyed: {0}")]
+    NotKeyable(String),
 }
 
 impl FormatError {
@@ -239,7 +347,249 @@ impl FormatError {
         match self {
             Self::Json(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::NotFound => StatusCode::NOT_FOUND,
-            Self::MultipleRows => StatusCode::NOT_ACCEPTABLE,
+            Self::MultipleRows | Self::NotKeyable(_) => StatusCode::NOT_ACCEPTABLE,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_format_response_ndjson() {
+        let request = ApiRequest {
+            accept_media_types: vec![MediaType::NdJson],
+            ..Default::default()
+        };
+        let result = QueryResult {
+            status: StatusCode::OK,
+            rows: vec![json!({"id": 1}), json!({"id": 2})],
+            ..Default::default()
+        };
+
+        let response = format_response(&request, &result).unwrap();
+        assert_eq!(
+            response.headers.get("content-type").unwrap(),
+            "application/x-ndjson; charset=utf-8"
+        );
+        assert_eq!(&response.body[..], b"{\"id\":1}\n{\"id\":2}\n");
+    }
+
+    #[test]
+    fn test_format_response_strips_nulls() {
+        let result = QueryResult {
+            status: StatusCode::OK,
+            rows: vec![json!({"id": 1, "name": null}), json!({"id": 2, "name": "b"})],
+            ..Default::default()
+        };
+
+        let request = ApiRequest {
+            accept_media_types: vec![MediaType::ArrayJsonStrip],
+            ..Default::default()
+        };
+        let response = format_response(&request, &result).unwrap();
+        assert_eq!(&response.body[..], br#"[{"id":1},{"id":2,"name":"b"}]"#);
+        assert_eq!(
+            response.headers.get("content-type").unwrap(),
+            "application/vnd.pgrst.array+json; nulls=stripped; charset=utf-8"
+        );
+
+        let request = ApiRequest {
+            accept_media_types: vec![MediaType::ApplicationJson],
+            ..Default::default()
+        };
+        let response = format_response(&request, &result).unwrap();
+        assert_eq!(&response.body[..], br#"[{"id":1,"name":null},{"id":2,"name":"b"}]"#);
+    }
+
+    #[test]
+    fn test_format_response_pretty_keeps_content_type() {
+        let mut request = ApiRequest {
+            accept_media_types: vec![MediaType::SingularJson { nullable: false, scalar: false }],
+            ..Default::default()
+        };
+        request.query_params.pretty = true;
+        let result = QueryResult {
+            status: StatusCode::OK,
+            rows: vec![json!({"id": 1})],
+            ..Default::default()
+        };
+
+        let response = format_response(&request, &result).unwrap();
+        assert_eq!(&response.body[..], b"{\n  \"id\": 1\n}");
+        assert_eq!(
+            response.headers.get("content-type").unwrap(),
+            "application/vnd.pgrst.object+json; charset=utf-8"
+        );
+
+        request.query_params.pretty = false;
+        let response = format_response(&request, &result).unwrap();
+        assert_eq!(&response.body[..], br#"{"id":1}"#);
+    }
+
+    #[test]
+    fn test_format_singular_scalar_unwraps_single_field() {
+        let request = ApiRequest {
+            accept_media_types: vec![MediaType::SingularJson { nullable: false, scalar: true }],
+            ..Default::default()
+        };
+        let result = |rows| QueryResult {
+            status: StatusCode::OK,
+            rows,
+            ..Default::default()
+        };
+
+        let response = format_response(&request, &result(vec![json!({"name": "alice"})])).unwrap();
+        assert_eq!(&response.body[..], br#""alice""#);
+        let response = format_response(&request, &result(vec![json!({"count": 42})])).unwrap();
+        assert_eq!(&response.body[..], b"42");
+
+        // Several fields stay an object
+        let response = format_response(&request, &result(vec![json!({"id": 1, "name": "a"})])).unwrap();
+        assert_eq!(&response.body[..], br#"{"id":1,"name":"a"}"#);
+
+        // The singular checks still apply
+        assert!(matches!(format_response(&request, &result(vec![])), Err(FormatError::NotFound)));
+    }
+
+    #[test]
+    fn test_format_response_head_on_read_routine() {
+        use postrust_core::api_request::{Action, DbAction, InvokeMethod, QualifiedIdentifier};
+
+        let request = ApiRequest {
+            action: Action::Db(DbAction::Routine {
+                qi: QualifiedIdentifier::new("public", "get_users"),
+                invoke_method: InvokeMethod::InvRead { headers_only: true },
+            }),
+            accept_media_types: vec![MediaType::ApplicationJson],
+            ..Default::default()
+        };
+        let result = QueryResult {
+            status: StatusCode::OK,
+            rows: vec![json!({"id": 1})],
+            ..Default::default()
+        };
+
+        let response = format_response(&request, &result).unwrap();
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.headers.get("content-type").unwrap(),
+            "application/json; charset=utf-8"
+        );
+        assert!(response.body.is_empty());
+        assert_eq!(response.headers.get("content-length").unwrap(), "10");
+    }
+
+    #[test]
+    fn test_format_response_limit_zero_reports_total() {
+        let request = ApiRequest {
+            accept_media_types: vec![MediaType::ApplicationJson],
+            ..Default::default()
+        };
+        let result = QueryResult {
+            status: StatusCode::OK,
+            rows: vec![],
+            total_count: Some(42),
+            content_range: Some(ContentRange::from_pagination(0, Some(0), 0, Some(42))),
+            ..Default::default()
+        };
+
+        let response = format_response(&request, &result).unwrap();
+        assert_eq!(&response.body[..], b"[]");
+        assert_eq!(response.headers.get("content-range").unwrap(), "items */42");
+    }
+
+    #[test]
+    fn test_format_response_headers_only_insert() {
+        use postrust_core::api_request::{Mutation, QualifiedIdentifier};
+
+        let mut request = ApiRequest {
+            action: Action::Db(DbAction::RelationMut {
+                qi: QualifiedIdentifier::new("public", "users"),
+                mutation: Mutation::Create,
+            }),
+            accept_media_types: vec![MediaType::ApplicationJson],
+            ..Default::default()
+        };
+        request.preferences.representation = PreferRepresentation::HeadersOnly;
+        let result = QueryResult {
+            status: StatusCode::CREATED,
+            rows: vec![json!({"id": 7})],
+            location: Some("/users?id=eq.7".into()),
+            ..Default::default()
+        };
+
+        let response = format_response(&request, &result).unwrap();
+        assert_eq!(response.status, StatusCode::NO_CONTENT);
+        assert_eq!(response.headers.get("location").unwrap(), "/users?id=eq.7");
+        assert!(response.headers.get("content-type").is_none());
+        assert!(response.headers.get("content-length").is_none());
+        assert!(response.body.is_empty());
+    }
+
+    #[test]
+    fn test_format_response_content_length() {
+        let request = ApiRequest::default();
+        let result = QueryResult {
+            status: StatusCode::OK,
+            rows: vec![json!({"id": 1})],
+            ..Default::default()
+        };
+
+        let response = format_response(&request, &result).unwrap();
+        assert_eq!(&response.body[..], b"[{\"id\":1}]");
+        assert_eq!(response.headers.get("content-length").unwrap(), "10");
+
+        // A 204 from the executor drops the formatted body
+        let result = QueryResult {
+            status: StatusCode::NO_CONTENT,
+            ..result
+        };
+        let response = format_response(&request, &result).unwrap();
+        assert!(response.body.is_empty());
+        assert!(response.headers.get("content-type").is_none());
+        assert!(response.headers.get("content-length").is_none());
+    }
+
+    #[test]
+    fn test_empty_response_has_no_content_type() {
+        let mut response = Response::empty(StatusCode::NO_CONTENT);
+        response.set_content_length();
+        assert!(response.headers.is_empty());
+    }
+
+    #[test]
+    fn test_format_response_warns_on_invalid_preferences() {
+        let mut request = ApiRequest::default();
+        request.preferences.handling = Some(postrust_core::api_request::PreferHandling::Strict);
+        request.preferences.invalid = vec!["bogus".into()];
+
+        let response = format_response(&request, &QueryResult::default()).unwrap();
+        assert_eq!(
+            response.headers.get("warning").unwrap(),
+            "199 postrust \"Invalid preferences ignored: bogus\""
+        );
+
+        request.preferences.handling = None;
+        let response = format_response(&request, &QueryResult::default()).unwrap();
+        assert!(response.headers.get("warning").is_none());
+    }
+
+    #[test]
+    fn test_format_csv_null_sentinel() {
+        let rows = vec![
+            json!({"id": 1, "name": null}),
+            json!({"id": 2, "name": ""}),
+            json!({"id": 3, "name": "\\N"}),
+        ];
+
+        // Default: NULL and empty string are both empty fields
+        let body = format_csv_response(&rows, "").unwrap();
+        assert_eq!(&body[..], b"id,name\n1,\n2,\n3,\\N\n");
+
+        let body = format_csv_response(&rows, "\\N").unwrap();
+        assert_eq!(&body[..], b"id,name\n1,\\N\n2,\n3,\"\\N\"\n");
+    }
+}
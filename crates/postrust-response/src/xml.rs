@@ -0,0 +1,169 @@
+//! XML response formatting.
+
+use super::FormatError;
+use bytes::Bytes;
+
+/// Element names used when serializing rows as XML.
+#[derive(Clone, Debug)]
+pub struct XmlOptions {
+    /// Root element wrapping all rows
+    pub root_element: String,
+    /// Element wrapping each row
+    pub row_element: String,
+}
+
+impl Default for XmlOptions {
+    fn default() -> Self {
+        Self {
+            root_element: "rows".to_string(),
+            row_element: "row".to_string(),
+        }
+    }
+}
+
+/// Format rows as XML.
+///
+/// Each row becomes a `row_element` with one child element per column.
+/// NULL columns are written as `xsi:nil` elements; nested JSON values are
+/// written as their JSON text.
+pub fn format_xml_response(
+    rows: &[serde_json::Value],
+    options: &XmlOptions,
+) -> Result<Bytes, FormatError> {
+    let root = element_name(&options.root_element);
+    let row_name = element_name(&options.row_element);
+
+    let mut out = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    out.push_str(&format!(
+        r#"<{} xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">"#,
+        root
+    ));
+
+    for row in rows {
+        out.push_str(&format!("<{}>", row_name));
+        match row {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map {
+                    write_element(&mut out, &element_name(key), value);
+                }
+            }
+            other => out.push_str(&escape_xml(&value_text(other))),
+        }
+        out.push_str(&format!("</{}>", row_name));
+    }
+
+    out.push_str(&format!("</{}>", root));
+    Ok(Bytes::from(out))
+}
+
+/// Write a single column element.
+fn write_element(out: &mut String, name: &str, value: &serde_json::Value) {
+    if value.is_null() {
+        out.push_str(&format!(r#"<{} xsi:nil="true"/>"#, name));
+    } else {
+        out.push_str(&format!("<{}>{}</{}>", name, escape_xml(&value_text(value)), name));
+    }
+}
+
+/// Text content for a JSON value.
+fn value_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Escape XML special characters.
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Turn a column name into a valid XML element name.
+fn element_name(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let starts_ok = out
+        .chars()
+        .next()
+        .map(|c| c.is_alphabetic() || c == '_')
+        .unwrap_or(false);
+    if !starts_ok || out.to_ascii_lowercase().starts_with("xml") {
+        out.insert(0, '_');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_format_xml_two_rows() {
+        let rows = vec![
+            json!({"email": null, "id": 1, "name": "Alice"}),
+            json!({"email": "bob@example.com", "id": 2, "name": "Bob"}),
+        ];
+
+        let result = format_xml_response(&rows, &XmlOptions::default()).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&result).unwrap(),
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                r#"<rows xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">"#,
+                r#"<row><email xsi:nil="true"/><id>1</id><name>Alice</name></row>"#,
+                r#"<row><email>bob@example.com</email><id>2</id><name>Bob</name></row>"#,
+                "</rows>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_format_xml_escaping() {
+        let rows = vec![json!({"note": "a < b & \"c\" > 'd'"})];
+
+        let result = format_xml_response(&rows, &XmlOptions::default()).unwrap();
+        let xml = std::str::from_utf8(&result).unwrap();
+        assert!(xml.contains("<note>a &lt; b &amp; &quot;c&quot; &gt; &apos;d&apos;</note>"));
+    }
+
+    #[test]
+    fn test_format_xml_custom_elements() {
+        let options = XmlOptions {
+            root_element: "users".into(),
+            row_element: "user".into(),
+        };
+
+        let result = format_xml_response(&[json!({"first name": "Al"})], &options).unwrap();
+        let xml = std::str::from_utf8(&result).unwrap();
+        assert!(xml.contains("<users "));
+        assert!(xml.contains("<user><first_name>Al</first_name></user></users>"));
+    }
+
+    #[test]
+    fn test_element_name() {
+        assert_eq!(element_name("name"), "name");
+        assert_eq!(element_name("1st"), "_1st");
+        assert_eq!(element_name("xmlthing"), "_xmlthing");
+    }
+}
@@ -288,7 +288,7 @@ async fn openapi_spec() {}
 
 /// Handler for the admin dashboard.
 async fn dashboard_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    let schema_cache = state.schema_cache.read().await;
+    let schema_cache = state.schema_cache();
     let table_count = schema_cache.tables.len();
     let routine_count = schema_cache.routines.len();
     let relationship_count = schema_cache.relationships.len();
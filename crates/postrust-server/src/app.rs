@@ -1,19 +1,50 @@
 //! Request handling.
 
+use crate::idempotency::{self, Lookup};
 use crate::state::AppState;
 use axum::{
     body::Body,
-    extract::{Request, State},
+    extract::{ConnectInfo, OriginalUri, Request, State},
     http::StatusCode,
     response::{IntoResponse, Response},
+    routing::any,
+    Router,
 };
 use bytes::Bytes;
 use postrust_auth::authenticate;
+use postrust_core::api_request::{ByteaEncoding, PreferCount, PreferTransaction};
 use postrust_core::{create_action_plan, parse_request, ActionPlan, ApiRequest};
-use postrust_response::{format_response, QueryResult, Response as PgrstResponse};
+use postrust_response::{format_response, ContentRange, FormatError, QueryResult, Response as PgrstResponse};
 use sqlx::Row;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error};
+
+/// Router serving the REST API under `/api`.
+///
+/// `nest` leaves `/api/` unrouted, so the root with a trailing slash gets
+/// its own route.
+pub fn api_router() -> Router<Arc<AppState>> {
+    let api: Router<Arc<AppState>> = Router::new()
+        .route("/", any(handle_request))
+        .route("/{*path}", any(handle_request));
+    Router::new()
+        .nest("/api", api)
+        .route("/api/", any(handle_api_root))
+}
+
+/// Serve `/api/` as the API root.
+async fn handle_api_root(state: State<Arc<AppState>>, mut request: Request) -> Response {
+    let root = match request.uri().query() {
+        Some(query) => format!("/?{}", query),
+        None => "/".to_string(),
+    };
+    if let Ok(uri) = root.parse() {
+        *request.uri_mut() = uri;
+    }
+    handle_request(state, request).await
+}
 
 /// Main request handler.
 pub async fn handle_request(
@@ -25,9 +56,9 @@ pub async fn handle_request(
 
     debug!("{} {}", method, path);
 
-    match process_request(state, request).await {
+    match process_request(state.clone(), request).await {
         Ok(response) => response.into_response(),
-        Err(e) => error_response(e).into_response(),
+        Err(e) => error_response(e, &state.config().db_sqlstate_status).into_response(),
     }
 }
 
@@ -36,15 +67,26 @@ async fn process_request(
     state: Arc<AppState>,
     request: Request,
 ) -> Result<Response, postrust_core::Error> {
+    let config = state.config();
+
     // Extract auth header
     let auth_header = request
         .headers()
         .get("authorization")
         .and_then(|v| v.to_str().ok());
 
-    // Authenticate
-    let auth_result = authenticate(auth_header, &state.jwt_config)
-        .map_err(|e| postrust_core::Error::InvalidJwt(e.to_string()))?;
+    // Authenticate; a trusted proxy may forward the role instead of a JWT
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    let auth_result = match forwarded_role(request.headers(), peer, &config) {
+        Some(role) => postrust_auth::authenticate_forwarded(role, &state.jwt_config),
+        None => authenticate(auth_header, &state.jwt_config),
+    }
+    .map_err(auth_error)?;
+    let origin = forwarded_origin(request.headers(), peer, &config);
+    let mount = mount_prefix(&request);
 
     debug!("Authenticated as role: {}", auth_result.role);
 
@@ -54,6 +96,30 @@ async fn process_request(
         .await
         .map_err(|e| postrust_core::Error::InvalidBody(e.to_string()))?;
 
+    // Replay a retried POST carrying a known Idempotency-Key from the same
+    // caller, holding the key while the first attempt runs
+    let idempotency_key = parts
+        .headers
+        .get("idempotency-key")
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| parts.method == http::Method::POST);
+    let reservation = match (&state.idempotency, idempotency_key) {
+        (Some(store), Some(key)) => {
+            let route = parts.uri.to_string();
+            match store.lookup(key, &route, &idempotency::caller(&auth_result), &body_bytes) {
+                Lookup::Miss(reservation) => Some(reservation),
+                Lookup::Replay(response) => return Ok(build_response(response)),
+                Lookup::Conflict => {
+                    return Err(postrust_core::Error::IdempotencyKeyReused(key.to_string()))
+                }
+                Lookup::InFlight => {
+                    return Err(postrust_core::Error::IdempotencyKeyInFlight(key.to_string()))
+                }
+            }
+        }
+        _ => None,
+    };
+
     // Build HTTP request for parsing
     let mut builder = http::Request::builder()
         .method(parts.method.clone())
@@ -70,31 +136,74 @@ async fn process_request(
     // Parse API request
     let mut api_request = parse_request(
         &http_request,
-        state.default_schema(),
-        state.schemas(),
+        config.default_schema(),
+        &config.db_schemas,
     )?;
+    config.query_limits().check(&api_request.query_params)?;
+    api_request.search_path = search_path_override(&parts.headers, peer, &config)?;
+
+    // Enforce the configured page size
+    api_request.top_level_range = api_request
+        .top_level_range
+        .bounded(config.default_limit, config.db_max_rows);
 
     // Parse payload
     if !body_bytes.is_empty() {
         let payload = postrust_core::api_request::payload::parse_payload(
-            body_bytes,
+            body_bytes.clone(),
             &api_request.content_media_type,
+            api_request.query_params.columns.as_ref(),
         )?;
         api_request.payload = payload;
     }
 
     // Get schema cache
-    let schema_cache = state.schema_cache().await;
+    let schema_cache = state.schema_cache();
+    schema_cache.resolve_names(&mut api_request)?;
 
     // Create execution plan
     let plan = create_action_plan(&api_request, &schema_cache)?;
+    let primary_key = keyed_primary_key(&api_request, &schema_cache);
+    drop(schema_cache);
 
-    // Execute plan
-    let result = execute_plan(&state, &api_request, &plan, &auth_result).await?;
+    // Execute plan and format response
+    let execute = || async {
+        let mut result = execute_plan(&state, &api_request, &plan, &auth_result).await?;
+        result.primary_key = primary_key.clone();
+        result.location = result.location.map(|location| public_url(origin.as_deref(), &mount, &location));
 
-    // Format response
-    let response = format_response(&api_request, &result)
-        .map_err(|e| postrust_core::Error::Internal(e.to_string()))?;
+        let mut response = format_response(&api_request, &result).map_err(|e| match e {
+            FormatError::NotKeyable(message) => postrust_core::Error::InvalidMediaType(message),
+            e => postrust_core::Error::Internal(e.to_string()),
+        })?;
+        add_debug_headers(&mut response, &api_request, config.server_debug_headers);
+        if config.server_pagination_links {
+            let path = public_url(origin.as_deref(), &mount, parts.uri.path());
+            add_pagination_links(&mut response, &api_request, &path, parts.uri.query(), &result);
+        }
+        Ok::<_, postrust_core::Error>(response)
+    };
+    // Identical concurrent reads share one execution
+    let response = match &state.coalescer {
+        Some(coalescer) if is_coalescable(&parts.method, &api_request) => {
+            let key = coalesce_key(
+                &parts,
+                &api_request,
+                &auth_result,
+                origin.as_deref(),
+                config.db_request_headers.as_deref(),
+            );
+            coalescer.run(key, execute).await?
+        }
+        _ => execute().await?,
+    };
+
+    // A failed attempt drops its reservation so the key can be retried
+    if let Some(reservation) = reservation {
+        if response.status.is_success() {
+            reservation.complete(&response);
+        }
+    }
 
     Ok(build_response(response))
 }
@@ -106,14 +215,24 @@ async fn execute_plan(
     plan: &ActionPlan,
     auth: &postrust_auth::AuthResult,
 ) -> Result<QueryResult, postrust_core::Error> {
+    let config = state.config();
     match plan {
         ActionPlan::Db(db_plan) => {
             // Build SQL
-            let query = postrust_core::query::build_query(
+            let mut query = postrust_core::query::build_query(
                 &ActionPlan::Db(db_plan.clone()),
                 Some(&auth.role),
             )?;
 
+            // A negative offset counts back from an exact total
+            let from_end = request.top_level_range.is_from_end();
+            if from_end {
+                query.count = postrust_core::query::build_count_query(plan, &PreferCount::Exact)?;
+            } else if let Some(count) = &request.preferences.count {
+                query.count = postrust_core::query::build_count_query(plan, count)?;
+            }
+            let count_query = query.count.take();
+
             if !query.has_main() {
                 return Ok(QueryResult::default());
             }
@@ -122,9 +241,16 @@ async fn execute_plan(
             debug!("Executing SQL: {}", sql);
             debug!("With {} parameters", params.len());
 
-            // Execute query
-            let mut conn = state.pool.acquire().await
-                .map_err(|e| postrust_core::Error::ConnectionPool(e.to_string()))?;
+            // Execute everything in one transaction so SET LOCAL applies
+            let mut conn = state.pool.begin().await.map_err(map_pool_error)?;
+
+            // GET/HEAD must not have side effects, including on /rpc
+            if request.action.is_read_only() {
+                sqlx::query("SET TRANSACTION READ ONLY")
+                    .execute(&mut *conn)
+                    .await
+                    .map_err(map_sqlx_error)?;
+            }
 
             // Set role
             sqlx::query(&format!(
@@ -143,6 +269,29 @@ async fn execute_plan(
                 column: None,
             }))?;
 
+            // Resolve unqualified names against the exposed schema first, or
+            // the schemas a trusted proxy chose
+            let search_path = match request.search_path.as_deref() {
+                Some([first, rest @ ..]) => postrust_core::query::build_search_path(
+                    first,
+                    &[rest, &config.db_extra_search_path].concat(),
+                ),
+                _ => postrust_core::query::build_search_path(
+                    &request.schema,
+                    &config.db_extra_search_path,
+                ),
+            };
+            sqlx::query(&search_path)
+                .execute(&mut *conn)
+                .await
+                .map_err(map_sqlx_error)?;
+
+            // Tag the backend with the role and request id for pg_stat_activity
+            sqlx::query(&postrust_core::query::build_application_name(request, &auth.role))
+                .execute(&mut *conn)
+                .await
+                .map_err(map_sqlx_error)?;
+
             // Set claims as GUC
             for (key, value) in &auth.claims {
                 let guc_key = format!("request.jwt.claims.{}", key);
@@ -159,29 +308,140 @@ async fn execute_plan(
                     .ok(); // Ignore errors for individual claims
             }
 
-            // Execute main query with bound parameters
-            let rows = bind_params(sqlx::query(&sql), &params)
-                .fetch_all(&mut *conn)
-                .await
-                .map_err(|e| {
-                    error!("Query error: {}", e);
-                    map_sqlx_error(e)
-                })?;
+            // Expose request method, path, headers and cookies as GUCs
+            for stmt in postrust_core::query::build_request_gucs(
+                request,
+                config.db_request_headers.as_deref(),
+            ) {
+                sqlx::query(&stmt)
+                    .execute(&mut *conn)
+                    .await
+                    .map_err(map_sqlx_error)?;
+            }
+
+            // Render timestamps in the requested time zone, if PostgreSQL knows it
+            let timezone = request
+                .preferences
+                .timezone
+                .clone()
+                .filter(|tz| state.schema_cache().timezones.contains(tz));
+            if let Some(tz) = &timezone {
+                sqlx::query("SELECT set_config('timezone', $1, true)")
+                    .bind(tz)
+                    .execute(&mut *conn)
+                    .await
+                    .map_err(map_sqlx_error)?;
+            }
+
+            // Count before the main query so Content-Range is known before any rows
+            let mut total_count = None;
+            let mut is_estimate = false;
+            let counted = count_query.is_some();
+            if let Some(count_query) = count_query {
+                is_estimate = count_query.is_estimate();
+                total_count = execute_count(&mut conn, count_query).await?;
+            }
+
+            // Page from the offset the total resolves to
+            let range = match total_count {
+                Some(total) if from_end => request.top_level_range.from_end(total),
+                _ => request.top_level_range.clone(),
+            };
+            let (sql, params) = match db_plan {
+                postrust_core::DbActionPlan::Read(tree) if from_end => {
+                    let mut tree = tree.clone();
+                    tree.root.range = range.clone();
+                    postrust_core::query::build_query(
+                        &ActionPlan::Db(postrust_core::DbActionPlan::Read(tree)),
+                        Some(&auth.role),
+                    )?
+                    .build_main()
+                }
+                _ => (sql, params),
+            };
+
+            // Execute main query with bound parameters; limit=0 reads only the count
+            let rows = if request.top_level_range.is_empty()
+                && matches!(db_plan, postrust_core::DbActionPlan::Read(_))
+            {
+                Vec::new()
+            } else {
+                bind_params(sqlx::query(&sql), &params)
+                    .fetch_all(&mut *conn)
+                    .await
+                    .map_err(|e| {
+                        error!("Query error: {}", e);
+                        map_sqlx_error(e)
+                    })?
+            };
+
+            // A stale If-Match leaves nothing updated
+            db_plan.check_preconditions(rows.len())?;
 
             // Convert rows to JSON
-            let json_rows: Vec<serde_json::Value> = rows
+            let bytea = request.bytea_encoding.unwrap_or(config.server_bytea_encoding);
+            let mut json_rows: Vec<serde_json::Value> = rows
                 .iter()
-                .map(|row| row_to_json(row))
+                .map(|row| row_to_json(row, bytea))
                 .collect();
+            let insert_outcome = db_plan.insert_outcome(&mut json_rows);
+            let etag = postrust_core::plan::take_etag(&mut json_rows);
+
+            // Reads always report the page they returned, counted or not;
+            // mutations report how many rows they affected
+            let content_range = if matches!(db_plan, postrust_core::DbActionPlan::MutateRead { .. }) {
+                Some(ContentRange::affected(json_rows.len()))
+            } else {
+                (counted || matches!(db_plan, postrust_core::DbActionPlan::Read(_))).then(|| {
+                    page_content_range(
+                        &range,
+                        json_rows.len(),
+                        total_count,
+                        is_estimate,
+                    )
+                })
+            };
+
+            // Post-request hook runs in the same transaction, so its errors roll back
+            if let Some(function) = &config.db_post_request {
+                let output: Option<serde_json::Value> = sqlx::query_scalar(
+                    &postrust_core::query::build_post_request(function, &request.schema),
+                )
+                .bind(serde_json::Value::Array(json_rows.clone()))
+                .fetch_one(&mut *conn)
+                .await
+                .map_err(map_sqlx_error)?;
+                json_rows = postrust_core::query::apply_post_request(json_rows, output);
+            }
+
+            let transaction = request.preferences.transaction.clone();
+            match transaction {
+                PreferTransaction::Commit => conn.commit().await,
+                PreferTransaction::Rollback => conn.rollback().await,
+            }
+            .map_err(map_sqlx_error)?;
+
+            let mut applied_preferences = db_plan.applied_preferences(&request.preferences, counted);
+            applied_preferences.timezone = timezone;
+            applied_preferences.transaction =
+                Some(transaction).filter(|tx| *tx == PreferTransaction::Rollback);
+
+            let (status, location) = match insert_outcome {
+                Some(outcome) => (outcome.status, outcome.location),
+                None => (StatusCode::OK, None),
+            };
 
             Ok(QueryResult {
-                status: StatusCode::OK,
+                status,
                 rows: json_rows,
-                total_count: None,
-                content_range: None,
-                location: None,
+                total_count,
+                content_range,
+                location,
+                etag,
                 guc_headers: None,
                 guc_status: None,
+                applied_preferences,
+                ..Default::default()
             })
         }
         ActionPlan::Info(info_plan) => {
@@ -190,12 +450,11 @@ async fn execute_plan(
             // Return appropriate metadata based on the info type
             let response_data = match info_plan {
                 InfoPlan::OpenApiSpec => {
-                    // Return basic server info for root endpoint
-                    serde_json::json!({
-                        "name": "postrust",
-                        "version": env!("CARGO_PKG_VERSION"),
-                        "description": "PostgREST-compatible REST API for PostgreSQL"
-                    })
+                    if config.openapi_mode == postrust_core::config::OpenApiMode::Disabled {
+                        return Err(postrust_core::Error::NotFound("OpenAPI is disabled".into()));
+                    }
+                    let schema_cache = state.schema_cache();
+                    postrust_core::openapi::openapi_spec(&schema_cache, &request.schema)
                 }
                 InfoPlan::RelationInfo(qi) => {
                     serde_json::json!({
@@ -222,8 +481,159 @@ async fn execute_plan(
     }
 }
 
-/// Convert a sqlx row to JSON.
-fn row_to_json(row: &sqlx::postgres::PgRow) -> serde_json::Value {
+/// Content-Range for a page of `returned` rows, with the total counted up front.
+fn page_content_range(
+    range: &postrust_core::api_request::Range,
+    returned: usize,
+    total: Option<i64>,
+    is_estimate: bool,
+) -> ContentRange {
+    let cr = ContentRange::from_pagination(range.offset, range.limit, returned as i64, total);
+    if is_estimate {
+        cr.estimated()
+    } else {
+        cr
+    }
+}
+
+/// The `X-Forwarded-Role` of a request from a trusted proxy, if enabled.
+fn forwarded_role<'a>(
+    headers: &'a http::HeaderMap,
+    peer: Option<IpAddr>,
+    config: &postrust_core::AppConfig,
+) -> Option<&'a str> {
+    if !config.server_trust_forwarded_role || !is_trusted_proxy(peer, config) {
+        return None;
+    }
+    headers
+        .get("x-forwarded-role")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|role| !role.is_empty())
+}
+
+/// The external `scheme://host` a trusted proxy forwarded the request for,
+/// if enabled.
+fn forwarded_origin(headers: &http::HeaderMap, peer: Option<IpAddr>, config: &postrust_core::AppConfig) -> Option<String> {
+    if !config.server_trust_forwarded_host || !is_trusted_proxy(peer, config) {
+        return None;
+    }
+    // Proxies chaining the headers append their own value; the first is the client's
+    let first = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+    let proto = first("x-forwarded-proto");
+    let host = first("x-forwarded-host");
+    if proto.is_none() && host.is_none() {
+        return None;
+    }
+
+    let proto = proto.unwrap_or("http").to_ascii_lowercase();
+    let host = host.or_else(|| first("host"))?;
+    let valid_host = host.parse::<http::uri::Authority>().is_ok_and(|a| a.as_str() == host && !host.contains('@'));
+    (matches!(proto.as_str(), "http" | "https") && valid_host).then(|| format!("{}://{}", proto, host))
+}
+
+/// The schemas of a trusted `X-Search-Path` header, if enabled.
+///
+/// Every schema must be one of the exposed `db_schemas`.
+fn search_path_override(
+    headers: &http::HeaderMap,
+    peer: Option<IpAddr>,
+    config: &postrust_core::AppConfig,
+) -> Result<Option<Vec<String>>, postrust_core::Error> {
+    if !config.server_trust_search_path || !is_trusted_proxy(peer, config) {
+        return Ok(None);
+    }
+    let Some(value) = headers.get("x-search-path") else {
+        return Ok(None);
+    };
+    let value = value.to_str().map_err(|_| postrust_core::Error::InvalidHeader("X-Search-Path"))?;
+
+    let schemas: Vec<String> = value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if let Some(schema) = schemas.iter().find(|s| !config.db_schemas.contains(s)) {
+        return Err(postrust_core::Error::UnacceptableSchema {
+            schema: schema.clone(),
+            acceptable: config.db_schemas.clone(),
+        });
+    }
+    Ok((!schemas.is_empty()).then_some(schemas))
+}
+
+/// Whether the peer is one of the configured trusted proxies.
+fn is_trusted_proxy(peer: Option<IpAddr>, config: &postrust_core::AppConfig) -> bool {
+    peer.is_some_and(|peer| {
+        config
+            .server_trusted_proxies
+            .iter()
+            .any(|p| p.parse::<IpAddr>().is_ok_and(|ip| ip == peer))
+    })
+}
+
+/// Map an authentication failure: disallowed roles are forbidden, the rest unauthorized.
+pub(crate) fn auth_error(error: postrust_auth::JwtError) -> postrust_core::Error {
+    match error {
+        postrust_auth::JwtError::RoleNotAllowed(role) => postrust_core::Error::InsufficientPermissions(
+            format!("role \"{}\" is not allowed", role),
+        ),
+        other => postrust_core::Error::InvalidJwt(other.to_string()),
+    }
+}
+
+/// Execute a count query and return the (possibly estimated) total.
+///
+/// Table statistics that are missing fall back to the planner's estimate.
+async fn execute_count(
+    conn: &mut sqlx::PgConnection,
+    count: postrust_core::query::CountQuery,
+) -> Result<Option<i64>, postrust_core::Error> {
+    use postrust_core::query::CountKind;
+
+    let total = fetch_count(&mut *conn, count.sql, count.kind).await?;
+    match (total, count.fallback) {
+        (None, Some(fallback)) => fetch_count(conn, fallback, CountKind::Planner).await,
+        (total, _) => Ok(total),
+    }
+}
+
+/// Run one count statement and read its total.
+async fn fetch_count(
+    conn: &mut sqlx::PgConnection,
+    sql: postrust_sql::SqlFragment,
+    kind: postrust_core::query::CountKind,
+) -> Result<Option<i64>, postrust_core::Error> {
+    use postrust_core::query::CountKind;
+
+    let (sql, params) = sql.build();
+    debug!("Executing count SQL: {}", sql);
+
+    let row = bind_params(sqlx::query(&sql), &params)
+        .fetch_one(conn)
+        .await
+        .map_err(map_sqlx_error)?;
+
+    let total = match kind {
+        CountKind::Exact | CountKind::Statistics => row.try_get::<Option<i64>, _>(0).ok().flatten(),
+        CountKind::Planner => row
+            .try_get::<serde_json::Value, _>(0)
+            .ok()
+            .and_then(|plan| postrust_core::query::explain_row_estimate(&plan)),
+    };
+
+    Ok(total)
+}
+
+/// Convert a sqlx row to JSON, writing bytea columns with `bytea`.
+fn row_to_json(row: &sqlx::postgres::PgRow, bytea: ByteaEncoding) -> serde_json::Value {
     use sqlx::{Column, Row, TypeInfo};
 
     let mut map = serde_json::Map::new();
@@ -248,13 +658,11 @@ fn row_to_json(row: &sqlx::postgres::PgRow) -> serde_json::Value {
             "FLOAT4" | "REAL" => row
                 .try_get::<f32, _>(name)
                 .ok()
-                .and_then(|v| serde_json::Number::from_f64(v as f64))
-                .map(serde_json::Value::Number),
+                .map(|v| float_to_json(v as f64)),
             "FLOAT8" | "DOUBLE PRECISION" => row
                 .try_get::<f64, _>(name)
                 .ok()
-                .and_then(|v| serde_json::Number::from_f64(v))
-                .map(serde_json::Value::Number),
+                .map(float_to_json),
             "NUMERIC" | "DECIMAL" => row
                 .try_get::<sqlx::types::BigDecimal, _>(name)
                 .ok()
@@ -264,6 +672,10 @@ fn row_to_json(row: &sqlx::postgres::PgRow) -> serde_json::Value {
                 .ok()
                 .map(serde_json::Value::Bool),
             "JSON" | "JSONB" => row.try_get::<serde_json::Value, _>(name).ok(),
+            "BYTEA" => row
+                .try_get::<Vec<u8>, _>(name)
+                .ok()
+                .map(|v| serde_json::Value::String(bytea.encode(&v))),
             "UUID" => row
                 .try_get::<sqlx::types::Uuid, _>(name)
                 .ok()
@@ -333,9 +745,29 @@ fn bind_params<'q>(
     query
 }
 
+/// Convert a float to JSON, spelling out the values JSON has no number for
+/// the way PostgreSQL does (`"NaN"`, `"Infinity"`, `"-Infinity"`).
+fn float_to_json(v: f64) -> serde_json::Value {
+    match serde_json::Number::from_f64(v) {
+        Some(n) => serde_json::Value::Number(n),
+        None if v.is_nan() => serde_json::Value::String("NaN".into()),
+        None if v > 0.0 => serde_json::Value::String("Infinity".into()),
+        None => serde_json::Value::String("-Infinity".into()),
+    }
+}
+
+/// Map an error from acquiring a pooled connection.
+fn map_pool_error(e: sqlx::Error) -> postrust_core::Error {
+    match e {
+        sqlx::Error::PoolTimedOut => postrust_core::Error::PoolExhausted,
+        other => postrust_core::Error::ConnectionPool(other.to_string()),
+    }
+}
+
 /// Map sqlx error to our error type.
 fn map_sqlx_error(e: sqlx::Error) -> postrust_core::Error {
     match e {
+        sqlx::Error::PoolTimedOut => postrust_core::Error::PoolExhausted,
         sqlx::Error::Database(db_err) => {
             // Try to downcast to Postgres-specific error for additional details
             let (details, hint) = db_err
@@ -357,8 +789,128 @@ fn map_sqlx_error(e: sqlx::Error) -> postrust_core::Error {
     }
 }
 
+/// Add headers that expose how the request was interpreted.
+fn add_debug_headers(response: &mut PgrstResponse, request: &ApiRequest, enabled: bool) {
+    if enabled {
+        response.set_header("x-canonical-query", &request.query_params.canonical);
+    }
+}
+
+/// The path the API router is mounted under, such as `/api`.
+///
+/// `nest` strips it from the URI the handler sees, so it is recovered from
+/// the original URI. Requests that never went through a router have none.
+fn mount_prefix(request: &Request) -> String {
+    let path = request.uri().path();
+    let Some(original) = request.extensions().get::<OriginalUri>() else {
+        return String::new();
+    };
+    let original = original.path();
+    match original.strip_suffix(path) {
+        Some(prefix) => prefix.to_string(),
+        None if path == "/" => original.to_string(),
+        None => String::new(),
+    }
+}
+
+/// A path under the API as clients address it: behind the mount path, and
+/// absolute when a trusted proxy forwarded the origin.
+fn public_url(origin: Option<&str>, mount: &str, path: &str) -> String {
+    format!("{}{}{}", origin.unwrap_or_default(), mount, path)
+}
+
+/// Link a read's response to its neighbouring pages at `path`, the URL the
+/// client requested.
+fn add_pagination_links(
+    response: &mut PgrstResponse,
+    request: &ApiRequest,
+    path: &str,
+    query: Option<&str>,
+    result: &QueryResult,
+) {
+    use postrust_core::api_request::{Action, DbAction};
+    if matches!(request.action, Action::Db(DbAction::RelationMut { .. })) {
+        return;
+    }
+    let Some(range) = &result.content_range else {
+        return;
+    };
+    if let Some(links) = postrust_response::pagination_links(path, query, range, request.top_level_range.limit) {
+        response.set_header("link", &links);
+    }
+}
+
+/// Whether a request is a plain read that may share another's execution.
+fn is_coalescable(method: &http::Method, request: &ApiRequest) -> bool {
+    use postrust_core::api_request::{Action, DbAction};
+    (method == http::Method::GET || method == http::Method::HEAD)
+        && matches!(request.action, Action::Db(DbAction::RelationRead { .. }))
+}
+
+/// Identity of a read: who asks, for what, and in which representation.
+///
+/// Headers and cookies exposed to SQL are part of it, since policies and
+/// views reading them may return different rows. The search path and origin
+/// are taken as resolved, so untrusted forwarding headers do not split reads.
+fn coalesce_key(
+    parts: &http::request::Parts,
+    request: &ApiRequest,
+    auth: &postrust_auth::AuthResult,
+    origin: Option<&str>,
+    header_allowlist: Option<&[String]>,
+) -> String {
+    let mut key = format!(
+        "{}\n{} {}?{}",
+        caller_key(auth),
+        parts.method,
+        parts.uri.path(),
+        request.query_params.canonical
+    );
+    for name in ["accept", "accept-profile", "prefer", "range"] {
+        for value in parts.headers.get_all(name) {
+            key.push('\n');
+            key.push_str(name);
+            key.push(':');
+            key.push_str(value.to_str().unwrap_or_default());
+        }
+    }
+    key.push('\n');
+    key.push_str(&serde_json::to_string(&request.search_path).unwrap_or_default());
+    key.push('\n');
+    key.push_str(origin.unwrap_or_default());
+    let exposed = postrust_core::query::exposed_headers(request, header_allowlist);
+    key.push('\n');
+    key.push_str(&serde_json::to_string(&exposed).unwrap_or_default());
+    key.push('\n');
+    key.push_str(&serde_json::to_string(&request.cookies).unwrap_or_default());
+    key
+}
+
+/// The role and claims a request runs with, with claims in a stable order.
+fn caller_key(auth: &postrust_auth::AuthResult) -> String {
+    let claims: std::collections::BTreeMap<_, _> = auth.claims.iter().collect();
+    format!("{}\n{}", auth.role, serde_json::to_string(&claims).unwrap_or_default())
+}
+
+/// Primary key of the requested table, when rows are to be keyed by it.
+fn keyed_primary_key(request: &ApiRequest, schema_cache: &postrust_core::SchemaCache) -> Vec<String> {
+    use postrust_core::api_request::{Action, DbAction};
+    if request.accept_media_types.first() != Some(&postrust_core::MediaType::KeyedJson) {
+        return vec![];
+    }
+    match &request.action {
+        Action::Db(DbAction::RelationRead { qi, .. } | DbAction::RelationMut { qi, .. }) => {
+            schema_cache.get_table(qi).map(|t| t.pk_cols.clone()).unwrap_or_default()
+        }
+        _ => vec![],
+    }
+}
+
 /// Build an HTTP response from our response type.
-fn build_response(response: PgrstResponse) -> Response {
+fn build_response(mut response: PgrstResponse) -> Response {
+    if !response.headers.contains_key(http::header::CONTENT_LENGTH) {
+        response.set_content_length();
+    }
     let mut builder = Response::builder().status(response.status);
 
     for (key, value) in &response.headers {
@@ -373,9 +925,10 @@ fn build_response(response: PgrstResponse) -> Response {
 /// Build an error response.
 ///
 /// In production mode (PGRST_DEBUG=false or unset), sensitive error details
-/// are hidden to prevent information leakage.
-fn error_response(error: postrust_core::Error) -> Response {
-    let status = error.status_code();
+/// are hidden to prevent information leakage. `sqlstate_status` maps
+/// database error codes to statuses ahead of the defaults.
+fn error_response(error: postrust_core::Error, sqlstate_status: &HashMap<String, u16>) -> Response {
+    let status = error.status_code_with(sqlstate_status);
 
     // Check if debug mode is enabled
     let debug_mode = std::env::var("PGRST_DEBUG")
@@ -386,19 +939,38 @@ fn error_response(error: postrust_core::Error) -> Response {
         // Full error details in debug mode
         serde_json::to_vec(&error.to_json()).unwrap_or_default()
     } else {
-        // Sanitized error in production
+        // Sanitized error in production; "did you mean" hints only name
+        // relations and functions that are already exposed.
+        let hint = match &error {
+            postrust_core::Error::TableNotFound { .. }
+            | postrust_core::Error::FunctionNotFound { .. } => error.to_json()["hint"].clone(),
+            _ => serde_json::Value::Null,
+        };
+        // The parse position only describes the client's own body, and the
+        // acceptable profiles are the schemas already exposed.
+        let details = match &error {
+            postrust_core::Error::InvalidJson { .. }
+            | postrust_core::Error::UnacceptableSchema { .. } => error.to_json()["details"].clone(),
+            _ => serde_json::Value::Null,
+        };
         let sanitized = serde_json::json!({
             "code": error.code(),
             "message": sanitize_error_message(&error),
-            "details": null,
-            "hint": null
+            "details": details,
+            "hint": hint
         });
         serde_json::to_vec(&sanitized).unwrap_or_default()
     };
 
-    Response::builder()
+    let mut builder = Response::builder()
         .status(status)
         .header("content-type", "application/json")
+        .header("content-length", body.len());
+    if let Some(seconds) = error.retry_after() {
+        builder = builder.header("retry-after", seconds);
+    }
+
+    builder
         .body(Body::from(body))
         .unwrap_or_else(|_| Response::new(Body::empty()))
 }
@@ -407,19 +979,344 @@ fn error_response(error: postrust_core::Error) -> Response {
 fn sanitize_error_message(error: &postrust_core::Error) -> &'static str {
     use postrust_core::Error;
     match error {
-        Error::TableNotFound(_) | Error::NotFound(_) => "Resource not found",
-        Error::FunctionNotFound(_) => "Function not found",
+        Error::TableNotFound { .. } | Error::NotFound(_) => "Resource not found",
+        Error::FunctionNotFound { .. } => "Function not found",
         Error::ColumnNotFound(_) | Error::UnknownColumn(_) => "Column not found",
         Error::RelationshipNotFound(_) => "Relationship not found",
         Error::InvalidPath(_) => "Invalid request path",
         Error::InvalidBody(_) => "Invalid request body",
+        Error::InvalidJson { .. } => "Invalid JSON body",
         Error::InvalidJwt(_) | Error::JwtExpired | Error::MissingAuth => "Unauthorized",
         Error::InsufficientPermissions(_) => "Forbidden",
-        Error::UnacceptableSchema(_) => "Invalid schema",
+        Error::UnacceptableSchema { .. } => "Invalid schema",
         Error::InvalidHeader(_) | Error::InvalidQueryParam(_) => "Invalid request",
         Error::Database(_) => "Database error",
-        Error::ConnectionPool(_) => "Service temporarily unavailable",
+        Error::ConnectionPool(_) | Error::PoolExhausted => "Service temporarily unavailable",
         Error::Internal(_) => "Internal server error",
         _ => "An error occurred",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anon_state(config: postrust_core::AppConfig) -> Arc<AppState> {
+        let cache = postrust_core::SchemaCache {
+            pg_version: 150000,
+            schemas: vec!["public".into()],
+            ..Default::default()
+        };
+        Arc::new(AppState {
+            pool: sqlx::PgPool::connect_lazy("postgres://localhost/postrust").unwrap(),
+            schema_cache: arc_swap::ArcSwap::from_pointee(cache),
+            config: arc_swap::ArcSwap::from_pointee(config),
+            jwt_config: postrust_auth::JwtConfig {
+                anon_role: Some("anon".into()),
+                ..Default::default()
+            },
+            idempotency: None,
+            coalescer: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_api_root_serves_openapi_spec() {
+        use tower::ServiceExt;
+        let get = |uri: &str| Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+
+        for uri in ["/api", "/api/"] {
+            let app = api_router().with_state(anon_state(Default::default()));
+            let response = app.oneshot(get(uri)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+            assert_eq!(
+                response.headers()["content-type"],
+                "application/openapi+json; charset=utf-8"
+            );
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let spec: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(spec["openapi"], "3.0.0");
+        }
+
+        let config = postrust_core::AppConfig {
+            openapi_mode: postrust_core::config::OpenApiMode::Disabled,
+            ..Default::default()
+        };
+        let app = api_router().with_state(anon_state(config));
+        let response = app.oneshot(get("/api/")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_canonical_query_header_behind_flag() {
+        let mut request = ApiRequest::default();
+        request.query_params.canonical = "id=eq.1&select=id".into();
+
+        let mut response = PgrstResponse::empty(StatusCode::OK);
+        add_debug_headers(&mut response, &request, true);
+        assert_eq!(
+            response.headers.get("x-canonical-query").unwrap(),
+            "id=eq.1&select=id"
+        );
+
+        let mut response = PgrstResponse::empty(StatusCode::OK);
+        add_debug_headers(&mut response, &request, false);
+        assert!(response.headers.get("x-canonical-query").is_none());
+    }
+
+    #[test]
+    fn test_build_response_content_length() {
+        let response = build_response(PgrstResponse::new(StatusCode::OK, "[1,2]"));
+        assert_eq!(response.headers()["content-length"], "5");
+
+        let response = build_response(PgrstResponse::empty(StatusCode::NO_CONTENT));
+        assert!(response.headers().get("content-type").is_none());
+        assert!(response.headers().get("content-length").is_none());
+    }
+
+    #[test]
+    fn test_special_floats_become_strings() {
+        assert_eq!(float_to_json(f64::NAN), serde_json::json!("NaN"));
+        assert_eq!(float_to_json(f64::INFINITY), serde_json::json!("Infinity"));
+        assert_eq!(float_to_json(f64::NEG_INFINITY), serde_json::json!("-Infinity"));
+        assert_eq!(float_to_json(f32::INFINITY as f64), serde_json::json!("Infinity"));
+        assert_eq!(float_to_json(1.5), serde_json::json!(1.5));
+    }
+
+    #[test]
+    fn test_content_range_uses_precounted_total() {
+        let range = postrust_core::api_request::Range::new(10, Some(10));
+        let cr = page_content_range(&range, 10, Some(250), false);
+        assert_eq!(cr.to_string(), "items 10-19/250");
+
+        let cr = page_content_range(&range, 10, Some(250), true);
+        assert_eq!(cr.to_string(), "items 10-19/~250");
+
+        // limit=0 skips the rows but still reports the total
+        let range = postrust_core::api_request::Range::new(0, Some(0));
+        assert_eq!(page_content_range(&range, 0, Some(250), false).to_string(), "items */250");
+    }
+
+    #[test]
+    fn test_forwarded_role_only_when_trusted() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert("x-forwarded-role", "web_user".parse().unwrap());
+        let local: Option<IpAddr> = Some("127.0.0.1".parse().unwrap());
+        let remote: Option<IpAddr> = Some("203.0.113.7".parse().unwrap());
+
+        // Off by default
+        let mut config = postrust_core::AppConfig::default();
+        assert_eq!(forwarded_role(&headers, local, &config), None);
+
+        config.server_trust_forwarded_role = true;
+        assert_eq!(forwarded_role(&headers, local, &config), Some("web_user"));
+        assert_eq!(forwarded_role(&headers, remote, &config), None);
+        assert_eq!(forwarded_role(&headers, None, &config), None);
+        assert_eq!(forwarded_role(&http::HeaderMap::new(), local, &config), None);
+
+        config.server_trusted_proxies = vec!["203.0.113.7".into()];
+        assert_eq!(forwarded_role(&headers, remote, &config), Some("web_user"));
+        assert_eq!(forwarded_role(&headers, local, &config), None);
+    }
+
+    #[test]
+    fn test_forwarded_origin_only_when_trusted() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert("host", "localhost:3000".parse().unwrap());
+        headers.insert("x-forwarded-proto", "https".parse().unwrap());
+        headers.insert("x-forwarded-host", "api.example.com, proxy.internal".parse().unwrap());
+        let local: Option<IpAddr> = Some("127.0.0.1".parse().unwrap());
+        let remote: Option<IpAddr> = Some("203.0.113.7".parse().unwrap());
+
+        // Off by default
+        let mut config = postrust_core::AppConfig::default();
+        assert_eq!(forwarded_origin(&headers, local, &config), None);
+
+        config.server_trust_forwarded_host = true;
+        assert_eq!(forwarded_origin(&headers, local, &config).as_deref(), Some("https://api.example.com"));
+        assert_eq!(forwarded_origin(&headers, remote, &config), None);
+
+        // The proto alone keeps the Host header
+        headers.remove("x-forwarded-host");
+        assert_eq!(forwarded_origin(&headers, local, &config).as_deref(), Some("https://localhost:3000"));
+
+        // Neither header: relative URLs as before
+        headers.remove("x-forwarded-proto");
+        assert_eq!(forwarded_origin(&headers, local, &config), None);
+
+        headers.insert("x-forwarded-proto", "javascript".parse().unwrap());
+        assert_eq!(forwarded_origin(&headers, local, &config), None);
+        headers.insert("x-forwarded-proto", "https".parse().unwrap());
+        headers.insert("x-forwarded-host", "evil.com/path".parse().unwrap());
+        assert_eq!(forwarded_origin(&headers, local, &config), None);
+    }
+
+    #[test]
+    fn test_mount_prefix_from_original_uri() {
+        let request = |original: &str, nested: &str| {
+            let mut request = Request::builder().uri(nested).body(Body::empty()).unwrap();
+            request.extensions_mut().insert(OriginalUri(original.parse().unwrap()));
+            request
+        };
+        assert_eq!(mount_prefix(&request("/api/users?id=eq.1", "/users?id=eq.1")), "/api");
+        assert_eq!(mount_prefix(&request("/api", "/")), "/api");
+        assert_eq!(mount_prefix(&request("/api/", "/")), "/api");
+        assert_eq!(mount_prefix(&Request::builder().uri("/users").body(Body::empty()).unwrap()), "");
+    }
+
+    #[test]
+    fn test_forwarded_origin_builds_absolute_location() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert("x-forwarded-proto", "https".parse().unwrap());
+        headers.insert("x-forwarded-host", "api.example.com".parse().unwrap());
+        let config = postrust_core::AppConfig {
+            server_trust_forwarded_host: true,
+            ..Default::default()
+        };
+        let origin = forwarded_origin(&headers, Some("127.0.0.1".parse().unwrap()), &config).unwrap();
+
+        let result = QueryResult {
+            status: StatusCode::CREATED,
+            location: Some(public_url(Some(&origin), "/api", "/users?id=eq.7")),
+            ..Default::default()
+        };
+        let response = format_response(&ApiRequest::default(), &result).unwrap();
+        assert_eq!(response.headers.get("location").unwrap(), "https://api.example.com/api/users?id=eq.7");
+        assert_eq!(public_url(None, "/api", "/users?id=eq.7"), "/api/users?id=eq.7");
+
+        let mut response = format_response(&ApiRequest::default(), &QueryResult::default()).unwrap();
+        let range = postrust_core::api_request::Range::new(0, Some(10));
+        let result = QueryResult {
+            content_range: Some(page_content_range(&range, 10, Some(25), false)),
+            ..Default::default()
+        };
+        let request = ApiRequest {
+            path: "/users".into(),
+            top_level_range: range,
+            ..Default::default()
+        };
+        let path = public_url(Some(&origin), "/api", "/users");
+        add_pagination_links(&mut response, &request, &path, Some("limit=10"), &result);
+        assert_eq!(
+            response.headers.get("link").unwrap(),
+            "<https://api.example.com/api/users?limit=10&offset=10>; rel=\"next\""
+        );
+    }
+
+    #[test]
+    fn test_search_path_override_only_when_enabled() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert("x-search-path", "tenant_a, public".parse().unwrap());
+        let local: Option<IpAddr> = Some("127.0.0.1".parse().unwrap());
+        let remote: Option<IpAddr> = Some("203.0.113.7".parse().unwrap());
+        let mut config = postrust_core::AppConfig {
+            db_schemas: vec!["public".into(), "tenant_a".into()],
+            ..Default::default()
+        };
+
+        // Off by default
+        assert_eq!(search_path_override(&headers, local, &config).unwrap(), None);
+
+        config.server_trust_search_path = true;
+        assert_eq!(
+            search_path_override(&headers, local, &config).unwrap(),
+            Some(vec!["tenant_a".to_string(), "public".to_string()])
+        );
+        assert_eq!(search_path_override(&headers, remote, &config).unwrap(), None);
+        assert_eq!(search_path_override(&http::HeaderMap::new(), local, &config).unwrap(), None);
+
+        // Only exposed schemas
+        headers.insert("x-search-path", "tenant_a,pg_catalog".parse().unwrap());
+        let err = search_path_override(&headers, local, &config).unwrap_err();
+        assert!(
+            matches!(err, postrust_core::Error::UnacceptableSchema { ref schema, .. } if schema == "pg_catalog"),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_coalesce_key_covers_headers_and_cookies_exposed_to_sql() {
+        let auth = postrust_auth::AuthResult::anonymous("anon");
+        let key = |cookie: &str, agent: &str, allowlist: Option<&[String]>| {
+            let request = http::Request::builder()
+                .uri("/projects?select=id")
+                .header("cookie", cookie)
+                .header("user-agent", agent)
+                .body(Bytes::new())
+                .unwrap();
+            let api_request = parse_request(&request, "public", &["public".to_string()]).unwrap();
+            coalesce_key(&request.into_parts().0, &api_request, &auth, None, allowlist)
+        };
+
+        assert_eq!(key("tenant=a", "curl", None), key("tenant=a", "curl", None));
+        assert_ne!(key("tenant=a", "curl", None), key("tenant=b", "curl", None));
+        assert_ne!(key("tenant=a", "curl", None), key("tenant=a", "wget", None));
+
+        // Headers hidden from SQL do not split reads
+        let allowlist = ["x-tenant".to_string()];
+        assert_eq!(key("tenant=a", "curl", Some(&allowlist)), key("tenant=a", "wget", Some(&allowlist)));
+        assert_ne!(key("tenant=a", "curl", Some(&allowlist)), key("tenant=b", "curl", Some(&allowlist)));
+    }
+
+    #[test]
+    fn test_coalesce_key_uses_resolved_search_path_and_origin() {
+        let auth = postrust_auth::AuthResult::anonymous("anon");
+        let allowlist = ["x-tenant".to_string()];
+        let key = |forwarded: &str, search_path: Option<&str>, origin: Option<&str>| {
+            let request = http::Request::builder()
+                .uri("/projects?select=id")
+                .header("x-forwarded-host", forwarded)
+                .header("x-search-path", forwarded)
+                .body(Bytes::new())
+                .unwrap();
+            let mut api_request = parse_request(&request, "public", &["public".to_string()]).unwrap();
+            api_request.search_path = search_path.map(|schema| vec![schema.to_string()]);
+            coalesce_key(&request.into_parts().0, &api_request, &auth, origin, Some(&allowlist))
+        };
+
+        // Ignored forwarding headers do not split reads
+        assert_eq!(key("a", None, None), key("b", None, None));
+        assert_ne!(key("a", Some("tenant_a"), None), key("a", Some("tenant_b"), None));
+        assert_ne!(key("a", None, Some("https://a.example.com")), key("a", None, Some("https://b.example.com")));
+    }
+
+    #[test]
+    fn test_disallowed_role_is_forbidden() {
+        let error = auth_error(postrust_auth::JwtError::RoleNotAllowed("postgres".into()));
+        assert_eq!(error_response(error, &HashMap::new()).status(), StatusCode::FORBIDDEN);
+
+        let error = auth_error(postrust_auth::JwtError::Expired);
+        assert_eq!(error_response(error, &HashMap::new()).status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_unacceptable_schema_keeps_acceptable_profiles() {
+        let error = postrust_core::Error::UnacceptableSchema {
+            schema: "internal".into(),
+            acceptable: vec!["public".into(), "api".into()],
+        };
+        let response = error_response(error, &HashMap::new());
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["code"], "PGRST105");
+        assert_eq!(json["details"], "Acceptable profiles: public, api");
+    }
+
+    #[test]
+    fn test_pool_timeout_returns_503_with_retry_after() {
+        let error = map_pool_error(sqlx::Error::PoolTimedOut);
+        assert!(matches!(error, postrust_core::Error::PoolExhausted));
+
+        let response = error_response(error, &HashMap::new());
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get("retry-after").unwrap(), "1");
+
+        // Other pool failures stay distinct and carry no backoff hint
+        let response = error_response(map_pool_error(sqlx::Error::PoolClosed), &HashMap::new());
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(response.headers().get("retry-after").is_none());
+    }
+}
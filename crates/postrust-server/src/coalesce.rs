@@ -0,0 +1,115 @@
+//! Coalescing of identical in-flight reads.
+//!
+//! Concurrent reads with the same key share one execution and its result.
+//! A flight is forgotten as soon as it finishes, so later reads always run
+//! again, and a failed execution is retried by the next waiting request
+//! rather than handed to it.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// Single-flight map of in-progress executions by key.
+#[derive(Debug)]
+pub struct Coalescer<T> {
+    inflight: Mutex<HashMap<String, Arc<OnceCell<T>>>>,
+}
+
+impl<T> Default for Coalescer<T> {
+    fn default() -> Self {
+        Self {
+            inflight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone> Coalescer<T> {
+    /// Create an empty coalescer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `execute`, or wait for an identical execution already running.
+    pub async fn run<F, Fut, E>(&self, key: String, execute: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let flight = self.inflight.lock().unwrap().entry(key.clone()).or_default().clone();
+        let result = flight.get_or_try_init(execute).await.cloned();
+
+        let mut inflight = self.inflight.lock().unwrap();
+        if inflight.get(&key).is_some_and(|f| Arc::ptr_eq(f, &flight)) {
+            inflight.remove(&key);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// A fake executor counting how often it is reached.
+    async fn execute(calls: &AtomicUsize, fail: bool) -> Result<String, String> {
+        calls.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        if fail {
+            Err("connection reset".into())
+        } else {
+            Ok(r#"[{"id":1}]"#.into())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_identical_reads_execute_once() {
+        let coalescer = Coalescer::new();
+        let calls = AtomicUsize::new(0);
+
+        let (a, b) = tokio::join!(
+            coalescer.run("GET /users".into(), || execute(&calls, false)),
+            coalescer.run("GET /users".into(), || execute(&calls, false)),
+        );
+        assert_eq!(a.unwrap(), r#"[{"id":1}]"#);
+        assert_eq!(b.unwrap(), r#"[{"id":1}]"#);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Finished flights are not reused
+        coalescer.run("GET /users".into(), || execute(&calls, false)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_different_keys_execute_separately() {
+        let coalescer = Coalescer::new();
+        let calls = AtomicUsize::new(0);
+
+        let (a, b) = tokio::join!(
+            coalescer.run("anon GET /users".into(), || execute(&calls, false)),
+            coalescer.run("admin GET /users".into(), || execute(&calls, false)),
+        );
+        assert!(a.is_ok() && b.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_not_shared() {
+        let coalescer = Coalescer::new();
+        let calls = AtomicUsize::new(0);
+
+        // The waiting read runs again instead of taking the leader's error
+        let (a, b) = tokio::join!(
+            coalescer.run("GET /users".into(), || execute(&calls, true)),
+            coalescer.run("GET /users".into(), || execute(&calls, false)),
+        );
+        assert_eq!(a.unwrap_err(), "connection reset");
+        assert_eq!(b.unwrap(), r#"[{"id":1}]"#);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        coalescer.run("GET /users".into(), || execute(&calls, false)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
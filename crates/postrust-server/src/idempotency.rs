@@ -0,0 +1,244 @@
+//! `Idempotency-Key` support for POST requests.
+//!
+//! A retried POST carrying the same key, route and body from the same caller
+//! gets the response of the first attempt instead of running again. Reusing a
+//! key for a different body is rejected, as is a retry that arrives while the
+//! first attempt is still running.
+
+use postrust_response::Response;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Idempotency key, route and caller (role and claims).
+type EntryKey = (String, String, String);
+
+/// Outcome of looking up an idempotency key.
+#[derive(Debug)]
+pub enum Lookup<'a> {
+    /// Key not seen (or expired); run the request, holding the reservation
+    Miss(Reservation<'a>),
+    /// Same key, route, caller and body; replay the stored response
+    Replay(Response),
+    /// Same key, route and caller with a different body
+    Conflict,
+    /// Same request still running under this key
+    InFlight,
+}
+
+/// A remembered response, or a reservation while the request runs.
+#[derive(Debug)]
+struct Entry {
+    body_hash: u64,
+    response: Option<Response>,
+    stored_at: Instant,
+}
+
+/// In-memory store of responses by idempotency key, route and caller.
+#[derive(Debug)]
+pub struct IdempotencyStore {
+    ttl: Duration,
+    entries: Mutex<HashMap<EntryKey, Entry>>,
+}
+
+impl IdempotencyStore {
+    /// Create a store that remembers responses for `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Look up a key for a route, caller and request body.
+    ///
+    /// An unknown key is reserved until the returned reservation is completed
+    /// or dropped.
+    pub fn lookup(&self, key: &str, route: &str, caller: &str, body: &[u8]) -> Lookup<'_> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, e| e.response.is_none() || now.duration_since(e.stored_at) < self.ttl);
+
+        let entry_key = (key.to_string(), route.to_string(), caller.to_string());
+        let body_hash = hash_body(body);
+        match entries.get(&entry_key) {
+            Some(entry) if entry.body_hash != body_hash => Lookup::Conflict,
+            Some(Entry { response: Some(response), .. }) => Lookup::Replay(response.clone()),
+            Some(_) => Lookup::InFlight,
+            None => {
+                entries.insert(
+                    entry_key.clone(),
+                    Entry {
+                        body_hash,
+                        response: None,
+                        stored_at: now,
+                    },
+                );
+                Lookup::Miss(Reservation {
+                    store: self,
+                    key: Some(entry_key),
+                })
+            }
+        }
+    }
+}
+
+/// A key held while its first request runs.
+///
+/// Dropping it without completing releases the key, so a failed request can
+/// be retried.
+#[derive(Debug)]
+pub struct Reservation<'a> {
+    store: &'a IdempotencyStore,
+    key: Option<EntryKey>,
+}
+
+impl Reservation<'_> {
+    /// Remember the response for later retries.
+    pub fn complete(mut self, response: &Response) {
+        if let Some(key) = self.key.take() {
+            if let Some(entry) = self.store.entries.lock().unwrap().get_mut(&key) {
+                entry.response = Some(response.clone());
+                entry.stored_at = Instant::now();
+            }
+        }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.store.entries.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+/// The caller a key belongs to: the role and JWT claims, without the
+/// registered claims that change when a token is refreshed between retries.
+pub fn caller(auth: &postrust_auth::AuthResult) -> String {
+    let claims: std::collections::BTreeMap<_, _> = auth
+        .claims
+        .iter()
+        .filter(|(name, _)| !matches!(name.as_str(), "exp" | "iat" | "nbf" | "jti"))
+        .collect();
+    format!("{}\n{}", auth.role, serde_json::to_string(&claims).unwrap_or_default())
+}
+
+fn hash_body(body: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+
+    fn created(body: &'static str) -> Response {
+        Response::new(StatusCode::CREATED, body)
+    }
+
+    fn remember(store: &IdempotencyStore, key: &str, caller: &str, body: &[u8], response: &Response) {
+        match store.lookup(key, "/users", caller, body) {
+            Lookup::Miss(reservation) => reservation.complete(response),
+            other => panic!("Expected miss, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_replayed_key_returns_cached_response() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let body = br#"{"name":"Alice"}"#;
+        remember(&store, "k1", "alice", body, &created(r#"[{"id":1}]"#));
+
+        match store.lookup("k1", "/users", "alice", body) {
+            Lookup::Replay(response) => {
+                assert_eq!(response.status, StatusCode::CREATED);
+                assert_eq!(&response.body[..], br#"[{"id":1}]"#);
+            }
+            other => panic!("Expected replay, got {:?}", other),
+        }
+
+        // Same key on another route is independent
+        assert!(matches!(store.lookup("k1", "/posts", "alice", body), Lookup::Miss(_)));
+    }
+
+    #[test]
+    fn test_key_is_scoped_to_the_caller() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let body = br#"{"name":"Alice"}"#;
+        remember(&store, "k1", "alice", body, &created(r#"[{"id":1}]"#));
+
+        // Another user sending the same key and body runs their own request
+        assert!(matches!(store.lookup("k1", "/users", "bob", body), Lookup::Miss(_)));
+    }
+
+    #[test]
+    fn test_refreshed_token_replays() {
+        let auth = |sub: &str, exp: i64| postrust_auth::AuthResult {
+            role: "web_user".into(),
+            claims: [
+                ("sub".to_string(), serde_json::json!(sub)),
+                ("exp".to_string(), serde_json::json!(exp)),
+                ("iat".to_string(), serde_json::json!(exp - 3600)),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let body = br#"{"name":"Alice"}"#;
+        remember(&store, "k1", &caller(&auth("alice", 1000)), body, &created(r#"[{"id":1}]"#));
+
+        // The same subject retrying with a fresh token gets the first response
+        let refreshed = caller(&auth("alice", 2000));
+        assert!(matches!(store.lookup("k1", "/users", &refreshed, body), Lookup::Replay(_)));
+        let other = caller(&auth("bob", 1000));
+        assert!(matches!(store.lookup("k1", "/users", &other, body), Lookup::Miss(_)));
+    }
+
+    #[test]
+    fn test_concurrent_retry_is_rejected_while_in_flight() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let body = b"{}";
+
+        let Lookup::Miss(first) = store.lookup("k1", "/users", "alice", body) else {
+            panic!("Expected miss");
+        };
+        assert!(matches!(store.lookup("k1", "/users", "alice", body), Lookup::InFlight));
+
+        first.complete(&created("[]"));
+        assert!(matches!(store.lookup("k1", "/users", "alice", body), Lookup::Replay(_)));
+    }
+
+    #[test]
+    fn test_abandoned_reservation_releases_the_key() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let Lookup::Miss(first) = store.lookup("k1", "/users", "alice", b"{}") else {
+            panic!("Expected miss");
+        };
+        drop(first);
+
+        assert!(matches!(store.lookup("k1", "/users", "alice", b"{}"), Lookup::Miss(_)));
+    }
+
+    #[test]
+    fn test_key_reuse_with_different_body_conflicts() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        remember(&store, "k1", "alice", br#"{"name":"Alice"}"#, &created("[]"));
+
+        assert!(matches!(
+            store.lookup("k1", "/users", "alice", br#"{"name":"Bob"}"#),
+            Lookup::Conflict
+        ));
+    }
+
+    #[test]
+    fn test_expired_entries_are_forgotten() {
+        let store = IdempotencyStore::new(Duration::ZERO);
+        remember(&store, "k1", "alice", b"{}", &created("[]"));
+
+        assert!(matches!(store.lookup("k1", "/users", "alice", b"{}"), Lookup::Miss(_)));
+    }
+}
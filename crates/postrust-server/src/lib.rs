@@ -8,6 +8,8 @@
 //!   Swagger UI, Scalar, and GraphQL Playground at `/admin`.
 
 pub mod app;
+pub mod coalesce;
+pub mod idempotency;
 pub mod state;
 
 #[cfg(feature = "admin-ui")]
@@ -3,16 +3,18 @@
 //! A PostgREST-compatible REST API server for PostgreSQL.
 
 use anyhow::Result;
-use axum::{http::Method, response::Json, routing::any, Router};
+use axum::{http::Method, response::Json, Router};
+use arc_swap::ArcSwap;
 use sqlx::postgres::PgPoolOptions;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tower_http::cors::{Any as CorsAny, CorsLayer};
+use tower_http::cors::{AllowOrigin, Any as CorsAny, CorsLayer};
 use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 mod app;
 mod custom;
+mod coalesce;
+mod idempotency;
 mod state;
 
 #[cfg(feature = "admin-ui")]
@@ -21,21 +23,27 @@ mod admin;
 #[cfg(feature = "admin-ui")]
 use axum::routing::{get, post};
 
-use app::handle_request;
+use coalesce::Coalescer;
+use idempotency::IdempotencyStore;
 use state::AppState;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "postrust=info".into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    // `--dump-schema`: print the schema cache as JSON and exit
+    let dump_schema = std::env::args().skip(1).any(|arg| arg == "--dump-schema");
 
     // Load configuration
     let config = postrust_core::AppConfig::from_env();
+
+    // Initialize tracing; logs go to stderr while stdout carries a dump.
+    // The filter sits behind a reload layer so SIGHUP can change the level
+    let (filter_layer, filter_handle) = tracing_subscriber::reload::Layer::new(log_filter(&config));
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with((!dump_schema).then(tracing_subscriber::fmt::layer))
+        .with(dump_schema.then(|| tracing_subscriber::fmt::layer().with_writer(std::io::stderr)))
+        .init();
+
     info!("Starting Postrust server");
     info!("Database: {}", mask_db_uri(&config.db_uri));
 
@@ -48,31 +56,62 @@ async fn main() -> Result<()> {
     info!("Connected to database");
 
     // Load schema cache
-    let schema_cache = postrust_core::SchemaCache::load(&pool, &config.db_schemas).await?;
+    let mut schema_cache = postrust_core::SchemaCache::load(&pool, &config.db_schemas)
+        .await?
+        .with_routine_filter(&config.routine_filter());
+    if config.db_case_insensitive_names {
+        schema_cache = schema_cache.with_case_insensitive_names();
+    }
     info!("{}", schema_cache.summary());
 
+    if dump_schema {
+        println!("{}", serde_json::to_string_pretty(&schema_cache)?);
+        return Ok(());
+    }
+
     // Create app state
     let state = Arc::new(AppState {
         pool,
-        schema_cache: RwLock::new(schema_cache),
-        config: config.clone(),
+        schema_cache: ArcSwap::from_pointee(schema_cache),
+        config: ArcSwap::from_pointee(config.clone()),
         jwt_config: postrust_auth::JwtConfig {
             secret: config.jwt_secret.clone(),
+            secret_previous: config.jwt_secret_previous.clone(),
             secret_is_base64: config.jwt_secret_is_base64,
             audience: config.jwt_aud.clone(),
             role_claim_key: config.jwt_role_claim_key.clone(),
             anon_role: config.db_anon_role.clone(),
+            role_allowlist: config.jwt_role_allowlist.clone(),
         },
+        idempotency: config
+            .server_idempotency_ttl
+            .map(|ttl| IdempotencyStore::new(std::time::Duration::from_secs(ttl))),
+        coalescer: config.server_coalesce_reads.then(Coalescer::new),
     });
 
-    // Build REST API router (under /api prefix)
-    let api_router: Router<Arc<AppState>> = Router::new()
-        .route("/", any(handle_request))
-        .route("/{*path}", any(handle_request));
+    // Reload runtime settings and the schema cache on SIGHUP
+    #[cfg(unix)]
+    {
+        let state = state.clone();
+        let mut hangups = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                state.reload_config(&postrust_core::AppConfig::from_env());
+                if let Err(e) = filter_handle.reload(log_filter(&state.config())) {
+                    tracing::warn!("Failed to reload log level: {}", e);
+                }
+                info!("Reloaded configuration");
+                // Requests keep being served from the current cache meanwhile
+                match state.reload_schema().await {
+                    Ok(()) => info!("Reloaded schema cache: {}", state.schema_cache().summary()),
+                    Err(e) => tracing::warn!("Failed to reload schema cache: {}", e),
+                }
+            }
+        });
+    }
 
-    // Build main router
-    let mut app: Router<Arc<AppState>> = Router::new()
-        .nest("/api", api_router);
+    // Build main router, with the REST API under the /api prefix
+    let mut app: Router<Arc<AppState>> = app::api_router();
 
     // Add custom routes (health checks, webhooks, etc.)
     app = app.nest("/_", custom::custom_router());
@@ -81,7 +120,7 @@ async fn main() -> Result<()> {
     // Add admin routes and GraphQL endpoint if feature is enabled
     #[cfg(feature = "admin-ui")]
     {
-        use async_graphql_axum::{GraphQLRequest as GqlRequest, GraphQLResponse as GqlResponse};
+        use async_graphql_axum::{GraphQLBatchRequest as GqlRequest, GraphQLResponse as GqlResponse};
         use axum::extract::State as AxumState;
         use axum::http::HeaderMap;
         use postrust_graphql::handler::GraphQLState;
@@ -90,11 +129,21 @@ async fn main() -> Result<()> {
         info!("Admin UI enabled at /admin");
         app = app.nest("/admin", admin::admin_router());
 
-        // Create GraphQL state with subscriptions enabled
-        let schema_cache_snapshot = state.schema_cache.read().await.clone();
-        let schema_cache_arc = Arc::new(schema_cache_snapshot);
+        // Create GraphQL state; subscriptions are opt-in
+        let schema_cache_arc = state.schema_cache();
         let graphql_config = SchemaConfig {
-            enable_subscriptions: true,
+            exposed_schemas: config.db_schemas.clone(),
+            enable_subscriptions: config.graphql_subscriptions,
+            prefix_schema_names: config.graphql_schema_prefix,
+            disable_introspection: config.graphql_disable_introspection,
+            ws_keepalive_timeout: config.graphql_ws_keepalive.map(std::time::Duration::from_secs),
+            ws_max_lifetime: config.graphql_ws_max_lifetime.map(std::time::Duration::from_secs),
+            notify_max_payload: config
+                .graphql_notify_max_payload
+                .unwrap_or(postrust_graphql::subscription::MAX_NOTIFY_PAYLOAD),
+            subscription_tables: config.graphql_subscription_tables.clone(),
+            subscription_exclude: config.graphql_subscription_exclude.clone(),
+            hidden_columns: config.graphql_hidden_columns.clone(),
             ..SchemaConfig::default()
         };
         let graphql_state = Arc::new(
@@ -107,10 +156,18 @@ async fn main() -> Result<()> {
         );
 
         // Initialize subscription broker
-        if let Err(e) = graphql_state.init_subscriptions().await {
-            tracing::warn!("Failed to initialize subscription broker: {}. Subscriptions may not work until triggers are created.", e);
-        } else {
-            info!("GraphQL subscriptions enabled");
+        if config.graphql_subscriptions {
+            if config.graphql_subscription_triggers {
+                match graphql_state.install_notify_triggers().await {
+                    Ok(n) => info!("Installed {} notify triggers", n),
+                    Err(e) => tracing::warn!("Failed to install notify triggers: {}", e),
+                }
+            }
+            if let Err(e) = graphql_state.init_subscriptions().await {
+                tracing::warn!("Failed to initialize subscription broker: {}. Subscriptions may not work until triggers are created.", e);
+            } else {
+                info!("GraphQL subscriptions enabled");
+            }
         }
 
         info!("GraphQL endpoint enabled at /api/graphql");
@@ -123,31 +180,35 @@ async fn main() -> Result<()> {
         }
 
         let graphql_app_state = GraphQLAppState {
-            gql_state: graphql_state.clone(),
+            gql_state: graphql_state,
             jwt_config: state.jwt_config.clone(),
         };
 
-        // Wrapper handler that creates context from request with proper auth
-        async fn handle_graphql(
-            AxumState(app_state): AxumState<GraphQLAppState>,
-            headers: HeaderMap,
-            req: GqlRequest,
-        ) -> GqlResponse {
-            // Extract auth header and authenticate
+        // Authenticate a GraphQL request, falling back to the anonymous role
+        fn graphql_auth(headers: &HeaderMap, jwt_config: &postrust_auth::JwtConfig) -> postrust_auth::AuthResult {
             let auth_header = headers
                 .get("authorization")
                 .and_then(|v| v.to_str().ok());
 
-            let auth_result = match postrust_auth::authenticate(auth_header, &app_state.jwt_config) {
+            match postrust_auth::authenticate(auth_header, jwt_config) {
                 Ok(auth) => auth,
                 Err(e) => {
                     tracing::debug!("GraphQL auth failed: {}, using anon role", e);
                     postrust_auth::AuthResult {
-                        role: app_state.jwt_config.anon_role.clone().unwrap_or_else(|| "anon".to_string()),
+                        role: jwt_config.anon_role.clone().unwrap_or_else(|| "anon".to_string()),
                         claims: std::collections::HashMap::new(),
                     }
                 }
-            };
+            }
+        }
+
+        // Wrapper handler that creates context from request with proper auth
+        async fn handle_graphql(
+            AxumState(app_state): AxumState<GraphQLAppState>,
+            headers: HeaderMap,
+            req: GqlRequest,
+        ) -> GqlResponse {
+            let auth_result = graphql_auth(&headers, &app_state.jwt_config);
 
             tracing::debug!("GraphQL request authenticated as role: {}", auth_result.role);
 
@@ -156,32 +217,49 @@ async fn main() -> Result<()> {
                 (*app_state.gql_state.schema_cache).clone()
             );
 
+            let profile = headers
+                .get("accept-profile")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.trim().to_string());
             let gql_ctx = postrust_graphql::context::GraphQLContext::new(
                 app_state.gql_state.pool.clone(),
                 schema_cache_ref,
                 auth_result,
-            );
+            )
+            .with_profile(profile);
 
-            let request = req
-                .into_inner()
-                .data(gql_ctx)
-                .data(app_state.gql_state.pool.clone())
-                .data(Arc::clone(&app_state.gql_state.broker));
-            app_state.gql_state.schema.execute(request).await.into()
+            postrust_graphql::handler::execute_batch(&app_state.gql_state, gql_ctx, req.into_inner())
+                .await
+                .into()
+        }
+
+        // Subscriptions run as the role that opened the WebSocket
+        async fn handle_graphql_ws(
+            AxumState(app_state): AxumState<GraphQLAppState>,
+            headers: HeaderMap,
+            protocol: async_graphql_axum::GraphQLProtocol,
+            ws: axum::extract::WebSocketUpgrade,
+        ) -> impl axum::response::IntoResponse {
+            let auth_result = graphql_auth(&headers, &app_state.jwt_config);
+            tracing::debug!("GraphQL subscriber authenticated as role: {}", auth_result.role);
+            postrust_graphql::handler::graphql_ws_handler(
+                &app_state.gql_state,
+                auth_result,
+                app_state.jwt_config.clone(),
+                protocol,
+                ws,
+            )
+            .await
         }
 
         // Add GraphQL routes with WebSocket support for subscriptions
         let graphql_router = Router::new()
             .route("/", post(handle_graphql))
             .route("/", get(postrust_graphql::handler::graphql_playground))
+            .route("/ws", get(handle_graphql_ws))
             .with_state(graphql_app_state);
 
-        // WebSocket handler needs separate state (just the GraphQL state)
-        let ws_router = Router::new()
-            .route("/ws", get(postrust_graphql::handler::graphql_ws_handler))
-            .with_state(graphql_state);
-
-        app = app.nest("/api/graphql", graphql_router.merge(ws_router));
+        app = app.nest("/api/graphql", graphql_router);
     }
 
     // Add root info endpoint
@@ -201,7 +279,11 @@ async fn main() -> Result<()> {
     let app = app
         .layer(
             CorsLayer::new()
-                .allow_origin(CorsAny)
+                // Checked against the current configuration, so a reload applies
+                .allow_origin(AllowOrigin::predicate({
+                    let state = state.clone();
+                    move |origin, _| origin.to_str().is_ok_and(|o| state.config().allows_origin(o))
+                }))
                 .allow_methods([
                     Method::GET,
                     Method::POST,
@@ -221,11 +303,23 @@ async fn main() -> Result<()> {
     let listener = tokio::net::TcpListener::bind(&addr).await?;
     info!("Listening on http://{}", addr);
 
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
 
+/// The log filter: `RUST_LOG` when set, otherwise `PGRST_LOG_LEVEL`.
+fn log_filter(config: &postrust_core::AppConfig) -> tracing_subscriber::EnvFilter {
+    match std::env::var("RUST_LOG") {
+        Ok(directives) => tracing_subscriber::EnvFilter::new(directives),
+        Err(_) => tracing_subscriber::EnvFilter::new(format!("postrust={}", config.log_level.to_tracing())),
+    }
+}
+
 /// Mask database URI for logging.
 fn mask_db_uri(uri: &str) -> String {
     if let Some(at_pos) = uri.find('@') {
@@ -1,44 +1,154 @@
 //! Application state.
 
+use crate::coalesce::Coalescer;
+use crate::idempotency::IdempotencyStore;
+use arc_swap::ArcSwap;
 use postrust_auth::JwtConfig;
 use postrust_core::{AppConfig, SchemaCache};
 use sqlx::PgPool;
+use std::future::Future;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 /// Shared application state.
 pub struct AppState {
     /// Database connection pool
     pub pool: PgPool,
-    /// Cached schema metadata
-    pub schema_cache: RwLock<SchemaCache>,
-    /// Application configuration
-    pub config: AppConfig,
+    /// Cached schema metadata, replaced whole on reload
+    pub schema_cache: ArcSwap<SchemaCache>,
+    /// Application configuration, with runtime settings replaced on reload
+    pub config: ArcSwap<AppConfig>,
     /// JWT configuration
     pub jwt_config: JwtConfig,
+    /// Responses remembered by `Idempotency-Key` (when enabled)
+    pub idempotency: Option<IdempotencyStore>,
+    /// Identical in-flight reads sharing one execution (when enabled)
+    pub coalescer: Option<Coalescer<postrust_response::Response>>,
 }
 
 impl AppState {
-    /// Get a read lock on the schema cache.
-    pub async fn schema_cache(&self) -> tokio::sync::RwLockReadGuard<'_, SchemaCache> {
-        self.schema_cache.read().await
+    /// Get the current schema cache.
+    ///
+    /// Never waits on a reload; requests holding a cache keep it until done.
+    pub fn schema_cache(&self) -> Arc<SchemaCache> {
+        self.schema_cache.load_full()
+    }
+
+    /// Get the current configuration.
+    pub fn config(&self) -> Arc<AppConfig> {
+        self.config.load_full()
+    }
+
+    /// Swap in the runtime settings of a reloaded configuration.
+    ///
+    /// Requests in flight keep the configuration they started with.
+    pub fn reload_config(&self, new: &AppConfig) {
+        self.config.store(Arc::new(self.config().with_runtime_settings(new)));
     }
 
     /// Reload the schema cache.
     pub async fn reload_schema(&self) -> Result<(), postrust_core::Error> {
-        let new_cache = SchemaCache::load(&self.pool, &self.config.db_schemas).await?;
-        let mut guard = self.schema_cache.write().await;
-        *guard = new_cache;
+        self.replace_schema_cache(SchemaCache::load(&self.pool, &self.config().db_schemas))
+            .await
+    }
+
+    /// Build a new schema cache off to the side, then swap it in.
+    ///
+    /// Reads use the current cache while `build` runs; if it fails, the
+    /// current cache stays in place.
+    pub async fn replace_schema_cache(
+        &self,
+        build: impl Future<Output = Result<SchemaCache, postrust_core::Error>>,
+    ) -> Result<(), postrust_core::Error> {
+        let config = self.config();
+        let mut new_cache = build.await?.with_routine_filter(&config.routine_filter());
+        if config.db_case_insensitive_names {
+            new_cache = new_cache.with_case_insensitive_names();
+        }
+        self.schema_cache.store(Arc::new(new_cache));
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn cache(pg_version: i32) -> SchemaCache {
+        SchemaCache {
+            pg_version,
+            schemas: vec!["public".into()],
+            ..Default::default()
+        }
+    }
 
-    /// Get the default schema.
-    pub fn default_schema(&self) -> &str {
-        self.config.default_schema()
+    fn state() -> AppState {
+        AppState {
+            pool: PgPool::connect_lazy("postgres://localhost/postrust").unwrap(),
+            schema_cache: ArcSwap::from_pointee(cache(150000)),
+            config: ArcSwap::from_pointee(AppConfig::default()),
+            jwt_config: JwtConfig::default(),
+            idempotency: None,
+            coalescer: None,
+        }
     }
 
-    /// Get exposed schemas.
-    pub fn schemas(&self) -> &[String] {
-        &self.config.db_schemas
+    #[tokio::test]
+    async fn test_reads_do_not_wait_for_a_slow_reload() {
+        let state = state();
+        let slow_build = async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Ok(cache(160000))
+        };
+
+        let reads = async {
+            // Let the reload start, then read while it is still building
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            for _ in 0..5 {
+                let cache = tokio::time::timeout(Duration::from_millis(10), async { state.schema_cache() })
+                    .await
+                    .expect("read blocked by the reload");
+                assert_eq!(cache.pg_version, 150000);
+            }
+        };
+        let (reloaded, ()) = tokio::join!(state.replace_schema_cache(slow_build), reads);
+
+        reloaded.unwrap();
+        assert_eq!(state.schema_cache().pg_version, 160000);
+    }
+
+    #[tokio::test]
+    async fn test_failed_reload_keeps_current_cache() {
+        let state = state();
+        let held = state.schema_cache();
+
+        let err = state
+            .replace_schema_cache(async { Err(postrust_core::Error::SchemaCacheLoadFailed("down".into())) })
+            .await;
+        assert!(err.is_err());
+        assert_eq!(state.schema_cache().pg_version, 150000);
+
+        // A request holding the old cache keeps it across a swap
+        state.replace_schema_cache(async { Ok(cache(160000)) }).await.unwrap();
+        assert_eq!(held.pg_version, 150000);
+        assert_eq!(state.schema_cache().pg_version, 160000);
+    }
+
+    #[tokio::test]
+    async fn test_reload_updates_runtime_settings() {
+        let state = state();
+        let before = state.config();
+
+        let new = AppConfig {
+            db_uri: "postgres://elsewhere/db".into(),
+            db_max_rows: Some(100),
+            ..AppConfig::default()
+        };
+        state.reload_config(&new);
+
+        assert_eq!(state.config().db_max_rows, Some(100));
+        assert_eq!(state.config().db_uri, AppConfig::default().db_uri);
+        // A request already holding the old configuration keeps it
+        assert_eq!(before.db_max_rows, None);
     }
 }
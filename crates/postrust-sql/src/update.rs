@@ -70,6 +70,12 @@ impl UpdateBuilder {
         self
     }
 
+    /// Add a raw RETURNING expression.
+    pub fn returning_raw(mut self, sql: SqlFragment) -> Self {
+        self.returning.push(sql);
+        self
+    }
+
     /// Add RETURNING * clause.
     pub fn returning_all(mut self) -> Self {
         self.returning.push(SqlFragment::raw("*"));